@@ -0,0 +1,77 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, Instant},
+};
+
+/// Compare the event samplers by fast-forwarding deterministic runs of increasing size.
+fn main() {
+    let bench_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("event_sampler");
+
+    println!(
+        "{:>10} {:>22} {:>12} {:>14}",
+        "n_agents", "event_sampler", "time (s)", "steps/s"
+    );
+    for n_agents in [1_000, 10_000, 100_000] {
+        let n_steps = 1_000_000;
+        for event_sampler in ["tree", "composition-rejection"] {
+            let elapsed = fast_forward(&bench_dir, event_sampler, n_agents, n_steps);
+            println!(
+                "{n_agents:>10} {event_sampler:>22} {:>12.3} {:>14.0}",
+                elapsed.as_secs_f64(),
+                n_steps as f64 / elapsed.as_secs_f64()
+            );
+        }
+    }
+}
+
+/// Fast-forward a new deterministic run with a given event sampler and number of agents, returning
+/// the elapsed time.
+fn fast_forward(
+    bench_dir: &Path,
+    event_sampler: &str,
+    n_agents: usize,
+    n_steps: usize,
+) -> Duration {
+    let sim_dir = bench_dir.join(format!("{event_sampler}-{n_agents}"));
+    fs::remove_dir_all(&sim_dir).ok();
+    fs::create_dir_all(&sim_dir).expect("failed to create simulation directory");
+
+    let config_contents = String::new()
+        + "[model]\n"
+        + "n_env = 2\n"
+        + "n_phe = 2\n"
+        + "rates_trans = [ [ -1.0, 1.0,], [ 1.0, -1.0,],]\n"
+        + "rates_birth = [ [ 1.2, 0.0,], [ 0.0, 0.8,],]\n"
+        + "rates_death = [ [ 0.0, 1.0,], [ 1.0, 0.0,],]\n"
+        + "prob_mut = 0.001\n"
+        + &format!("event_sampler = \"{event_sampler}\"\n")
+        + "\n"
+        + "[init]\n"
+        + &format!("n_agents = {n_agents}\n")
+        + "\n"
+        + "[output]\n"
+        + "file_steps_factor = 1024\n"
+        + "save_steps_factor = 16\n"
+        + "hist_bins = 64\n";
+    fs::write(sim_dir.join("config.toml"), config_contents).expect("failed to write config file");
+
+    let start = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_mutare"))
+        .arg("--sim-dir")
+        .arg(&sim_dir)
+        .args(["--run-idx", "0", "fast-forward", "--seed", "42", "--steps"])
+        .arg(n_steps.to_string())
+        .output()
+        .expect("failed to execute command");
+    let elapsed = start.elapsed();
+
+    assert!(
+        output.status.success(),
+        "failed to fast-forward run\nstderr:\n{}\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    elapsed
+}
@@ -0,0 +1,34 @@
+#!/home/marcomc/Documents/Doctorado/mutare/.venv/bin/python3
+
+import argparse
+from pathlib import Path
+
+from mutare_tools.exec import create_sim_jobs
+from mutare_tools.results import collect_results
+
+from sims_configs import SIMS_DIR, SIMS_CONFIGS
+
+
+def parse_args() -> argparse.Namespace:
+    parser = argparse.ArgumentParser(
+        prog="collect_results",
+        description="collect the analyses of all the simulations into a results table",
+    )
+    parser.add_argument(
+        "--output",
+        type=Path,
+        default=SIMS_DIR / "results.csv",
+        help="results file (CSV table)",
+    )
+    return parser.parse_args()
+
+
+if __name__ == "__main__":
+    args = parse_args()
+
+    sim_jobs = [
+        sim_job
+        for sims_config in SIMS_CONFIGS
+        for sim_job in create_sim_jobs(sims_config)
+    ]
+    collect_results(sim_jobs, args.output)
@@ -7,6 +7,7 @@ import requests
 import time
 
 from mutare_tools.exec import SimsConfig, create_sim_jobs, exec_sim_jobs
+from mutare_tools.schedule import AllocationPolicy, Budget, exec_sim_jobs_with_budget
 from mutare_tools.plots.core import plot_sim_jobs
 
 from sims_configs import SIMS_DIR, SIMS_CONFIGS
@@ -20,6 +21,15 @@ def parse_args() -> argparse.Namespace:
         "--plots-only", action="store_true", help="only generate simulation plots"
     )
     parser.add_argument("--notify", action="store_true", help="send notifications")
+    parser.add_argument(
+        "--cpu-hours", type=float, help="global CPU-hour budget of the simulations"
+    )
+    parser.add_argument(
+        "--policy",
+        choices=[policy.name.lower() for policy in AllocationPolicy],
+        default="equal",
+        help="allocation policy of the CPU-hour budget",
+    )
     return parser.parse_args()
 
 
@@ -40,7 +50,9 @@ def log(message: str, notify: bool) -> None:
         print("failed to find notifications configuration")
 
 
-def make_sims(sims_config: SimsConfig, plots_only: bool, notify: bool) -> None:
+def make_sims(
+    sims_config: SimsConfig, plots_only: bool, notify: bool, budget: Budget | None
+) -> None:
     base_dir = sims_config.init_sim_job.base_dir
     if not base_dir.resolve().is_relative_to(SIMS_DIR.resolve()):
         raise ValueError(f"'{base_dir}' must be inside '{SIMS_DIR}'")
@@ -48,9 +60,13 @@ def make_sims(sims_config: SimsConfig, plots_only: bool, notify: bool) -> None:
     sim_jobs = create_sim_jobs(sims_config)
 
     if not plots_only:
-        exec_sim_jobs(sim_jobs)
+        if budget is None:
+            exec_sim_jobs(sim_jobs)
+        elif not exec_sim_jobs_with_budget(sim_jobs, budget):
+            log(f"'{base_dir.name}' simulations stopped (budget exhausted)", notify)
+            return
 
-    plot_sim_jobs(sim_jobs)
+    plot_sim_jobs(sim_jobs, sims_config.grids, sims_config.env_sweep)
 
     log(f"'{base_dir.name}' simulations finished", notify)
 
@@ -60,12 +76,15 @@ if __name__ == "__main__":
 
     plots_only = args.plots_only
     notify = args.notify
+    budget = None
+    if args.cpu_hours is not None:
+        budget = Budget(args.cpu_hours, AllocationPolicy[args.policy.upper()])
 
     log("starting 'make_all_sims'", notify)
 
     try:
         for sims_config in SIMS_CONFIGS:
-            make_sims(sims_config, plots_only, notify)
+            make_sims(sims_config, plots_only, notify, budget)
 
     except Exception as exception:
         log(f"'make_all_sims' failed: {exception}", notify)
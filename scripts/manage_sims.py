@@ -64,7 +64,7 @@ def scan_sims_dir() -> ProgressInfo:
         }
         return dir_entry_names
 
-    expected_sims_dir_entry_names = {"output.log"}
+    expected_sims_dir_entry_names = {"output.log", "results.csv"}
 
     for sims_config in SIMS_CONFIGS:
         yield_or_raise(worker)
@@ -83,8 +83,19 @@ def scan_sims_dir() -> ProgressInfo:
         run_dir_names = {f"run-{run_idx:04}" for run_idx in range(n_runs)}
         expected_sim_dir_entry_names = run_dir_names | {"config.toml"}
         expected_run_dir_entry_names = {
-            f"output-{file_idx:04}.msgpack" for file_idx in range(n_files)
-        } | {"checkpoint.msgpack", "analysis.msgpack", ".lock", "output.log"}
+            f"{kind}-{file_idx:04}.msgpack"
+            for kind in ["output", "tracers"]
+            for file_idx in range(n_files)
+        } | {
+            "checkpoint.msgpack",
+            "checkpoint-metadata.msgpack",
+            "seed.msgpack",
+            "clock.msgpack",
+            "analysis.msgpack",
+            "analysis-partial.msgpack",
+            ".lock",
+            "output.log",
+        }
 
         progress_info.n_expected_msgpacks += len(sim_dirs) * n_runs * (n_files + 2)
 
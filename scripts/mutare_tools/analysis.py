@@ -5,7 +5,9 @@ import numpy as np
 from enum import IntEnum, auto
 from typing import Any
 
-from .exec import SimJob, print_process_msg
+from scipy.interpolate import griddata
+
+from .exec import SimJob, SweepGrid, env_stats, get_config_param, print_process_msg
 
 OBSERVABLES = [
     "time",
@@ -16,10 +18,42 @@ OBSERVABLES = [
     "avg_strat_phe",
     "std_dev_strat_phe",
     "dist_phe",
+    "event_counts",
+    "env",
+    "step",
+    "event_type",
+    "marker_heterozygosity",
+    "marker_afs",
+    "trans_factor",
+    "n_agents_phe",
+    "grid_weight",
+    "strat_diversity",
+    "mut_load",
+    "n_agents_species",
+    "avg_strat_phe_species",
+    "n_agents_patch",
+    "env_patch",
+    "avg_strat_phe_patch",
+    "spatial_avg_strat_phe",
+    "avg_cue_strat_phe",
+    "avg_accuracy",
+    "resources",
+    "env_value",
+    "avg_log_prob_mut",
+    "std_dev_log_prob_mut",
 ]
 
 SCALAR_OBSERVABLES = [
-    obs for obs in OBSERVABLES if obs not in {"avg_strat_phe", "dist_phe"}
+    obs
+    for obs in OBSERVABLES
+    if obs
+    not in {
+        "avg_strat_phe",
+        "dist_phe",
+        "event_counts",
+        "marker_afs",
+        "n_agents_phe",
+    }
 ]
 
 ANALYSIS = [
@@ -33,6 +67,59 @@ ANALYSIS = [
     "avg_dist_phe",
     "avg_birth_rate",
     "tau_avg_strat_phe",
+    "birth_rate",
+    "death_rate",
+    "env_trans_rate",
+    "mut_rate",
+    "cull_rate",
+    "dist_cull_phe",
+    "aligned_n_agents",
+    "aligned_avg_strat_phe",
+    "avg_marker_heterozygosity",
+    "avg_n_marker_alleles",
+    "error_estimates",
+    "time_window",
+    "burn_in_time",
+    "env_frac",
+    "env_avg_growth_rate",
+    "env_avg_avg_strat_phe",
+    "env_dist_avg_strat_phe",
+    "avg_n_agents_phe",
+    "std_dev_phe_frac",
+    "cum_log_growth",
+    "range_n_agents",
+    "range_avg_strat_phe",
+    "var_n_agents",
+    "skew_n_agents",
+    "tau_n_agents",
+    "avg_strat_diversity",
+    "avg_mut_load",
+    "bottleneck_rate",
+    "birth_rate_phe",
+    "death_rate_phe",
+    "env_trans",
+    "avg_n_agents_species",
+    "avg_avg_strat_phe_species",
+    "migration_rate",
+    "avg_n_agents_patch",
+    "avg_avg_strat_phe_patch",
+    "avg_spatial_avg_strat_phe",
+    "patch_env_frac",
+    "avg_avg_cue_strat_phe",
+    "avg_avg_accuracy",
+    "switch_rate",
+    "avg_resources",
+    "avg_env_value",
+    "avg_avg_log_prob_mut",
+    "avg_std_dev_log_prob_mut",
+]
+
+GARDEN_ANALYSIS = [
+    "realizations",
+    "avg_growth_rate",
+    "var_growth_rate",
+    "avg_strat_phe",
+    "var_strat_phe",
 ]
 
 
@@ -73,10 +160,10 @@ def collect_run_time_series(sim_job: SimJob, run_idx: int) -> pd.DataFrame:
                 if n_rows >= MAX_ROWS:
                     break
 
-                obs = {key: message[idx] for idx, key in enumerate(OBSERVABLES)}
+                obs = dict(zip(OBSERVABLES, message))
                 row = {key: obs.get(key) for key in SCALAR_OBSERVABLES}
-                row.update({"avg_strat_phe_0": obs["avg_strat_phe"][0]})
-                row.update({"dist_phe_0": obs["dist_phe"][0]})
+                for key in ["avg_strat_phe", "dist_phe"]:
+                    row[f"{key}_0"] = obs[key][0] if obs[key] is not None else None
                 add_sim_info(row, sim_job)
                 run_time_series.append(row)
                 n_rows += 1
@@ -90,7 +177,18 @@ def read_analysis(sim_dir: Path, run_idx: int) -> dict[str, Any]:
     file_path = sim_dir / f"run-{run_idx:04}" / "analysis.msgpack"
     with file_path.open("rb") as file:
         message: Any = msgpack.unpack(file)
-    return {key: message[idx] for idx, key in enumerate(ANALYSIS)}
+    return dict(zip(ANALYSIS, message))
+
+
+def read_garden_analysis(sim_dir: Path, run_idx: int) -> dict[str, Any]:
+    file_path = sim_dir / f"run-{run_idx:04}" / "garden.msgpack"
+    with file_path.open("rb") as file:
+        message: Any = msgpack.unpack(file)
+    garden = {key: message[idx] for idx, key in enumerate(GARDEN_ANALYSIS)}
+    garden["realizations"] = [
+        dict(zip(ANALYSIS, realization)) for realization in garden["realizations"]
+    ]
+    return garden
 
 
 def collect_avg_analyses(sim_jobs: list[SimJob]) -> pd.DataFrame:
@@ -115,6 +213,23 @@ def collect_avg_analyses(sim_jobs: list[SimJob]) -> pd.DataFrame:
                 analysis[f"tau_{tau_idx}"] = ele[0]
                 analysis[f"tau_avg_strat_phe_0_{tau_idx}"] = ele[1]
             analysis.pop("tau_avg_strat_phe")
+            analysis["dist_cull_phe_0"] = analysis["dist_cull_phe"][0]
+            analysis.pop("dist_cull_phe")
+            analysis.pop("aligned_n_agents")
+            analysis.pop("aligned_avg_strat_phe")
+            analysis.pop("range_n_agents", None)
+            analysis.pop("range_avg_strat_phe", None)
+            analysis.pop("birth_rate_phe", None)
+            analysis.pop("death_rate_phe", None)
+            analysis.pop("env_trans", None)
+            analysis.pop("avg_n_agents_species", None)
+            analysis.pop("avg_avg_strat_phe_species", None)
+            analysis.pop("avg_n_agents_patch", None)
+            analysis.pop("avg_avg_strat_phe_patch", None)
+            analysis.pop("avg_spatial_avg_strat_phe", None)
+            analysis.pop("patch_env_frac", None)
+            analysis.pop("avg_avg_cue_strat_phe", None)
+            analysis.pop("avg_resources", None)
             analysis = pd.DataFrame(analysis, index=[run_idx])
 
             analyses.append(analysis)
@@ -145,3 +260,77 @@ def collect_avg_analyses(sim_jobs: list[SimJob]) -> pd.DataFrame:
     print_process_msg("collected 'avg_analyses'")
 
     return pd.concat(avg_analyses, ignore_index=True)
+
+
+def collect_phase_diagram(sim_jobs: list[SimJob], grid: SweepGrid) -> pd.DataFrame:
+    phase_diagram = []
+    for sim_job in sim_jobs:
+        analyses = pd.DataFrame(
+            [
+                read_analysis(sim_job.sim_dir, run_idx)
+                for run_idx in range(sim_job.n_runs)
+            ]
+        )
+        analyses["avg_avg_strat_phe_0"] = analyses["avg_avg_strat_phe"].str[0]
+
+        row = {
+            grid.x_param: get_config_param(sim_job.config, grid.x_param),
+            grid.y_param: get_config_param(sim_job.config, grid.y_param),
+        }
+        for column in ["avg_growth_rate", "avg_avg_strat_phe_0", "extinct_rate"]:
+            row[f"{column}_mean"] = analyses[column].mean()
+            row[f"{column}_sem"] = analyses[column].sem()
+        phase_diagram.append(row)
+
+    print_process_msg(f"collected '{grid.name}' phase diagram")
+
+    return pd.DataFrame(phase_diagram)
+
+
+SURFACE_COLUMNS = ["avg_growth_rate", "avg_avg_strat_phe_0", "extinct_rate"]
+
+
+def collect_response_surface(sim_jobs: list[SimJob]) -> pd.DataFrame:
+    response_surface = []
+    for sim_job in sim_jobs:
+        analyses = pd.DataFrame(
+            [
+                read_analysis(sim_job.sim_dir, run_idx)
+                for run_idx in range(sim_job.n_runs)
+            ]
+        )
+        analyses["avg_avg_strat_phe_0"] = analyses["avg_avg_strat_phe"].str[0]
+
+        duty_cycle, switching_rate = env_stats(sim_job.config["model"]["rates_trans"])
+        row = {"duty_cycle": duty_cycle, "switching_rate": switching_rate}
+        for column in SURFACE_COLUMNS:
+            row[f"{column}_mean"] = analyses[column].mean()
+            row[f"{column}_sem"] = analyses[column].sem()
+        response_surface.append(row)
+
+    print_process_msg("collected 'response_surface'")
+
+    return pd.DataFrame(response_surface)
+
+
+def interpolate_response_surface(
+    response_surface: pd.DataFrame, n_points: int = 64
+) -> pd.DataFrame:
+    x = response_surface["duty_cycle"].to_numpy()
+    y = np.log10(response_surface["switching_rate"].to_numpy())
+    x_grid, y_grid = np.meshgrid(
+        np.linspace(x.min(), x.max(), n_points),
+        np.linspace(y.min(), y.max(), n_points),
+    )
+
+    grid = pd.DataFrame(
+        {
+            "duty_cycle": x_grid.ravel(),
+            "switching_rate": 10.0 ** y_grid.ravel(),
+        }
+    )
+    for column in SURFACE_COLUMNS:
+        z = response_surface[f"{column}_mean"].to_numpy()
+        grid[column] = griddata((x, y), z, (x_grid, y_grid), method="linear").ravel()
+
+    return grid
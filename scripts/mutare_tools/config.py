@@ -13,11 +13,21 @@ class ModelParams(TypedDict):
     rates_death: list[list[float]]
     prob_mut: float
     std_dev_mut: NotRequired[float]
+    prob_mut_marker: NotRequired[float]
+    expression: NotRequired[str]
+    bottlenecks: NotRequired[list[dict[str, float]]]
+    mutation_kernel: NotRequired[str]
+    concentration_mut: NotRequired[float]
+    event_sampler: NotRequired[str]
+    pinned_env: NotRequired[int]
+    trans_schedule: NotRequired[list[dict[str, float]]]
 
 
 class InitParams(TypedDict):
     n_agents: int
     strat_phe: NotRequired[list[float]]
+    env: NotRequired[int]
+    master_seed: NotRequired[int]
 
 
 class OutputParams(TypedDict):
@@ -7,9 +7,9 @@ import os
 from datetime import datetime
 from signal import signal, SIGUSR1
 import fcntl
-from dataclasses import dataclass
+from dataclasses import dataclass, field
 from enum import Enum, auto
-from typing import Optional
+from typing import Any, Optional
 from types import FrameType
 
 from .config import Config, hash_sim_dir
@@ -139,6 +139,24 @@ class SimJob:
         return hash_sim_dir(self.base_dir, self.config)
 
 
+@dataclass
+class SweepGrid:
+    x_param: str
+    x_values: list[Any]
+    y_param: str
+    y_values: list[Any]
+
+    @property
+    def name(self) -> str:
+        return f"{self.x_param}-{self.y_param}"
+
+
+@dataclass
+class EnvSweep:
+    duty_cycle_values: list[float]
+    switching_rate_values: list[float]
+
+
 @dataclass
 class SimsConfig:
     init_sim_job: SimJob
@@ -146,6 +164,65 @@ class SimsConfig:
     prob_mut_values: list[float]
     n_agents_i_values: list[int]
     fixed_n_agents_i_values: list[int]
+    grids: list[SweepGrid] = field(default_factory=list)
+    env_sweep: EnvSweep | None = None
+
+
+def set_config_param(config: Config, param: str, value: Any) -> None:
+    section, key = param.split(".")
+    config[section][key] = value
+
+
+def get_config_param(config: Config, param: str) -> Any:
+    section, key = param.split(".")
+    return config[section].get(key)
+
+
+def create_grid_sim_jobs(init_sim_job: SimJob, grid: SweepGrid) -> list[SimJob]:
+    base_dir = init_sim_job.base_dir
+    n_runs = init_sim_job.n_runs
+    n_files = init_sim_job.n_files
+
+    sim_jobs = []
+    for x_value in grid.x_values:
+        for y_value in grid.y_values:
+            config = deepcopy(init_sim_job.config)
+            set_config_param(config, grid.x_param, x_value)
+            set_config_param(config, grid.y_param, y_value)
+            sim_jobs.append(SimJob(base_dir, config, n_runs, n_files))
+
+    return sim_jobs
+
+
+def env_rates_trans(duty_cycle: float, switching_rate: float) -> list[list[float]]:
+    rate_0_1 = switching_rate * (1 - duty_cycle)
+    rate_1_0 = switching_rate * duty_cycle
+    return [[-rate_0_1, rate_0_1], [rate_1_0, -rate_1_0]]
+
+
+def env_stats(rates_trans: list[list[float]]) -> tuple[float, float]:
+    (_, rate_0_1), (rate_1_0, _) = rates_trans
+    switching_rate = rate_0_1 + rate_1_0
+    duty_cycle = rate_1_0 / switching_rate
+    return duty_cycle, switching_rate
+
+
+def create_env_sweep_sim_jobs(
+    init_sim_job: SimJob, env_sweep: EnvSweep
+) -> list[SimJob]:
+    base_dir = init_sim_job.base_dir
+    n_runs = init_sim_job.n_runs
+    n_files = init_sim_job.n_files
+
+    sim_jobs = []
+    for duty_cycle in env_sweep.duty_cycle_values:
+        for switching_rate in env_sweep.switching_rate_values:
+            config = deepcopy(init_sim_job.config)
+            rates_trans = env_rates_trans(duty_cycle, switching_rate)
+            config["model"]["rates_trans"] = rates_trans
+            sim_jobs.append(SimJob(base_dir, config, n_runs, n_files))
+
+    return sim_jobs
 
 
 def create_sim_jobs(sims_config: SimsConfig) -> list[SimJob]:
@@ -188,6 +265,17 @@ def create_sim_jobs(sims_config: SimsConfig) -> list[SimJob]:
             config["init"]["n_agents"] = n_agents_i
             sim_jobs.append(SimJob(base_dir, config, n_runs, n_files))
 
+    for grid in sims_config.grids:
+        for grid_sim_job in create_grid_sim_jobs(init_sim_job, grid):
+            if all(grid_sim_job.config != sim_job.config for sim_job in sim_jobs):
+                sim_jobs.append(grid_sim_job)
+
+    env_sweep = sims_config.env_sweep
+    if env_sweep is not None and init_sim_job.config["model"]["n_env"] == 2:
+        for env_sim_job in create_env_sweep_sim_jobs(init_sim_job, env_sweep):
+            if all(env_sim_job.config != sim_job.config for sim_job in sim_jobs):
+                sim_jobs.append(env_sim_job)
+
     return sim_jobs
 
 
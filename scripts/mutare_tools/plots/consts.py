@@ -51,6 +51,11 @@ COL_TEX_LABELS: dict[str, str] = {
     "strat_phe_0_i": "$s(A)_\\text{ini}$",
     "prob_mut": "$p_\\text{mut}$",
     "n_agents_i": "$N_\\text{ini}$",
+    "model.prob_mut": "$p_\\text{mut}$",
+    "model.std_dev_mut": "$\\sigma_\\text{mut}$",
+    "init.n_agents": "$N_\\text{ini}$",
+    "duty_cycle": "$\\pi_0$",
+    "switching_rate": "$k_\\text{env}$",
     "time": "$t$",
     "n_agents": "$N$",
     "n_extinct": "$n_\\text{ext}$",
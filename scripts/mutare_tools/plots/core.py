@@ -5,8 +5,24 @@ from matplotlib.axes import Axes
 from concurrent.futures import ProcessPoolExecutor, as_completed
 from typing import cast
 
-from ..exec import N_CORES, SimJob, print_process_msg
-from ..analysis import SimType, collect_avg_analyses, collect_run_time_series
+from ..exec import (
+    N_CORES,
+    EnvSweep,
+    SimJob,
+    SweepGrid,
+    create_env_sweep_sim_jobs,
+    create_grid_sim_jobs,
+    print_process_msg,
+)
+from ..analysis import (
+    SimType,
+    collect_avg_analyses,
+    collect_run_time_series,
+    collect_phase_diagram,
+    collect_response_surface,
+    interpolate_response_surface,
+    SURFACE_COLUMNS,
+)
 
 from .utils import (
     LINE_STYLE,
@@ -33,6 +49,8 @@ from .utils import (
     interpolate_extinct_rates,
     plot_avg_avg_strat_phe_0,
     plot_tau_avg_strat_phe_0,
+    plot_phase_diagram,
+    plot_response_surface,
 )
 
 
@@ -321,7 +339,42 @@ def make_fixed_plots(df: pd.DataFrame, job: SimJob) -> None:
     print_process_msg("made 'fixed' plots")
 
 
-def plot_sim_jobs(sim_jobs: list[SimJob]) -> None:
+def make_phase_diagram_plots(grid: SweepGrid, df: pd.DataFrame, job: SimJob) -> None:
+    fig_dir = job.base_dir / "plots" / "phase_diagrams" / grid.name
+    fig_dir.mkdir(parents=True, exist_ok=True)
+
+    df.to_csv(fig_dir / "phase_diagram.csv", index=False)
+
+    for z_col in ["avg_growth_rate", "avg_avg_strat_phe_0", "extinct_rate"]:
+        fig, axs = create_colorbar_figure(grid.x_param, grid.y_param, False)
+        plot_phase_diagram(fig, axs[0], axs[1], df, grid, z_col)
+        fig.savefig(fig_dir / f"{z_col}.pdf")
+
+    print_process_msg(f"made '{grid.name}' phase diagram plots")
+
+
+def make_response_surface_plots(df: pd.DataFrame, job: SimJob) -> None:
+    fig_dir = job.base_dir / "plots" / "response_surface"
+    fig_dir.mkdir(parents=True, exist_ok=True)
+
+    grid_df = interpolate_response_surface(df)
+
+    df.to_csv(fig_dir / "response_surface.csv", index=False)
+    grid_df.to_csv(fig_dir / "response_surface_grid.csv", index=False)
+
+    for z_col in SURFACE_COLUMNS:
+        fig, axs = create_colorbar_figure("duty_cycle", "switching_rate", False)
+        plot_response_surface(fig, axs[0], axs[1], df, grid_df, z_col)
+        fig.savefig(fig_dir / f"{z_col}.pdf")
+
+    print_process_msg("made 'response_surface' plots")
+
+
+def plot_sim_jobs(
+    sim_jobs: list[SimJob],
+    grids: list[SweepGrid] = [],
+    env_sweep: EnvSweep | None = None,
+) -> None:
     avg_analyses = collect_avg_analyses(sim_jobs)
     job = sim_jobs[0]
     run_time_series = collect_run_time_series(job, 0)
@@ -336,6 +389,19 @@ def plot_sim_jobs(sim_jobs: list[SimJob]) -> None:
             pool.submit(make_time_series_plots, run_time_series, job),
             pool.submit(make_fixed_plots, avg_analyses, job),
         ]
+        for grid in grids:
+            grid_sim_jobs = create_grid_sim_jobs(job, grid)
+            phase_diagram = collect_phase_diagram(grid_sim_jobs, grid)
+            futures.append(
+                pool.submit(make_phase_diagram_plots, grid, phase_diagram, job)
+            )
+
+        if env_sweep is not None and job.config["model"]["n_env"] == 2:
+            env_sim_jobs = create_env_sweep_sim_jobs(job, env_sweep)
+            response_surface = collect_response_surface(env_sim_jobs)
+            futures.append(
+                pool.submit(make_response_surface_plots, response_surface, job)
+            )
 
         for future in as_completed(futures):
             future.result()
@@ -8,7 +8,7 @@ from matplotlib.cm import ScalarMappable
 from scipy.interpolate import BSpline, make_splrep, LSQBivariateSpline
 from typing import cast, Any, Literal
 
-from ..exec import SimJob
+from ..exec import SimJob, SweepGrid
 
 from .consts import (
     FIGSIZE,
@@ -391,3 +391,41 @@ def plot_tau_avg_strat_phe_0(
     add_top_label(ax_main, label)
 
     set_colorbar(fig, ax_bar, param, norm)
+
+
+def plot_phase_diagram(
+    fig: Figure,
+    ax_main: Axes,
+    ax_bar: Axes,
+    df: pd.DataFrame,
+    grid: SweepGrid,
+    z_col: str,
+) -> None:
+    hm_df = df.pivot(index=grid.y_param, columns=grid.x_param, values=f"{z_col}_mean")
+    hm_x = hm_df.columns.tolist()
+    hm_y = hm_df.index.tolist()
+    image = ax_main.pcolormesh(hm_x, hm_y, hm_df.to_numpy(), cmap=CMAP)
+    for param, set_scale in [
+        (grid.x_param, ax_main.set_xscale),
+        (grid.y_param, ax_main.set_yscale),
+    ]:
+        if param in ["model.prob_mut", "init.n_agents"]:
+            set_scale("log")
+    set_colorbar(fig, ax_bar, z_col, image)
+
+
+def plot_response_surface(
+    fig: Figure,
+    ax_main: Axes,
+    ax_bar: Axes,
+    df: pd.DataFrame,
+    grid_df: pd.DataFrame,
+    z_col: str,
+) -> None:
+    hm_df = grid_df.pivot(index="switching_rate", columns="duty_cycle", values=z_col)
+    hm_x = hm_df.columns.tolist()
+    hm_y = hm_df.index.tolist()
+    image = ax_main.pcolormesh(hm_x, hm_y, hm_df.to_numpy(), cmap=CMAP)
+    ax_main.scatter(df["duty_cycle"], df["switching_rate"], c="k", s=4)
+    ax_main.set_yscale("log")
+    set_colorbar(fig, ax_bar, z_col, image)
@@ -0,0 +1,19 @@
+import shutil
+import subprocess
+from pathlib import Path
+
+from .exec import SimJob, print_process_msg
+
+
+def collect_results(sim_jobs: list[SimJob], file: Path) -> None:
+    project_root = Path(__file__).resolve().parents[2]
+    binary = str(project_root / "target" / "release" / "mutare")
+
+    sim_dirs = [str(sim_job.sim_dir) for sim_job in sim_jobs]
+    args = [binary, "--sim-dir", sim_dirs[0], "collect"]
+    for sim_dir in sim_dirs[1:]:
+        args += ["--other-sim-dir", sim_dir]
+    subprocess.run(args, check=True, capture_output=True)
+    shutil.copyfile(Path(sim_dirs[0]) / "results.csv", file)
+
+    print_process_msg(f"wrote '{file}'")
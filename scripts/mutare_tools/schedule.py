@@ -0,0 +1,106 @@
+import time
+import multiprocessing as mp
+from statistics import stdev
+from dataclasses import dataclass
+from enum import Enum, auto
+
+from .exec import (
+    N_CORES,
+    RunResult,
+    SimJob,
+    SimRun,
+    build_bin,
+    exec_sim_run,
+    print_process_msg,
+    set_signal_handler,
+)
+from .analysis import read_analysis
+
+
+class AllocationPolicy(Enum):
+    EQUAL = auto()
+    UNCERTAINTY = auto()
+
+
+@dataclass
+class Budget:
+    cpu_hours: float
+    policy: AllocationPolicy
+    spent_cpu_hours: float = 0.0
+
+
+def count_job_files(sim_job: SimJob) -> int:
+    n_files = []
+    for run_idx in range(sim_job.n_runs):
+        run_dir = sim_job.sim_dir / f"run-{run_idx:04}"
+        n_files.append(len(list(run_dir.glob("output-*"))))
+    return min(n_files)
+
+
+def calc_job_uncertainty(sim_job: SimJob) -> float:
+    avg_growth_rates = []
+    for run_idx in range(sim_job.n_runs):
+        try:
+            analysis = read_analysis(sim_job.sim_dir, run_idx)
+        except FileNotFoundError:
+            return float("inf")
+        avg_growth_rates.append(analysis["avg_growth_rate"])
+    if len(avg_growth_rates) < 2:
+        return float("inf")
+    return stdev(avg_growth_rates) / len(avg_growth_rates) ** 0.5
+
+
+def select_sim_job(sim_jobs: list[SimJob], policy: AllocationPolicy) -> SimJob | None:
+    pending_sim_jobs = [
+        sim_job for sim_job in sim_jobs if count_job_files(sim_job) < sim_job.n_files
+    ]
+    if not pending_sim_jobs:
+        return None
+
+    match policy:
+        case AllocationPolicy.EQUAL:
+            return min(pending_sim_jobs, key=count_job_files)
+        case AllocationPolicy.UNCERTAINTY:
+            return max(pending_sim_jobs, key=calc_job_uncertainty)
+
+
+def exec_sim_job_round(sim_job: SimJob) -> float:
+    n_files = count_job_files(sim_job) + 1
+    print_process_msg(f"advancing job ({sim_job.sim_dir.name}) to {n_files} files")
+
+    sim_runs = [
+        SimRun(sim_job.sim_dir, run_idx, n_files) for run_idx in range(sim_job.n_runs)
+    ]
+    n_processes = min(N_CORES, len(sim_runs))
+    start = time.monotonic()
+    with mp.Pool(processes=n_processes) as pool:
+        run_results = pool.map(exec_sim_run, sim_runs)
+    cpu_hours = n_processes * (time.monotonic() - start) / 3_600
+
+    if run_results.count(RunResult.FAILED) > 0:
+        raise RuntimeError("some run failed")
+
+    if run_results.count(RunResult.PAUSED) > 0:
+        raise RuntimeError("some run was paused")
+
+    return cpu_hours
+
+
+def exec_sim_jobs_with_budget(sim_jobs: list[SimJob], budget: Budget) -> bool:
+    set_signal_handler()
+
+    build_bin()
+
+    print_process_msg("starting jobs")
+
+    while (sim_job := select_sim_job(sim_jobs, budget.policy)) is not None:
+        if budget.spent_cpu_hours >= budget.cpu_hours:
+            print_process_msg("budget exhausted")
+            return False
+        budget.spent_cpu_hours += exec_sim_job_round(sim_job)
+        print_process_msg(
+            f"spent {budget.spent_cpu_hours:.3f}/{budget.cpu_hours} CPU hours"
+        )
+
+    print_process_msg("jobs finished")
+    return True
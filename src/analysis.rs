@@ -1,60 +1,293 @@
 //! Simulation analysis.
 
-use crate::config::Config;
-use crate::types::{Event, Observables, State, TimeStat};
-use anyhow::{Context, Result};
-use rmp_serde::{decode, encode};
-use serde::Serialize;
-use std::{
-    fs::File,
-    io::{BufReader, BufWriter},
-    path::Path,
+use crate::config::{Config, Dynamics};
+use crate::engine::fold_agents;
+use crate::stats::{Blocking, BlockingEstimate};
+use crate::types::{
+    Agent, ClockShift, EnvVisitCounts, Event, EventCounts, EventType, Extinction, Observables,
+    State, TimeStat,
 };
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use rmp_serde::{decode, encode};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
 
 /// Calculate simulation observables.
+///
+/// The sums over agents are computed in parallel with up to `n_threads` threads.
+#[allow(clippy::too_many_arguments, reason = "mirrors the engine step state")]
 pub fn calc_observables(
     cfg: &Config,
     state: &State,
     event: &Event,
+    step: usize,
     time_step: f64,
     n_extinct: usize,
+    event_counts: EventCounts,
+    n_threads: usize,
 ) -> Observables {
     let n_phe = cfg.model.n_phe;
     let n_agents = state.agents.len() as f64;
 
-    let growth_rate = match event {
-        Event::Replication { .. } => 1.0,
-        Event::Death { .. } => -1.0,
-        _ => 0.0,
-    } / (n_agents * time_step);
+    // Births and deaths do not change the number of agents of a Moran process, and the growth
+    // rate of a Wright-Fisher generation is the logarithm of the mean fitness.
+    let growth_rate = if let Event::Generation = event {
+        let total_fitness: f64 = (state.agents.iter())
+            .map(|agent| cfg.fitness(state.env, agent))
+            .sum();
+        (total_fitness / n_agents).ln() / time_step
+    } else {
+        let n_agents_change = match event {
+            _ if cfg.model.dynamics == Dynamics::Moran => 0.0,
+            Event::Replication { .. } => 1.0,
+            Event::Death { .. } => -1.0,
+            _ => 0.0,
+        };
+        n_agents_change / (n_agents * time_step)
+    };
 
-    let mut avg_strat_phe = vec![0.0; n_phe];
-    for agent in &state.agents {
-        for (sum, &ele) in avg_strat_phe.iter_mut().zip(agent.strat_phe()) {
-            *sum += ele;
-        }
-    }
-    avg_strat_phe.iter_mut().for_each(|ele| *ele /= n_agents);
-    avg_strat_phe.pop();
+    let selection = &cfg.output.observables;
 
-    let mut std_dev_strat_phe = 0.0;
-    for agent in &state.agents {
-        let mut variation = 0.0;
-        for (ele, avg_ele) in agent.strat_phe().iter().zip(&avg_strat_phe) {
-            variation += (ele - avg_ele).abs();
+    // Add up the partial sums of every chunk of agents.
+    let sum_agents = |fold: &(dyn Fn(&[Agent]) -> Vec<f64> + Sync), len: usize| {
+        let mut sums = vec![0.0; len];
+        for partial_sums in fold_agents(&state.agents, n_threads, fold) {
+            sums.iter_mut()
+                .zip(partial_sums)
+                .for_each(|(sum, partial_sum)| *sum += partial_sum);
         }
-        variation /= 2.0;
-        std_dev_strat_phe += variation * variation;
-    }
-    std_dev_strat_phe /= n_agents;
-    std_dev_strat_phe = std_dev_strat_phe.sqrt();
+        sums
+    };
 
-    let mut dist_phe = vec![0.0; n_phe];
-    for agent in &state.agents {
-        dist_phe[agent.phe()] += 1.0;
+    let mut avg_strat_phe = vec![0.0; n_phe];
+    if selection.avg_strat_phe || selection.std_dev_strat_phe {
+        avg_strat_phe = sum_agents(
+            &|agents| {
+                let mut sums = vec![0.0; n_phe];
+                for agent in agents {
+                    for (sum, &ele) in sums.iter_mut().zip(agent.strat_phe()) {
+                        *sum += ele;
+                    }
+                }
+                sums
+            },
+            n_phe,
+        );
+        avg_strat_phe.iter_mut().for_each(|ele| *ele /= n_agents);
+        avg_strat_phe.pop();
     }
-    dist_phe.iter_mut().for_each(|ele| *ele /= n_agents);
-    dist_phe.pop();
+
+    let std_dev_strat_phe = selection.std_dev_strat_phe.then(|| {
+        let sums = sum_agents(
+            &|agents| {
+                let mut std_dev_strat_phe = 0.0;
+                for agent in agents {
+                    let mut variation = 0.0;
+                    for (ele, avg_ele) in agent.strat_phe().iter().zip(&avg_strat_phe) {
+                        variation += (ele - avg_ele).abs();
+                    }
+                    variation /= 2.0;
+                    std_dev_strat_phe += variation * variation;
+                }
+                vec![std_dev_strat_phe]
+            },
+            1,
+        );
+        (sums[0] / n_agents).sqrt()
+    });
+
+    let n_agents_phe = selection.dist_phe.then(|| {
+        sum_agents(
+            &|agents| {
+                let mut n_agents_phe = vec![0.0; n_phe];
+                for agent in agents {
+                    n_agents_phe[agent.phe()] += 1.0;
+                }
+                n_agents_phe
+            },
+            n_phe,
+        )
+    });
+
+    let dist_phe = n_agents_phe.as_ref().map(|n_agents_phe| {
+        let mut dist_phe: Vec<f64> = n_agents_phe.iter().map(|ele| ele / n_agents).collect();
+        dist_phe.pop();
+        dist_phe
+    });
+
+    let avg_strat_phe = selection.avg_strat_phe.then_some(avg_strat_phe);
+
+    let mut_load = sum_agents(
+        &|agents| vec![agents.iter().map(|agent| agent.n_mut() as f64).sum()],
+        1,
+    )[0] / n_agents;
+
+    let strat_diversity = selection.strat_diversity.then(|| {
+        let binning = cfg.strat_binning();
+        let mut strat_bins: Vec<Vec<usize>> = state
+            .agents
+            .iter()
+            .map(|agent| {
+                agent.strat_phe()[..n_phe - 1]
+                    .iter()
+                    .map(|&ele| binning.bin(ele))
+                    .collect()
+            })
+            .collect();
+        strat_bins.sort_unstable();
+        let entropy: f64 = strat_bins
+            .chunk_by(|a, b| a == b)
+            .map(|chunk| {
+                let frac = chunk.len() as f64 / n_agents;
+                -frac * frac.ln()
+            })
+            .sum();
+        entropy.exp()
+    });
+
+    // Add up the number of agents and the strategy of every group of agents (species or patch) in
+    // a single pass, giving the average strategy of every group without its last element.
+    let group_avgs =
+        |group: &(dyn Fn(&Agent) -> usize + Sync), n_groups: usize| -> (Vec<f64>, Vec<Vec<f64>>) {
+            let len = n_phe + 1;
+            let sums = sum_agents(
+                &|agents| {
+                    let mut sums = vec![0.0; n_groups * len];
+                    for agent in agents {
+                        let group_sums = &mut sums[group(agent) * len..][..len];
+                        group_sums[0] += 1.0;
+                        for (sum, &ele) in group_sums[1..].iter_mut().zip(agent.strat_phe()) {
+                            *sum += ele;
+                        }
+                    }
+                    sums
+                },
+                n_groups * len,
+            );
+            sums.chunks(len)
+                .map(|group_sums| {
+                    let n_agents = group_sums[0];
+                    let avg_strat_phe = group_sums[1..n_phe]
+                        .iter()
+                        .map(|sum| sum / n_agents)
+                        .collect();
+                    (n_agents, avg_strat_phe)
+                })
+                .unzip()
+        };
+
+    let n_species = cfg.species.len();
+    let (n_agents_species, avg_strat_phe_species) = (n_species > 0)
+        .then(|| group_avgs(&Agent::species, n_species))
+        .unzip();
+
+    let (n_agents_patch, avg_strat_phe_patch) = (cfg.model.has_patches())
+        .then(|| group_avgs(&Agent::patch, cfg.n_patches()))
+        .unzip();
+    let avg_strat_phe_patch = avg_strat_phe_patch.filter(|_| selection.avg_strat_phe);
+
+    // Empty patches have no average strategy, and are left out of the spatial average.
+    let spatial_avg_strat_phe = avg_strat_phe_patch.as_ref().map(|avg_strat_phe_patch| {
+        (0..n_phe - 1)
+            .map(|phe| {
+                let values: Vec<f64> = avg_strat_phe_patch
+                    .iter()
+                    .map(|avg_strat_phe| avg_strat_phe[phe])
+                    .filter(|ele| !ele.is_nan())
+                    .collect();
+                values.iter().sum::<f64>() / values.len() as f64
+            })
+            .collect()
+    });
+
+    // Add up the strategy conditioned on every cue and the cue accuracy in a single pass.
+    let (avg_cue_strat_phe, avg_accuracy) = cfg
+        .model
+        .sensing
+        .as_ref()
+        .map(|sensing| {
+            let n_cues = cfg.model.n_env;
+            let len = n_cues * (n_phe - 1) + 1;
+            let sums = sum_agents(
+                &|agents| {
+                    let mut sums = vec![0.0; len];
+                    for agent in agents {
+                        for cue in 0..n_cues {
+                            let cue_sums = &mut sums[cue * (n_phe - 1)..(cue + 1) * (n_phe - 1)];
+                            for (sum, &ele) in cue_sums.iter_mut().zip(agent.cue_strat(cue)) {
+                                *sum += ele;
+                            }
+                        }
+                        sums[len - 1] += agent.accuracy().unwrap_or(sensing.accuracy).get();
+                    }
+                    sums
+                },
+                len,
+            );
+            let avg_cue_strat_phe: Vec<Vec<f64>> = (0..n_cues)
+                .map(|cue| {
+                    sums[cue * (n_phe - 1)..(cue + 1) * (n_phe - 1)]
+                        .iter()
+                        .map(|sum| sum / n_agents)
+                        .collect()
+                })
+                .collect();
+            (avg_cue_strat_phe, sums[len - 1] / n_agents)
+        })
+        .unzip();
+    let avg_cue_strat_phe = avg_cue_strat_phe.filter(|_| selection.avg_strat_phe);
+
+    // Add up the decimal logarithm of the mutation probability and its square in a single pass.
+    let (avg_log_prob_mut, std_dev_log_prob_mut) = cfg
+        .model
+        .evolvable_mut
+        .as_ref()
+        .map(|_| {
+            let sums = sum_agents(
+                &|agents| {
+                    let mut sums = vec![0.0; 2];
+                    for agent in agents {
+                        let prob_mut = (agent.prob_mut())
+                            .unwrap_or(cfg.species_prob_mut(agent.species()))
+                            .get();
+                        sums[0] += prob_mut.log10();
+                        sums[1] += prob_mut.log10().powi(2);
+                    }
+                    sums
+                },
+                2,
+            );
+            let avg_log_prob_mut = sums[0] / n_agents;
+            let var_log_prob_mut = sums[1] / n_agents - avg_log_prob_mut.powi(2);
+            (avg_log_prob_mut, var_log_prob_mut.max(0.0).sqrt())
+        })
+        .unzip();
+
+    let (marker_heterozygosity, marker_afs) = cfg
+        .model
+        .prob_mut_marker
+        .map(|_| {
+            let mut markers: Vec<usize> = state.agents.iter().map(|agent| agent.marker()).collect();
+            markers.sort_unstable();
+            let counts: Vec<usize> = markers
+                .chunk_by(|a, b| a == b)
+                .map(|chunk| chunk.len())
+                .collect();
+
+            let homozygosity: f64 = counts
+                .iter()
+                .map(|&count| (count as f64 / n_agents).powi(2))
+                .sum();
+
+            let mut afs = vec![0; counts.iter().copied().max().unwrap_or(0)];
+            for count in counts {
+                afs[count - 1] += 1;
+            }
+
+            (1.0 - homozygosity, afs)
+        })
+        .unzip();
 
     Observables {
         time: state.time,
@@ -65,16 +298,41 @@ pub fn calc_observables(
         avg_strat_phe,
         std_dev_strat_phe,
         dist_phe,
+        event_counts,
+        env: Some(state.env),
+        step: Some(step),
+        event_type: Some(event.event_type()),
+        marker_heterozygosity,
+        marker_afs,
+        trans_factor: (!cfg.model.trans_schedule.is_empty())
+            .then(|| cfg.model.trans_factor(state.time)),
+        n_agents_phe,
+        grid_weight: None,
+        strat_diversity,
+        mut_load: Some(mut_load),
+        n_agents_species,
+        avg_strat_phe_species,
+        n_agents_patch,
+        env_patch: cfg.model.has_patches().then(|| state.patch_envs.clone()),
+        avg_strat_phe_patch,
+        spatial_avg_strat_phe,
+        avg_cue_strat_phe,
+        avg_accuracy,
+        resources: (!state.resources.is_empty()).then(|| state.resources.clone()),
+        env_value: state.env_value,
+        avg_log_prob_mut,
+        std_dev_log_prob_mut,
     }
 }
 
 /// Simulation analysis results.
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Analysis {
     /// Distribution of the number of agents.
     pub dist_n_agents: Vec<f64>,
 
-    /// Average population growth rate.
+    /// Average population growth rate: the long-term (Lyapunov) growth rate, estimated from the
+    /// cumulative change in the logarithm of the population size if available.
     pub avg_growth_rate: f64,
 
     /// Standard deviation of the population growth rate.
@@ -100,182 +358,2269 @@ pub struct Analysis {
 
     /// Time-dependent average phenotypic strategy.
     pub tau_avg_strat_phe: Vec<Vec<TimeStat>>,
+
+    /// Empirical agent birth rate (per agent).
+    pub birth_rate: f64,
+
+    /// Empirical agent death rate (per agent).
+    pub death_rate: f64,
+
+    /// Empirical environment transition rate.
+    pub env_trans_rate: f64,
+
+    /// Empirical strategy mutation rate (per agent).
+    pub mut_rate: f64,
+
+    /// Empirical population cap culling rate (per agent).
+    pub cull_rate: f64,
+
+    /// Distribution of phenotypes of the culled agents.
+    pub dist_cull_phe: Vec<f64>,
+
+    /// Average number of agents as a function of the time since the last environment transition,
+    /// for every environment.
+    pub aligned_n_agents: Vec<Vec<TimeStat>>,
+
+    /// Average average phenotypic strategy as a function of the time since the last environment
+    /// transition, for every environment.
+    pub aligned_avg_strat_phe: Vec<Vec<Vec<TimeStat>>>,
+
+    /// Average heterozygosity of the neutral marker.
+    pub avg_marker_heterozygosity: f64,
+
+    /// Average number of neutral marker alleles.
+    pub avg_n_marker_alleles: f64,
+
+    /// Autocorrelation-corrected error estimates of the scalar observables.
+    #[serde(default)]
+    pub error_estimates: ErrorEstimates,
+
+    /// Window of simulation times of the analyzed observables.
+    #[serde(default)]
+    pub time_window: TimeWindow,
+
+    /// Simulation time before which the observables were discarded as burn-in (if detected, see
+    /// `Manager::analyze_run`).
+    #[serde(default)]
+    pub burn_in_time: Option<f64>,
+
+    /// Fraction of the (time-weighted) observables saved in every environment.
+    #[serde(default)]
+    pub env_frac: Vec<f64>,
+
+    /// Average population growth rate in every environment (over the save intervals ending in
+    /// it).
+    #[serde(default)]
+    pub env_avg_growth_rate: Vec<f64>,
+
+    /// Average average phenotypic strategy in every environment.
+    #[serde(default)]
+    pub env_avg_avg_strat_phe: Vec<Vec<f64>>,
+
+    /// Distribution of average phenotypic strategies in every environment.
+    #[serde(default)]
+    pub env_dist_avg_strat_phe: Vec<Vec<Vec<f64>>>,
+
+    /// Average number of agents with every phenotype.
+    #[serde(default)]
+    pub avg_n_agents_phe: Vec<f64>,
+
+    /// Standard deviation of the fraction of agents with every phenotype.
+    #[serde(default)]
+    pub std_dev_phe_frac: Vec<f64>,
+
+    /// Cumulative change in the logarithm of the population size over the analyzed save
+    /// intervals, corrected for culls (`None` for outputs written by older versions).
+    #[serde(default)]
+    pub cum_log_growth: Option<f64>,
+
+    /// Range of the distribution of the number of agents (`None` for analyses written by older
+    /// versions, whose range is from 0 to `init.n_agents`).
+    #[serde(default)]
+    pub range_n_agents: Option<[f64; 2]>,
+
+    /// Range of the distributions of average phenotypic strategies (`None` for analyses written by
+    /// older versions, whose range is from 0 to 1).
+    #[serde(default)]
+    pub range_avg_strat_phe: Option<[f64; 2]>,
+
+    /// Variance of the number of agents.
+    #[serde(default)]
+    pub var_n_agents: f64,
+
+    /// Skewness of the number of agents.
+    #[serde(default)]
+    pub skew_n_agents: f64,
+
+    /// Integrated autocorrelation time of the number of agents, in simulation time: its blocking
+    /// estimate (see `ErrorEstimates`) times the average time between saved observables.
+    #[serde(default)]
+    pub tau_n_agents: f64,
+
+    /// Average effective number of distinct phenotypic strategies.
+    #[serde(default)]
+    pub avg_strat_diversity: f64,
+
+    /// Average mutational load (see `Observables::mut_load`).
+    #[serde(default)]
+    pub avg_mut_load: f64,
+
+    /// Empirical population bottleneck rate.
+    #[serde(default)]
+    pub bottleneck_rate: f64,
+
+    /// Empirical agent birth rate of every (parent) phenotype (per agent with that phenotype).
+    #[serde(default)]
+    pub birth_rate_phe: Vec<f64>,
+
+    /// Empirical agent death rate of every phenotype (per agent with that phenotype).
+    #[serde(default)]
+    pub death_rate_phe: Vec<f64>,
+
+    /// Environment transitions and visits reconstructed from the observables.
+    #[serde(default)]
+    pub env_trans: EnvTransStats,
+
+    /// Average number of agents of every species.
+    #[serde(default)]
+    pub avg_n_agents_species: Vec<f64>,
+
+    /// Average of the average phenotypic strategy of every species.
+    #[serde(default)]
+    pub avg_avg_strat_phe_species: Vec<Vec<f64>>,
+
+    /// Empirical migration rate between patches (per agent).
+    #[serde(default)]
+    pub migration_rate: f64,
+
+    /// Average number of agents in every patch.
+    #[serde(default)]
+    pub avg_n_agents_patch: Vec<f64>,
+
+    /// Average of the average phenotypic strategy of every patch (while not empty).
+    #[serde(default)]
+    pub avg_avg_strat_phe_patch: Vec<Vec<f64>>,
+
+    /// Average of the spatial average of the average phenotypic strategy.
+    #[serde(default)]
+    pub avg_spatial_avg_strat_phe: Vec<f64>,
+
+    /// Average fraction of patches in every environment.
+    #[serde(default)]
+    pub patch_env_frac: Vec<f64>,
+
+    /// Average of the average phenotypic strategy conditioned on every cue.
+    #[serde(default)]
+    pub avg_avg_cue_strat_phe: Vec<Vec<f64>>,
+
+    /// Average of the average cue accuracy.
+    #[serde(default)]
+    pub avg_avg_accuracy: f64,
+
+    /// Empirical phenotype switching rate (per agent).
+    #[serde(default)]
+    pub switch_rate: f64,
+
+    /// Average concentration of every resource.
+    #[serde(default)]
+    pub avg_resources: Vec<f64>,
+
+    /// Average of the continuous environment value.
+    #[serde(default)]
+    pub avg_env_value: f64,
+
+    /// Average of the average decimal logarithm of the mutation probability.
+    #[serde(default)]
+    pub avg_avg_log_prob_mut: f64,
+
+    /// Average of the standard deviation of the decimal logarithm of the mutation probability.
+    #[serde(default)]
+    pub avg_std_dev_log_prob_mut: f64,
 }
 
-/// Simulation analyzer.
-///
-/// Provides methods to read the simulation output files and analyze them.
-pub struct Analyzer {
-    /// Simulation configuration parameters.
-    cfg: Config,
-    /// Vector of all the simulation observables.
-    all_observables: Vec<Observables>,
+/// Statistics of the environment transitions and visits counted by the engine (or reconstructed
+/// from the observables in outputs written by older versions), next to the values expected from the configuration (`NaN` if the transition rates follow a
+/// schedule or the environment a deterministic trajectory). Confidence intervals are normal
+/// approximations of the 95% ones.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct EnvTransStats {
+    /// Number of observed transitions from every environment to every other one.
+    pub n_trans: Vec<Vec<usize>>,
+
+    /// Number of transitions missed because several happened between two saved observables (or
+    /// outside the analyzed time window), in outputs written by older versions (the engine counts
+    /// every transition otherwise).
+    pub n_missed: usize,
+
+    /// Frequency of the transitions from every environment to every other one.
+    pub trans_freq: Vec<Vec<f64>>,
+
+    /// Half-width of the confidence interval of every transition frequency.
+    pub err_trans_freq: Vec<Vec<f64>>,
+
+    /// Expected frequency of the transitions from every environment to every other one.
+    pub expected_trans_freq: Vec<Vec<f64>>,
+
+    /// Number of complete visits to every environment (whose dwell time was observed).
+    pub n_visits: Vec<usize>,
+
+    /// Average dwell time in every environment.
+    pub avg_dwell_time: Vec<f64>,
+
+    /// Half-width of the confidence interval of every average dwell time.
+    pub err_dwell_time: Vec<f64>,
+
+    /// Expected dwell time in every environment.
+    pub expected_dwell_time: Vec<f64>,
+
+    /// Distribution of the dwell times in every environment, in `hist_bins` bins from 0 to
+    /// `max_dwell_time` (the last bin also holds longer dwell times).
+    pub dist_dwell_time: Vec<Vec<f64>>,
+
+    /// Upper end of the range of the distribution of dwell times in every environment (4 times
+    /// the expected dwell time without schedule, or 1 if infinite).
+    pub max_dwell_time: Vec<f64>,
+
+    /// Descriptions of the statistics deviating significantly (outside their confidence
+    /// interval) from the expected values, which usually indicates a too short run, and of the
+    /// missed transitions (if more than 1%), which indicate too sparse saved observables.
+    pub deviations: Vec<String>,
 }
 
-impl Analyzer {
-    /// Create a new `Analyzer` with the given configuration.
-    pub fn new(cfg: Config) -> Self {
-        Self {
-            cfg,
-            all_observables: Vec::new(),
-        }
+/// Window of simulation times (of the original clock) of the analyzed observables.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct TimeWindow {
+    /// Minimum time (unbounded if unset).
+    pub t_min: Option<f64>,
+
+    /// Maximum time (unbounded if unset).
+    pub t_max: Option<f64>,
+}
+
+impl TimeWindow {
+    /// Check whether the window contains a given time.
+    pub fn contains(&self, time: f64) -> bool {
+        self.t_min.is_none_or(|t_min| time >= t_min) && self.t_max.is_none_or(|t_max| time <= t_max)
     }
 
-    /// Read simulation output file and add it to the analysis.
-    pub fn add_output_file<P: AsRef<Path>>(&mut self, file: P) -> Result<()> {
-        let file = file.as_ref();
-        let file = File::open(file).with_context(|| format!("failed to open {file:?}"))?;
-        let mut reader = BufReader::new(file);
+    /// Check whether the window is unbounded.
+    pub fn is_unbounded(&self) -> bool {
+        self.t_min.is_none() && self.t_max.is_none()
+    }
+}
 
-        // Read and collect all the observables in the file.
-        use decode::Error::InvalidMarkerRead;
-        use std::io::ErrorKind::UnexpectedEof;
-        loop {
-            match decode::from_read(&mut reader) {
-                Ok(observables) => self.all_observables.push(observables),
-                Err(InvalidMarkerRead(error)) if error.kind() == UnexpectedEof => break,
-                Err(error) => return Err(error).context("failed to deserialize observables"),
-            }
-        }
+/// Autocorrelation-corrected error estimates of the (unweighted) means of the scalar observables
+/// over every saved observables (see `stats::Blocking`).
+#[derive(Default, Serialize, Deserialize)]
+pub struct ErrorEstimates {
+    /// Number of agents.
+    pub n_agents: BlockingEstimate,
+
+    /// Population growth rate over every save interval.
+    pub growth_rate: BlockingEstimate,
+
+    /// Average phenotypic strategy.
+    pub avg_strat_phe: Vec<BlockingEstimate>,
+
+    /// Standard deviation of the phenotypic strategy.
+    pub std_dev_strat_phe: BlockingEstimate,
+}
 
+impl Analysis {
+    /// Write the analysis as pretty-printed JSON (non-finite numbers are written as `null`).
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, self)
+            .context("failed to serialize analysis as JSON")?;
+        writeln!(writer).context("failed to write analysis")?;
         Ok(())
     }
+}
 
-    /// Make the analysis and save it to a file.
-    pub fn analyze<P: AsRef<Path>>(&self, file: P) -> Result<()> {
-        let file = file.as_ref();
-        let file = File::create(file).with_context(|| format!("failed to create {file:?}"))?;
-        let mut writer = BufWriter::new(file);
+/// Common-garden analysis: analyses of the same population evolving in several independent
+/// environment realizations, and their between-realization statistics.
+#[derive(Serialize, Deserialize)]
+pub struct GardenAnalysis {
+    /// Analyses of every environment realization.
+    pub realizations: Vec<Analysis>,
 
-        let first_observables = self
-            .all_observables
-            .first()
-            .context("failed to get first observables")?;
+    /// Average over realizations of the average population growth rate.
+    pub avg_growth_rate: f64,
 
-        let last_observables = self
-            .all_observables
-            .last()
-            .context("failed to get last observables")?;
+    /// Between-realization variance of the average population growth rate.
+    pub var_growth_rate: f64,
 
-        let time_steps = self
-            .all_observables
-            .iter()
-            .map(|obs| obs.time_step)
-            .collect::<Vec<_>>();
-
-        let obs_weighted_average = |f: &dyn Fn(&Observables) -> f64| {
-            weighted_average(
-                &self.all_observables.iter().map(f).collect::<Vec<_>>(),
-                &time_steps,
-            )
+    /// Average over realizations of the average phenotypic strategy.
+    pub avg_strat_phe: Vec<f64>,
+
+    /// Between-realization variance of the average phenotypic strategy.
+    pub var_strat_phe: Vec<f64>,
+}
+
+impl GardenAnalysis {
+    /// Calculate the between-realization statistics of the given analyses.
+    pub fn new(realizations: Vec<Analysis>) -> Self {
+        // Sample mean and (unbiased) variance of a quantity over realizations.
+        let mean_var = |f: &dyn Fn(&Analysis) -> f64| {
+            let vals: Vec<f64> = realizations.iter().map(f).collect();
+            let n = vals.len() as f64;
+            let mean = vals.iter().sum::<f64>() / n;
+            let var = vals.iter().map(|val| (val - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            (mean, var)
         };
 
-        let avg_growth_rate = obs_weighted_average(&|obs| obs.growth_rate);
+        let (avg_growth_rate, var_growth_rate) = mean_var(&|analysis| analysis.avg_growth_rate);
 
-        let extinct_rate = last_observables.n_extinct as f64 / last_observables.time;
+        let n_ele = realizations
+            .first()
+            .map_or(0, |analysis| analysis.avg_avg_strat_phe.len());
+        let (avg_strat_phe, var_strat_phe) = (0..n_ele)
+            .map(|ele| mean_var(&|analysis| analysis.avg_avg_strat_phe[ele]))
+            .unzip();
 
-        let tau_max = if extinct_rate > 0.0 {
-            1.0 / extinct_rate
-        } else {
-            last_observables.time
-        };
-        let taus: Vec<f64> = std::iter::successors(Some(4.0), |&prev| {
-            let next = prev * 2.0;
-            (next < tau_max).then_some(next)
-        })
-        .collect();
-
-        let mut tau_avg_strat_phe_vals: Vec<Vec<Vec<f64>>> = vec![Vec::new(); taus.len()];
-
-        let mut avg_strat_phe = &first_observables.avg_strat_phe;
-        let mut n_extinct = first_observables.n_extinct;
-        let mut time_origin = first_observables.time;
-        let mut tau_idx = 0;
-        for obs in &self.all_observables {
-            if obs.n_extinct > n_extinct {
-                n_extinct = obs.n_extinct;
-                time_origin = obs.time;
-                tau_idx = 0;
-            }
-            while tau_idx < taus.len() && (obs.time - time_origin) > taus[tau_idx] {
-                tau_avg_strat_phe_vals[tau_idx].push(avg_strat_phe.clone());
-                tau_idx += 1;
-            }
-            avg_strat_phe = &obs.avg_strat_phe;
+        Self {
+            realizations,
+            avg_growth_rate,
+            var_growth_rate,
+            avg_strat_phe,
+            var_strat_phe,
         }
+    }
+}
 
-        let analysis = Analysis {
-            dist_n_agents: (0..self.cfg.output.hist_bins)
+/// Mean of a quantity over the runs of a simulation and its inter-run standard error.
+#[derive(Serialize, Deserialize)]
+pub struct RunStat {
+    /// Mean over runs.
+    pub mean: f64,
+
+    /// Standard error of the mean over runs.
+    pub std_err: f64,
+}
+
+/// Aggregate analysis: statistics over the analyses of every run of a simulation.
+#[derive(Serialize, Deserialize)]
+pub struct AggregateAnalysis {
+    /// Number of analyzed runs.
+    pub n_runs: usize,
+
+    /// Average population growth rate.
+    pub avg_growth_rate: RunStat,
+
+    /// Standard deviation of the population growth rate.
+    pub std_dev_growth_rate: RunStat,
+
+    /// Total extinction rate.
+    pub extinct_rate: RunStat,
+
+    /// Average average phenotypic strategy.
+    pub avg_avg_strat_phe: Vec<RunStat>,
+
+    /// Average standard deviation of the phenotypic strategy.
+    pub avg_std_dev_strat_phe: RunStat,
+
+    /// Average distribution of phenotypes.
+    pub avg_dist_phe: Vec<RunStat>,
+
+    /// Average population birth rate.
+    pub avg_birth_rate: RunStat,
+
+    /// Pooled distribution of the number of agents (average over runs).
+    pub dist_n_agents: Vec<f64>,
+
+    /// Pooled distribution of average phenotypic strategies (average over runs).
+    pub dist_avg_strat_phe: Vec<Vec<f64>>,
+}
+
+impl AggregateAnalysis {
+    /// Calculate the statistics over runs of the given analyses.
+    pub fn new(analyses: &[Analysis]) -> Self {
+        let n = analyses.len() as f64;
+
+        // Sample mean and standard error of the mean of a quantity over runs.
+        let run_stat = |f: &dyn Fn(&Analysis) -> f64| {
+            let vals: Vec<f64> = analyses.iter().map(f).collect();
+            let mean = vals.iter().sum::<f64>() / n;
+            let var = vals.iter().map(|val| (val - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            RunStat {
+                mean,
+                std_err: (var / n).sqrt(),
+            }
+        };
+
+        // Average over runs of a distribution.
+        let pool = |f: &dyn Fn(&Analysis) -> &[f64]| -> Vec<f64> {
+            let n_bins = analyses.first().map_or(0, |analysis| f(analysis).len());
+            (0..n_bins)
                 .map(|bin| {
-                    obs_weighted_average(&|obs| {
-                        let obs_bin = ((obs.n_agents / self.cfg.init.n_agents as f64
-                            * self.cfg.output.hist_bins as f64)
-                            as usize)
-                            .min(self.cfg.output.hist_bins - 1);
-                        if obs_bin == bin { 1.0 } else { 0.0 }
-                    })
+                    analyses
+                        .iter()
+                        .map(|analysis| f(analysis)[bin])
+                        .sum::<f64>()
+                        / n
                 })
+                .collect()
+        };
+
+        let n_ele = analyses
+            .first()
+            .map_or(0, |analysis| analysis.avg_avg_strat_phe.len());
+        let n_phe = analyses
+            .first()
+            .map_or(0, |analysis| analysis.avg_dist_phe.len());
+
+        Self {
+            n_runs: analyses.len(),
+            avg_growth_rate: run_stat(&|analysis| analysis.avg_growth_rate),
+            std_dev_growth_rate: run_stat(&|analysis| analysis.std_dev_growth_rate),
+            extinct_rate: run_stat(&|analysis| analysis.extinct_rate),
+            avg_avg_strat_phe: (0..n_ele)
+                .map(|ele| run_stat(&|analysis| analysis.avg_avg_strat_phe[ele]))
+                .collect(),
+            avg_std_dev_strat_phe: run_stat(&|analysis| analysis.avg_std_dev_strat_phe),
+            avg_dist_phe: (0..n_phe)
+                .map(|phe| run_stat(&|analysis| analysis.avg_dist_phe[phe]))
                 .collect(),
+            avg_birth_rate: run_stat(&|analysis| analysis.avg_birth_rate),
+            dist_n_agents: pool(&|analysis| &analysis.dist_n_agents),
+            dist_avg_strat_phe: (0..n_ele)
+                .map(|ele| pool(&|analysis| &analysis.dist_avg_strat_phe[ele]))
+                .collect(),
+        }
+    }
 
-            avg_growth_rate,
+    /// Write the aggregate analysis as pretty-printed JSON (non-finite numbers are written as
+    /// `null`).
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, self)
+            .context("failed to serialize aggregate analysis as JSON")?;
+        writeln!(writer).context("failed to write aggregate analysis")?;
+        Ok(())
+    }
+}
+
+/// Statistic estimated from a sample.
+pub struct Estimate {
+    /// Estimated value.
+    pub val: f64,
+
+    /// Half-width of the 95% confidence interval.
+    pub err: f64,
+}
+
+/// Empirical statistics of the environment process.
+pub struct EnvCalibration {
+    /// Number of visits to every environment.
+    pub n_visits: Vec<usize>,
+
+    /// Average dwell time in every environment.
+    pub dwell_time: Vec<Estimate>,
+
+    /// Frequency of the transitions from every environment to every other one.
+    pub trans_freq: Vec<Vec<Estimate>>,
+}
 
-            std_dev_growth_rate: obs_weighted_average(&|obs| {
-                (obs.growth_rate - avg_growth_rate).powi(2) * obs.time_step
+impl EnvCalibration {
+    /// Calculate the statistics of a sequence of environment visits, given as the environment,
+    /// the time spent in it and the next environment.
+    pub fn new(n_env: usize, visits: &[(usize, f64, usize)]) -> Self {
+        // Normal approximation of the 95% confidence interval.
+        let z = 1.96;
+
+        let mut n_visits = vec![0; n_env];
+        let mut dwell_times = vec![Vec::new(); n_env];
+        let mut n_trans = vec![vec![0; n_env]; n_env];
+        for &(env, dwell_time, next_env) in visits {
+            n_visits[env] += 1;
+            dwell_times[env].push(dwell_time);
+            n_trans[env][next_env] += 1;
+        }
+
+        let dwell_time = dwell_times
+            .iter()
+            .map(|vals| {
+                let n = vals.len() as f64;
+                let avg = vals.iter().sum::<f64>() / n;
+                let var = vals.iter().map(|val| (val - avg).powi(2)).sum::<f64>() / (n - 1.0);
+                Estimate {
+                    val: avg,
+                    err: z * (var / n).sqrt(),
+                }
             })
-            .sqrt(),
+            .collect();
 
-            extinct_rate,
+        let trans_freq = n_trans
+            .iter()
+            .zip(&n_visits)
+            .map(|(counts, &n)| {
+                let n = n as f64;
+                counts
+                    .iter()
+                    .map(|&count| {
+                        let freq = count as f64 / n;
+                        Estimate {
+                            val: freq,
+                            err: z * (freq * (1.0 - freq) / n).sqrt(),
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
 
-            avg_avg_strat_phe: (0..self.cfg.model.n_phe - 1)
-                .map(|phe| obs_weighted_average(&|obs| obs.avg_strat_phe[phe]))
-                .collect(),
+        Self {
+            n_visits,
+            dwell_time,
+            trans_freq,
+        }
+    }
+}
 
-            avg_std_dev_strat_phe: obs_weighted_average(&|obs| obs.std_dev_strat_phe),
+/// Analysis of the extinctions of a simulation run.
+#[derive(Serialize, Deserialize)]
+pub struct ExtinctionAnalysis {
+    /// Logged extinctions.
+    pub extinctions: Vec<Extinction>,
 
-            dist_avg_strat_phe: (0..self.cfg.model.n_phe - 1)
-                .map(|phe| {
-                    (0..self.cfg.output.hist_bins)
-                        .map(|bin| {
-                            obs_weighted_average(&|obs| {
-                                let obs_bin = ((obs.avg_strat_phe[phe]
-                                    * self.cfg.output.hist_bins as f64)
-                                    as usize)
-                                    .min(self.cfg.output.hist_bins - 1);
-                                if obs_bin == bin { 1.0 } else { 0.0 }
-                            })
-                        })
-                        .collect()
-                })
-                .collect(),
+    /// Time from the start of the run or the previous extinction to every extinction.
+    pub intervals: Vec<f64>,
 
-            avg_dist_phe: (0..self.cfg.model.n_phe - 1)
-                .map(|phe| obs_weighted_average(&|obs| obs.dist_phe[phe]))
-                .collect(),
+    /// Time from the last extinction (or the start of the run) to the current time of the run,
+    /// during which the population has survived so far.
+    pub censored_interval: f64,
 
-            avg_birth_rate: obs_weighted_average(&|obs| obs.growth_rate.max(0.0)),
+    /// Number of extinctions per unit time.
+    pub rate_extinct: f64,
 
-            tau_avg_strat_phe: (0..self.cfg.model.n_phe - 1)
-                .map(|phe| {
-                    taus.iter()
-                        .zip(&tau_avg_strat_phe_vals)
-                        .map(|(&tau, vals)| {
-                            let sum: f64 = vals.iter().map(|v| v[phe]).sum();
-                            TimeStat {
-                                tau,
-                                val: sum / vals.len() as f64,
-                            }
-                        })
-                        .collect()
-                })
-                .collect(),
-        };
+    /// Average interval between extinctions.
+    pub avg_interval: f64,
+
+    /// Coefficient of variation of the intervals between extinctions (1 for a Poisson process).
+    pub cv_interval: f64,
+
+    /// Time at which the survival curve falls to 1/2 (if it does).
+    pub median_interval: Option<f64>,
+
+    /// Kaplan–Meier survival curve: probability that the population survives for a given time
+    /// after the start of the run or an extinction.
+    pub survival: Vec<TimeStat>,
+}
 
-        encode::write(&mut writer, &analysis).context("failed to serialize analysis")?;
+impl ExtinctionAnalysis {
+    /// Calculate the statistics of the extinctions of a simulation run, given in order, up to its
+    /// current time.
+    pub fn new(extinctions: Vec<Extinction>, time: f64) -> Self {
+        let mut intervals = Vec::with_capacity(extinctions.len());
+        let mut prev_time = 0.0;
+        for extinction in &extinctions {
+            intervals.push(extinction.time - prev_time);
+            prev_time = extinction.time;
+        }
+        let censored_interval = time - prev_time;
+
+        let n = intervals.len() as f64;
+        let avg_interval = intervals.iter().sum::<f64>() / n;
+        let var_interval = intervals
+            .iter()
+            .map(|interval| (interval - avg_interval).powi(2))
+            .sum::<f64>()
+            / (n - 1.0);
+
+        // Every interval ends in an extinction except the censored one, which stays at risk
+        // until its end.
+        let mut sorted_intervals = intervals.clone();
+        sorted_intervals.sort_by(f64::total_cmp);
+        let mut survival = vec![TimeStat { tau: 0.0, val: 1.0 }];
+        let mut median_interval = None;
+        let (mut n_ended, mut prob) = (0, 1.0);
+        for group in sorted_intervals.chunk_by(|a, b| a == b) {
+            let tau = group[0];
+            let n_at_risk = intervals.len() - n_ended + usize::from(censored_interval >= tau);
+            prob *= 1.0 - group.len() as f64 / n_at_risk as f64;
+            n_ended += group.len();
+            survival.push(TimeStat { tau, val: prob });
+            if median_interval.is_none() && prob <= 0.5 {
+                median_interval = Some(tau);
+            }
+        }
+
+        Self {
+            extinctions,
+            intervals,
+            censored_interval,
+            rate_extinct: n / time,
+            avg_interval,
+            cv_interval: var_interval.sqrt() / avg_interval,
+            median_interval,
+            survival,
+        }
+    }
 
+    /// Write the analysis as pretty-printed JSON (non-finite numbers are written as `null`).
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, self)
+            .context("failed to serialize extinction analysis as JSON")?;
+        writeln!(writer).context("failed to write extinction analysis")?;
         Ok(())
     }
 }
 
+/// Time-weighted sum of an observable.
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct WeightedSum {
+    /// Sum of the weighted values.
+    sum: f64,
+    /// Sum of the weights.
+    weight: f64,
+}
+
+impl WeightedSum {
+    /// Add a value with a given weight.
+    fn add(&mut self, value: f64, weight: f64) {
+        self.sum += value * weight;
+        self.weight += weight;
+    }
+
+    /// Add a value with a given weight, if present.
+    fn add_present(&mut self, value: Option<f64>, weight: f64) {
+        if let Some(value) = value {
+            self.add(value, weight);
+        }
+    }
+
+    /// Get the weighted average of the added values (`NaN` if none).
+    fn average(&self) -> f64 {
+        self.sum / self.weight
+    }
+}
+
+/// Blocking analyses of the scalar observables.
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct ObservableBlocking {
+    /// Blocking analysis of the number of agents.
+    n_agents: Blocking,
+    /// Blocking analysis of the growth rate over every save interval.
+    growth_rate: Blocking,
+    /// Blocking analyses of the average phenotypic strategy.
+    avg_strat_phe: Vec<Blocking>,
+    /// Blocking analysis of the standard deviation of the phenotypic strategy.
+    std_dev_strat_phe: Blocking,
+}
+
+impl ObservableBlocking {
+    /// Estimate the errors of the means of the scalar observables.
+    fn estimate(&self) -> ErrorEstimates {
+        ErrorEstimates {
+            n_agents: self.n_agents.estimate(),
+            growth_rate: self.growth_rate.estimate(),
+            avg_strat_phe: self.avg_strat_phe.iter().map(Blocking::estimate).collect(),
+            std_dev_strat_phe: self.std_dev_strat_phe.estimate(),
+        }
+    }
+}
+
+/// Accumulated sums of the time-dependent average phenotypic strategy.
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct TauSums {
+    /// Number of extinctions at the current time origin.
+    n_extinct: usize,
+    /// Current time origin (time of the first observables or the last extinction).
+    time_origin: f64,
+    /// Index of the next time to reach since the time origin.
+    tau_idx: usize,
+    /// Sums of the average phenotypic strategy reached at every time since the time origin.
+    sums: Vec<Vec<f64>>,
+    /// Number of average phenotypic strategies reached at every time since the time origin.
+    counts: Vec<usize>,
+}
+
+impl TauSums {
+    /// Get the time of a given index (doubling from 4).
+    fn tau(tau_idx: usize) -> f64 {
+        4.0 * 2.0_f64.powi(tau_idx as i32)
+    }
+}
+
+/// Accumulated sums of the observables saved in an environment (see `Analysis::env_frac`).
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct EnvSums {
+    /// Sum of the time steps (weights) of the observables.
+    weight: f64,
+    /// Sums of the durations and of the changes in the logarithm of the population size of the
+    /// save intervals ending in the environment.
+    growth_sums: [f64; 2],
+    /// Time-weighted sums of the average phenotypic strategy.
+    avg_strat_phe: Vec<WeightedSum>,
+    /// Time-weighted sums of the average phenotypic strategy histogram bins.
+    dist_avg_strat_phe: Vec<Vec<f64>>,
+}
+
+/// Accumulated sums of the event-aligned observables (see `Analysis::aligned_n_agents`).
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct AlignedSums {
+    /// Environment of the previous observables.
+    prev_env: Option<usize>,
+    /// Estimated time of the last environment transition.
+    time_trans: Option<f64>,
+    /// Time-weighted sums of the number of agents.
+    n_agents: Vec<Vec<WeightedSum>>,
+    /// Time-weighted sums of the average phenotypic strategy.
+    avg_strat_phe: Vec<Vec<Vec<WeightedSum>>>,
+}
+
+/// Maximum fraction of missed environment transitions before they are flagged as a deviation.
+const MAX_MISSED_TRANS_FRAC: f64 = 0.01;
+
+/// Accumulated statistics of the environment visits (see `Analysis::env_trans`).
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct EnvVisitSums {
+    /// Environment of the previous observables and estimated time of the transition to it (if
+    /// observed), to reconstruct the visits from outputs written by older versions.
+    prev_env: Option<(usize, Option<f64>)>,
+    /// Observed transitions, with the dwell times they ended.
+    counts: EnvVisitCounts,
+    /// Number of missed transitions.
+    n_missed: usize,
+}
+
+/// Simulation analyzer.
+///
+/// Provides methods to read the simulation output files and analyze them. Observables are
+/// accumulated as they are added, so the analyzer can be saved and later resumed with new
+/// observables.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Analyzer {
+    /// Simulation configuration parameters.
+    cfg: Config,
+    /// Shift of the simulation clock of the observables (if any).
+    #[serde(skip)]
+    clock_shift: Option<ClockShift>,
+
+    /// First simulation observables.
+    first: Option<Observables>,
+    /// Last simulation observables.
+    last: Option<Observables>,
+
+    /// Time-weighted sum of the number of agents.
+    n_agents: WeightedSum,
+    /// Time-weighted sums of the number of agents histogram bins.
+    dist_n_agents: Vec<f64>,
+    /// Time-weighted sum of the instantaneous growth rate.
+    growth_rate: WeightedSum,
+    /// Sums of the squared time steps times the zeroth, first and second powers of the
+    /// instantaneous growth rate.
+    growth_rate_moments: [f64; 3],
+    /// Time-weighted sum of the instantaneous birth rate.
+    birth_rate: WeightedSum,
+    /// Window-weighted sums of the zeroth, first and second powers of the growth rate over every
+    /// save interval (`None` if any is unavailable), and number of save intervals.
+    window_growth_moments: (Option<[f64; 3]>, usize),
+
+    /// Time-weighted sums of the average phenotypic strategy.
+    avg_strat_phe: Vec<WeightedSum>,
+    /// Time-weighted sums of the average phenotypic strategy histogram bins.
+    dist_avg_strat_phe: Vec<Vec<f64>>,
+    /// Time-weighted sum of the standard deviation of the phenotypic strategy.
+    std_dev_strat_phe: WeightedSum,
+    /// Time-weighted sums of the distribution of phenotypes.
+    dist_phe: Vec<WeightedSum>,
+    /// Accumulated sums of the time-dependent average phenotypic strategy.
+    tau_sums: TauSums,
+    /// Accumulated sums of the event-aligned observables.
+    aligned_sums: AlignedSums,
+
+    /// Event counts of all observables except the first ones.
+    event_counts: EventCounts,
+
+    /// Time-weighted sum of the heterozygosity of the neutral marker.
+    marker_heterozygosity: WeightedSum,
+    /// Time-weighted sum of the number of neutral marker alleles.
+    n_marker_alleles: WeightedSum,
+
+    /// Blocking analyses of the scalar observables.
+    #[serde(default)]
+    blocking: ObservableBlocking,
+
+    /// Window of simulation times of the analyzed observables (the others are discarded).
+    #[serde(default)]
+    time_window: TimeWindow,
+
+    /// Accumulated sums of the observables saved in every environment.
+    #[serde(default)]
+    env_sums: Vec<EnvSums>,
+
+    /// Time-weighted sums of the number of agents with every phenotype.
+    #[serde(default)]
+    n_agents_phe: Vec<WeightedSum>,
+    /// Time-weighted sums of the first and second powers of the fraction of agents with every
+    /// phenotype.
+    #[serde(default)]
+    phe_frac_moments: Vec<[WeightedSum; 2]>,
+    /// Time-weighted sums of the second and third powers of the number of agents.
+    #[serde(default)]
+    n_agents_moments: [WeightedSum; 2],
+    /// Time-weighted sum of the effective number of distinct phenotypic strategies.
+    #[serde(default)]
+    strat_diversity: WeightedSum,
+    /// Time-weighted sum of the mutational load.
+    #[serde(default)]
+    mut_load: WeightedSum,
+    /// Accumulated statistics of the environment visits.
+    #[serde(default)]
+    env_visit_sums: EnvVisitSums,
+    /// Time-weighted sums of the number of agents of every species.
+    #[serde(default)]
+    n_agents_species: Vec<WeightedSum>,
+    /// Time-weighted sums of the average phenotypic strategy of every species.
+    #[serde(default)]
+    avg_strat_phe_species: Vec<Vec<WeightedSum>>,
+    /// Time-weighted sums of the number of agents in every patch.
+    #[serde(default)]
+    n_agents_patch: Vec<WeightedSum>,
+    /// Time-weighted sums of the average phenotypic strategy of every patch.
+    #[serde(default)]
+    avg_strat_phe_patch: Vec<Vec<WeightedSum>>,
+    /// Time-weighted sums of the spatial average of the average phenotypic strategy.
+    #[serde(default)]
+    spatial_avg_strat_phe: Vec<WeightedSum>,
+    /// Time-weighted sums of the fraction of patches in every environment.
+    #[serde(default)]
+    patch_env_frac: Vec<WeightedSum>,
+    /// Time-weighted sums of the average phenotypic strategy conditioned on every cue.
+    #[serde(default)]
+    avg_cue_strat_phe: Vec<Vec<WeightedSum>>,
+    /// Time-weighted sum of the average cue accuracy.
+    #[serde(default)]
+    avg_accuracy: WeightedSum,
+    /// Time-weighted sums of the concentration of every resource.
+    #[serde(default)]
+    resources: Vec<WeightedSum>,
+    /// Time-weighted sum of the continuous environment value.
+    #[serde(default)]
+    env_value: WeightedSum,
+    /// Time-weighted sum of the average decimal logarithm of the mutation probability.
+    #[serde(default)]
+    avg_log_prob_mut: WeightedSum,
+    /// Time-weighted sum of the standard deviation of the decimal logarithm of the mutation
+    /// probability.
+    #[serde(default)]
+    std_dev_log_prob_mut: WeightedSum,
+}
+
+impl Analyzer {
+    /// Create a new `Analyzer` with the given configuration.
+    pub fn new(cfg: Config) -> Self {
+        let n_env = cfg.model.n_env;
+        let n_phe = cfg.model.n_phe;
+        let n_bins = cfg.strat_binning().n_bins;
+        let n_aligned_bins = if cfg.output.aligned_time.is_some() {
+            cfg.output.hist_bins
+        } else {
+            0
+        };
+        // The spatial sums are left empty without patches.
+        let (n_patches, n_spatial_env, n_spatial_phe) = if cfg.model.has_patches() {
+            (cfg.n_patches(), n_env, n_phe - 1)
+        } else {
+            (0, 0, 0)
+        };
+        // The cue sums are left empty without sensing.
+        let n_cues = if cfg.model.sensing.is_some() {
+            n_env
+        } else {
+            0
+        };
+        Self {
+            clock_shift: None,
+            first: None,
+            last: None,
+            n_agents: WeightedSum::default(),
+            dist_n_agents: vec![0.0; cfg.n_agents_binning().n_bins],
+            growth_rate: WeightedSum::default(),
+            growth_rate_moments: [0.0; 3],
+            birth_rate: WeightedSum::default(),
+            window_growth_moments: (Some([0.0; 3]), 0),
+            avg_strat_phe: vec![WeightedSum::default(); n_phe - 1],
+            dist_avg_strat_phe: vec![vec![0.0; n_bins]; n_phe - 1],
+            std_dev_strat_phe: WeightedSum::default(),
+            dist_phe: vec![WeightedSum::default(); n_phe - 1],
+            tau_sums: TauSums::default(),
+            aligned_sums: AlignedSums {
+                prev_env: None,
+                time_trans: None,
+                n_agents: vec![vec![WeightedSum::default(); n_aligned_bins]; n_env],
+                avg_strat_phe: vec![
+                    vec![vec![WeightedSum::default(); n_aligned_bins]; n_phe - 1];
+                    n_env
+                ],
+            },
+            event_counts: EventCounts::new(n_phe, n_env, cfg.output.hist_bins),
+            marker_heterozygosity: WeightedSum::default(),
+            n_marker_alleles: WeightedSum::default(),
+            blocking: ObservableBlocking {
+                avg_strat_phe: vec![Blocking::default(); n_phe - 1],
+                ..Default::default()
+            },
+            time_window: TimeWindow::default(),
+            env_sums: vec![
+                EnvSums {
+                    avg_strat_phe: vec![WeightedSum::default(); n_phe - 1],
+                    dist_avg_strat_phe: vec![vec![0.0; n_bins]; n_phe - 1],
+                    ..Default::default()
+                };
+                n_env
+            ],
+            n_agents_phe: vec![WeightedSum::default(); n_phe],
+            phe_frac_moments: vec![Default::default(); n_phe],
+            n_agents_moments: Default::default(),
+            strat_diversity: WeightedSum::default(),
+            mut_load: WeightedSum::default(),
+            env_visit_sums: EnvVisitSums {
+                prev_env: None,
+                counts: EnvVisitCounts::new(n_env, cfg.output.hist_bins),
+                n_missed: 0,
+            },
+            n_agents_species: vec![WeightedSum::default(); cfg.species.len()],
+            avg_strat_phe_species: vec![vec![WeightedSum::default(); n_phe - 1]; cfg.species.len()],
+            n_agents_patch: vec![WeightedSum::default(); n_patches],
+            avg_strat_phe_patch: vec![vec![WeightedSum::default(); n_phe - 1]; n_patches],
+            spatial_avg_strat_phe: vec![WeightedSum::default(); n_spatial_phe],
+            patch_env_frac: vec![WeightedSum::default(); n_spatial_env],
+            avg_cue_strat_phe: vec![vec![WeightedSum::default(); n_phe - 1]; n_cues],
+            avg_accuracy: WeightedSum::default(),
+            resources: vec![WeightedSum::default(); cfg.model.resources.len()],
+            env_value: WeightedSum::default(),
+            avg_log_prob_mut: WeightedSum::default(),
+            std_dev_log_prob_mut: WeightedSum::default(),
+            cfg,
+        }
+    }
+
+    /// Get the simulation configuration parameters.
+    pub fn cfg(&self) -> &Config {
+        &self.cfg
+    }
+
+    /// Set the shift of the simulation clock of the observables added afterwards, which are
+    /// mapped back to the original clock so that the analysis is consistent with it.
+    pub fn set_clock_shift(&mut self, clock_shift: ClockShift) {
+        self.clock_shift = Some(clock_shift);
+    }
+
+    /// Discard the observables outside a given window of simulation times.
+    pub fn set_time_window(&mut self, time_window: TimeWindow) {
+        self.time_window = time_window;
+    }
+
+    /// Add simulation observables to the analysis.
+    pub fn add_observables(&mut self, mut observables: Observables) {
+        if let Some(clock_shift) = &self.clock_shift {
+            clock_shift.unshift(&mut observables);
+        }
+        if !self.time_window.contains(observables.time) {
+            return;
+        }
+        let obs = &observables;
+        let (time_step, time_weight) = (obs.time_step, obs.weight());
+        let strat_binning = self.cfg.strat_binning();
+
+        self.n_agents.add(obs.n_agents, time_weight);
+        self.n_agents_moments[0].add(obs.n_agents.powi(2), time_weight);
+        self.n_agents_moments[1].add(obs.n_agents.powi(3), time_weight);
+        self.blocking.n_agents.push(obs.n_agents);
+        let n_agents_bin = self.cfg.n_agents_binning().bin(obs.n_agents);
+        self.dist_n_agents[n_agents_bin] += time_weight;
+        self.growth_rate.add(obs.growth_rate, time_step);
+        let weight = time_step * time_step;
+        self.growth_rate_moments[0] += weight;
+        self.growth_rate_moments[1] += obs.growth_rate * weight;
+        self.growth_rate_moments[2] += obs.growth_rate.powi(2) * weight;
+        self.birth_rate.add(obs.growth_rate.max(0.0), time_step);
+
+        if let Some(avg_strat_phe) = &obs.avg_strat_phe {
+            for (phe, &ele) in avg_strat_phe.iter().enumerate() {
+                self.avg_strat_phe[phe].add(ele, time_weight);
+                self.blocking.avg_strat_phe[phe].push(ele);
+                let bin = strat_binning.bin(ele);
+                self.dist_avg_strat_phe[phe][bin] += time_weight;
+            }
+        }
+        self.std_dev_strat_phe
+            .add_present(obs.std_dev_strat_phe, time_weight);
+        if let Some(std_dev_strat_phe) = obs.std_dev_strat_phe {
+            self.blocking.std_dev_strat_phe.push(std_dev_strat_phe);
+        }
+        if let Some(dist_phe) = &obs.dist_phe {
+            for (sum, &ele) in self.dist_phe.iter_mut().zip(dist_phe) {
+                sum.add(ele, time_weight);
+            }
+        }
+        if let Some(n_agents_phe) = &obs.n_agents_phe {
+            let sums = self.n_agents_phe.iter_mut().zip(&mut self.phe_frac_moments);
+            for ((sum, moments), &ele) in sums.zip(n_agents_phe) {
+                sum.add(ele, time_weight);
+                let phe_frac = ele / obs.n_agents;
+                moments[0].add(phe_frac, time_weight);
+                moments[1].add(phe_frac * phe_frac, time_weight);
+            }
+        }
+
+        self.strat_diversity
+            .add_present(obs.strat_diversity, time_weight);
+        self.mut_load.add_present(obs.mut_load, time_weight);
+        if let Some(n_agents_species) = &obs.n_agents_species {
+            for (sum, &ele) in self.n_agents_species.iter_mut().zip(n_agents_species) {
+                sum.add(ele, time_weight);
+            }
+        }
+        if let Some(avg_strat_phe_species) = &obs.avg_strat_phe_species {
+            let sums = self.avg_strat_phe_species.iter_mut();
+            for (species_sums, avg_strat_phe) in sums.zip(avg_strat_phe_species) {
+                for (sum, &ele) in species_sums.iter_mut().zip(avg_strat_phe) {
+                    sum.add(ele, time_weight);
+                }
+            }
+        }
+        if let Some(n_agents_patch) = &obs.n_agents_patch {
+            for (sum, &ele) in self.n_agents_patch.iter_mut().zip(n_agents_patch) {
+                sum.add(ele, time_weight);
+            }
+        }
+        if let Some(avg_strat_phe_patch) = &obs.avg_strat_phe_patch {
+            // Empty patches have no average strategy, and are left out.
+            let sums = self.avg_strat_phe_patch.iter_mut();
+            for (patch_sums, avg_strat_phe) in sums.zip(avg_strat_phe_patch) {
+                for (sum, &ele) in patch_sums.iter_mut().zip(avg_strat_phe) {
+                    if !ele.is_nan() {
+                        sum.add(ele, time_weight);
+                    }
+                }
+            }
+        }
+        if let Some(spatial_avg_strat_phe) = &obs.spatial_avg_strat_phe {
+            for (sum, &ele) in self
+                .spatial_avg_strat_phe
+                .iter_mut()
+                .zip(spatial_avg_strat_phe)
+            {
+                sum.add(ele, time_weight);
+            }
+        }
+        if let Some(env_patch) = &obs.env_patch {
+            let n_patches = env_patch.len() as f64;
+            for (env, sum) in self.patch_env_frac.iter_mut().enumerate() {
+                let n_patches_env = env_patch
+                    .iter()
+                    .filter(|&&patch_env| patch_env == env)
+                    .count();
+                sum.add(n_patches_env as f64 / n_patches, time_weight);
+            }
+        }
+
+        if let Some(avg_cue_strat_phe) = &obs.avg_cue_strat_phe {
+            let sums = self.avg_cue_strat_phe.iter_mut();
+            for (cue_sums, avg_strat_phe) in sums.zip(avg_cue_strat_phe) {
+                for (sum, &ele) in cue_sums.iter_mut().zip(avg_strat_phe) {
+                    sum.add(ele, time_weight);
+                }
+            }
+        }
+        self.avg_accuracy.add_present(obs.avg_accuracy, time_weight);
+        if let Some(resources) = &obs.resources {
+            for (sum, &conc) in self.resources.iter_mut().zip(resources) {
+                sum.add(conc, time_weight);
+            }
+        }
+        self.env_value.add_present(obs.env_value, time_weight);
+        self.avg_log_prob_mut
+            .add_present(obs.avg_log_prob_mut, time_weight);
+        self.std_dev_log_prob_mut
+            .add_present(obs.std_dev_log_prob_mut, time_weight);
+
+        self.marker_heterozygosity
+            .add_present(obs.marker_heterozygosity, time_weight);
+        let n_marker_alleles = obs
+            .marker_afs
+            .as_ref()
+            .map(|marker_afs| marker_afs.iter().sum::<usize>() as f64);
+        self.n_marker_alleles
+            .add_present(n_marker_alleles, time_weight);
+
+        // Events counted in the first observables happened before the analyzed time interval.
+        if let Some(last) = &self.last {
+            self.event_counts.accumulate(&obs.event_counts);
+
+            let blocking = &mut self.blocking;
+            let (moments, n_windows) = &mut self.window_growth_moments;
+            *moments = moments
+                .zip(obs.event_counts.log_growth)
+                .map(|(moments, log_growth)| {
+                    let window_time = obs.time - last.time;
+                    let rate = log_growth / window_time;
+                    blocking.growth_rate.push(rate);
+                    [
+                        moments[0] + window_time,
+                        moments[1] + rate * window_time,
+                        moments[2] + rate.powi(2) * window_time,
+                    ]
+                });
+            *n_windows += 1;
+        }
+
+        self.add_tau_observables(obs);
+        self.add_aligned_observables(obs);
+        self.add_env_observables(obs);
+        self.add_env_visit(obs);
+
+        if self.first.is_none() {
+            self.first = Some(observables.clone());
+        }
+        self.last = Some(observables);
+    }
+
+    /// Add simulation observables to the time-dependent average phenotypic strategy sums.
+    fn add_tau_observables(&mut self, obs: &Observables) {
+        let tau_sums = &mut self.tau_sums;
+        if self.first.is_none() {
+            tau_sums.n_extinct = obs.n_extinct;
+            tau_sums.time_origin = obs.time;
+        }
+        if obs.n_extinct > tau_sums.n_extinct {
+            tau_sums.n_extinct = obs.n_extinct;
+            tau_sums.time_origin = obs.time;
+            tau_sums.tau_idx = 0;
+        }
+
+        // The average phenotypic strategy reached at every time is the one before crossing it.
+        let prev = self.last.as_ref().unwrap_or(obs);
+        while (obs.time - tau_sums.time_origin) > TauSums::tau(tau_sums.tau_idx) {
+            if tau_sums.tau_idx == tau_sums.counts.len() {
+                tau_sums.sums.push(vec![0.0; self.cfg.model.n_phe - 1]);
+                tau_sums.counts.push(0);
+            }
+            if let Some(avg_strat_phe) = &prev.avg_strat_phe {
+                let sums = &mut tau_sums.sums[tau_sums.tau_idx];
+                sums.iter_mut()
+                    .zip(avg_strat_phe)
+                    .for_each(|(sum, ele)| *sum += ele);
+                tau_sums.counts[tau_sums.tau_idx] += 1;
+            }
+            tau_sums.tau_idx += 1;
+        }
+    }
+
+    /// Add simulation observables to the sums of the environment they were saved in.
+    ///
+    /// The growth rate of every save interval is attributed to the environment at its end (which is
+    /// usually the one of the whole interval).
+    fn add_env_observables(&mut self, obs: &Observables) {
+        // Old observables have no environment, and old partial analyses no sums.
+        let Some(env_sums) = obs.env.and_then(|env| self.env_sums.get_mut(env)) else {
+            return;
+        };
+        let time_weight = obs.weight();
+        let strat_binning = self.cfg.strat_binning();
+
+        env_sums.weight += time_weight;
+        if let (Some(last), Some(log_growth)) = (&self.last, obs.event_counts.log_growth) {
+            env_sums.growth_sums[0] += obs.time - last.time;
+            env_sums.growth_sums[1] += log_growth;
+        }
+        if let Some(avg_strat_phe) = &obs.avg_strat_phe {
+            for (phe, &ele) in avg_strat_phe.iter().enumerate() {
+                env_sums.avg_strat_phe[phe].add(ele, time_weight);
+                let bin = strat_binning.bin(ele);
+                env_sums.dist_avg_strat_phe[phe][bin] += time_weight;
+            }
+        }
+    }
+
+    /// Add simulation observables to the statistics of the environment visits.
+    ///
+    /// The transitions and dwell times are counted by the engine. In outputs written by older
+    /// versions, they are instead reconstructed from the observables: a transition is observed
+    /// when the environment changes between two observables with a single transition counted in
+    /// between. It happened at the end of the step of the previous observables if they were
+    /// followed by a transition (and not saved on a time grid), and is otherwise estimated as the
+    /// time of the first observables in the new environment. Consecutive transitions give the
+    /// dwell time in an environment.
+    fn add_env_visit(&mut self, obs: &Observables) {
+        // With patches, the transitions of every patch are counted together, so the environment
+        // process (of the first patch) can not be reconstructed.
+        if self.cfg.model.has_patches() {
+            return;
+        }
+
+        let sums = &mut self.env_visit_sums;
+        if let Some(env_visits) = &obs.event_counts.env_visits {
+            // Events counted in the first observables happened before the analyzed time interval.
+            if self.last.is_some() {
+                sums.counts.accumulate(env_visits);
+            }
+            return;
+        }
+
+        // Old observables have no environment, and old partial analyses no sums.
+        let Some(env) = obs.env.filter(|&env| env < sums.counts.n_trans.len()) else {
+            return;
+        };
+        let Some((prev_env, time_prev_trans)) = sums.prev_env else {
+            sums.prev_env = Some((env, None));
+            return;
+        };
+
+        let n_env_trans = obs.event_counts.n_env_trans;
+        if n_env_trans == 0 && env == prev_env {
+            return;
+        }
+        if n_env_trans != 1 || env == prev_env {
+            sums.n_missed += n_env_trans.max(1);
+            sums.prev_env = Some((env, None));
+            return;
+        }
+
+        let time_trans = match &self.last {
+            Some(last)
+                if last.event_type == Some(EventType::EnvTrans) && last.grid_weight.is_none() =>
+            {
+                last.time + last.time_step
+            }
+            _ => obs.time,
+        };
+        sums.counts.n_trans[prev_env][env] += 1;
+        if let Some(time_prev_trans) = time_prev_trans {
+            let dwell_time = time_trans - time_prev_trans;
+            let moments = &mut sums.counts.dwell_moments[prev_env];
+            moments[0] += 1.0;
+            moments[1] += dwell_time;
+            moments[2] += dwell_time * dwell_time;
+            let bin = self.cfg.dwell_time_binning(prev_env).bin(dwell_time);
+            sums.counts.dist_dwell_time[prev_env][bin] += 1;
+        }
+        sums.prev_env = Some((env, Some(time_trans)));
+    }
+
+    /// Calculate the statistics of the environment visits and compare them with the values
+    /// expected from the configuration.
+    fn calc_env_trans_stats(&self) -> EnvTransStats {
+        // Normal approximation of the 95% confidence interval.
+        let z = 1.96;
+        let sums = &self.env_visit_sums;
+        let n_env = sums.counts.n_trans.len();
+        let model = &self.cfg.model;
+        let scheduled = !model.trans_schedule.is_empty() || model.environment.is_some();
+        let total_rate = |env: usize| -> f64 {
+            (0..n_env)
+                .filter(|&next_env| next_env != env)
+                .map(|next_env| model.rates_trans[env][next_env].get())
+                .sum()
+        };
+
+        let mut stats = EnvTransStats {
+            n_trans: sums.counts.n_trans.clone(),
+            n_missed: sums.n_missed,
+            ..Default::default()
+        };
+        for env in 0..n_env {
+            let n = sums.counts.n_trans[env].iter().sum::<usize>() as f64;
+            let (freqs, (errs, expected_freqs)) = (0..n_env)
+                .map(|next_env| {
+                    let freq = sums.counts.n_trans[env][next_env] as f64 / n;
+                    let err = z * (freq * (1.0 - freq) / n).sqrt();
+                    let expected_freq = match (scheduled, next_env == env) {
+                        (true, _) => f64::NAN,
+                        (false, true) => 0.0,
+                        (false, false) => model.rates_trans[env][next_env].get() / total_rate(env),
+                    };
+                    (freq, (err, expected_freq))
+                })
+                .unzip();
+            stats.trans_freq.push(freqs);
+            stats.err_trans_freq.push(errs);
+            stats.expected_trans_freq.push(expected_freqs);
+
+            let [n, sum, sq_sum] = sums.counts.dwell_moments[env];
+            let avg = sum / n;
+            let var = (sq_sum - n * avg * avg).max(0.0) / (n - 1.0);
+            stats.n_visits.push(n as usize);
+            stats.avg_dwell_time.push(avg);
+            stats.err_dwell_time.push(z * (var / n).sqrt());
+            stats.expected_dwell_time.push(if scheduled {
+                f64::NAN
+            } else {
+                1.0 / total_rate(env)
+            });
+            stats.dist_dwell_time.push(
+                sums.counts.dist_dwell_time[env]
+                    .iter()
+                    .map(|&count| count as f64 / n)
+                    .collect(),
+            );
+            stats
+                .max_dwell_time
+                .push(self.cfg.dwell_time_binning(env).max);
+        }
+
+        // Missed transitions bias the statistics towards long visits.
+        let n_total: usize = sums.counts.n_trans.iter().flatten().sum::<usize>() + sums.n_missed;
+        if sums.n_missed as f64 > MAX_MISSED_TRANS_FRAC * n_total as f64 {
+            stats.deviations.push(format!(
+                "{} of {n_total} transitions missed between saved observables",
+                sums.n_missed
+            ));
+        }
+
+        // Deviations are only flagged for statistics with a confidence interval.
+        let deviates = |val: f64, err: f64, expected: f64| (val - expected).abs() > err;
+        for env in 0..n_env {
+            let (avg, err, expected) = (
+                stats.avg_dwell_time[env],
+                stats.err_dwell_time[env],
+                stats.expected_dwell_time[env],
+            );
+            if deviates(avg, err, expected) {
+                stats.deviations.push(format!(
+                    "env {env}: dwell time {avg:.4} ± {err:.4} (expected {expected:.4})"
+                ));
+            }
+            for next_env in (0..n_env).filter(|&next_env| next_env != env) {
+                let (freq, err, expected) = (
+                    stats.trans_freq[env][next_env],
+                    stats.err_trans_freq[env][next_env],
+                    stats.expected_trans_freq[env][next_env],
+                );
+                if deviates(freq, err, expected) {
+                    stats.deviations.push(format!(
+                        "env {env} -> env {next_env}: frequency {freq:.4} ± {err:.4} \
+                         (expected {expected:.4})"
+                    ));
+                }
+            }
+        }
+
+        stats
+    }
+
+    /// Add simulation observables to the event-aligned sums.
+    ///
+    /// Observables are aligned relative to the last environment transition (estimated as the time
+    /// of the first observables in the new environment), in `hist_bins` bins up to
+    /// `aligned_time`.
+    fn add_aligned_observables(&mut self, obs: &Observables) {
+        let Some(aligned_time) = self.cfg.output.aligned_time else {
+            return;
+        };
+        let n_bins = self.cfg.output.hist_bins;
+        let aligned_sums = &mut self.aligned_sums;
+
+        let Some(env) = obs.env else {
+            return;
+        };
+        if aligned_sums
+            .prev_env
+            .is_some_and(|prev_env| prev_env != env)
+        {
+            aligned_sums.time_trans = Some(obs.time);
+        }
+        aligned_sums.prev_env = Some(env);
+
+        let Some(time_trans) = aligned_sums.time_trans else {
+            return;
+        };
+        let lag = obs.time - time_trans;
+        if lag >= aligned_time {
+            return;
+        }
+        let bin = ((lag / aligned_time * n_bins as f64) as usize).min(n_bins - 1);
+
+        aligned_sums.n_agents[env][bin].add(obs.n_agents, obs.weight());
+        if let Some(avg_strat_phe) = &obs.avg_strat_phe {
+            for (sums, &ele) in aligned_sums.avg_strat_phe[env]
+                .iter_mut()
+                .zip(avg_strat_phe)
+            {
+                sums[bin].add(ele, obs.weight());
+            }
+        }
+    }
+
+    /// Read simulation output stream and add it to the analysis.
+    pub fn add_output<R: Read>(&mut self, reader: &mut R) -> Result<()> {
+        let observables = read_observables(reader)?;
+        observables
+            .into_iter()
+            .for_each(|observables| self.add_observables(observables));
+        Ok(())
+    }
+
+    /// Make the analysis and write it to a binary stream.
+    pub fn analyze<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let analysis = self.calc_analysis()?;
+
+        encode::write(writer, &analysis).context("failed to serialize analysis")?;
+
+        Ok(())
+    }
+
+    /// Make the analysis.
+    pub fn calc_analysis(&self) -> Result<Analysis> {
+        let first_observables = self
+            .first
+            .as_ref()
+            .context("failed to get first observables")?;
+
+        let last_observables = self
+            .last
+            .as_ref()
+            .context("failed to get last observables")?;
+
+        // Estimate the growth rate over every save interval from the change in the logarithm of
+        // the population size (corrected for culls), if available in the outputs.
+        let (avg_growth_rate, std_dev_growth_rate) = match self.window_growth_moments {
+            (Some([weight, sum, sq_sum]), n_windows) if n_windows > 0 => {
+                let avg = sum / weight;
+                (avg, (sq_sum / weight - avg * avg).max(0.0).sqrt())
+            }
+            // Fall back to the instantaneous growth rate for outputs written by older versions.
+            _ => {
+                let avg = self.growth_rate.average();
+                let [weight, sum, sq_sum] = self.growth_rate_moments;
+                let sq_dev_sum = sq_sum - 2.0 * avg * sum + avg * avg * weight;
+                (avg, (sq_dev_sum / self.growth_rate.weight).max(0.0).sqrt())
+            }
+        };
+
+        let cum_log_growth = match self.window_growth_moments {
+            (Some([_, sum, _]), n_windows) if n_windows > 0 => Some(sum),
+            _ => None,
+        };
+
+        let extinct_rate = last_observables.n_extinct as f64 / last_observables.time;
+
+        let tau_max = if extinct_rate > 0.0 {
+            1.0 / extinct_rate
+        } else {
+            last_observables.time
+        };
+        let n_taus = 1
+            + (1..)
+                .take_while(|&tau_idx| TauSums::tau(tau_idx) < tau_max)
+                .count();
+
+        let elapsed_time = last_observables.time - first_observables.time;
+        let avg_n_agents = self.n_agents.average();
+        let event_rate = |count: usize| count as f64 / elapsed_time;
+        let counts = &self.event_counts;
+
+        // Central moments of the number of agents from its raw moments (`NaN` if missing from a
+        // partial analysis made by an older version).
+        let [sq_n_agents, cube_n_agents] =
+            self.n_agents_moments.each_ref().map(WeightedSum::average);
+        let var_n_agents = (sq_n_agents - avg_n_agents.powi(2)).clamp(0.0, f64::INFINITY);
+        let third_moment_n_agents =
+            cube_n_agents - 3.0 * avg_n_agents * sq_n_agents + 2.0 * avg_n_agents.powi(3);
+        let error_estimates = self.blocking.estimate();
+        let n_samples = error_estimates.n_agents.n_samples as f64;
+        let tau_n_agents = error_estimates.n_agents.tau_int * elapsed_time / (n_samples - 1.0);
+
+        let (aligned_n_agents, aligned_avg_strat_phe) = self.calc_aligned_averages();
+        let (env_frac, env_avg_growth_rate, env_avg_avg_strat_phe, env_dist_avg_strat_phe) =
+            self.calc_env_averages();
+
+        let analysis = Analysis {
+            dist_n_agents: self
+                .dist_n_agents
+                .iter()
+                .map(|bin_weight| bin_weight / self.n_agents.weight)
+                .collect(),
+
+            avg_growth_rate,
+
+            std_dev_growth_rate,
+
+            extinct_rate,
+
+            avg_avg_strat_phe: self
+                .avg_strat_phe
+                .iter()
+                .map(WeightedSum::average)
+                .collect(),
+
+            avg_std_dev_strat_phe: self.std_dev_strat_phe.average(),
+
+            dist_avg_strat_phe: self
+                .dist_avg_strat_phe
+                .iter()
+                .zip(&self.avg_strat_phe)
+                .map(|(bin_weights, avg_strat_phe)| {
+                    bin_weights
+                        .iter()
+                        .map(|bin_weight| bin_weight / avg_strat_phe.weight)
+                        .collect()
+                })
+                .collect(),
+
+            avg_dist_phe: self.dist_phe.iter().map(WeightedSum::average).collect(),
+
+            avg_birth_rate: self.birth_rate.average(),
+
+            tau_avg_strat_phe: (0..self.cfg.model.n_phe - 1)
+                .map(|phe| {
+                    (0..n_taus)
+                        .map(|tau_idx| {
+                            let sum = self.tau_sums.sums.get(tau_idx).map_or(0.0, |s| s[phe]);
+                            let count = self.tau_sums.counts.get(tau_idx).copied();
+                            TimeStat {
+                                tau: TauSums::tau(tau_idx),
+                                val: sum / count.unwrap_or(0) as f64,
+                            }
+                        })
+                        .collect()
+                })
+                .collect(),
+
+            birth_rate: event_rate(counts.n_births) / avg_n_agents,
+
+            death_rate: event_rate(counts.n_deaths) / avg_n_agents,
+
+            env_trans_rate: event_rate(counts.n_env_trans),
+
+            mut_rate: event_rate(counts.n_mut) / avg_n_agents,
+
+            cull_rate: event_rate(counts.n_culls) / avg_n_agents,
+
+            dist_cull_phe: (0..self.cfg.model.n_phe - 1)
+                .map(|phe| event_rate(counts.n_culls_phe[phe]) / event_rate(counts.n_culls))
+                .collect(),
+
+            aligned_n_agents,
+
+            aligned_avg_strat_phe,
+
+            avg_marker_heterozygosity: self.marker_heterozygosity.average(),
+
+            avg_n_marker_alleles: self.n_marker_alleles.average(),
+
+            error_estimates,
+
+            time_window: self.time_window,
+
+            burn_in_time: None,
+
+            env_frac,
+
+            env_avg_growth_rate,
+
+            env_avg_avg_strat_phe,
+
+            env_dist_avg_strat_phe,
+
+            avg_n_agents_phe: self.n_agents_phe.iter().map(WeightedSum::average).collect(),
+
+            std_dev_phe_frac: self
+                .phe_frac_moments
+                .iter()
+                .map(|[sum, sq_sum]| (sq_sum.average() - sum.average().powi(2)).max(0.0).sqrt())
+                .collect(),
+
+            cum_log_growth,
+
+            range_n_agents: Some({
+                let binning = self.cfg.n_agents_binning();
+                [binning.min, binning.max]
+            }),
+
+            range_avg_strat_phe: Some({
+                let binning = self.cfg.strat_binning();
+                [binning.min, binning.max]
+            }),
+
+            var_n_agents,
+
+            skew_n_agents: third_moment_n_agents / var_n_agents.powf(1.5),
+
+            tau_n_agents,
+
+            avg_strat_diversity: self.strat_diversity.average(),
+
+            avg_mut_load: self.mut_load.average(),
+
+            bottleneck_rate: event_rate(counts.n_bottlenecks),
+
+            birth_rate_phe: (0..self.cfg.model.n_phe)
+                .map(|phe| event_rate(counts.n_births_phe[phe]) / self.n_agents_phe[phe].average())
+                .collect(),
+
+            death_rate_phe: (0..self.cfg.model.n_phe)
+                .map(|phe| event_rate(counts.n_deaths_phe[phe]) / self.n_agents_phe[phe].average())
+                .collect(),
+
+            env_trans: self.calc_env_trans_stats(),
+
+            avg_n_agents_species: self
+                .n_agents_species
+                .iter()
+                .map(WeightedSum::average)
+                .collect(),
+
+            avg_avg_strat_phe_species: self
+                .avg_strat_phe_species
+                .iter()
+                .map(|sums| sums.iter().map(WeightedSum::average).collect())
+                .collect(),
+
+            migration_rate: event_rate(counts.n_migrations) / avg_n_agents,
+
+            avg_n_agents_patch: self
+                .n_agents_patch
+                .iter()
+                .map(WeightedSum::average)
+                .collect(),
+
+            avg_avg_strat_phe_patch: self
+                .avg_strat_phe_patch
+                .iter()
+                .map(|sums| sums.iter().map(WeightedSum::average).collect())
+                .collect(),
+
+            avg_spatial_avg_strat_phe: self
+                .spatial_avg_strat_phe
+                .iter()
+                .map(WeightedSum::average)
+                .collect(),
+
+            patch_env_frac: self
+                .patch_env_frac
+                .iter()
+                .map(WeightedSum::average)
+                .collect(),
+
+            avg_avg_cue_strat_phe: self
+                .avg_cue_strat_phe
+                .iter()
+                .map(|sums| sums.iter().map(WeightedSum::average).collect())
+                .collect(),
+
+            avg_avg_accuracy: self.avg_accuracy.average(),
+
+            switch_rate: event_rate(counts.n_switches) / avg_n_agents,
+
+            avg_resources: self.resources.iter().map(WeightedSum::average).collect(),
+
+            avg_env_value: self.env_value.average(),
+
+            avg_avg_log_prob_mut: self.avg_log_prob_mut.average(),
+            avg_std_dev_log_prob_mut: self.std_dev_log_prob_mut.average(),
+        };
+
+        Ok(analysis)
+    }
+
+    /// Calculate the averages of the observables saved in every environment: the fraction of
+    /// observables, the growth rate, the average phenotypic strategy and its distribution.
+    #[allow(
+        clippy::type_complexity,
+        reason = "one vector per environment of every average"
+    )]
+    fn calc_env_averages(&self) -> (Vec<f64>, Vec<f64>, Vec<Vec<f64>>, Vec<Vec<Vec<f64>>>) {
+        let total_weight: f64 = self.env_sums.iter().map(|env_sums| env_sums.weight).sum();
+        let env_frac = self
+            .env_sums
+            .iter()
+            .map(|env_sums| env_sums.weight / total_weight)
+            .collect();
+        let env_avg_growth_rate = self
+            .env_sums
+            .iter()
+            .map(|env_sums| env_sums.growth_sums[1] / env_sums.growth_sums[0])
+            .collect();
+        let env_avg_avg_strat_phe = self
+            .env_sums
+            .iter()
+            .map(|env_sums| {
+                env_sums
+                    .avg_strat_phe
+                    .iter()
+                    .map(WeightedSum::average)
+                    .collect()
+            })
+            .collect();
+        let env_dist_avg_strat_phe = self
+            .env_sums
+            .iter()
+            .map(|env_sums| {
+                env_sums
+                    .dist_avg_strat_phe
+                    .iter()
+                    .zip(&env_sums.avg_strat_phe)
+                    .map(|(bin_weights, avg_strat_phe)| {
+                        bin_weights
+                            .iter()
+                            .map(|bin_weight| bin_weight / avg_strat_phe.weight)
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        (
+            env_frac,
+            env_avg_growth_rate,
+            env_avg_avg_strat_phe,
+            env_dist_avg_strat_phe,
+        )
+    }
+
+    /// Calculate the event-aligned averages of the number of agents and the average phenotypic
+    /// strategy, averaged over all transitions to each environment. Empty if `aligned_time` is
+    /// unset.
+    fn calc_aligned_averages(&self) -> (Vec<Vec<TimeStat>>, Vec<Vec<Vec<TimeStat>>>) {
+        let Some(aligned_time) = self.cfg.output.aligned_time else {
+            return (Vec::new(), Vec::new());
+        };
+        let n_bins = self.cfg.output.hist_bins;
+
+        let time_stats = |sums: &[WeightedSum]| -> Vec<TimeStat> {
+            sums.iter()
+                .enumerate()
+                .map(|(bin, sum)| TimeStat {
+                    tau: (bin as f64 + 0.5) * aligned_time / n_bins as f64,
+                    val: sum.average(),
+                })
+                .collect()
+        };
+
+        let aligned_sums = &self.aligned_sums;
+        let aligned_n_agents = aligned_sums
+            .n_agents
+            .iter()
+            .map(|sums| time_stats(sums))
+            .collect();
+        let aligned_avg_strat_phe = aligned_sums
+            .avg_strat_phe
+            .iter()
+            .map(|env_sums| env_sums.iter().map(|sums| time_stats(sums)).collect())
+            .collect();
+
+        (aligned_n_agents, aligned_avg_strat_phe)
+    }
+}
+
+/// Read and collect all the observables in a simulation output stream.
+///
+/// Fails if the stream is truncated in the middle of some observables.
+pub fn read_observables<R: Read>(reader: &mut R) -> Result<Vec<Observables>> {
+    let mut reader = BufReader::new(reader);
+    let mut all_observables = Vec::new();
+    while !reader
+        .fill_buf()
+        .context("failed to read observables")?
+        .is_empty()
+    {
+        all_observables.push(read_record(&mut reader, all_observables.len())?);
+    }
+    Ok(all_observables)
+}
+
+/// Read the first `n_records` observables of a simulation output stream that is still being
+/// written, ignoring any partially written observables after them.
+pub fn read_flushed_observables<R: Read>(
+    reader: &mut R,
+    n_records: usize,
+) -> Result<Vec<Observables>> {
+    let mut reader = BufReader::new(reader);
+    (0..n_records)
+        .map(|record_idx| read_record(&mut reader, record_idx))
+        .collect()
+}
+
+/// Read the observables of a given record of a simulation output stream.
+fn read_record<R: Read>(reader: &mut R, record_idx: usize) -> Result<Observables> {
+    use decode::Error::{InvalidDataRead, InvalidMarkerRead};
+    use std::io::ErrorKind::UnexpectedEof;
+    match decode::from_read(reader) {
+        Ok(observables) => Ok(observables),
+        Err(InvalidMarkerRead(error) | InvalidDataRead(error)) if error.kind() == UnexpectedEof => {
+            bail!("output stream is truncated at record {record_idx}")
+        }
+        Err(error) => Err(error).context("failed to deserialize observables"),
+    }
+}
+
+/// Write all the observables into a simulation output stream.
+pub fn write_observables<W: Write>(writer: &mut W, all_observables: &[Observables]) -> Result<()> {
+    for observables in all_observables {
+        encode::write(writer, observables).context("failed to serialize observables")?;
+    }
+    Ok(())
+}
+
+/// Number of columns of the vector observables of a CSV table of simulation observables.
+#[derive(Clone, Copy)]
+pub struct CsvLayout {
+    /// Number of phenotypes.
+    pub n_phe: usize,
+    /// Number of species (0 with a single species).
+    pub n_species: usize,
+    /// Number of patches (0 without patches).
+    pub n_patches: usize,
+    /// Number of cues (0 without sensing).
+    pub n_cues: usize,
+    /// Number of resources.
+    pub n_resources: usize,
+}
+
+impl CsvLayout {
+    /// Get the layout of the CSV table of the observables of a given configuration.
+    pub fn new(cfg: &Config) -> Self {
+        Self {
+            n_phe: cfg.model.n_phe,
+            n_species: cfg.species.len(),
+            n_patches: if cfg.model.has_patches() {
+                cfg.n_patches()
+            } else {
+                0
+            },
+            n_cues: if cfg.model.sensing.is_some() {
+                cfg.model.n_env
+            } else {
+                0
+            },
+            n_resources: cfg.model.resources.len(),
+        }
+    }
+}
+
+/// Write the header of a CSV table of simulation observables (see `write_csv_rows`).
+pub fn write_csv_header<W: Write>(writer: &mut W, layout: &CsvLayout) -> Result<()> {
+    let CsvLayout {
+        n_phe,
+        n_species,
+        n_patches,
+        n_cues,
+        n_resources,
+    } = *layout;
+    let columns =
+        |names: &[&str]| -> Vec<String> { names.iter().map(|&name| name.into()).collect() };
+    let per_phe = |name: &str, n: usize| -> Vec<String> {
+        (0..n).map(|phe| format!("{name}_{phe}")).collect()
+    };
+    let columns: Vec<String> = [
+        columns(&[
+            "file_idx",
+            "time",
+            "time_step",
+            "n_agents",
+            "growth_rate",
+            "n_extinct",
+        ]),
+        per_phe("avg_strat_phe", n_phe - 1),
+        columns(&["std_dev_strat_phe"]),
+        per_phe("dist_phe", n_phe - 1),
+        columns(&["n_births"]),
+        per_phe("n_births_phe", n_phe),
+        columns(&["n_deaths"]),
+        per_phe("n_deaths_phe", n_phe),
+        columns(&["n_env_trans", "n_mut", "n_culls"]),
+        per_phe("n_culls_phe", n_phe),
+        columns(&["log_growth", "n_bottlenecks", "env", "step", "event_type"]),
+        columns(&["marker_heterozygosity", "trans_factor"]),
+        per_phe("n_agents_phe", n_phe),
+        columns(&["grid_weight", "strat_diversity", "mut_load"]),
+        per_phe("n_agents_species", n_species),
+        (0..n_species)
+            .flat_map(|species| per_phe(&format!("avg_strat_phe_species_{species}"), n_phe - 1))
+            .collect(),
+        columns(&["n_migrations"]),
+        per_phe("n_agents_patch", n_patches),
+        per_phe("env_patch", n_patches),
+        (0..n_patches)
+            .flat_map(|patch| per_phe(&format!("avg_strat_phe_patch_{patch}"), n_phe - 1))
+            .collect(),
+        per_phe(
+            "spatial_avg_strat_phe",
+            if n_patches > 0 { n_phe - 1 } else { 0 },
+        ),
+        (0..n_cues)
+            .flat_map(|cue| per_phe(&format!("avg_cue_strat_phe_{cue}"), n_phe - 1))
+            .collect(),
+        columns(&["avg_accuracy", "n_switches"]),
+        per_phe("resource", n_resources),
+        columns(&["env_value", "avg_log_prob_mut", "std_dev_log_prob_mut"]),
+    ]
+    .concat();
+    writeln!(writer, "{}", columns.join(",")).context("failed to write CSV header")?;
+    Ok(())
+}
+
+/// Write the observables of an output file as rows of a CSV table, one row per observables.
+///
+/// Vector observables are flattened into one column per phenotype (omitting the last, redundant,
+/// element of the strategy and phenotype distributions), species or patch, and missing
+/// observables are left empty.
+/// The marker allele frequency spectrum, whose length varies, is not exported.
+pub fn write_csv_rows<W: Write>(
+    writer: &mut W,
+    file_idx: usize,
+    all_observables: &[Observables],
+    layout: &CsvLayout,
+) -> Result<()> {
+    let CsvLayout {
+        n_phe,
+        n_species,
+        n_patches,
+        n_cues,
+        n_resources,
+    } = *layout;
+    let cell = |value: Option<String>| value.unwrap_or_default();
+    let per_free_phe = |values: &Option<Vec<f64>>| -> Vec<String> {
+        (0..n_phe - 1)
+            .map(|phe| {
+                cell(
+                    values
+                        .as_ref()
+                        .and_then(|values| values.get(phe))
+                        .map(f64::to_string),
+                )
+            })
+            .collect()
+    };
+    let per_all_phe = |values: &Option<Vec<f64>>| -> Vec<String> {
+        (0..n_phe)
+            .map(|phe| {
+                cell(
+                    values
+                        .as_ref()
+                        .and_then(|values| values.get(phe))
+                        .map(f64::to_string),
+                )
+            })
+            .collect()
+    };
+    let per_phe = |counts: &[usize]| -> Vec<String> {
+        (0..n_phe)
+            .map(|phe| cell(counts.get(phe).map(usize::to_string)))
+            .collect()
+    };
+    for obs in all_observables {
+        let counts = &obs.event_counts;
+        let row: Vec<String> = [
+            vec![
+                file_idx.to_string(),
+                obs.time.to_string(),
+                obs.time_step.to_string(),
+                obs.n_agents.to_string(),
+                obs.growth_rate.to_string(),
+                obs.n_extinct.to_string(),
+            ],
+            per_free_phe(&obs.avg_strat_phe),
+            vec![cell(obs.std_dev_strat_phe.map(|value| value.to_string()))],
+            per_free_phe(&obs.dist_phe),
+            vec![counts.n_births.to_string()],
+            per_phe(&counts.n_births_phe),
+            vec![counts.n_deaths.to_string()],
+            per_phe(&counts.n_deaths_phe),
+            vec![
+                counts.n_env_trans.to_string(),
+                counts.n_mut.to_string(),
+                counts.n_culls.to_string(),
+            ],
+            per_phe(&counts.n_culls_phe),
+            vec![
+                cell(counts.log_growth.map(|value| value.to_string())),
+                counts.n_bottlenecks.to_string(),
+                cell(obs.env.map(|env| env.to_string())),
+                cell(obs.step.map(|step| step.to_string())),
+                cell(obs.event_type.map(|event_type| format!("{event_type:?}"))),
+            ],
+            vec![
+                cell(obs.marker_heterozygosity.map(|value| value.to_string())),
+                cell(obs.trans_factor.map(|value| value.to_string())),
+            ],
+            per_all_phe(&obs.n_agents_phe),
+            vec![
+                cell(obs.grid_weight.map(|value| value.to_string())),
+                cell(obs.strat_diversity.map(|value| value.to_string())),
+                cell(obs.mut_load.map(|value| value.to_string())),
+            ],
+            (0..n_species)
+                .map(|species| {
+                    cell(
+                        obs.n_agents_species
+                            .as_ref()
+                            .and_then(|values| values.get(species))
+                            .map(f64::to_string),
+                    )
+                })
+                .collect(),
+            (0..n_species)
+                .flat_map(|species| {
+                    per_free_phe(
+                        &obs.avg_strat_phe_species
+                            .as_ref()
+                            .and_then(|values| values.get(species))
+                            .cloned(),
+                    )
+                })
+                .collect(),
+            vec![counts.n_migrations.to_string()],
+            (0..n_patches)
+                .map(|patch| {
+                    cell(
+                        obs.n_agents_patch
+                            .as_ref()
+                            .and_then(|values| values.get(patch))
+                            .map(f64::to_string),
+                    )
+                })
+                .collect(),
+            (0..n_patches)
+                .map(|patch| {
+                    cell(
+                        obs.env_patch
+                            .as_ref()
+                            .and_then(|values| values.get(patch))
+                            .map(usize::to_string),
+                    )
+                })
+                .collect(),
+            (0..n_patches)
+                .flat_map(|patch| {
+                    per_free_phe(
+                        &obs.avg_strat_phe_patch
+                            .as_ref()
+                            .and_then(|values| values.get(patch))
+                            .cloned(),
+                    )
+                })
+                .collect(),
+            if n_patches > 0 {
+                per_free_phe(&obs.spatial_avg_strat_phe)
+            } else {
+                Vec::new()
+            },
+            (0..n_cues)
+                .flat_map(|cue| {
+                    per_free_phe(
+                        &obs.avg_cue_strat_phe
+                            .as_ref()
+                            .and_then(|values| values.get(cue))
+                            .cloned(),
+                    )
+                })
+                .collect(),
+            vec![
+                cell(obs.avg_accuracy.map(|value| value.to_string())),
+                counts.n_switches.to_string(),
+            ],
+            (0..n_resources)
+                .map(|resource| {
+                    cell(
+                        obs.resources
+                            .as_ref()
+                            .and_then(|values| values.get(resource))
+                            .map(f64::to_string),
+                    )
+                })
+                .collect(),
+            vec![
+                cell(obs.env_value.map(|value| value.to_string())),
+                cell(obs.avg_log_prob_mut.map(|value| value.to_string())),
+                cell(obs.std_dev_log_prob_mut.map(|value| value.to_string())),
+            ],
+        ]
+        .concat();
+        writeln!(writer, "{}", row.join(",")).context("failed to write CSV row")?;
+    }
+    Ok(())
+}
+
+/// Rebinning mode of the simulation observables.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum RebinMode {
+    /// Keep the last observables of every block.
+    Sample,
+    /// Average the observables of every block, weighting them by their time weight (see
+    /// `Observables::weight`).
+    Average,
+}
+
+/// Rebin the simulation observables into blocks of `factor` consecutive observables.
+///
+/// Event counts are always summed over every block, so that event rates are preserved.
+pub fn rebin_observables(
+    all_observables: &[Observables],
+    factor: usize,
+    mode: RebinMode,
+) -> Vec<Observables> {
+    all_observables
+        .chunks(factor)
+        .map(|block| {
+            let first = &block[0];
+            let last = &block[block.len() - 1];
+
+            let mut event_counts = first.event_counts.clone();
+            for obs in &block[1..] {
+                event_counts.accumulate(&obs.event_counts);
+            }
+
+            match mode {
+                RebinMode::Sample => Observables {
+                    event_counts,
+                    ..last.clone()
+                },
+                RebinMode::Average => {
+                    let weights: Vec<f64> = block.iter().map(Observables::weight).collect();
+                    let avg = |f: &dyn Fn(&Observables) -> f64| {
+                        weighted_average(&block.iter().map(f).collect::<Vec<_>>(), &weights)
+                    };
+                    let opt_avg = |f: &dyn Fn(&Observables) -> Option<f64>| {
+                        weighted_average_present(&block.iter().map(f).collect::<Vec<_>>(), &weights)
+                    };
+                    Observables {
+                        time: first.time,
+                        time_step: block.iter().map(|obs| obs.time_step).sum(),
+                        n_agents: avg(&|obs| obs.n_agents),
+                        growth_rate: avg(&|obs| obs.growth_rate),
+                        n_extinct: last.n_extinct,
+                        avg_strat_phe: first.avg_strat_phe.as_ref().map(|avg_strat_phe| {
+                            (0..avg_strat_phe.len())
+                                .map(|phe| opt_avg(&|obs| Some(obs.avg_strat_phe.as_ref()?[phe])))
+                                .collect()
+                        }),
+                        std_dev_strat_phe: first
+                            .std_dev_strat_phe
+                            .map(|_| opt_avg(&|obs| obs.std_dev_strat_phe)),
+                        dist_phe: first.dist_phe.as_ref().map(|dist_phe| {
+                            (0..dist_phe.len())
+                                .map(|phe| opt_avg(&|obs| Some(obs.dist_phe.as_ref()?[phe])))
+                                .collect()
+                        }),
+                        event_counts,
+                        env: first.env,
+                        step: first.step,
+                        event_type: first.event_type,
+                        marker_heterozygosity: first
+                            .marker_heterozygosity
+                            .map(|_| opt_avg(&|obs| obs.marker_heterozygosity)),
+                        marker_afs: first.marker_afs.clone(),
+                        trans_factor: first.trans_factor.map(|_| opt_avg(&|obs| obs.trans_factor)),
+                        n_agents_phe: first.n_agents_phe.as_ref().map(|n_agents_phe| {
+                            (0..n_agents_phe.len())
+                                .map(|phe| opt_avg(&|obs| Some(obs.n_agents_phe.as_ref()?[phe])))
+                                .collect()
+                        }),
+                        grid_weight: first.grid_weight.map(|_| weights.iter().sum()),
+                        strat_diversity: first
+                            .strat_diversity
+                            .map(|_| opt_avg(&|obs| obs.strat_diversity)),
+                        mut_load: first.mut_load.map(|_| opt_avg(&|obs| obs.mut_load)),
+                        n_agents_species: first.n_agents_species.as_ref().map(|n_agents_species| {
+                            (0..n_agents_species.len())
+                                .map(|species| {
+                                    opt_avg(&|obs| Some(obs.n_agents_species.as_ref()?[species]))
+                                })
+                                .collect()
+                        }),
+                        avg_strat_phe_species: first.avg_strat_phe_species.as_ref().map(
+                            |avg_strat_phe_species| {
+                                (0..avg_strat_phe_species.len())
+                                    .map(|species| {
+                                        (0..avg_strat_phe_species[species].len())
+                                            .map(|phe| {
+                                                opt_avg(&|obs| {
+                                                    Some(
+                                                        obs.avg_strat_phe_species.as_ref()?
+                                                            [species][phe],
+                                                    )
+                                                })
+                                            })
+                                            .collect()
+                                    })
+                                    .collect()
+                            },
+                        ),
+                        n_agents_patch: first.n_agents_patch.as_ref().map(|n_agents_patch| {
+                            (0..n_agents_patch.len())
+                                .map(|patch| {
+                                    opt_avg(&|obs| Some(obs.n_agents_patch.as_ref()?[patch]))
+                                })
+                                .collect()
+                        }),
+                        env_patch: first.env_patch.clone(),
+                        // Empty patches have no average strategy, and are left out.
+                        avg_strat_phe_patch: first.avg_strat_phe_patch.as_ref().map(
+                            |avg_strat_phe_patch| {
+                                (0..avg_strat_phe_patch.len())
+                                    .map(|patch| {
+                                        (0..avg_strat_phe_patch[patch].len())
+                                            .map(|phe| {
+                                                opt_avg(&|obs| {
+                                                    let avg_strat_phe =
+                                                        &obs.avg_strat_phe_patch.as_ref()?[patch];
+                                                    Some(avg_strat_phe[phe])
+                                                        .filter(|ele| !ele.is_nan())
+                                                })
+                                            })
+                                            .collect()
+                                    })
+                                    .collect()
+                            },
+                        ),
+                        spatial_avg_strat_phe: first.spatial_avg_strat_phe.as_ref().map(
+                            |spatial_avg_strat_phe| {
+                                (0..spatial_avg_strat_phe.len())
+                                    .map(|phe| {
+                                        opt_avg(&|obs| {
+                                            Some(obs.spatial_avg_strat_phe.as_ref()?[phe])
+                                        })
+                                    })
+                                    .collect()
+                            },
+                        ),
+                        avg_cue_strat_phe: first.avg_cue_strat_phe.as_ref().map(
+                            |avg_cue_strat_phe| {
+                                (0..avg_cue_strat_phe.len())
+                                    .map(|cue| {
+                                        (0..avg_cue_strat_phe[cue].len())
+                                            .map(|phe| {
+                                                opt_avg(&|obs| {
+                                                    Some(obs.avg_cue_strat_phe.as_ref()?[cue][phe])
+                                                })
+                                            })
+                                            .collect()
+                                    })
+                                    .collect()
+                            },
+                        ),
+                        avg_accuracy: first.avg_accuracy.map(|_| opt_avg(&|obs| obs.avg_accuracy)),
+                        resources: first.resources.as_ref().map(|resources| {
+                            (0..resources.len())
+                                .map(|idx| opt_avg(&|obs| Some(obs.resources.as_ref()?[idx])))
+                                .collect()
+                        }),
+                        env_value: first.env_value.map(|_| opt_avg(&|obs| obs.env_value)),
+                        avg_log_prob_mut: first
+                            .avg_log_prob_mut
+                            .map(|_| opt_avg(&|obs| obs.avg_log_prob_mut)),
+                        std_dev_log_prob_mut: first
+                            .std_dev_log_prob_mut
+                            .map(|_| opt_avg(&|obs| obs.std_dev_log_prob_mut)),
+                    }
+                }
+            }
+        })
+        .collect()
+}
+
+/// Compute the weighted average of the present values of a slice, ignoring the missing ones.
+fn weighted_average_present(values: &[Option<f64>], weights: &[f64]) -> f64 {
+    let (values, weights): (Vec<f64>, Vec<f64>) = values
+        .iter()
+        .zip(weights)
+        .filter_map(|(value, &weight)| value.map(|value| (value, weight)))
+        .unzip();
+    weighted_average(&values, &weights)
+}
+
 /// Compute the weighted average of a slice of values.
 fn weighted_average(values: &[f64], weights: &[f64]) -> f64 {
     if values.is_empty() || values.len() != weights.len() {
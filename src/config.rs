@@ -1,8 +1,12 @@
 //! Simulation configuration parameters.
 
-use anyhow::{Context, Result, bail};
-use serde::{Deserialize, Serialize};
-use std::{fmt::Debug, fs, ops::RangeBounds, path::Path};
+use crate::types::{Agent, AgentGroup, EnvSwitch, Probability, Rate, Simplex};
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Deserializer, Serialize, de::Error as _};
+use std::{borrow::Cow, fmt::Debug, ops::RangeBounds};
+
+/// Fully commented example configuration (2 environments and 2 phenotypes), written by `init`.
+pub const CONFIG_TEMPLATE: &str = include_str!("config_template.toml");
 
 /// Simulation configuration parameters.
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
@@ -13,17 +17,264 @@ pub struct Config {
     pub init: InitParams,
     /// Output format parameters.
     pub output: OutputParams,
+    /// Species of a multi-species model sharing the environment (a single species with the model
+    /// parameters if empty).
+    #[serde(default)]
+    pub species: Vec<SpeciesParams>,
 }
 
 impl Config {
     /// Get number of steps per output file.
     pub fn steps_per_file(&self) -> usize {
-        self.output.file_steps_factor * self.init.n_agents
+        self.output.file_steps_factor * self.steps_per_factor()
     }
 
     /// Get number of steps per saved observables.
     pub fn steps_per_save(&self) -> usize {
-        self.output.save_steps_factor * self.init.n_agents
+        self.output.save_steps_factor * self.steps_per_factor()
+    }
+
+    /// Get the number of steps per unit of the step factors: the total number of agents, or one
+    /// with Wright-Fisher dynamics, whose every step is a whole generation.
+    fn steps_per_factor(&self) -> usize {
+        if self.model.dynamics == Dynamics::WrightFisher {
+            1
+        } else {
+            self.total_n_agents()
+        }
+    }
+
+    /// Get the fitness of an agent in an environment with Wright-Fisher dynamics: its expected
+    /// growth factor over a generation (of unit time), the exponential of its birth rate minus its
+    /// death rate (averaged over its strategy with mixed expression).
+    pub fn fitness(&self, env: usize, agent: &Agent) -> f64 {
+        let rates_birth = &self.model.rates_birth[env];
+        let rates_death = &self.model.rates_death[env];
+        let net_rate = match self.model.expression {
+            Expression::Discrete => {
+                let phe = agent.phe();
+                rates_birth[phe].get() - rates_death[phe].get()
+            }
+            Expression::Mixed => (rates_birth.iter().zip(rates_death))
+                .zip(agent.strat_phe())
+                .map(|((rate_birth, rate_death), ele)| (rate_birth.get() - rate_death.get()) * ele)
+                .sum(),
+        };
+        net_rate.exp()
+    }
+
+    /// Get the total number of agents of every species or patch (initially and at the population
+    /// cap).
+    pub fn total_n_agents(&self) -> usize {
+        if self.species.is_empty() {
+            (0..self.n_patches())
+                .map(|patch| self.patch_n_agents(patch))
+                .sum()
+        } else {
+            (0..self.species.len())
+                .map(|species| self.species_n_agents(species))
+                .sum()
+        }
+    }
+
+    /// Get the number of agents of a species (initially and at its population cap).
+    pub fn species_n_agents(&self, species: usize) -> usize {
+        self.species
+            .get(species)
+            .and_then(|params| params.n_agents)
+            .unwrap_or(self.init.n_agents)
+    }
+
+    /// Get the number of patches of the lattice or demes (1 without patches).
+    pub fn n_patches(&self) -> usize {
+        match (&self.model.lattice, &self.model.demes) {
+            (Some(lattice), _) => lattice.shape.iter().product(),
+            (None, Some(demes)) => demes.n_agents.len(),
+            (None, None) => 1,
+        }
+    }
+
+    /// Get the number of agents of a patch (initially and at its population cap).
+    pub fn patch_n_agents(&self, patch: usize) -> usize {
+        self.model
+            .demes
+            .as_ref()
+            .and_then(|demes| demes.n_agents.get(patch).copied())
+            .unwrap_or(self.init.n_agents)
+    }
+
+    /// Get every patch to which the agents of a patch migrate and its migration rate (none
+    /// without patches).
+    pub fn migration_rates(&self, patch: usize) -> Vec<(usize, f64)> {
+        if let Some(lattice) = &self.model.lattice {
+            let neighbors = lattice.neighbors(patch);
+            let rate = lattice.rate_migration.get() / neighbors.len() as f64;
+            return neighbors
+                .into_iter()
+                .map(|neighbor| (neighbor, rate))
+                .collect();
+        }
+        let Some(demes) = &self.model.demes else {
+            return Vec::new();
+        };
+        demes.rates_migration[patch]
+            .iter()
+            .enumerate()
+            .filter(|&(dest, rate)| dest != patch && rate.get() > 0.0)
+            .map(|(dest, rate)| (dest, rate.get()))
+            .collect()
+    }
+
+    /// Get the birth rates of a species (matrix `n_env x n_phe`).
+    pub fn species_rates_birth(&self, species: usize) -> &[Vec<Rate>] {
+        self.species
+            .get(species)
+            .and_then(|params| params.rates_birth.as_deref())
+            .unwrap_or(&self.model.rates_birth)
+    }
+
+    /// Get the death rates of a species (matrix `n_env x n_phe`).
+    pub fn species_rates_death(&self, species: usize) -> &[Vec<Rate>] {
+        self.species
+            .get(species)
+            .and_then(|params| params.rates_death.as_deref())
+            .unwrap_or(&self.model.rates_death)
+    }
+
+    /// Get the mutation probability of a species.
+    pub fn species_prob_mut(&self, species: usize) -> Probability {
+        self.species
+            .get(species)
+            .and_then(|params| params.prob_mut)
+            .unwrap_or(self.model.prob_mut)
+    }
+
+    /// Get the model parameters of a species: those of the model with the parameters set for the
+    /// species overriding them.
+    pub fn species_model(&self, species: usize) -> Cow<'_, ModelParams> {
+        let Some(params) = self.species.get(species) else {
+            return Cow::Borrowed(&self.model);
+        };
+        let mut model = self.model.clone();
+        if let Some(rates_birth) = &params.rates_birth {
+            model.rates_birth = rates_birth.clone();
+        }
+        if let Some(rates_death) = &params.rates_death {
+            model.rates_death = rates_death.clone();
+        }
+        if let Some(prob_mut) = params.prob_mut {
+            model.prob_mut = prob_mut;
+        }
+        if params.mutation_kernel.is_some() {
+            model.mutation_kernel = params.mutation_kernel;
+        }
+        if params.std_dev_mut.is_some() {
+            model.std_dev_mut = params.std_dev_mut;
+        }
+        if params.concentration_mut.is_some() {
+            model.concentration_mut = params.concentration_mut;
+        }
+        Cow::Owned(model)
+    }
+
+    /// Get the binning of the histogram of the number of agents.
+    pub fn n_agents_binning(&self) -> Binning {
+        let spec = &self.output.histograms.n_agents;
+        let [min, max] = spec.range.unwrap_or([0.0, self.total_n_agents() as f64]);
+        Binning {
+            n_bins: spec.bins.unwrap_or(self.output.hist_bins),
+            min,
+            max,
+        }
+    }
+
+    /// Get the binning of the histograms of the average phenotypic strategy.
+    pub fn strat_binning(&self) -> Binning {
+        let spec = &self.output.histograms.avg_strat_phe;
+        let [min, max] = spec.range.unwrap_or([0.0, 1.0]);
+        Binning {
+            n_bins: spec.bins.unwrap_or(self.output.hist_bins),
+            min,
+            max,
+        }
+    }
+
+    /// Get the binning of the histogram of the dwell times in an environment: `hist_bins` bins up
+    /// to 4 times the expected dwell time (or 1 if infinite).
+    pub fn dwell_time_binning(&self, env: usize) -> Binning {
+        let total_rate: f64 = (0..self.model.n_env)
+            .filter(|&next_env| next_env != env)
+            .map(|next_env| self.model.rates_trans[env][next_env].get())
+            .sum();
+        let max = 4.0 / total_rate;
+        Binning {
+            n_bins: self.output.hist_bins,
+            min: 0.0,
+            max: if max.is_finite() { max } else { 1.0 },
+        }
+    }
+
+    /// Get number of steps per population snapshot (if enabled).
+    pub fn steps_per_snapshot(&self) -> Option<usize> {
+        self.output
+            .snapshot_steps_factor
+            .map(|factor| factor * self.steps_per_factor())
+    }
+}
+
+impl LatticeParams {
+    /// Get the nearest neighbors of a patch, given by its row-major index (without duplicates or
+    /// the patch itself, so small lattices have fewer neighbors).
+    pub fn neighbors(&self, patch: usize) -> Vec<usize> {
+        let mut neighbors = Vec::new();
+        let mut stride = 1;
+        for &len in self.shape.iter().rev() {
+            let coord = (patch / stride) % len;
+            let steps = if self.periodic {
+                [Some((coord + len - 1) % len), Some((coord + 1) % len)]
+            } else {
+                [
+                    coord.checked_sub(1),
+                    Some(coord + 1).filter(|&next| next < len),
+                ]
+            };
+            for next in steps.into_iter().flatten() {
+                neighbors.push(patch - coord * stride + next * stride);
+            }
+            stride *= len;
+        }
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors.retain(|&neighbor| neighbor != patch);
+        neighbors
+    }
+}
+
+impl ModelParams {
+    /// Check whether the agents live on patches (of a lattice or demes), each with its own
+    /// environment.
+    pub fn has_patches(&self) -> bool {
+        self.lattice.is_some() || self.demes.is_some()
+    }
+
+    /// Get the factor multiplying every environment transition rate at a given time.
+    ///
+    /// The factor is linearly interpolated between the knots of `trans_schedule` (and constant
+    /// before the first and after the last one), or one without a schedule.
+    pub fn trans_factor(&self, time: f64) -> f64 {
+        let schedule = &self.trans_schedule;
+        let next = schedule.partition_point(|knot| knot.time <= time);
+        match (
+            next.checked_sub(1).map(|prev| &schedule[prev]),
+            schedule.get(next),
+        ) {
+            (None, None) => 1.0,
+            (Some(knot), None) | (None, Some(knot)) => knot.factor,
+            (Some(prev), Some(next)) => {
+                let weight = (time - prev.time) / (next.time - prev.time);
+                prev.factor + weight * (next.factor - prev.factor)
+            }
+        }
     }
 }
 
@@ -36,16 +287,435 @@ pub struct ModelParams {
     pub n_phe: usize,
 
     /// Environment transition rates (matrix `n_env x n_env`).
-    pub rates_trans: Vec<Vec<f64>>,
+    ///
+    /// The diagonal elements (conventionally minus the total rate of leaving every environment)
+    /// are ignored and set to zero.
+    #[serde(deserialize_with = "deserialize_rates_trans")]
+    pub rates_trans: Vec<Vec<Rate>>,
+    /// Schedule of the factor multiplying every environment transition rate (constant if empty).
+    #[serde(default)]
+    pub trans_schedule: Vec<ScheduleKnot>,
+    /// Agent birth rates (matrix `n_env x n_phe`).
+    pub rates_birth: Vec<Vec<Rate>>,
+    /// Agent death rates (matrix `n_env x n_phe`).
+    pub rates_death: Vec<Vec<Rate>>,
+
+    /// Mutation probability.
+    pub prob_mut: Probability,
+    /// Mutation standard deviation.
+    pub std_dev_mut: Option<f64>,
+
+    /// Neutral marker mutation probability (enables the neutral marker).
+    #[serde(default)]
+    pub prob_mut_marker: Option<Probability>,
+
+    /// Phenotypic expression mode (discrete if unset).
+    #[serde(default)]
+    pub expression: Expression,
+
+    /// Population bottlenecks.
+    #[serde(default)]
+    pub bottlenecks: Vec<BottleneckParams>,
+
+    /// Strategy mutation kernel (gaussian if `std_dev_mut` is set, uniform otherwise, if unset).
+    #[serde(default)]
+    pub mutation_kernel: Option<MutationKernelKind>,
+    /// Mutation concentration (of the Dirichlet mutation kernel).
+    #[serde(default)]
+    pub concentration_mut: Option<f64>,
+
+    /// Event sampling algorithm (tree if unset), which does not change the simulated dynamics.
+    #[serde(default)]
+    pub event_sampler: EventSampler,
+
+    /// Environment pinned to a constant value, without any environment transitions (for control
+    /// runs).
+    #[serde(default)]
+    pub pinned_env: Option<usize>,
+
+    /// Apply the population cap to all species together (the total of their numbers of agents),
+    /// so that they compete for space, instead of to every species separately.
+    #[serde(default)]
+    pub shared_cap: bool,
+
+    /// Spatial lattice of patches on which the agents live (well-mixed population if unset).
+    #[serde(default)]
+    pub lattice: Option<LatticeParams>,
+
+    /// Well-mixed demes (patches) in which the agents live (well-mixed population if unset).
+    #[serde(default)]
+    pub demes: Option<DemeParams>,
+
+    /// Environmental cue sensing of the newborns (pure bet-hedging if unset).
+    #[serde(default)]
+    pub sensing: Option<SensingParams>,
+
+    /// Phenotype switching rates of every agent during its lifetime (tensor
+    /// `n_env x n_phe x n_phe`, element `[env][phe][next_phe]` is the rate of switching from phe
+    /// to next_phe in env, the diagonal is ignored; no switching if unset).
+    #[serde(default)]
+    pub rates_switch: Option<Vec<Vec<Vec<Rate>>>>,
+
+    /// Carrying capacity of logistic density-dependent regulation, scaling every death rate by
+    /// the number of agents over it, instead of culling the agents beyond the population cap
+    /// (hard population cap if unset).
+    #[serde(default)]
+    pub carrying_capacity: Option<f64>,
+
+    /// Population dynamics (birth-death process if unset).
+    #[serde(default)]
+    pub dynamics: Dynamics,
+
+    /// Resource pools limiting the birth rates (unlimited if empty).
+    #[serde(default)]
+    pub resources: Vec<ResourceParams>,
+
+    /// Deterministic trajectory of the environment, replacing the transitions at the rates of
+    /// `rates_trans` (Markovian transitions if unset).
+    #[serde(default)]
+    pub environment: Option<EnvironmentParams>,
+
+    /// Continuous environment value on which the birth and death rates depend, replacing those of
+    /// `rates_birth` and `rates_death` (discrete environment only if unset).
+    #[serde(default)]
+    pub continuous_env: Option<ContinuousEnvParams>,
+
+    /// Evolution of the mutation probability of every agent (fixed mutation probabilities if
+    /// unset).
+    #[serde(default)]
+    pub evolvable_mut: Option<EvolvableMutParams>,
+}
+
+/// Spatial lattice parameters.
+///
+/// Every patch of the lattice has its own environment, following the environment Markov chain
+/// independently of the others, and its own population cap (`n_agents`). Agents migrate at a
+/// given rate to a random nearest neighbor of their patch.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct LatticeParams {
+    /// Number of patches along every dimension (1 or 2 dimensions, row-major).
+    pub shape: Vec<usize>,
+    /// Migration rate of every agent.
+    pub rate_migration: Rate,
+    /// Connect the opposite edges of the lattice (true if unset).
+    #[serde(default = "LatticeParams::default_periodic")]
+    pub periodic: bool,
+}
+
+impl LatticeParams {
+    fn default_periodic() -> bool {
+        true
+    }
+}
+
+/// Island model parameters.
+///
+/// Every deme is a well-mixed patch with its own environment, following the environment Markov
+/// chain independently of the others, and its own population cap. Agents migrate between demes
+/// with given rates.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct DemeParams {
+    /// Number of agents of every deme, initially and at its population cap.
+    pub n_agents: Vec<usize>,
+    /// Migration rates of every agent (matrix `n_demes x n_demes`, element `[deme][dest]` is the
+    /// rate of migration from deme to dest, the diagonal is ignored).
+    pub rates_migration: Vec<Vec<Rate>>,
+}
+
+/// Resource pool parameters.
+///
+/// The concentration of the resource relaxes towards its capacity at a supply rate (as in a
+/// chemostat) and is depleted by a given cost at every birth, and the birth rate of every agent is
+/// multiplied by the Monod factor `R / (R + half_saturation)` of its concentration `R`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ResourceParams {
+    /// Capacity (and initial concentration) of the resource.
+    pub capacity: f64,
+    /// Rate of relaxation of the concentration towards the capacity.
+    pub rate_supply: f64,
+    /// Concentration at which the Monod factor is one half.
+    pub half_saturation: f64,
+    /// Amount of resource consumed by every birth.
+    pub cost: f64,
+}
+
+/// Deterministic environment trajectory parameters.
+///
+/// The trajectory is either read from a file or periodic, repeating a cycle of visits: every
+/// environment in order for its dwell time, or an explicit cycle.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct EnvironmentParams {
+    /// Path to a CSV file (relative to the simulation directory) listing the environment from
+    /// every switch time on (see `parse_env_trajectory`).
+    #[serde(default)]
+    pub file: Option<String>,
+
+    /// Dwell time of every environment, visited in order.
+    #[serde(default)]
+    pub dwell_times: Option<Vec<f64>>,
+
+    /// Cycle of visits, every one of an environment for a dwell time.
+    #[serde(default)]
+    pub cycle: Option<Vec<CycleVisit>>,
+}
+
+impl EnvironmentParams {
+    /// Get the number of visits of the cycle of a periodic trajectory (0 if read from a file).
+    pub fn cycle_len(&self) -> usize {
+        match (&self.dwell_times, &self.cycle) {
+            (Some(dwell_times), _) => dwell_times.len(),
+            (None, Some(cycle)) => cycle.len(),
+            (None, None) => 0,
+        }
+    }
+
+    /// Get the environment and dwell time of a visit of the cycle of a periodic trajectory (none
+    /// if the trajectory is not periodic or the cycle has no such visit).
+    pub fn cycle_visit(&self, visit_idx: usize) -> Option<(usize, f64)> {
+        match (&self.dwell_times, &self.cycle) {
+            (Some(dwell_times), _) => Some((visit_idx, *dwell_times.get(visit_idx)?)),
+            (None, Some(cycle)) => cycle
+                .get(visit_idx)
+                .map(|visit| (visit.env, visit.dwell_time)),
+            (None, None) => None,
+        }
+    }
+
+    /// Get the period of a periodic trajectory.
+    pub fn period(&self) -> f64 {
+        (0..self.cycle_len())
+            .filter_map(|visit_idx| self.cycle_visit(visit_idx))
+            .map(|(_, dwell_time)| dwell_time)
+            .sum()
+    }
+}
+
+/// Continuous environment parameters.
+///
+/// The environment value follows an Ornstein-Uhlenbeck process, relaxing towards its mean at a
+/// given rate with a given stationary standard deviation, and the birth and death rates of every
+/// phenotype are functions of it. The value is updated exactly at the times of a regular grid,
+/// keeping the rates piecewise constant in between, so the update interval should be short
+/// compared to the relaxation time.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ContinuousEnvParams {
+    /// Mean (and initial) value.
+    pub mean: f64,
+    /// Rate of relaxation towards the mean.
+    pub rate_relax: f64,
+    /// Stationary standard deviation.
+    pub std_dev: f64,
+    /// Time between updates of the value (and of the rates depending on it).
+    pub update_interval: f64,
+
+    /// Birth rate of every phenotype as a function of the value.
+    pub rates_birth: Vec<RateFunction>,
+    /// Death rate of every phenotype as a function of the value.
+    pub rates_death: Vec<RateFunction>,
+}
+
+impl ContinuousEnvParams {
+    /// Advance the value over an update interval, given a standard normal sample.
+    pub fn advance(&self, value: f64, normal: f64) -> f64 {
+        let decay = (-self.rate_relax * self.update_interval).exp();
+        self.mean
+            + (value - self.mean) * decay
+            + self.std_dev * (1.0 - decay * decay).sqrt() * normal
+    }
+}
+
+/// Rate as a function of the continuous environment value, either a polynomial or tabulated
+/// (negative rates are clamped to zero).
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RateFunction {
+    /// Coefficients of the polynomial, from the constant term up.
+    #[serde(default)]
+    pub coeffs: Option<Vec<f64>>,
+
+    /// Values tabulated on a uniform grid of the range, linearly interpolated (and constant
+    /// outside the range).
+    #[serde(default)]
+    pub values: Option<Vec<f64>>,
+    /// Range of the tabulated values.
+    #[serde(default)]
+    pub range: Option<[f64; 2]>,
+}
+
+impl RateFunction {
+    /// Evaluate the rate at a value.
+    pub fn eval(&self, value: f64) -> f64 {
+        let rate = match (&self.coeffs, &self.values, self.range) {
+            (Some(coeffs), _, _) => coeffs
+                .iter()
+                .rev()
+                .fold(0.0, |acc, coeff| acc * value + coeff),
+            (None, Some(values), Some([min, max])) => {
+                let pos = ((value - min) / (max - min)).clamp(0.0, 1.0) * (values.len() - 1) as f64;
+                let idx = (pos.floor() as usize).min(values.len() - 2);
+                let frac = pos - idx as f64;
+                values[idx] * (1.0 - frac) + values[idx + 1] * frac
+            }
+            _ => 0.0,
+        };
+        rate.max(0.0)
+    }
+}
+
+/// Visit of the cycle of a periodic environment trajectory.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CycleVisit {
+    /// Environment.
+    pub env: usize,
+    /// Time spent in the environment.
+    pub dwell_time: f64,
+}
+
+/// Environmental cue sensing parameters.
+///
+/// Every newborn senses a cue about the current environment (of its patch), which is the current
+/// environment with probability `accuracy` and a uniformly random other one otherwise, and its
+/// phenotype is drawn from the phenotypic strategy of its parent conditioned on the cue. Every
+/// agent carries one strategy per cue, all of them mutating together.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SensingParams {
+    /// Probability that the cue is the current environment (of the initial agents, if evolvable).
+    pub accuracy: Probability,
+    /// Standard deviation of the gaussian mutations of the accuracy, along with the strategies
+    /// (fixed accuracy if unset).
+    #[serde(default)]
+    pub std_dev_accuracy: Option<f64>,
+}
+
+/// Evolvable mutation probability parameters.
+///
+/// The mutation probability of every agent is a heritable trait, starting at that of its species,
+/// which mutates in every newborn with a given probability (independently of the strategy): it is
+/// multiplied by ten to the power of a gaussian sample, and capped at one.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct EvolvableMutParams {
+    /// Probability that the mutation probability of a newborn mutates.
+    pub prob_mut: Probability,
+    /// Standard deviation of the gaussian mutations of the decimal logarithm of the mutation
+    /// probability.
+    pub std_dev_log: f64,
+}
+
+/// Parameters of a species of a multi-species model.
+///
+/// Every parameter left unset is taken from the model (or initialization) parameters. All
+/// species share the environments and phenotypes of the model.
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeciesParams {
+    /// Number of agents, initially and at the population cap.
+    pub n_agents: Option<usize>,
+    /// Initial phenotypic strategy (random if unset here and in the initialization parameters).
+    pub strat_phe: Option<Simplex>,
+
     /// Agent birth rates (matrix `n_env x n_phe`).
-    pub rates_birth: Vec<Vec<f64>>,
+    pub rates_birth: Option<Vec<Vec<Rate>>>,
     /// Agent death rates (matrix `n_env x n_phe`).
-    pub rates_death: Vec<Vec<f64>>,
+    pub rates_death: Option<Vec<Vec<Rate>>>,
 
     /// Mutation probability.
-    pub prob_mut: f64,
+    pub prob_mut: Option<Probability>,
+    /// Strategy mutation kernel.
+    pub mutation_kernel: Option<MutationKernelKind>,
     /// Mutation standard deviation.
     pub std_dev_mut: Option<f64>,
+    /// Mutation concentration (of the Dirichlet mutation kernel).
+    pub concentration_mut: Option<f64>,
+}
+
+/// Knot of a schedule of the environment transition rates.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ScheduleKnot {
+    /// Time of the knot.
+    pub time: f64,
+    /// Factor multiplying every environment transition rate at this time.
+    pub factor: f64,
+}
+
+/// Population bottleneck parameters.
+///
+/// Every bottleneck either happens once at a given time or at random times with a given rate,
+/// and keeps either a given fraction or a given number of randomly chosen agents.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct BottleneckParams {
+    /// Time of the bottleneck (deterministic).
+    pub time: Option<f64>,
+    /// Rate of the bottleneck (stochastic).
+    pub rate: Option<Rate>,
+
+    /// Fraction of surviving agents.
+    pub survival: Option<Probability>,
+    /// Number of surviving agents.
+    pub n_survivors: Option<usize>,
+}
+
+/// Phenotypic expression mode of the agents.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Expression {
+    /// Every agent expresses a single phenotype, with its birth and death rates.
+    #[default]
+    Discrete,
+
+    /// Every agent expresses all phenotypes simultaneously, with birth and death rates averaged
+    /// over its phenotypic strategy.
+    Mixed,
+}
+
+/// Population dynamics of the model.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dynamics {
+    /// Independent births and deaths, with the number of agents held by the population cap.
+    #[default]
+    BirthDeath,
+
+    /// Moran process: every birth simultaneously removes a uniformly chosen agent (possibly the
+    /// parent), and every death is simultaneously replaced by the offspring of a uniformly chosen
+    /// agent, so the number of agents is strictly constant.
+    Moran,
+
+    /// Wright-Fisher process: synchronous, non-overlapping generations of unit time, whose
+    /// offspring are sampled multinomially with the fitnesses of the parents as weights (see
+    /// `Config::fitness`), with at most one environment transition per generation.
+    WrightFisher,
+}
+
+/// Algorithm used to sample the next event.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventSampler {
+    /// Search a Fenwick tree of cumulative rates (logarithmic time in the number of events).
+    #[default]
+    Tree,
+
+    /// Choose a bucket of rates within a factor of two, then an event in it by rejection
+    /// (constant time in the number of events).
+    CompositionRejection,
+}
+
+/// Mutation kernel of the phenotypic strategy (see `engine::MutationKernel`).
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MutationKernelKind {
+    /// The mutated strategy is sampled uniformly at random, independently of the parent's.
+    Uniform,
+
+    /// The parent's strategy is perturbed with a gaussian of standard deviation `std_dev_mut`
+    /// and projected back onto the simplex (the default kernel if `std_dev_mut` is set).
+    Gaussian,
+
+    /// The mutated strategy is sampled from a Dirichlet distribution with its mode at the
+    /// parent's strategy and concentration `concentration_mut`.
+    Dirichlet,
+
+    /// The log-ratios of the parent's strategy are perturbed with a gaussian of standard
+    /// deviation `std_dev_mut` (logistic-normal distribution).
+    LogitNormal,
 }
 
 /// State initialization parameters.
@@ -54,8 +724,34 @@ pub struct InitParams {
     /// Number of agents.
     pub n_agents: usize,
 
+    /// Initial environment (randomly chosen if unset).
+    #[serde(default)]
+    pub env: Option<usize>,
+
     /// Phenotypic strategy.
-    pub strat_phe: Option<Vec<f64>>,
+    pub strat_phe: Option<Simplex>,
+
+    /// Source of the phenotypic strategies, as `analysis:PATH` (relative to the simulation
+    /// directory) to sample them from the strategy distribution of a previous analysis.
+    pub strategy_source: Option<String>,
+
+    /// Path to a TOML file (relative to the simulation directory) listing the initial agents.
+    pub agents_file: Option<String>,
+
+    /// Distribution of phenotypes (relative weights, e.g. counts or probabilities).
+    pub dist_phe: Option<Vec<f64>>,
+
+    /// Master seed from which the seed of every new run is derived (runs are randomly seeded if
+    /// unset).
+    #[serde(default)]
+    pub master_seed: Option<u64>,
+}
+
+/// Initial agents file contents.
+#[derive(Deserialize)]
+struct AgentsFile {
+    /// Groups of initial agents.
+    agents: Vec<AgentGroup>,
 }
 
 /// Output format parameters.
@@ -69,59 +765,783 @@ pub struct OutputParams {
 
     /// Number of histogram bins.
     pub hist_bins: usize,
+
+    /// Maximum time since the last environment transition of the event-aligned averages
+    /// (disabled if unset).
+    pub aligned_time: Option<f64>,
+
+    /// Number of most recent output files kept by `prune`, at least one (pruning is disabled if
+    /// unset).
+    pub keep_files: Option<usize>,
+
+    /// Selection of the computed and saved observables.
+    #[serde(default)]
+    pub observables: ObservableParams,
+
+    /// Number of records (saved observables) between output file flushes (only flushed when
+    /// the buffer is full if unset).
+    #[serde(default)]
+    pub flush_every_records: Option<usize>,
+
+    /// Size of the output file buffer in bytes (8 KiB if unset).
+    #[serde(default)]
+    pub buffer_size: Option<usize>,
+
+    /// Number of agents randomly tagged as tracers at the start of every output file, whose
+    /// lineages have all their events logged (disabled if unset).
+    #[serde(default)]
+    pub n_tracers: Option<usize>,
+
+    /// Step-level observables, updated at every step and stored at the end of every output file.
+    #[serde(default)]
+    pub observers: Vec<ObserverKind>,
+    /// Simulation time until which `resume` keeps generating output files (a single output file
+    /// per resume if unset).
+    #[serde(default)]
+    pub target_time: Option<f64>,
+
+    /// Convergence criterion until which `resume` keeps generating output files (disabled if
+    /// unset).
+    #[serde(default)]
+    pub convergence: Option<ConvergenceParams>,
+
+    /// Log the birth and death of every agent, with unique ids, in a lineage file next to every
+    /// output file.
+    #[serde(default)]
+    pub lineage: bool,
+
+    /// Number of most recent checkpoints kept in the checkpoint history of every run (no history
+    /// if unset).
+    #[serde(default)]
+    pub keep_checkpoints: Option<usize>,
+
+    /// Log the event selected at every step and the time elapsed until it in an event log file
+    /// next to every output file (for debugging with `replay`).
+    #[serde(default)]
+    pub event_log: bool,
+
+    /// Number of steps per agent per full population snapshot, stored in a snapshots file next to
+    /// every output file (disabled if unset).
+    #[serde(default)]
+    pub snapshot_steps_factor: Option<usize>,
+
+    /// Binning of every histogram, overriding `hist_bins` and the default ranges.
+    #[serde(default)]
+    pub histograms: HistogramParams,
+
+    /// Simulation time between saved observables, which are then saved on a uniform time grid
+    /// instead of every `save_steps_factor` steps per agent (disabled if unset).
+    #[serde(default)]
+    pub save_time_step: Option<f64>,
+}
+
+/// Binning of every histogram of the analysis.
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistogramParams {
+    /// Histogram of the number of agents (from 0 to `init.n_agents` by default).
+    pub n_agents: HistogramSpec,
+
+    /// Histograms of the average phenotypic strategy (from 0 to 1 by default).
+    pub avg_strat_phe: HistogramSpec,
+}
+
+/// Binning of a histogram.
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistogramSpec {
+    /// Number of bins (`hist_bins` if unset).
+    pub bins: Option<usize>,
+
+    /// Range of the binned values (the default range of the histogram if unset). Values outside
+    /// the range are counted in the first or last bin.
+    pub range: Option<[f64; 2]>,
+}
+
+/// Regular binning of the values of a histogram within a range.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Binning {
+    /// Number of bins.
+    pub n_bins: usize,
+    /// Lower end of the range.
+    pub min: f64,
+    /// Upper end of the range.
+    pub max: f64,
+}
+
+impl Binning {
+    /// Get the bin of a value (the first or last one if it is outside the range).
+    pub fn bin(&self, value: f64) -> usize {
+        let frac = (value - self.min) / (self.max - self.min);
+        ((frac * self.n_bins as f64) as usize).min(self.n_bins - 1)
+    }
+}
+
+/// Convergence criterion parameters.
+///
+/// The running estimate of the observable is the average of its estimates over every output file
+/// (batch means), and its standard error is estimated from their spread, so output files should
+/// be much longer than the correlation time of the observable.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ConvergenceParams {
+    /// Observable whose running estimate must converge.
+    #[serde(default)]
+    pub observable: ConvergenceObservable,
+
+    /// Maximum standard error of the running estimate.
+    pub tolerance: f64,
+
+    /// Minimum number of output files of the running estimate.
+    #[serde(default = "ConvergenceParams::default_min_files")]
+    pub min_files: usize,
+}
+
+impl ConvergenceParams {
+    fn default_min_files() -> usize {
+        4
+    }
+}
+
+/// Observable of a convergence criterion.
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConvergenceObservable {
+    /// Average population growth rate.
+    #[default]
+    GrowthRate,
+
+    /// Average population birth rate.
+    BirthRate,
+
+    /// Average standard deviation of the phenotypic strategy.
+    StdDevStratPhe,
+}
+
+/// Kind of step-level observable (see `observers::Observable`).
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ObserverKind {
+    /// Fraction of time spent in every environment.
+    EnvOccupancy,
+
+    /// Time average of the number of agents.
+    AvgNAgents,
+
+    /// Time average of the distribution of phenotypes.
+    AvgDistPhe,
+
+    /// Rate of every type of event.
+    EventRates,
+}
+
+/// Observable selection parameters.
+///
+/// Every optional observable is computed and saved by default.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ObservableParams {
+    /// Compute the average phenotypic strategy.
+    pub avg_strat_phe: bool,
+
+    /// Compute the standard deviation of the phenotypic strategy.
+    pub std_dev_strat_phe: bool,
+
+    /// Compute the distribution of phenotypes.
+    pub dist_phe: bool,
+
+    /// Compute the effective number of distinct phenotypic strategies.
+    pub strat_diversity: bool,
+}
+
+impl Default for ObservableParams {
+    fn default() -> Self {
+        Self {
+            avg_strat_phe: true,
+            std_dev_strat_phe: true,
+            dist_phe: true,
+            strat_diversity: true,
+        }
+    }
 }
 
 impl Config {
-    /// Load a `Config` from a TOML file.
+    /// Load a `Config` from a TOML string.
     ///
     /// Performs validation on all parameters before returning.
-    pub fn from_file<P: AsRef<Path>>(file: P) -> Result<Self> {
-        let file = file.as_ref();
-        let file = fs::read_to_string(file).with_context(|| format!("failed to read {file:?}"))?;
-
-        let config: Config = toml::from_str(&file).context("failed to deserialize config")?;
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        let config: Config = toml::from_str(toml).context("failed to deserialize config")?;
 
         config.validate().context("failed to validate config")?;
 
         Ok(config)
     }
 
+    /// Apply a `KEY=VALUE` override, where `KEY` is a dotted parameter path (e.g. `output.hist_bins`).
+    ///
+    /// `VALUE` is parsed as a TOML value. Performs validation on all parameters after applying it.
+    pub fn apply_override(&mut self, assignment: &str) -> Result<()> {
+        let (key, value) = assignment
+            .split_once('=')
+            .with_context(|| format!("override {assignment:?} is not of the form KEY=VALUE"))?;
+        let key = key.trim();
+
+        let value = toml::from_str::<toml::Table>(&format!("value = {value}"))
+            .with_context(|| format!("failed to parse value of {key:?}"))?
+            .remove("value")
+            .context("failed to get parsed value")?;
+
+        let mut table = toml::Table::try_from(&*self).context("failed to serialize config")?;
+        let (path, field) = key.rsplit_once('.').unwrap_or(("", key));
+        get_section(&mut table, path)?.insert(field.to_string(), value);
+
+        let config: Config = table.try_into().context("failed to deserialize config")?;
+
+        // Unknown parameters are silently dropped during deserialization, so check that it survived.
+        let mut table = toml::Table::try_from(&config).context("failed to serialize config")?;
+        if !get_section(&mut table, path)?.contains_key(field) {
+            bail!("unknown config parameter {key:?}");
+        }
+
+        config.validate().context("failed to validate config")?;
+        *self = config;
+
+        Ok(())
+    }
+
+    /// Validate all parameters, failing with the first problem found.
     fn validate(&self) -> Result<()> {
+        match self.problems().into_iter().next() {
+            Some(problem) => Err(problem),
+            None => Ok(()),
+        }
+    }
+
+    /// Check all parameters, returning every problem found (empty if the config is valid).
+    pub fn problems(&self) -> Vec<anyhow::Error> {
         let model = &self.model;
         let init = &self.init;
         let output = &self.output;
 
-        check_num(model.n_env, 1..=16).context("invalid number of environments")?;
-        check_num(model.n_phe, 1..=16).context("invalid number of phenotypes")?;
+        let mut problems = Vec::new();
+        let mut check = |result: Result<()>| {
+            if let Err(problem) = result {
+                problems.push(problem);
+            }
+        };
+
+        check(check_num(model.n_env, 1..=16).context("invalid number of environments"));
+        check(check_num(model.n_phe, 1..=16).context("invalid number of phenotypes"));
+
+        check(
+            check_mat(&model.rates_trans, (model.n_env, model.n_env))
+                .context("invalid environment transition rates"),
+        );
+        for (knot_idx, knot) in model.trans_schedule.iter().enumerate() {
+            let min_time = match knot_idx {
+                0 => 0.0,
+                _ => model.trans_schedule[knot_idx - 1].time,
+            };
+            check(
+                check_num(knot.time, min_time..)
+                    .and_then(|_| check_num(knot.factor, 0.0..))
+                    .with_context(|| format!("invalid transition schedule knot {knot_idx}")),
+            );
+            if knot_idx > 0 && knot.time == min_time {
+                check(Err(anyhow!(
+                    "transition schedule knot times must be strictly increasing"
+                )));
+            }
+        }
+        check(
+            check_mat(&model.rates_birth, (model.n_env, model.n_phe))
+                .context("invalid agent birth rates"),
+        );
+        check(
+            check_mat(&model.rates_death, (model.n_env, model.n_phe))
+                .context("invalid agent death rates"),
+        );
+
+        if let Some(rates_switch) = &model.rates_switch {
+            check(
+                check_num(rates_switch.len(), model.n_env..=model.n_env)
+                    .context("invalid number of phenotype switching environments"),
+            );
+            for (env, rates) in rates_switch.iter().enumerate() {
+                check(
+                    check_mat(rates, (model.n_phe, model.n_phe))
+                        .with_context(|| format!("invalid phenotype switching rates of env {env}")),
+                );
+            }
+            if model.expression == Expression::Mixed {
+                check(Err(anyhow!(
+                    "phenotype switching can not be set together with mixed expression"
+                )));
+            }
+        }
+
+        if let Some(carrying_capacity) = model.carrying_capacity {
+            check(
+                check_num(carrying_capacity, f64::MIN_POSITIVE..)
+                    .context("invalid carrying capacity"),
+            );
+            if model.has_patches() {
+                check(Err(anyhow!(
+                    "carrying capacity can not be set together with patches"
+                )));
+            }
+        }
 
-        check_mat(&model.rates_trans, (model.n_env, model.n_env))
-            .context("invalid environment transition rates")?;
-        check_mat(&model.rates_birth, (model.n_env, model.n_phe))
-            .context("invalid agent birth rates")?;
-        check_mat(&model.rates_death, (model.n_env, model.n_phe))
-            .context("invalid agent death rates")?;
+        if model.dynamics != Dynamics::BirthDeath {
+            if model.has_patches() || !self.species.is_empty() {
+                check(Err(anyhow!(
+                    "only birth-death dynamics can be set together with patches or species"
+                )));
+            }
+            if !model.bottlenecks.is_empty() || model.carrying_capacity.is_some() {
+                check(Err(anyhow!(
+                    "only birth-death dynamics can be set together with bottlenecks or a carrying \
+                     capacity"
+                )));
+            }
+        }
+        check(check_num(model.resources.len(), 0..=16).context("invalid number of resources"));
+        for (resource, params) in model.resources.iter().enumerate() {
+            check(check_resource(params).with_context(|| format!("invalid resource {resource}")));
+        }
+        if model.dynamics != Dynamics::BirthDeath && !model.resources.is_empty() {
+            check(Err(anyhow!(
+                "only birth-death dynamics can be set together with resources"
+            )));
+        }
+        if let Some(environment) = &model.environment {
+            check(
+                check_environment(environment, model.n_env)
+                    .context("invalid environment trajectory"),
+            );
+            if model.pinned_env.is_some() || model.has_patches() {
+                check(Err(anyhow!(
+                    "environment trajectory can not be set together with a pinned environment or \
+                     patches"
+                )));
+            }
+            if model.dynamics == Dynamics::WrightFisher {
+                check(Err(anyhow!(
+                    "wright-fisher dynamics can not be set together with an environment trajectory"
+                )));
+            }
+        }
+        if let Some(continuous_env) = &model.continuous_env {
+            check(
+                check_continuous_env(continuous_env, model.n_phe)
+                    .context("invalid continuous environment"),
+            );
+            if model.n_env != 1 {
+                check(Err(anyhow!(
+                    "continuous environment requires a single discrete environment"
+                )));
+            }
+            if model.has_patches() || !self.species.is_empty() || model.environment.is_some() {
+                check(Err(anyhow!(
+                    "continuous environment can not be set together with patches, species or an \
+                     environment trajectory"
+                )));
+            }
+            if model.dynamics == Dynamics::WrightFisher {
+                check(Err(anyhow!(
+                    "wright-fisher dynamics can not be set together with a continuous environment"
+                )));
+            }
+        }
+        if model.dynamics == Dynamics::WrightFisher && model.rates_switch.is_some() {
+            check(Err(anyhow!(
+                "wright-fisher dynamics can not be set together with phenotype switching"
+            )));
+        }
+
+        check(check_mutation(model));
 
-        check_num(model.prob_mut, 0.0..=1.0).context("invalid mutation probability")?;
-        if let Some(std_dev_mut) = model.std_dev_mut {
-            check_num(std_dev_mut, 0.0..).context("invalid mutation standard deviation")?;
+        check(check_num(self.species.len(), 0..=16).context("invalid number of species"));
+        for (species, params) in self.species.iter().enumerate() {
+            check(
+                check_species(self, species, params)
+                    .with_context(|| format!("invalid species {species}")),
+            );
+        }
+        if !self.species.is_empty()
+            && (init.strategy_source.is_some() || init.agents_file.is_some())
+        {
+            check(Err(anyhow!(
+                "species can not be set together with a strategy source or an agents file"
+            )));
         }
 
-        check_num(init.n_agents, 1..=16_384).context("invalid number of agents")?;
+        if let Some(lattice) = &model.lattice {
+            check(
+                check_num(lattice.shape.len(), 1..=2)
+                    .context("invalid number of lattice dimensions"),
+            );
+            for &len in &lattice.shape {
+                check(check_num(len, 1..).context("invalid number of lattice patches"));
+            }
+            check(check_num(self.n_patches(), 1..=65_536).context("invalid number of patches"));
+            if !self.species.is_empty() {
+                check(Err(anyhow!(
+                    "species can not be set together with a lattice"
+                )));
+            }
+        }
+        if let Some(demes) = &model.demes {
+            let n_demes = demes.n_agents.len();
+            check(check_num(n_demes, 1..=65_536).context("invalid number of demes"));
+            for &n_agents in &demes.n_agents {
+                check(check_num(n_agents, 1..=1_048_576).context("invalid number of deme agents"));
+            }
+            check(
+                check_mat(&demes.rates_migration, (n_demes, n_demes))
+                    .context("invalid deme migration rates"),
+            );
+            if model.lattice.is_some() {
+                check(Err(anyhow!("lattice and demes can not be both set")));
+            }
+            if !self.species.is_empty() {
+                check(Err(anyhow!("species can not be set together with demes")));
+            }
+        }
+
+        if let Some(std_dev_accuracy) = model.sensing.as_ref().and_then(|s| s.std_dev_accuracy) {
+            check(
+                check_num(std_dev_accuracy, 0.0..)
+                    .context("invalid accuracy mutation standard deviation"),
+            );
+        }
+
+        if let Some(evolvable_mut) = &model.evolvable_mut {
+            check(
+                check_num(evolvable_mut.std_dev_log, 0.0..f64::INFINITY)
+                    .context("invalid mutation probability mutation standard deviation"),
+            );
+            let species = 0..self.species.len().max(1);
+            if species
+                .into_iter()
+                .any(|species| self.species_prob_mut(species).get() == 0.0)
+            {
+                check(Err(anyhow!(
+                    "evolvable mutation probability requires positive mutation probabilities"
+                )));
+            }
+        }
+
+        for (bottleneck_idx, bottleneck) in model.bottlenecks.iter().enumerate() {
+            check(
+                check_bottleneck(bottleneck)
+                    .with_context(|| format!("invalid bottleneck {bottleneck_idx}")),
+            );
+        }
+
+        if let Some(pinned_env) = model.pinned_env {
+            check(check_num(pinned_env, 0..model.n_env).context("invalid pinned environment"));
+        }
+
+        check(check_num(init.n_agents, 1..=1_048_576).context("invalid number of agents"));
+
+        if let Some(env) = init.env {
+            check(check_num(env, 0..model.n_env).context("invalid initial environment"));
+            if model.pinned_env.is_some_and(|pinned_env| pinned_env != env) {
+                check(Err(anyhow!(
+                    "initial environment must be the pinned environment"
+                )));
+            }
+        }
 
         if let Some(strat_phe) = &init.strat_phe {
-            check_vec(strat_phe, model.n_phe).context("invalid phenotypic strategy")?;
+            check(check_vec(strat_phe, model.n_phe).context("invalid phenotypic strategy"));
+        }
+
+        if let Some(strategy_source) = &init.strategy_source {
+            if !strategy_source.starts_with("analysis:") {
+                check(Err(anyhow!(
+                    "strategy source must be of the form \"analysis:PATH\""
+                )));
+            }
+            if init.strat_phe.is_some() {
+                check(Err(anyhow!(
+                    "strategy source and phenotypic strategy can not be both set"
+                )));
+            }
         }
 
-        check_num(output.file_steps_factor, 64..)
-            .context("invalid number of steps per agent per output file")?;
+        if init.agents_file.is_some()
+            && (init.strat_phe.is_some() || init.strategy_source.is_some())
+        {
+            check(Err(anyhow!(
+                "agents file can not be set together with a phenotypic strategy or its source"
+            )));
+        }
 
-        check_num(output.save_steps_factor, 1..output.file_steps_factor)
-            .context("invalid number of steps per agent per saved observables")?;
+        if let Some(dist_phe) = &init.dist_phe {
+            check(check_vec(dist_phe, model.n_phe).context("invalid distribution of phenotypes"));
+            for &weight in dist_phe {
+                check(check_num(weight, 0.0..).context("invalid distribution of phenotypes"));
+            }
+            if dist_phe.iter().sum::<f64>() <= 0.0 {
+                check(Err(anyhow!(
+                    "distribution of phenotypes must have a positive sum"
+                )));
+            }
+            if init.agents_file.is_some() {
+                check(Err(anyhow!(
+                    "agents file and distribution of phenotypes can not be both set"
+                )));
+            }
+        }
 
-        check_num(output.hist_bins, 1..).context("invalid number of histogram bins")?;
+        check(
+            check_num(output.file_steps_factor, 64..)
+                .context("invalid number of steps per agent per output file"),
+        );
 
-        Ok(())
+        check(
+            check_num(output.save_steps_factor, 1..output.file_steps_factor)
+                .context("invalid number of steps per agent per saved observables"),
+        );
+
+        check(check_num(output.hist_bins, 1..).context("invalid number of histogram bins"));
+
+        if output
+            .aligned_time
+            .is_some_and(|aligned_time| aligned_time <= 0.0)
+        {
+            check(Err(anyhow!(
+                "maximum time of the event-aligned averages must be positive"
+            )));
+        }
+
+        if let Some(flush_every_records) = output.flush_every_records {
+            check(
+                check_num(flush_every_records, 1..).context("invalid number of records per flush"),
+            );
+        }
+        if let Some(buffer_size) = output.buffer_size {
+            check(check_num(buffer_size, 1..).context("invalid output buffer size"));
+        }
+        if let Some(n_tracers) = output.n_tracers {
+            check(check_num(n_tracers, 1..).context("invalid number of tracers"));
+        }
+        for (spec, name, bounds) in [
+            (&output.histograms.n_agents, "number of agents", None),
+            (
+                &output.histograms.avg_strat_phe,
+                "average phenotypic strategy",
+                Some([0.0, 1.0]),
+            ),
+        ] {
+            if let Some(bins) = spec.bins {
+                check(
+                    check_num(bins, 1..)
+                        .with_context(|| format!("invalid number of {name} histogram bins")),
+                );
+            }
+            if let Some([min, max]) = spec.range {
+                if !(min.is_finite() && max.is_finite() && min < max) {
+                    check(Err(anyhow!(
+                        "{name} histogram range must be finite and increasing"
+                    )));
+                }
+                if let Some([lower, upper]) = bounds
+                    && (min < lower || max > upper)
+                {
+                    check(Err(anyhow!(
+                        "{name} histogram range must be within [{lower}, {upper}]"
+                    )));
+                }
+            }
+        }
+        if output
+            .save_time_step
+            .is_some_and(|save_time_step| !(save_time_step.is_finite() && save_time_step > 0.0))
+        {
+            check(Err(anyhow!(
+                "time between saved observables must be positive and finite"
+            )));
+        }
+        if let Some(snapshot_steps_factor) = output.snapshot_steps_factor {
+            check(
+                check_num(snapshot_steps_factor, 1..)
+                    .context("invalid number of steps per agent per snapshot"),
+            );
+        }
+        if output
+            .target_time
+            .is_some_and(|target_time| !(target_time.is_finite() && target_time > 0.0))
+        {
+            check(Err(anyhow!(
+                "target simulation time must be positive and finite"
+            )));
+        }
+        if let Some(convergence) = &output.convergence {
+            if !(convergence.tolerance.is_finite() && convergence.tolerance > 0.0) {
+                check(Err(anyhow!(
+                    "convergence tolerance must be positive and finite"
+                )));
+            }
+            check(
+                check_num(convergence.min_files, 2..)
+                    .context("invalid minimum number of output files of the convergence criterion"),
+            );
+            if convergence.observable == ConvergenceObservable::StdDevStratPhe
+                && !output.observables.std_dev_strat_phe
+            {
+                check(Err(anyhow!(
+                    "convergence observable std-dev-strat-phe is not computed"
+                )));
+            }
+        }
+        if let Some(keep_files) = output.keep_files {
+            check(check_num(keep_files, 1..).context("invalid number of kept output files"));
+        }
+        if let Some(keep_checkpoints) = output.keep_checkpoints {
+            check(check_num(keep_checkpoints, 1..).context("invalid number of kept checkpoints"));
+        }
+
+        problems
+    }
+}
+
+/// Load the initial agent groups from a TOML agents file.
+///
+/// Performs validation on all groups before returning.
+pub fn parse_agent_groups(toml: &str, n_phe: usize) -> Result<Vec<AgentGroup>> {
+    let file: AgentsFile = toml::from_str(toml).context("failed to deserialize agents file")?;
+
+    for (group_idx, group) in file.agents.iter().enumerate() {
+        let context = || format!("invalid agent group {group_idx}");
+        check_num(group.phe, 0..n_phe)
+            .context("invalid phenotype")
+            .with_context(context)?;
+        check_vec(&group.strat_phe, n_phe)
+            .context("invalid phenotypic strategy")
+            .with_context(context)?;
+        check_num(group.count, 1..)
+            .context("invalid number of agents")
+            .with_context(context)?;
+    }
+    check_num(file.agents.len(), 1..).context("invalid number of agent groups")?;
+
+    Ok(file.agents)
+}
+
+/// Load the environment trajectory from a CSV environment file, with a `time,env` header and the
+/// environment from every switch time on in every row (blank lines and lines starting with `#`
+/// are skipped).
+///
+/// The first switch time must be zero and times must be strictly increasing. Consecutive rows with
+/// the same environment are merged, so that every switch is an environment transition.
+pub fn parse_env_trajectory(csv: &str, n_env: usize) -> Result<Vec<EnvSwitch>> {
+    let mut lines = (csv.lines().enumerate())
+        .map(|(line_idx, line)| (line_idx + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+    let header = lines.next().map(|(_, line)| line.replace(' ', ""));
+    if header.as_deref() != Some("time,env") {
+        bail!("environment file must start with a time,env header");
+    }
+
+    let mut trajectory: Vec<EnvSwitch> = Vec::new();
+    for (line_no, line) in lines {
+        let context = || format!("invalid row at line {line_no}");
+        let (time, env) = line
+            .split_once(',')
+            .ok_or_else(|| anyhow!("row must have 2 fields"))
+            .with_context(context)?;
+        let time: f64 = (time.trim().parse())
+            .context("failed to parse time")
+            .with_context(context)?;
+        let env: usize = (env.trim().parse())
+            .context("failed to parse environment")
+            .with_context(context)?;
+        let min_time = trajectory.last().map_or(0.0, |switch| switch.time);
+        check_num(time, min_time..f64::INFINITY)
+            .context("invalid time")
+            .with_context(context)?;
+        if trajectory.is_empty() && time != 0.0 {
+            bail!("first switch time must be zero");
+        }
+        if !trajectory.is_empty() && time == min_time {
+            bail!("switch times must be strictly increasing");
+        }
+        check_num(env, 0..n_env)
+            .context("invalid environment")
+            .with_context(context)?;
+        if trajectory.last().is_none_or(|switch| switch.env != env) {
+            trajectory.push(EnvSwitch { time, env });
+        }
+    }
+    check_num(trajectory.len(), 1..).context("invalid number of environment switches")?;
+
+    Ok(trajectory)
+}
+
+fn check_mutation(model: &ModelParams) -> Result<()> {
+    if let Some(std_dev_mut) = model.std_dev_mut {
+        check_num(std_dev_mut, 0.0..).context("invalid mutation standard deviation")?;
+    }
+    if let Some(concentration_mut) = model.concentration_mut {
+        check_num(concentration_mut, f64::MIN_POSITIVE..)
+            .context("invalid mutation concentration")?;
+    }
+    match model.mutation_kernel {
+        Some(MutationKernelKind::Gaussian | MutationKernelKind::LogitNormal)
+            if model.std_dev_mut.is_none() =>
+        {
+            bail!("mutation kernel requires a mutation standard deviation");
+        }
+        Some(MutationKernelKind::Dirichlet) if model.concentration_mut.is_none() => {
+            bail!("mutation kernel requires a mutation concentration");
+        }
+        _ => {}
     }
+    Ok(())
+}
+
+fn check_species(cfg: &Config, species: usize, params: &SpeciesParams) -> Result<()> {
+    let model = &cfg.model;
+    if let Some(n_agents) = params.n_agents {
+        check_num(n_agents, 1..=1_048_576).context("invalid number of agents")?;
+    }
+    if let Some(strat_phe) = &params.strat_phe {
+        check_vec(strat_phe, model.n_phe).context("invalid phenotypic strategy")?;
+    }
+    if let Some(rates_birth) = &params.rates_birth {
+        check_mat(rates_birth, (model.n_env, model.n_phe)).context("invalid agent birth rates")?;
+    }
+    if let Some(rates_death) = &params.rates_death {
+        check_mat(rates_death, (model.n_env, model.n_phe)).context("invalid agent death rates")?;
+    }
+    check_mutation(&cfg.species_model(species))
+}
+
+fn check_bottleneck(bottleneck: &BottleneckParams) -> Result<()> {
+    if bottleneck.time.is_some() == bottleneck.rate.is_some() {
+        bail!("exactly one of time and rate must be set");
+    }
+    if let Some(time) = bottleneck.time {
+        check_num(time, f64::MIN_POSITIVE..).context("invalid time")?;
+    }
+    if bottleneck.survival.is_some() == bottleneck.n_survivors.is_some() {
+        bail!("exactly one of survival and n_survivors must be set");
+    }
+    Ok(())
+}
+
+fn get_section<'a>(table: &'a mut toml::Table, path: &str) -> Result<&'a mut toml::Table> {
+    let mut section = table;
+    for name in path.split('.').filter(|name| !name.is_empty()) {
+        section = section
+            .get_mut(name)
+            .and_then(|value| value.as_table_mut())
+            .with_context(|| format!("unknown config section {name:?}"))?;
+    }
+    Ok(section)
 }
 
 fn check_num<T, R>(num: T, range: R) -> Result<()>
@@ -135,7 +1555,7 @@ where
     Ok(())
 }
 
-fn check_vec(vec: &[f64], exp_len: usize) -> Result<()> {
+fn check_vec<T>(vec: &[T], exp_len: usize) -> Result<()> {
     let len = vec.len();
     if len != exp_len {
         bail!("vector length must be {exp_len}, but is {len}");
@@ -143,7 +1563,115 @@ fn check_vec(vec: &[f64], exp_len: usize) -> Result<()> {
     Ok(())
 }
 
-fn check_mat(mat: &[Vec<f64>], exp_shape: (usize, usize)) -> Result<()> {
+fn check_environment(environment: &EnvironmentParams, n_env: usize) -> Result<()> {
+    let n_sources = usize::from(environment.file.is_some())
+        + usize::from(environment.dwell_times.is_some())
+        + usize::from(environment.cycle.is_some());
+    if n_sources != 1 {
+        bail!("environment trajectory requires exactly one of a file, dwell times or a cycle");
+    }
+    if let Some(dwell_times) = &environment.dwell_times {
+        check_num(n_env, 2..).context("invalid number of environments")?;
+        check_vec(dwell_times, n_env).context("invalid dwell times")?;
+    }
+    if let Some(cycle) = &environment.cycle {
+        check_num(cycle.len(), 2..).context("invalid number of cycle visits")?;
+        for (visit_idx, visit) in cycle.iter().enumerate() {
+            check_num(visit.env, 0..n_env)
+                .context("invalid environment")
+                .with_context(|| format!("invalid cycle visit {visit_idx}"))?;
+            if visit.env == cycle[(visit_idx + 1) % cycle.len()].env {
+                bail!("consecutive cycle visits must be of different environments");
+            }
+        }
+    }
+    for visit_idx in 0..environment.cycle_len() {
+        let Some((_, dwell_time)) = environment.cycle_visit(visit_idx) else {
+            continue;
+        };
+        check_num(dwell_time, f64::MIN_POSITIVE..f64::INFINITY)
+            .with_context(|| format!("invalid dwell time of cycle visit {visit_idx}"))?;
+    }
+    Ok(())
+}
+
+fn check_continuous_env(params: &ContinuousEnvParams, n_phe: usize) -> Result<()> {
+    check_num(params.mean, f64::MIN..=f64::MAX).context("invalid mean")?;
+    check_num(params.rate_relax, f64::MIN_POSITIVE..f64::INFINITY)
+        .context("invalid relaxation rate")?;
+    check_num(params.std_dev, 0.0..f64::INFINITY).context("invalid standard deviation")?;
+    check_num(params.update_interval, f64::MIN_POSITIVE..f64::INFINITY)
+        .context("invalid update interval")?;
+    check_vec(&params.rates_birth, n_phe).context("invalid birth rate functions")?;
+    check_vec(&params.rates_death, n_phe).context("invalid death rate functions")?;
+    let functions = (params
+        .rates_birth
+        .iter()
+        .map(|function| ("birth", function)))
+    .chain(
+        params
+            .rates_death
+            .iter()
+            .map(|function| ("death", function)),
+    );
+    for (phe, (kind, function)) in functions.enumerate() {
+        check_rate_function(function).with_context(|| {
+            format!("invalid {kind} rate function of phenotype {}", phe % n_phe)
+        })?;
+    }
+    Ok(())
+}
+
+fn check_rate_function(function: &RateFunction) -> Result<()> {
+    match (&function.coeffs, &function.values, function.range) {
+        (Some(coeffs), None, None) => {
+            check_num(coeffs.len(), 1..).context("invalid number of coefficients")?;
+            if coeffs.iter().any(|coeff| !coeff.is_finite()) {
+                bail!("coefficients must be finite");
+            }
+        }
+        (None, Some(values), Some([min, max])) => {
+            check_num(values.len(), 2..).context("invalid number of values")?;
+            if values.iter().any(|value| !value.is_finite()) {
+                bail!("values must be finite");
+            }
+            check_num(min, f64::MIN..max).context("invalid range")?;
+        }
+        _ => bail!("rate function requires either coefficients or values and a range"),
+    }
+    Ok(())
+}
+
+fn check_resource(params: &ResourceParams) -> Result<()> {
+    check_num(params.capacity, 0.0..f64::INFINITY).context("invalid capacity")?;
+    check_num(params.rate_supply, 0.0..f64::INFINITY).context("invalid supply rate")?;
+    check_num(params.half_saturation, f64::MIN_POSITIVE..f64::INFINITY)
+        .context("invalid half saturation")?;
+    check_num(params.cost, 0.0..f64::INFINITY).context("invalid cost")?;
+    Ok(())
+}
+
+/// Deserialize the environment transition rates, setting the diagonal elements to zero.
+fn deserialize_rates_trans<'de, D>(deserializer: D) -> Result<Vec<Vec<Rate>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let rates_trans: Vec<Vec<f64>> = Vec::deserialize(deserializer)?;
+    rates_trans
+        .into_iter()
+        .enumerate()
+        .map(|(env, rates)| {
+            rates
+                .into_iter()
+                .enumerate()
+                .map(|(next_env, rate)| Rate::new(if next_env == env { 0.0 } else { rate }))
+                .collect()
+        })
+        .collect::<Result<_>>()
+        .map_err(D::Error::custom)
+}
+
+fn check_mat<T>(mat: &[Vec<T>], exp_shape: (usize, usize)) -> Result<()> {
     let exp_n_rows = exp_shape.0;
     let exp_n_cols = exp_shape.1;
     let n_rows = mat.len();
@@ -1,58 +1,234 @@
 //! Simulation engine.
 
 use crate::analysis::calc_observables;
-use crate::config::Config;
-use crate::types::{Agent, Event, Observables, State};
-use anyhow::{Context, Result};
-use rand::prelude::*;
+use crate::config::{
+    Config, Dynamics, Expression, ModelParams, MutationKernelKind, ObserverKind, OutputParams,
+};
+use crate::interrupt;
+use crate::observers::{Observable, ObserverResult, build_observers};
+use crate::sampling::EventPool;
+use crate::types::{
+    Agent, ClockShift, Event, EventCounts, Extinction, InitSources, LineageEntry, LoggedEvent,
+    Observables, Probability, Simplex, Snapshot, State, StepOutcome, TracerEvent, TracerEventKind,
+};
+use anyhow::{Context, Result, bail};
+use rand::{prelude::*, seq::index};
 use rand_chacha::ChaCha12Rng;
-use rand_distr::{Exp, Normal, weighted::WeightedIndex};
+use rand_distr::{Exp, Gamma, Normal, weighted::WeightedIndex};
 use rmp_serde::{decode, encode};
 use serde::{Deserialize, Serialize};
 use std::{
-    fs::File,
-    io::{BufReader, BufWriter, Write},
-    path::Path,
+    io::{Read, Write},
+    time::{Duration, Instant},
 };
 
-/// Collection of all possible events and their associated rates at a certain step.
-#[derive(Default)]
-pub struct EventPool {
-    /// Vector of possible events.
-    events: Vec<Event>,
-    /// Vector of associated rates.
-    rates: Vec<f64>,
+/// Stream of the random number generator used to tag tracers.
+const TRACER_STREAM: u64 = 1;
+
+/// Mutation kernel of the phenotypic strategy.
+///
+/// Samples the strategy of a mutated offspring given its parent's.
+pub trait MutationKernel: Send + Sync {
+    /// Sample the mutated strategy from the parent's strategy.
+    fn mutate(&self, strat_phe: &Simplex, rng: &mut ChaCha12Rng) -> Result<Simplex>;
+
+    /// Clone the kernel into a new box.
+    fn clone_box(&self) -> Box<dyn MutationKernel>;
+}
+
+impl Clone for Box<dyn MutationKernel> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Uniform resampling kernel: the mutated strategy is independent of the parent's.
+#[derive(Clone)]
+pub struct UniformKernel;
+
+impl MutationKernel for UniformKernel {
+    fn mutate(&self, strat_phe: &Simplex, rng: &mut ChaCha12Rng) -> Result<Simplex> {
+        let weights = (0..strat_phe.len())
+            .map(|_| rng.random_range(0.0..1.0))
+            .collect();
+        Simplex::normalize(weights)
+    }
+
+    fn clone_box(&self) -> Box<dyn MutationKernel> {
+        Box::new(self.clone())
+    }
+}
+
+/// Simplex gaussian kernel: every element of the parent's strategy is perturbed with a gaussian
+/// and the result is projected back onto the simplex (reflecting negative elements).
+#[derive(Clone)]
+pub struct GaussianKernel {
+    /// Distribution of the perturbation of every element.
+    ele_mut_dist: Normal<f64>,
+}
+
+impl GaussianKernel {
+    /// Create a new gaussian kernel with a given standard deviation.
+    pub fn new(std_dev: f64) -> Result<Self> {
+        let ele_mut_dist = Normal::new(0.0, std_dev)?;
+        Ok(Self { ele_mut_dist })
+    }
+}
+
+impl MutationKernel for GaussianKernel {
+    fn mutate(&self, strat_phe: &Simplex, rng: &mut ChaCha12Rng) -> Result<Simplex> {
+        let weights = strat_phe
+            .iter()
+            .map(|ele| (ele + self.ele_mut_dist.sample(rng)).abs())
+            .collect();
+        Simplex::normalize(weights)
+    }
+
+    fn clone_box(&self) -> Box<dyn MutationKernel> {
+        Box::new(self.clone())
+    }
+}
+
+/// Dirichlet kernel: the mutated strategy is sampled from a Dirichlet distribution with
+/// parameters `1 + concentration * ele`, whose mode is the parent's strategy.
+#[derive(Clone)]
+pub struct DirichletKernel {
+    /// Concentration of the distribution around the parent's strategy.
+    concentration: f64,
 }
 
-impl EventPool {
-    /// Clear the event pool.
-    pub fn clear(&mut self) {
-        self.events.clear();
-        self.rates.clear();
+impl DirichletKernel {
+    /// Create a new Dirichlet kernel with a given concentration.
+    pub fn new(concentration: f64) -> Self {
+        Self { concentration }
     }
+}
+
+impl MutationKernel for DirichletKernel {
+    fn mutate(&self, strat_phe: &Simplex, rng: &mut ChaCha12Rng) -> Result<Simplex> {
+        let weights = strat_phe
+            .iter()
+            .map(|ele| Ok(Gamma::new(1.0 + self.concentration * ele, 1.0)?.sample(rng)))
+            .collect::<Result<_>>()?;
+        Simplex::normalize(weights)
+    }
+
+    fn clone_box(&self) -> Box<dyn MutationKernel> {
+        Box::new(self.clone())
+    }
+}
 
-    /// Add to the pool a new event with its associated rate.
-    pub fn push(&mut self, event: Event, rate: f64) {
-        self.events.push(event);
-        self.rates.push(rate);
+/// Logit-normal kernel: the logarithm of every element of the parent's strategy (floored at the
+/// machine epsilon) is perturbed with a gaussian and the result is mapped back onto the simplex
+/// (softmax), so that the log-ratios between elements perform a gaussian random walk.
+#[derive(Clone)]
+pub struct LogitNormalKernel {
+    /// Distribution of the perturbation of every log-element.
+    ele_mut_dist: Normal<f64>,
+}
+
+impl LogitNormalKernel {
+    /// Create a new logit-normal kernel with a given standard deviation.
+    pub fn new(std_dev: f64) -> Result<Self> {
+        let ele_mut_dist = Normal::new(0.0, std_dev)?;
+        Ok(Self { ele_mut_dist })
     }
+}
 
-    /// Get all events in the pool.
-    pub fn events(&self) -> &[Event] {
-        &self.events
+impl MutationKernel for LogitNormalKernel {
+    fn mutate(&self, strat_phe: &Simplex, rng: &mut ChaCha12Rng) -> Result<Simplex> {
+        let logits: Vec<f64> = strat_phe
+            .iter()
+            .map(|ele| ele.max(f64::EPSILON).ln() + self.ele_mut_dist.sample(rng))
+            .collect();
+        let max_logit = logits.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let weights = logits
+            .iter()
+            .map(|logit| (logit - max_logit).exp())
+            .collect();
+        Simplex::normalize(weights)
     }
 
-    /// Get all rates in the pool.
-    pub fn rates(&self) -> &[f64] {
-        &self.rates
+    fn clone_box(&self) -> Box<dyn MutationKernel> {
+        Box::new(self.clone())
     }
 }
 
+/// Create the mutation kernel selected by the model parameters.
+///
+/// Without an explicit `mutation_kernel`, the gaussian kernel is used if `std_dev_mut` is set and
+/// the uniform kernel otherwise.
+pub fn mutation_kernel(model: &ModelParams) -> Result<Box<dyn MutationKernel>> {
+    let kind = model.mutation_kernel.unwrap_or(match model.std_dev_mut {
+        Some(_) => MutationKernelKind::Gaussian,
+        None => MutationKernelKind::Uniform,
+    });
+    let std_dev_mut = || {
+        model
+            .std_dev_mut
+            .context("missing mutation standard deviation")
+    };
+    Ok(match kind {
+        MutationKernelKind::Uniform => Box::new(UniformKernel),
+        MutationKernelKind::Gaussian => Box::new(GaussianKernel::new(std_dev_mut()?)?),
+        MutationKernelKind::Dirichlet => {
+            let concentration_mut = model
+                .concentration_mut
+                .context("missing mutation concentration")?;
+            Box::new(DirichletKernel::new(concentration_mut))
+        }
+        MutationKernelKind::LogitNormal => Box::new(LogitNormalKernel::new(std_dev_mut()?)?),
+    })
+}
+
+/// Create the mutation kernel of every species (a single one without species).
+fn species_mutation_kernels(cfg: &Config) -> Result<Vec<Box<dyn MutationKernel>>> {
+    (0..cfg.species.len().max(1))
+        .map(|species| {
+            mutation_kernel(&cfg.species_model(species))
+                .with_context(|| format!("failed to create mutation kernel of species {species}"))
+        })
+        .collect()
+}
+
+/// Minimum number of agents per thread when computing event rates and observables in parallel.
+const MIN_AGENTS_PER_THREAD: usize = 4096;
+
+/// Fold every chunk of a slice of agents into a partial result, using up to `n_threads` threads
+/// (with at least `MIN_AGENTS_PER_THREAD` agents each), and return the partial results in order.
+///
+/// With a single thread, the whole slice is folded at once, in the calling thread.
+pub fn fold_agents<T, F>(agents: &[Agent], n_threads: usize, fold: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&[Agent]) -> T + Sync,
+{
+    let n_threads = n_threads.min(agents.len() / MIN_AGENTS_PER_THREAD).max(1);
+    if n_threads == 1 {
+        return vec![fold(agents)];
+    }
+    let chunk_size = agents.len().div_ceil(n_threads);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = agents
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| fold(chunk)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|payload| std::panic::resume_unwind(payload))
+            })
+            .collect()
+    })
+}
+
 /// Simulation engine.
 ///
 /// Holds the configuration, a random number generator and the current step and state.
 /// Provides methods to initialize, run, save, and load simulations.
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Engine {
     /// Simulation configuration parameters.
     cfg: Config,
@@ -64,18 +240,128 @@ pub struct Engine {
     state: State,
     /// Number of extinctions so far.
     n_extinct: usize,
+    /// Event counts since the previous saved observables.
+    event_counts: EventCounts,
+    /// Initial agent data loaded from external sources.
+    init_sources: InitSources,
+    /// Number of neutral marker mutations so far.
+    #[serde(default)]
+    n_marker_mut: usize,
+    /// Id of the next agent added to the lineage log.
+    #[serde(default)]
+    next_agent_id: u64,
+    /// Time of the last environment transition without patches, or of the start of the
+    /// simulation (unknown in checkpoints written by older versions).
+    #[serde(default)]
+    time_env_trans: Option<f64>,
+    /// Event pool kept in sync with the state across steps.
+    #[serde(skip)]
+    event_pool: EventPool,
+    /// Whether the event pool is in sync with the state (otherwise it is rebuilt at the next step).
+    #[serde(skip)]
+    event_pool_synced: bool,
+    /// Death events of every agent, at the position of the agent, with their rates at a number of
+    /// agents equal to the carrying capacity (empty without logistic regulation).
+    #[serde(skip)]
+    death_pool: EventPool,
+    /// Replication events of every agent, at the position of the agent, with their rates at
+    /// unlimited resources (empty without resources).
+    #[serde(skip)]
+    birth_pool: EventPool,
+    /// Events affecting the tracer lineages since they were last taken.
+    #[serde(skip)]
+    tracer_events: Vec<TracerEvent>,
+    /// Lineage log entries since they were last taken.
+    #[serde(skip)]
+    lineage_entries: Vec<LineageEntry>,
+    /// Event log entries since it was started (not logging if unset).
+    #[serde(skip)]
+    event_log: Option<Vec<LoggedEvent>>,
+    /// Population snapshots since they were started (not taken if unset).
+    #[serde(skip)]
+    snapshots: Option<Vec<Snapshot>>,
+    /// Extinctions since they were last taken.
+    #[serde(skip)]
+    extinctions: Vec<Extinction>,
+    /// Number of agents of every species (recounted at the next step if out of date).
+    #[serde(skip)]
+    species_counts: Vec<usize>,
+    /// Number of agents in every patch (recounted at the next step if out of date).
+    #[serde(skip)]
+    patch_counts: Vec<usize>,
+    /// Destination patches and migration rates of every patch (computed at the next step
+    /// if out of date).
+    #[serde(skip)]
+    patch_migration: Vec<Vec<(usize, f64)>>,
+    /// Mutation kernel of every species (a single one without species), built from the
+    /// configuration.
+    #[serde(skip)]
+    mutation_kernels: Vec<Box<dyn MutationKernel>>,
+    /// Number of threads used to compute the event rates and observables (serial if at most 1).
+    #[serde(skip)]
+    n_threads: usize,
+    /// Step-level observables being accumulated.
+    #[serde(skip)]
+    observers: Vec<(ObserverKind, Box<dyn Observable>)>,
+    /// Wall-clock interval between progress reports of the simulation (silent if unset).
+    #[serde(skip)]
+    progress_interval: Option<Duration>,
 }
 
 impl Engine {
     /// Create a new `Engine` with the given configuration and a random initial state.
-    pub fn new(cfg: Config) -> Result<Self> {
-        let mut sys_rng = rand::rngs::SysRng;
-        let mut rng = ChaCha12Rng::try_from_rng(&mut sys_rng)?;
+    ///
+    /// The initial agents (and those generated after every extinction) may use data loaded from
+    /// external sources, as specified in the configuration. If a seed is given, the random number
+    /// generator is seeded from it, making the simulation deterministic.
+    pub fn new(cfg: Config, init_sources: InitSources, seed: Option<u64>) -> Result<Self> {
+        let mut rng = match seed {
+            Some(seed) => ChaCha12Rng::seed_from_u64(seed),
+            None => Engine::seed_rng().context("failed to seed rng")?,
+        };
 
-        let env = rng.random_range(0..cfg.model.n_env);
+        let sample_env = |rng: &mut ChaCha12Rng| match cfg.model.pinned_env.or(cfg.init.env) {
+            Some(env) => env,
+            None => rng.random_range(0..cfg.model.n_env),
+        };
+        let env = match (&init_sources.env_trajectory, &cfg.model.environment) {
+            (Some(env_trajectory), _) => env_trajectory[0].env,
+            (None, Some(environment)) => {
+                let (env, _) = (environment.cycle_visit(0))
+                    .context("environment trajectory is neither periodic nor loaded")?;
+                env
+            }
+            (None, None) => sample_env(&mut rng),
+        };
 
-        let agents = Engine::generate_random_agents(&cfg, &mut rng)
-            .context("failed to generate random agents")?;
+        // Every patch starts in an independent environment.
+        let mut patch_envs = Vec::new();
+        if cfg.model.has_patches() {
+            patch_envs.push(env);
+            for _ in 1..cfg.n_patches() {
+                patch_envs.push(sample_env(&mut rng));
+            }
+        }
+
+        let agents = if cfg.species.is_empty() {
+            Engine::generate_patch_agents(&cfg, &init_sources, &mut rng)
+                .context("failed to generate random agents")?
+        } else {
+            let mut agents = Vec::with_capacity(cfg.total_n_agents());
+            for species in 0..cfg.species.len() {
+                agents.extend(
+                    Engine::generate_species_agents(&cfg, species, &mut rng).with_context(
+                        || format!("failed to generate random agents of species {species}"),
+                    )?,
+                );
+            }
+            agents
+        };
+
+        let event_counts = EventCounts::new(cfg.model.n_phe, cfg.model.n_env, cfg.output.hist_bins);
+        let resources = cfg.model.resources.iter().map(|r| r.capacity).collect();
+        let env_value = cfg.model.continuous_env.as_ref().map(|params| params.mean);
+        let mutation_kernels = species_mutation_kernels(&cfg)?;
 
         Ok(Self {
             cfg,
@@ -85,207 +371,1618 @@ impl Engine {
                 time: 0.0,
                 env,
                 agents,
+                patch_envs,
+                resources,
+                env_value,
             },
             n_extinct: 0,
+            event_counts,
+            init_sources,
+            n_marker_mut: 0,
+            next_agent_id: 0,
+            time_env_trans: Some(0.0),
+            event_pool: EventPool::default(),
+            event_pool_synced: false,
+            death_pool: EventPool::default(),
+            birth_pool: EventPool::default(),
+            tracer_events: Vec::new(),
+            lineage_entries: Vec::new(),
+            event_log: None,
+            snapshots: None,
+            extinctions: Vec::new(),
+            species_counts: Vec::new(),
+            patch_counts: Vec::new(),
+            patch_migration: Vec::new(),
+            mutation_kernels,
+            n_threads: 1,
+            observers: Vec::new(),
+            progress_interval: None,
         })
     }
 
-    /// Perform the simulation and save the simulation observables to a binary file.
-    pub fn perform_simulation<P: AsRef<Path>>(&mut self, file: P) -> Result<()> {
-        let file = file.as_ref();
-        let file = File::create(file).with_context(|| format!("failed to create {file:?}"))?;
-        let mut writer = BufWriter::new(file);
+    /// Derive the seed of a simulation run from a master seed.
+    ///
+    /// The derived seed is the first word of the ChaCha12 stream with id `run_idx` of the random
+    /// number generator seeded from the master seed, so every run gets an independent seed.
+    pub fn derive_seed(master_seed: u64, run_idx: usize) -> u64 {
+        let mut rng = ChaCha12Rng::seed_from_u64(master_seed);
+        rng.set_stream(run_idx as u64);
+        rng.next_u64()
+    }
 
-        let mut event_pool = EventPool::default();
+    /// Fork the engine: keep the current step and state, but use the given configuration and a
+    /// fresh random number generator stream.
+    ///
+    /// The new configuration must keep the number of environments, phenotypes, species and lattice
+    /// patches. If it pins the environment, the current environment (of every patch) is set to the
+    /// pinned one.
+    pub fn fork(mut self, cfg: Config) -> Result<Self> {
+        if cfg.model.n_env != self.cfg.model.n_env
+            || cfg.model.n_phe != self.cfg.model.n_phe
+            || cfg.species.len() != self.cfg.species.len()
+            || cfg.model.has_patches() != self.cfg.model.has_patches()
+            || cfg.n_patches() != self.cfg.n_patches()
+        {
+            bail!(
+                "forked config must keep the number of environments, phenotypes, species and patches"
+            );
+        }
+        if cfg.model.environment != self.cfg.model.environment {
+            bail!("forked config must keep the environment trajectory");
+        }
 
-        for _ in 0..self.cfg.steps_per_file() {
-            let observables = self
-                .perform_step(&mut event_pool)
-                .context("failed to perform step")?;
+        if let Some(pinned_env) = cfg.model.pinned_env {
+            self.state.env = pinned_env;
+            self.state.patch_envs.fill(pinned_env);
+        }
+        if self.state.resources.len() != cfg.model.resources.len() {
+            self.state.resources = cfg.model.resources.iter().map(|r| r.capacity).collect();
+        }
+        if self.state.env_value.is_some() != cfg.model.continuous_env.is_some() {
+            self.state.env_value = cfg.model.continuous_env.as_ref().map(|params| params.mean);
+        }
 
-            if let Some(observables) = observables {
-                encode::write(&mut writer, &observables)
-                    .context("failed to serialize observables")?;
+        let rng = Engine::seed_rng().context("failed to seed rng")?;
+        let mutation_kernels = species_mutation_kernels(&cfg)?;
+
+        Ok(Self {
+            cfg,
+            rng,
+            event_pool_synced: false,
+            patch_migration: Vec::new(),
+            mutation_kernels,
+            ..self
+        })
+    }
+
+    /// Perform the simulation and write the simulation observables to a binary stream.
+    ///
+    /// The simulation stops after `steps_per_file` steps or, if `output.target_time` is set, as soon
+    /// as the simulation time reaches it. It also stops early if an interruption signal is received
+    /// (see `interrupt::requested`).
+    ///
+    /// If `output.flush_every_records` is set, the stream is flushed every that many records and
+    /// then `on_flush` is called with the number of records written so far and the last one.
+    ///
+    /// If a progress interval is set, the progress (step, simulation time, number of agents and,
+    /// once some progress has been made, estimated remaining time) is logged at most once per
+    /// interval, whenever observables are saved.
+    pub fn perform_simulation<W, F>(&mut self, writer: &mut W, mut on_flush: F) -> Result<()>
+    where
+        W: Write,
+        F: FnMut(usize, &Observables) -> Result<()>,
+    {
+        let (first_step, first_time) = (self.step, self.state.time);
+        let last_step = first_step + self.cfg.steps_per_file();
+        let target_time = self.cfg.output.target_time.unwrap_or(f64::INFINITY);
+        let flush_every_records = self.cfg.output.flush_every_records;
+        let progress_interval = self.progress_interval;
+        let start = Instant::now();
+        let mut last_report = start;
+        let mut n_records: usize = 0;
+        self.perform_steps_while(
+            |engine| {
+                engine.step_count() < last_step
+                    && engine.time() < target_time
+                    && interrupt::requested().is_none()
+            },
+            |observables| {
+                if progress_interval.is_some_and(|interval| last_report.elapsed() >= interval) {
+                    last_report = Instant::now();
+                    let step = observables.step.unwrap_or(first_step);
+                    // Whichever of the step and time limits is closer determines the progress.
+                    let progress = f64::max(
+                        (step - first_step) as f64 / (last_step - first_step) as f64,
+                        (observables.time - first_time) / (target_time - first_time),
+                    );
+                    // The remaining time can only be estimated once some progress has been made.
+                    let eta = if progress > 0.0 {
+                        let elapsed = start.elapsed().as_secs_f64();
+                        let remaining = elapsed * (1.0 - progress) / progress;
+                        format!(", ETA {:?}", Duration::from_secs(remaining.round() as u64))
+                    } else {
+                        String::new()
+                    };
+                    log::info!(
+                        "step {step}, time {:.3}, {} agents, {:.1}% done{eta}",
+                        observables.time,
+                        observables.n_agents,
+                        100.0 * progress,
+                    );
+                }
+
+                encode::write(writer, &observables).context("failed to serialize observables")?;
+                n_records += 1;
+                if flush_every_records.is_some_and(|n| n_records.is_multiple_of(n)) {
+                    writer.flush().context("failed to flush writer stream")?;
+                    on_flush(n_records, &observables)?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Perform simulation steps while a condition on the engine holds, passing the simulation
+    /// observables to a callback every time they are saved.
+    pub fn perform_steps_while<C, F>(&mut self, mut cond: C, mut on_save: F) -> Result<()>
+    where
+        C: FnMut(&Engine) -> bool,
+        F: FnMut(Observables) -> Result<()>,
+    {
+        while cond(self) {
+            let outcome = self.step().context("failed to perform step")?;
+
+            if let Some(observables) = outcome.observables {
+                on_save(observables)?;
             }
         }
 
-        writer.flush().context("failed to flush writer stream")?;
+        Ok(())
+    }
 
+    /// Perform a single simulation step.
+    ///
+    /// Returns the performed event, the time elapsed during the step and the simulation
+    /// observables (if saved at this step).
+    pub fn step(&mut self) -> Result<StepOutcome> {
+        self.perform_step(true)
+    }
+
+    /// Perform a given number of simulation steps without calculating any observables.
+    ///
+    /// The resulting engine state is the same as after performing the steps one by one.
+    pub fn fast_forward(&mut self, n_steps: usize) -> Result<()> {
+        for _ in 0..n_steps {
+            self.perform_step(false).context("failed to perform step")?;
+        }
         Ok(())
     }
 
+    /// Perform a single simulation step, optionally calculating the simulation observables when
+    /// they are due.
+    fn perform_step(&mut self, observe: bool) -> Result<StepOutcome> {
+        self.count_species();
+        self.count_patches();
+
+        // Every step of Wright-Fisher dynamics is a whole generation, of unit time.
+        let (mut event, mut time_step) = if self.cfg.model.dynamics == Dynamics::WrightFisher {
+            (Event::Generation, 1.0)
+        } else {
+            self.sample_event()?
+        };
+
+        // Scheduled bottlenecks preempt the sampled event (valid since events are memoryless).
+        if let Some((bottleneck_idx, time)) = self.next_scheduled_bottleneck()
+            && time <= self.state.time + time_step
+        {
+            event = Event::Bottleneck { bottleneck_idx };
+            time_step = time - self.state.time;
+        }
+
+        // So do the switches of a deterministic environment trajectory.
+        if let Some((next_env, time)) = self.next_env_switch()
+            && time <= self.state.time + time_step
+        {
+            event = Event::EnvTrans { next_env };
+            time_step = time - self.state.time;
+        }
+
+        // And so do the updates of a continuous environment, on a regular grid (skipping the grid
+        // time just reached, in case it was reached with a rounding error).
+        if let Some(continuous_env) = &self.cfg.model.continuous_env {
+            let interval = continuous_env.update_interval;
+            let mut time = ((self.state.time / interval).floor() + 1.0) * interval;
+            if time - self.state.time < 1e-9 * interval {
+                time += interval;
+            }
+            if time <= self.state.time + time_step {
+                event = Event::EnvUpdate;
+                time_step = time - self.state.time;
+            }
+        }
+
+        if let Some(event_log) = &mut self.event_log {
+            event_log.push(LoggedEvent { event, time_step });
+        }
+
+        // Update step-level observables with the state held until the event.
+        if observe {
+            for (_, observer) in &mut self.observers {
+                observer.update(&self.state, &event, time_step);
+            }
+        }
+
+        // Observables are saved at the grid times within the step on a uniform time grid, and
+        // otherwise every `steps_per_save` steps.
+        let (save, grid) = match self.cfg.output.save_time_step {
+            Some(save_time_step) => {
+                let first_idx = (self.state.time / save_time_step).ceil();
+                let end_idx = ((self.state.time + time_step) / save_time_step).ceil();
+                let grid = (
+                    first_idx * save_time_step,
+                    (end_idx - first_idx) * save_time_step,
+                );
+                (end_idx > first_idx, Some(grid))
+            }
+            None => (self.step.is_multiple_of(self.cfg.steps_per_save()), None),
+        };
+
+        // Calculate simulation observables (resetting the event counts even if not observed).
+        let observables = save
+            .then(|| {
+                let event_counts = std::mem::replace(
+                    &mut self.event_counts,
+                    EventCounts::new(
+                        self.cfg.model.n_phe,
+                        self.cfg.model.n_env,
+                        self.cfg.output.hist_bins,
+                    ),
+                );
+                observe.then(|| {
+                    let mut observables = calc_observables(
+                        &self.cfg,
+                        &self.state,
+                        &event,
+                        self.step,
+                        time_step,
+                        self.n_extinct,
+                        event_counts,
+                        self.n_threads,
+                    );
+                    if let Some((grid_time, grid_weight)) = grid {
+                        observables.time = grid_time;
+                        observables.grid_weight = Some(grid_weight);
+                    }
+                    observables
+                })
+            })
+            .flatten();
+
+        // Take a population snapshot with the state held until the event.
+        if let Some(snapshots) = &mut self.snapshots
+            && let Some(steps_per_snapshot) = self.cfg.steps_per_snapshot()
+            && self.step.is_multiple_of(steps_per_snapshot)
+        {
+            snapshots.push(Snapshot {
+                step: self.step,
+                time: self.state.time,
+                env: self.state.env,
+                phe: self.state.agents.iter().map(Agent::phe).collect(),
+                strat_phe: self
+                    .state
+                    .agents
+                    .iter()
+                    .map(|agent| agent.strat_phe().clone())
+                    .collect(),
+            });
+        }
+
+        // Keep the state of the species that may go extinct with the event.
+        let extinct_candidates: Vec<usize> = match event {
+            Event::Death { agent_idx } => {
+                let species = self.state.agents[agent_idx].species();
+                (self.species_counts[species] == 1)
+                    .then_some(species)
+                    .into_iter()
+                    .collect()
+            }
+            Event::Bottleneck { .. } => (0..self.species_counts.len()).collect(),
+            _ => Vec::new(),
+        };
+        let prev_states: Vec<(usize, usize, Vec<f64>)> = extinct_candidates
+            .into_iter()
+            .map(|species| {
+                let n_agents = self.species_counts[species];
+                let mut avg_strat_phe = vec![0.0; self.cfg.model.n_phe];
+                let agents = self.state.agents.iter();
+                for agent in agents.filter(|agent| agent.species() == species) {
+                    for (sum, ele) in avg_strat_phe.iter_mut().zip(agent.strat_phe().iter()) {
+                        *sum += ele / n_agents as f64;
+                    }
+                }
+                (species, n_agents, avg_strat_phe)
+            })
+            .collect();
+
+        // Update simulation state.
+        self.supply_resources(time_step);
+        self.state.time += time_step;
+        let n_agents = self.state.agents.len() as f64;
+        match event {
+            Event::EnvTrans { next_env } => {
+                // Every rate may change with the environment.
+                self.count_env_trans(next_env);
+                self.state.env = next_env;
+                self.event_pool_synced = false;
+                self.log_env_trans(None);
+            }
+            Event::PatchEnvTrans { patch, next_env } => {
+                // The rates of the agents in the patch may change with its environment.
+                self.state.patch_envs[patch] = next_env;
+                if patch == 0 {
+                    self.state.env = next_env;
+                }
+                self.event_pool_synced = false;
+                self.event_counts.n_env_trans += 1;
+                self.log_env_trans(Some(patch));
+            }
+            Event::Migration { agent_idx } => {
+                self.migrate_agent(agent_idx)
+                    .context("failed to migrate agent")?;
+                self.event_counts.n_migrations += 1;
+            }
+            Event::EnvUpdate => {
+                // Every rate may change with the environment value.
+                self.update_env_value()
+                    .context("failed to update environment value")?;
+                self.event_pool_synced = false;
+            }
+            Event::Generation => {
+                let log_mean_fitness = self
+                    .advance_generation()
+                    .context("failed to advance generation")?;
+                self.add_log_growth(log_mean_fitness);
+            }
+            Event::Switch { agent_idx } => {
+                self.switch_agent(agent_idx)
+                    .context("failed to switch agent phenotype")?;
+                self.event_counts.n_switches += 1;
+            }
+            Event::Replication { agent_idx } => {
+                self.replicate_agent(agent_idx)
+                    .context("failed to replicate agent")?;
+                let phe = self.state.agents[agent_idx].phe();
+                self.event_counts.n_births += 1;
+                self.event_counts.n_births_phe[phe] += 1;
+                self.consume_resources();
+                if self.cfg.model.dynamics == Dynamics::Moran {
+                    // The offspring replaces a uniformly chosen agent other than itself.
+                    let removed_idx = self.rng.random_range(0..n_agents as usize);
+                    self.remove_dead_agent(removed_idx);
+                } else {
+                    self.add_log_growth(((n_agents + 1.0) / n_agents).ln());
+                }
+            }
+            Event::Death { agent_idx } => {
+                if self.cfg.model.dynamics == Dynamics::Moran {
+                    // The dead agent is replaced by the offspring of a uniformly chosen other agent
+                    // (or of itself, if it is the only one).
+                    let parent_idx = if n_agents == 1.0 {
+                        agent_idx
+                    } else {
+                        let other_idx = self.rng.random_range(0..n_agents as usize - 1);
+                        if other_idx < agent_idx {
+                            other_idx
+                        } else {
+                            other_idx + 1
+                        }
+                    };
+                    self.replicate_agent(parent_idx)
+                        .context("failed to replicate agent")?;
+                    let phe = self.state.agents[parent_idx].phe();
+                    self.event_counts.n_births += 1;
+                    self.event_counts.n_births_phe[phe] += 1;
+                    self.remove_dead_agent(agent_idx);
+                } else {
+                    self.remove_dead_agent(agent_idx);
+                    if n_agents > 1.0 {
+                        self.add_log_growth(((n_agents - 1.0) / n_agents).ln());
+                    }
+                }
+            }
+            Event::Bottleneck { bottleneck_idx } => {
+                // Avoid rounding errors in the time of scheduled bottlenecks, so they happen once.
+                if let Some(time) = self.cfg.model.bottlenecks[bottleneck_idx].time {
+                    self.state.time = time;
+                }
+                let n_survivors = self.apply_bottleneck(bottleneck_idx);
+                self.event_counts.n_bottlenecks += 1;
+                if n_survivors > 0 {
+                    self.add_log_growth((n_survivors as f64 / n_agents).ln());
+                }
+            }
+        }
+
+        // Update number of extinctions so far (of every species).
+        for (species, n_agents, avg_strat_phe) in prev_states {
+            if self.species_counts[species] == 0 {
+                self.n_extinct += 1;
+                self.extinctions.push(Extinction {
+                    step: self.step,
+                    time: self.state.time,
+                    env: self.state.env,
+                    cause: event.event_type(),
+                    n_agents,
+                    avg_strat_phe,
+                    species,
+                });
+            }
+        }
+
+        // Normalize population size.
+        self.normalize_population()
+            .context("failed to normalize population size")?;
+
+        // Increment simulation step.
+        self.step += 1;
+
+        Ok(StepOutcome {
+            event,
+            time_step,
+            observables,
+        })
+    }
+
+    /// Sample the next event and the time until it from the event pool, rebuilding it first if
+    /// it is out of sync with the state.
+    fn sample_event(&mut self) -> Result<(Event, f64)> {
+        // Rebuild the event pool if it is out of sync with the state (otherwise, only update the
+        // transition rates if they follow a schedule).
+        if !self.event_pool_synced {
+            let mut event_pool = std::mem::take(&mut self.event_pool);
+            let mut death_pool = std::mem::take(&mut self.death_pool);
+            let mut birth_pool = std::mem::take(&mut self.birth_pool);
+            self.rebuild_event_pool(&mut event_pool, &mut death_pool, &mut birth_pool);
+            (self.event_pool, self.death_pool) = (event_pool, death_pool);
+            self.birth_pool = birth_pool;
+            self.event_pool_synced = true;
+        } else if !self.cfg.model.trans_schedule.is_empty() {
+            for (idx, (_, rate)) in self.trans_rates().into_iter().enumerate() {
+                self.event_pool.set_rate(idx, rate);
+            }
+        }
+
+        // Select next simulation event.
+        let model = &self.cfg.model;
+        let (event, total_rate) =
+            if model.carrying_capacity.is_some() || !model.resources.is_empty() {
+                // Every death rate is scaled by the number of agents over the carrying capacity, and
+                // every birth rate by the Monod factors of the resources.
+                let crowding = (model.carrying_capacity).map_or(0.0, |carrying_capacity| {
+                    self.state.agents.len() as f64 / carrying_capacity
+                });
+                let death_rate = crowding * self.death_pool.total_rate();
+                let birth_rate = self.resource_factor() * self.birth_pool.total_rate();
+                let total_rate = self.event_pool.total_rate() + death_rate + birth_rate;
+                if !(total_rate.is_finite() && total_rate > 0.0) {
+                    bail!("total rate must be positive and finite, but is {total_rate}");
+                }
+                let rate = self.rng.random_range(0.0..total_rate);
+                let event = if rate < death_rate {
+                    self.death_pool.sample(&mut self.rng)?
+                } else if rate < death_rate + birth_rate {
+                    self.birth_pool.sample(&mut self.rng)?
+                } else {
+                    self.event_pool.sample(&mut self.rng)?
+                };
+                (event, total_rate)
+            } else {
+                let event = self.event_pool.sample(&mut self.rng)?;
+                (event, self.event_pool.total_rate())
+            };
+
+        // Sample time to the next event.
+        let time_step = Exp::new(total_rate)?.sample(&mut self.rng);
+
+        Ok((event, time_step))
+    }
+
     /// Save a checkpoint of the entire engine state.
     ///
     /// Can be used to resume the simulation later.
-    pub fn save_checkpoint<P: AsRef<Path>>(&self, file: P) -> Result<()> {
-        let file = file.as_ref();
-        let file = File::create(file).with_context(|| format!("failed to create {file:?}"))?;
-        let mut writer = BufWriter::new(file);
-        encode::write(&mut writer, &self).context("failed to serialize engine")?;
+    pub fn save_checkpoint<W: Write>(&self, writer: &mut W) -> Result<()> {
+        encode::write(writer, &self).context("failed to serialize engine")?;
         Ok(())
     }
 
     /// Load a previously saved engine checkpoint.
-    pub fn load_checkpoint<P: AsRef<Path>>(file: P) -> Result<Self> {
-        let file = file.as_ref();
-        let file = File::open(file).with_context(|| format!("failed to open {file:?}"))?;
-        let mut reader = BufReader::new(file);
-        let engine = decode::from_read(&mut reader).context("failed to deserialize engine")?;
+    pub fn load_checkpoint<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut engine: Self = decode::from_read(reader).context("failed to deserialize engine")?;
+        engine.mutation_kernels = species_mutation_kernels(&engine.cfg)?;
         Ok(engine)
     }
 
+    /// Get the simulation configuration parameters.
+    pub fn cfg(&self) -> &Config {
+        &self.cfg
+    }
+
+    /// Get the number of steps performed so far.
+    pub fn step_count(&self) -> usize {
+        self.step
+    }
+
+    /// Get the current simulation time.
+    pub fn time(&self) -> f64 {
+        self.state.time
+    }
+
+    /// Get the current simulation state.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Get the number of extinctions so far.
+    pub fn n_extinct(&self) -> usize {
+        self.n_extinct
+    }
+
+    /// Get the random number generator.
+    pub fn rng(&self) -> &ChaCha12Rng {
+        &self.rng
+    }
+
+    /// Set the number of threads used to compute the event rates and observables of large
+    /// populations (experimental).
+    ///
+    /// Events are still sampled serially from the same random number generator, so the
+    /// simulation is the same for any number of threads (up to the floating-point rounding of the
+    /// observables).
+    pub fn set_n_threads(&mut self, n_threads: usize) {
+        self.n_threads = n_threads;
+    }
+
+    /// Set the wall-clock interval between progress reports of the simulation (silent if `None`).
+    pub fn set_progress_interval(&mut self, progress_interval: Option<Duration>) {
+        self.progress_interval = progress_interval;
+    }
+
+    /// Tag a given number of random agents as tracers, replacing the previous ones.
+    ///
+    /// Every tagged agent starts a tracer lineage, inherited by its offspring, whose events are
+    /// logged until taken with `take_tracer_events`.
+    ///
+    /// The agents are drawn from a separate stream of the random number generator, so tagging
+    /// tracers does not change the simulated trajectory.
+    pub fn tag_tracers(&mut self, n_tracers: usize) {
+        for agent in &mut self.state.agents {
+            agent.set_tracer(None);
+        }
+        let mut rng = self.rng.clone();
+        rng.set_stream(TRACER_STREAM);
+        let n_agents = self.state.agents.len();
+        let i_agents = (0..n_agents).sample(&mut rng, n_tracers.min(n_agents));
+        for (tracer, i_agent) in i_agents.into_iter().enumerate() {
+            self.state.agents[i_agent].set_tracer(Some(tracer));
+        }
+    }
+
+    /// Take the events affecting the tracer lineages logged so far.
+    pub fn take_tracer_events(&mut self) -> Vec<TracerEvent> {
+        std::mem::take(&mut self.tracer_events)
+    }
+
+    /// Start the lineage log, assigning a unique id to every agent without one.
+    ///
+    /// While `output.lineage` is set, the birth and death of every agent are logged until taken
+    /// with `take_lineage_entries`.
+    pub fn start_lineage(&mut self) {
+        for agent_idx in 0..self.state.agents.len() {
+            if self.state.agents[agent_idx].id().is_none() {
+                self.assign_agent_id(agent_idx, None);
+            }
+        }
+    }
+
+    /// Take the lineage log entries logged so far.
+    pub fn take_lineage_entries(&mut self) -> Vec<LineageEntry> {
+        std::mem::take(&mut self.lineage_entries)
+    }
+
+    /// Start the event log, recording the event selected at every step and the time elapsed until
+    /// it until taken with `take_event_log`.
+    ///
+    /// The event pool is rebuilt at the next step, as after loading a checkpoint, so that the
+    /// logged events can be reproduced exactly from a checkpoint of the current state.
+    pub fn start_event_log(&mut self) {
+        self.event_log = Some(Vec::new());
+        self.event_pool_synced = false;
+    }
+
+    /// Take the event log entries logged so far, stopping the event log.
+    pub fn take_event_log(&mut self) -> Vec<LoggedEvent> {
+        self.event_log.take().unwrap_or_default()
+    }
+
+    /// Start taking full population snapshots every `steps_per_snapshot` steps (if enabled), until
+    /// taken with `take_snapshots`.
+    pub fn start_snapshots(&mut self) {
+        self.snapshots = Some(Vec::new());
+    }
+
+    /// Take the population snapshots taken so far, stopping them.
+    pub fn take_snapshots(&mut self) -> Vec<Snapshot> {
+        self.snapshots.take().unwrap_or_default()
+    }
+
+    /// Take the extinctions logged so far.
+    pub fn take_extinctions(&mut self) -> Vec<Extinction> {
+        std::mem::take(&mut self.extinctions)
+    }
+
+    /// Start accumulating the step-level observables selected in the configuration from scratch.
+    pub fn start_observers(&mut self) {
+        self.observers = build_observers(&self.cfg);
+    }
+
+    /// Stop accumulating the step-level observables, returning their final values.
+    pub fn finish_observers(&mut self) -> Vec<ObserverResult> {
+        std::mem::take(&mut self.observers)
+            .into_iter()
+            .map(|(kind, observer)| ObserverResult {
+                kind,
+                values: observer.finalize(),
+            })
+            .collect()
+    }
+
+    /// Set the simulation time and step counters, returning the resulting clock shift.
+    pub fn set_clock(&mut self, time: f64, step: usize) -> ClockShift {
+        let shift = ClockShift {
+            from_time: self.state.time,
+            from_step: self.step,
+            to_time: time,
+            to_step: step,
+        };
+        self.state.time = time;
+        self.step = step;
+        shift
+    }
+
+    /// Replace the output format parameters.
+    ///
+    /// These do not affect the simulation dynamics, so they can be changed at any step.
+    pub fn set_output_params(&mut self, output: OutputParams) {
+        self.cfg.output = output;
+    }
+
+    /// Simulate only the environment process for a given number of transitions.
+    ///
+    /// Returns every environment visited, the time spent in it and the next environment (with the
+    /// scheduled transition rates evaluated at the start of every visit).
+    pub fn simulate_env_process(cfg: &Config, n_trans: usize) -> Result<Vec<(usize, f64, usize)>> {
+        let mut rng = Engine::seed_rng().context("failed to seed rng")?;
+
+        let mut env = match cfg.model.pinned_env.or(cfg.init.env) {
+            Some(env) => env,
+            None => rng.random_range(0..cfg.model.n_env),
+        };
+        if cfg.model.pinned_env.is_some() {
+            log::warn!("environment {env} is pinned");
+            return Ok(Vec::new());
+        }
+
+        let mut visits = Vec::with_capacity(n_trans);
+        let mut time = 0.0;
+        for _ in 0..n_trans {
+            let factor = cfg.model.trans_factor(time);
+            let rates: Vec<f64> = cfg.model.rates_trans[env]
+                .iter()
+                .map(|rate| rate.get() * factor)
+                .collect();
+            let Ok(next_env_dist) = WeightedIndex::new(&rates) else {
+                log::warn!("environment {env} is absorbing");
+                break;
+            };
+            let next_env = next_env_dist.sample(&mut rng);
+            let dwell_time = Exp::new(next_env_dist.total_weight())?.sample(&mut rng);
+            visits.push((env, dwell_time, next_env));
+            env = next_env;
+            time += dwell_time;
+        }
+
+        Ok(visits)
+    }
+
+    /// Seed a new random number generator from the system's entropy source.
+    fn seed_rng() -> Result<ChaCha12Rng> {
+        let mut sys_rng = rand::rngs::SysRng;
+        let rng = ChaCha12Rng::try_from_rng(&mut sys_rng)?;
+        Ok(rng)
+    }
+
+    /// Generate random vector of agents of every patch (or of the whole population,
+    /// without patches).
+    fn generate_patch_agents(
+        cfg: &Config,
+        init_sources: &InitSources,
+        rng: &mut ChaCha12Rng,
+    ) -> Result<Vec<Agent>> {
+        let mut agents = Vec::with_capacity(cfg.total_n_agents());
+        for patch in 0..cfg.n_patches() {
+            let n_agents = cfg.patch_n_agents(patch);
+            for mut agent in Engine::generate_random_agents(cfg, init_sources, n_agents, rng)? {
+                agent.set_patch(patch);
+                agents.push(agent);
+            }
+        }
+        Ok(agents)
+    }
+
     /// Generate random vector of agents.
-    fn generate_random_agents(cfg: &Config, rng: &mut ChaCha12Rng) -> Result<Vec<Agent>> {
-        let mut agents = Vec::with_capacity(cfg.init.n_agents);
-        if let Some(strat_phe) = &cfg.init.strat_phe {
+    fn generate_random_agents(
+        cfg: &Config,
+        init_sources: &InitSources,
+        n_agents: usize,
+        rng: &mut ChaCha12Rng,
+    ) -> Result<Vec<Agent>> {
+        let mut agents = Vec::with_capacity(n_agents);
+        if let Some(agent_groups) = &init_sources.agent_groups {
+            for group in agent_groups {
+                for _ in 0..group.count {
+                    agents.push(Agent::new(group.phe, group.strat_phe.clone()));
+                }
+            }
+        } else if let Some(strat_phe) = &cfg.init.strat_phe {
             let phe_dist = WeightedIndex::new(strat_phe)?;
-            for _ in 0..cfg.init.n_agents {
+            for _ in 0..n_agents {
                 let phe = phe_dist.sample(rng);
                 let strat_phe = strat_phe.clone();
                 agents.push(Agent::new(phe, strat_phe));
             }
+        } else if let Some(strat_hists) = &init_sources.strat_hists {
+            let bin_dists = strat_hists
+                .iter()
+                .map(WeightedIndex::new)
+                .collect::<Result<Vec<_>, _>>()?;
+            let [min, max] = init_sources.strat_hist_range.unwrap_or([0.0, 1.0]);
+            for _ in 0..n_agents {
+                // Sample every element but the last one uniformly within a histogram bin.
+                let mut strat_phe: Vec<_> = bin_dists
+                    .iter()
+                    .zip(strat_hists)
+                    .map(|(bin_dist, hist)| {
+                        let bin = bin_dist.sample(rng) as f64;
+                        min + (bin + rng.random_range(0.0..1.0)) / hist.len() as f64 * (max - min)
+                    })
+                    .collect();
+                // If the sampled elements add up to more than one, rescale them instead.
+                let sum: f64 = strat_phe.iter().sum();
+                if sum > 1.0 {
+                    strat_phe.iter_mut().for_each(|ele| *ele /= sum);
+                }
+                strat_phe.push((1.0 - sum).max(0.0));
+                let strat_phe = Simplex::normalize(strat_phe)?;
+                let phe = WeightedIndex::new(&strat_phe)?.sample(rng);
+                agents.push(Agent::new(phe, strat_phe));
+            }
         } else {
-            for _ in 0..cfg.init.n_agents {
+            for _ in 0..n_agents {
                 let phe = rng.random_range(0..cfg.model.n_phe);
-                let mut strat_phe: Vec<_> = (0..cfg.model.n_phe)
+                let strat_phe = (0..cfg.model.n_phe)
                     .map(|_| rng.random_range(0.0..1.0))
                     .collect();
-                let sum: f64 = strat_phe.iter().sum();
-                strat_phe.iter_mut().for_each(|ele| *ele /= sum);
+                let strat_phe = Simplex::normalize(strat_phe)?;
                 agents.push(Agent::new(phe, strat_phe));
             }
         }
+        if let Some(dist_phe) = &cfg.init.dist_phe {
+            // Override the initial phenotypes, independently of the phenotypic strategies.
+            let phe_dist = WeightedIndex::new(dist_phe)?;
+            for agent in &mut agents {
+                agent.set_phe(phe_dist.sample(rng));
+            }
+        }
         Ok(agents)
     }
 
-    /// Perform a single simulation step and optionally return the simulation observables.
-    fn perform_step(&mut self, event_pool: &mut EventPool) -> Result<Option<Observables>> {
-        // Create event distribution.
-        self.update_event_pool(event_pool);
-        let event_dist = WeightedIndex::new(event_pool.rates())?;
+    /// Generate random vector of agents of a species.
+    fn generate_species_agents(
+        cfg: &Config,
+        species: usize,
+        rng: &mut ChaCha12Rng,
+    ) -> Result<Vec<Agent>> {
+        let n_agents = cfg.species_n_agents(species);
+        let strat_phe = cfg.species[species]
+            .strat_phe
+            .as_ref()
+            .or(cfg.init.strat_phe.as_ref());
+        let mut agents = Vec::with_capacity(n_agents);
+        for _ in 0..n_agents {
+            let mut agent = match strat_phe {
+                Some(strat_phe) => {
+                    let phe = WeightedIndex::new(strat_phe)?.sample(rng);
+                    Agent::new(phe, strat_phe.clone())
+                }
+                None => {
+                    let phe = rng.random_range(0..cfg.model.n_phe);
+                    let strat_phe = (0..cfg.model.n_phe)
+                        .map(|_| rng.random_range(0.0..1.0))
+                        .collect();
+                    Agent::new(phe, Simplex::normalize(strat_phe)?)
+                }
+            };
+            agent.set_species(species);
+            agents.push(agent);
+        }
+        if let Some(dist_phe) = &cfg.init.dist_phe {
+            // Override the initial phenotypes, independently of the phenotypic strategies.
+            let phe_dist = WeightedIndex::new(dist_phe)?;
+            for agent in &mut agents {
+                agent.set_phe(phe_dist.sample(rng));
+            }
+        }
+        Ok(agents)
+    }
 
-        // Select next simulation event.
-        let event = &event_pool.events()[event_dist.sample(&mut self.rng)];
+    /// Count the agents of every species, unless they are already counted.
+    fn count_species(&mut self) {
+        let n_species = self.cfg.species.len().max(1);
+        if self.species_counts.len() == n_species {
+            return;
+        }
+        self.species_counts = vec![0; n_species];
+        for agent in &self.state.agents {
+            self.species_counts[agent.species()] += 1;
+        }
+    }
 
-        // Sample time to the next event.
-        let total_rate = event_dist.total_weight();
-        let time_step = Exp::new(total_rate)?.sample(&mut self.rng);
+    /// Count the agents in every patch and list the migration rates of every patch, unless
+    /// they are already up to date.
+    fn count_patches(&mut self) {
+        let n_patches = self.cfg.n_patches();
+        if self.patch_migration.len() != n_patches {
+            self.patch_migration = (0..n_patches)
+                .map(|patch| self.cfg.migration_rates(patch))
+                .collect();
+        }
+        if self.patch_counts.len() == n_patches {
+            return;
+        }
+        self.patch_counts = vec![0; n_patches];
+        for agent in &self.state.agents {
+            self.patch_counts[agent.patch()] += 1;
+        }
+    }
 
-        // Calculate simulation observables.
-        let observables = (self.step % self.cfg.steps_per_save() == 0)
-            .then(|| calc_observables(&self.cfg, &self.state, event, time_step, self.n_extinct));
+    /// Add a change in the logarithm of the population size to the event counts.
+    fn add_log_growth(&mut self, log_growth: f64) {
+        if let Some(sum) = &mut self.event_counts.log_growth {
+            *sum += log_growth;
+        }
+    }
 
-        // Update simulation state.
-        self.state.time += time_step;
-        match *event {
-            Event::EnvTrans { next_env } => {
-                self.state.env = next_env;
+    /// Rebuild the event pool based on the configuration and current state.
+    ///
+    /// The events of every agent follow those of the environment and the bottlenecks, with the
+    /// replication and death events of agent `agent_idx` at positions `2 * agent_idx` and
+    /// `2 * agent_idx + 1` from there, so agents can later be added and removed incrementally. With
+    /// patches, every agent also has a migration event, and with phenotype switching a switching
+    /// event, after the others (`n_agent_events` events per agent). With logistic regulation, the
+    /// death events are kept in the death pool instead, and with resources the replication events
+    /// in the birth pool, with a zero rate in the event pool.
+    fn rebuild_event_pool(
+        &self,
+        event_pool: &mut EventPool,
+        death_pool: &mut EventPool,
+        birth_pool: &mut EventPool,
+    ) {
+        event_pool.reset(self.cfg.model.event_sampler);
+        death_pool.reset(self.cfg.model.event_sampler);
+        birth_pool.reset(self.cfg.model.event_sampler);
+
+        for (event, rate) in self.trans_rates() {
+            event_pool.push(event, rate);
+        }
+
+        for (bottleneck_idx, bottleneck) in self.cfg.model.bottlenecks.iter().enumerate() {
+            if let Some(rate) = bottleneck.rate {
+                event_pool.push(Event::Bottleneck { bottleneck_idx }, rate.get());
             }
-            Event::Replication { agent_idx } => {
-                self.replicate_agent(agent_idx)
-                    .context("failed to replicate agent")?;
+        }
+
+        // Only the rates are computed in parallel, the events are pushed in the same order.
+        let agent_rates = fold_agents(&self.state.agents, self.n_threads, |agents| {
+            agents
+                .iter()
+                .map(|agent| self.agent_rates(agent))
+                .collect::<Vec<_>>()
+        });
+        for (agent_idx, rates) in agent_rates.into_iter().flatten().enumerate() {
+            let pools = (&mut *event_pool, &mut *death_pool, &mut *birth_pool);
+            Engine::push_agent_events(&self.cfg.model, pools, agent_idx, rates);
+        }
+    }
+
+    /// Push the events of an agent to the event, death and birth pools, given their rates (see
+    /// `agent_rates`).
+    fn push_agent_events(
+        model: &ModelParams,
+        (event_pool, death_pool, birth_pool): (&mut EventPool, &mut EventPool, &mut EventPool),
+        agent_idx: usize,
+        rates: (f64, f64, f64, f64),
+    ) {
+        let (rate_birth, rate_death, rate_migration, rate_switch) = rates;
+        if model.resources.is_empty() {
+            event_pool.push(Event::Replication { agent_idx }, rate_birth);
+        } else {
+            event_pool.push(Event::Replication { agent_idx }, 0.0);
+            birth_pool.push(Event::Replication { agent_idx }, rate_birth);
+        }
+        if model.carrying_capacity.is_some() {
+            event_pool.push(Event::Death { agent_idx }, 0.0);
+            death_pool.push(Event::Death { agent_idx }, rate_death);
+        } else {
+            event_pool.push(Event::Death { agent_idx }, rate_death);
+        }
+        if model.has_patches() {
+            event_pool.push(Event::Migration { agent_idx }, rate_migration);
+        }
+        if model.rates_switch.is_some() {
+            event_pool.push(Event::Switch { agent_idx }, rate_switch);
+        }
+    }
+
+    /// Get the number of events of every agent in the event pool.
+    fn n_agent_events(&self) -> usize {
+        let model = &self.cfg.model;
+        2 + usize::from(model.has_patches()) + usize::from(model.rates_switch.is_some())
+    }
+
+    /// Get the transition event to every next environment (of every patch) and its rate
+    /// at the current time (none if the environment is pinned or follows a trajectory).
+    ///
+    /// Scheduled rates are evaluated at the time of every step, so the schedule should vary
+    /// slowly compared to the time between events.
+    fn trans_rates(&self) -> Vec<(Event, f64)> {
+        if self.cfg.model.pinned_env.is_some() || self.cfg.model.environment.is_some() {
+            return Vec::new();
+        }
+        let factor = self.cfg.model.trans_factor(self.state.time);
+        let next_envs = |env: usize| {
+            self.cfg.model.rates_trans[env]
+                .iter()
+                .enumerate()
+                .filter(move |&(next_env, _)| next_env != env)
+                .map(move |(next_env, rate)| (next_env, rate.get() * factor))
+        };
+        if !self.cfg.model.has_patches() {
+            return next_envs(self.state.env)
+                .map(|(next_env, rate)| (Event::EnvTrans { next_env }, rate))
+                .collect();
+        }
+        (self.state.patch_envs.iter().enumerate())
+            .flat_map(|(patch, &env)| {
+                next_envs(env)
+                    .map(move |(next_env, rate)| (Event::PatchEnvTrans { patch, next_env }, rate))
+            })
+            .collect()
+    }
+
+    /// Get the environment of an agent (that of its patch, if there are patches).
+    fn agent_env(&self, agent: &Agent) -> usize {
+        if self.cfg.model.has_patches() {
+            self.state.patch_envs[agent.patch()]
+        } else {
+            self.state.env
+        }
+    }
+
+    /// Get the birth, death, migration and phenotype switching rates of an agent (of its species)
+    /// in the current environment (of its patch).
+    fn agent_rates(&self, agent: &Agent) -> (f64, f64, f64, f64) {
+        let species = agent.species();
+        let env = self.agent_env(agent);
+        let rate_migration = self
+            .patch_migration
+            .get(agent.patch())
+            .map_or(0.0, |rates| rates.iter().map(|&(_, rate)| rate).sum());
+        let rate_switch = self
+            .switch_rates(env, agent.phe())
+            .map(|(_, rate)| rate)
+            .sum();
+        let rates_birth = &self.cfg.species_rates_birth(species)[env];
+        let rates_death = &self.cfg.species_rates_death(species)[env];
+        // With a continuous environment, the rates are functions of its value.
+        let rates = |phe: usize| match (&self.cfg.model.continuous_env, self.state.env_value) {
+            (Some(continuous_env), Some(value)) => (
+                continuous_env.rates_birth[phe].eval(value),
+                continuous_env.rates_death[phe].eval(value),
+            ),
+            _ => (rates_birth[phe].get(), rates_death[phe].get()),
+        };
+        let (rate_birth, rate_death) = match self.cfg.model.expression {
+            Expression::Discrete => rates(agent.phe()),
+            Expression::Mixed => {
+                let (mut rate_birth, mut rate_death) = (0.0, 0.0);
+                for (phe, ele) in agent.strat_phe().iter().enumerate() {
+                    let (phe_rate_birth, phe_rate_death) = rates(phe);
+                    rate_birth += phe_rate_birth * ele;
+                    rate_death += phe_rate_death * ele;
+                }
+                (rate_birth, rate_death)
             }
-            Event::Death { agent_idx } => {
-                self.state.agents.swap_remove(agent_idx);
+        };
+        (rate_birth, rate_death, rate_migration, rate_switch)
+    }
+
+    /// Add an agent at the end of the population, keeping the event pool in sync.
+    fn push_agent(&mut self, agent: Agent) {
+        if self.event_pool_synced {
+            let agent_idx = self.state.agents.len();
+            let rates = self.agent_rates(&agent);
+            let pools = (
+                &mut self.event_pool,
+                &mut self.death_pool,
+                &mut self.birth_pool,
+            );
+            Engine::push_agent_events(&self.cfg.model, pools, agent_idx, rates);
+        }
+        if let Some(count) = self.species_counts.get_mut(agent.species()) {
+            *count += 1;
+        }
+        if let Some(count) = self.patch_counts.get_mut(agent.patch()) {
+            *count += 1;
+        }
+        self.state.agents.push(agent);
+    }
+
+    /// Remove an agent from the population, replacing it with the last agent, and keep the event
+    /// pool in sync.
+    fn swap_remove_agent(&mut self, agent_idx: usize) -> Agent {
+        let n_agents = self.state.agents.len();
+        let agent = self.state.agents.swap_remove(agent_idx);
+        if let Some(count) = self.species_counts.get_mut(agent.species()) {
+            *count -= 1;
+        }
+        if let Some(count) = self.patch_counts.get_mut(agent.patch()) {
+            *count -= 1;
+        }
+        if let Some(id) = agent.id()
+            && self.cfg.output.lineage
+        {
+            let time = self.state.time;
+            self.lineage_entries.push(LineageEntry::Death { id, time });
+        }
+        if self.event_pool_synced {
+            let n_agent_events = self.n_agent_events();
+            let len = self.event_pool.len() - n_agent_events;
+            if agent_idx < n_agents - 1 {
+                let idx = len - n_agent_events * (n_agents - 1 - agent_idx);
+                self.set_agent_rates(idx, agent_idx);
+            }
+            self.event_pool.truncate(len);
+            if self.cfg.model.carrying_capacity.is_some() {
+                self.death_pool.truncate(n_agents - 1);
+            }
+            if !self.cfg.model.resources.is_empty() {
+                self.birth_pool.truncate(n_agents - 1);
             }
         }
+        agent
+    }
 
-        // Update number of extinctions so far.
-        if self.state.agents.len() == 0 {
-            self.n_extinct += 1;
+    /// Advance the continuous environment value over an update interval (of the
+    /// Ornstein-Uhlenbeck process, exactly).
+    fn update_env_value(&mut self) -> Result<()> {
+        let (Some(continuous_env), Some(value)) =
+            (&self.cfg.model.continuous_env, self.state.env_value)
+        else {
+            bail!("environment is not continuous");
+        };
+        let normal = Normal::new(0.0, 1.0)?.sample(&mut self.rng);
+        self.state.env_value = Some(continuous_env.advance(value, normal));
+        Ok(())
+    }
+
+    /// Get the factor multiplying every birth rate: the product of the Monod factors of the
+    /// concentrations of the resources.
+    fn resource_factor(&self) -> f64 {
+        (self.cfg.model.resources.iter())
+            .zip(&self.state.resources)
+            .map(|(params, &conc)| conc / (conc + params.half_saturation))
+            .product()
+    }
+
+    /// Relax the concentration of every resource towards its capacity over a time step.
+    ///
+    /// The birth rates are only updated at every step, so the concentrations should vary slowly
+    /// compared to the time between events.
+    fn supply_resources(&mut self, time_step: f64) {
+        for (params, conc) in (self.cfg.model.resources.iter()).zip(&mut self.state.resources) {
+            *conc = params.capacity
+                - (params.capacity - *conc) * (-params.rate_supply * time_step).exp();
         }
+    }
 
-        // Normalize population size.
-        self.normalize_population()
-            .context("failed to normalize population size")?;
+    /// Deplete every resource by the cost of a birth.
+    fn consume_resources(&mut self) {
+        for (params, conc) in (self.cfg.model.resources.iter()).zip(&mut self.state.resources) {
+            *conc = (*conc - params.cost).max(0.0);
+        }
+    }
 
-        // Increment simulation step.
-        self.step += 1;
+    /// Replace every agent by an offspring of a parent sampled with the fitnesses as weights, and
+    /// then change the environment with the probability of at least one transition over the
+    /// generation, returning the logarithm of the mean fitness of the parents.
+    fn advance_generation(&mut self) -> Result<f64> {
+        let n_agents = self.state.agents.len();
+        let env = self.state.env;
+        let fitnesses: Vec<f64> = (self.state.agents.iter())
+            .map(|agent| self.cfg.fitness(env, agent))
+            .collect();
+        let mean_fitness = fitnesses.iter().sum::<f64>() / n_agents as f64;
+
+        let parent_dist = WeightedIndex::new(&fitnesses)?;
+        for _ in 0..n_agents {
+            let parent_idx = parent_dist.sample(&mut self.rng);
+            self.replicate_agent(parent_idx)?;
+            let phe = self.state.agents[parent_idx].phe();
+            self.event_counts.n_births += 1;
+            self.event_counts.n_births_phe[phe] += 1;
+        }
+        // Removing the parents from the last one keeps every other parent in place.
+        for agent_idx in (0..n_agents).rev() {
+            self.remove_dead_agent(agent_idx);
+        }
+
+        let trans_rates = self.trans_rates();
+        let rate_trans: f64 = trans_rates.iter().map(|&(_, rate)| rate).sum();
+        if rate_trans > 0.0 && self.rng.random_bool(1.0 - (-rate_trans).exp()) {
+            let trans_dist = WeightedIndex::new(trans_rates.iter().map(|&(_, rate)| rate))?;
+            if let Event::EnvTrans { next_env } = trans_rates[trans_dist.sample(&mut self.rng)].0 {
+                self.count_env_trans(next_env);
+                self.state.env = next_env;
+                self.log_env_trans(None);
+            }
+        }
 
-        Ok(observables)
+        Ok(mean_fitness.ln())
     }
 
-    /// Update the event pool based on the configuration and current state.
-    fn update_event_pool(&self, event_pool: &mut EventPool) {
-        event_pool.clear();
+    /// Remove a dead agent from the population, counting its death.
+    fn remove_dead_agent(&mut self, agent_idx: usize) {
+        let agent = self.swap_remove_agent(agent_idx);
+        self.event_counts.n_deaths += 1;
+        self.event_counts.n_deaths_phe[agent.phe()] += 1;
+        let phe = agent.phe();
+        self.log_tracer_event(agent.tracer(), TracerEventKind::Death { phe });
+    }
 
-        for (next_env, &rate) in self.cfg.model.rates_trans[self.state.env]
-            .iter()
+    /// Set the rates of the events of an agent, starting at a given position of the event pool.
+    fn set_agent_rates(&mut self, idx: usize, agent_idx: usize) {
+        let (rate_birth, rate_death, rate_migration, rate_switch) =
+            self.agent_rates(&self.state.agents[agent_idx]);
+        if self.cfg.model.resources.is_empty() {
+            self.event_pool.set_rate(idx, rate_birth);
+        } else {
+            self.birth_pool.set_rate(agent_idx, rate_birth);
+        }
+        if self.cfg.model.carrying_capacity.is_some() {
+            self.death_pool.set_rate(agent_idx, rate_death);
+        } else {
+            self.event_pool.set_rate(idx + 1, rate_death);
+        }
+        let mut idx = idx + 2;
+        if self.cfg.model.has_patches() {
+            self.event_pool.set_rate(idx, rate_migration);
+            idx += 1;
+        }
+        if self.cfg.model.rates_switch.is_some() {
+            self.event_pool.set_rate(idx, rate_switch);
+        }
+    }
+
+    /// Get the switching rate from a phenotype to every other one in an environment (none without
+    /// phenotype switching).
+    fn switch_rates(&self, env: usize, phe: usize) -> impl Iterator<Item = (usize, f64)> {
+        let rates = self
+            .cfg
+            .model
+            .rates_switch
+            .as_ref()
+            .map(|rates| &rates[env][phe]);
+        rates
+            .into_iter()
+            .flatten()
             .enumerate()
-        {
-            if next_env != self.state.env {
-                event_pool.push(Event::EnvTrans { next_env }, rate);
-            }
+            .filter(move |&(next_phe, _)| next_phe != phe)
+            .map(|(next_phe, rate)| (next_phe, rate.get()))
+    }
+
+    /// Switch the phenotype of an agent to a random one, according to the switching rates of its
+    /// phenotype, keeping the event pool in sync.
+    fn switch_agent(&mut self, agent_idx: usize) -> Result<()> {
+        let agent = &self.state.agents[agent_idx];
+        let rates: Vec<(usize, f64)> = self
+            .switch_rates(self.agent_env(agent), agent.phe())
+            .collect();
+        let next_phe_dist = WeightedIndex::new(rates.iter().map(|&(_, rate)| rate))?;
+        let next_phe = rates[next_phe_dist.sample(&mut self.rng)].0;
+
+        self.state.agents[agent_idx].set_phe(next_phe);
+        if self.event_pool_synced {
+            let n_agents = self.state.agents.len();
+            let idx = self.event_pool.len() - self.n_agent_events() * (n_agents - agent_idx);
+            self.set_agent_rates(idx, agent_idx);
         }
+        Ok(())
+    }
 
-        for (agent_idx, agent) in self.state.agents.iter().enumerate() {
-            let phe = agent.phe();
-            event_pool.push(
-                Event::Replication { agent_idx },
-                self.cfg.model.rates_birth[self.state.env][phe],
-            );
-            event_pool.push(
-                Event::Death { agent_idx },
-                self.cfg.model.rates_death[self.state.env][phe],
-            );
+    /// Migrate an agent to a random patch, according to the migration rates of its patch, keeping
+    /// the event pool in sync.
+    fn migrate_agent(&mut self, agent_idx: usize) -> Result<()> {
+        let patch = self.state.agents[agent_idx].patch();
+        let rates = &self.patch_migration[patch];
+        let dest_dist = WeightedIndex::new(rates.iter().map(|&(_, rate)| rate))?;
+        let dest = rates[dest_dist.sample(&mut self.rng)].0;
+
+        self.state.agents[agent_idx].set_patch(dest);
+        self.patch_counts[patch] -= 1;
+        self.patch_counts[dest] += 1;
+        if self.event_pool_synced {
+            let n_agents = self.state.agents.len();
+            let idx = self.event_pool.len() - self.n_agent_events() * (n_agents - agent_idx);
+            self.set_agent_rates(idx, agent_idx);
         }
+        Ok(())
     }
 
     /// Replicate agent: create a new agent with a new phenotype and phenotypic strategy.
     fn replicate_agent(&mut self, agent_idx: usize) -> Result<()> {
         let parent = &self.state.agents[agent_idx];
-        let strat_phe = parent.strat_phe().clone();
+        let n_env = self.cfg.model.n_env;
+        let sensing = self.cfg.model.sensing.as_ref();
+
+        // With sensing, draw a cue, wrong (a uniformly random other environment) with probability
+        // one minus the accuracy of the parent.
+        let cue = match sensing {
+            Some(sensing) => {
+                let env = self.agent_env(parent);
+                let accuracy = parent.accuracy().unwrap_or(sensing.accuracy).get();
+                if n_env == 1 || self.rng.random_bool(accuracy) {
+                    Some(env)
+                } else {
+                    let other_env = self.rng.random_range(0..n_env - 1);
+                    Some(if other_env < env {
+                        other_env
+                    } else {
+                        other_env + 1
+                    })
+                }
+            }
+            None => None,
+        };
+
+        let strat_phe = match cue {
+            Some(cue) => parent.cue_strat(cue).clone(),
+            None => parent.strat_phe().clone(),
+        };
         let phe_dist = WeightedIndex::new(&strat_phe)?;
         let phe_new = phe_dist.sample(&mut self.rng);
         let mut strat_phe_new = strat_phe.clone();
+        let mut cue_strats_new = parent.cue_strats().to_vec();
+        let mut accuracy_new = parent.accuracy();
 
-        if self.rng.random_bool(self.cfg.model.prob_mut) {
-            if let Some(std_dev_mut) = self.cfg.model.std_dev_mut {
-                let ele_mut_dist = Normal::new(0.0, std_dev_mut)?;
-                strat_phe_new = strat_phe_new
-                    .iter()
-                    .map(|ele| (ele + ele_mut_dist.sample(&mut self.rng)).abs())
-                    .collect();
+        let (species, patch) = (parent.species(), parent.patch());
+        let prob_mut = (parent.prob_mut())
+            .unwrap_or(self.cfg.species_prob_mut(species))
+            .get();
+        let mutated = self.rng.random_bool(prob_mut);
+        if mutated {
+            self.event_counts.n_mut += 1;
+            let kernel = &self.mutation_kernels[species];
+            if let (Some(sensing), Some(cue)) = (sensing, cue) {
+                cue_strats_new = (0..n_env)
+                    .map(|cue| kernel.mutate(parent.cue_strat(cue), &mut self.rng))
+                    .collect::<Result<_>>()?;
+                strat_phe_new = cue_strats_new[cue].clone();
+                if let Some(std_dev_accuracy) = sensing.std_dev_accuracy {
+                    let accuracy = parent.accuracy().unwrap_or(sensing.accuracy).get();
+                    let noise = Normal::new(0.0, std_dev_accuracy)?.sample(&mut self.rng);
+                    accuracy_new = Some(Probability::new((accuracy + noise).clamp(0.0, 1.0))?);
+                }
             } else {
-                strat_phe_new = (0..self.cfg.model.n_phe)
-                    .map(|_| self.rng.random_range(0.0..1.0))
-                    .collect();
+                strat_phe_new = kernel.mutate(&strat_phe, &mut self.rng)?;
             }
-            let sum: f64 = strat_phe_new.iter().sum();
-            strat_phe_new.iter_mut().for_each(|ele| *ele /= sum);
         }
 
-        self.state.agents.push(Agent::new(phe_new, strat_phe_new));
+        // An evolvable mutation probability mutates independently of the strategy.
+        let mut prob_mut_new = parent.prob_mut();
+        if let Some(evolvable_mut) = &self.cfg.model.evolvable_mut
+            && self.rng.random_bool(evolvable_mut.prob_mut.get())
+        {
+            let noise = Normal::new(0.0, evolvable_mut.std_dev_log)?.sample(&mut self.rng);
+            prob_mut_new = Some(Probability::new(
+                (prob_mut * 10.0_f64.powf(noise)).min(1.0),
+            )?);
+        }
+
+        let (phe, tracer) = (parent.phe(), parent.tracer());
+        let mut agent_new = Agent::new(phe_new, strat_phe_new);
+        agent_new.set_species(species);
+        agent_new.set_patch(patch);
+        agent_new.set_cue_strats(cue_strats_new);
+        agent_new.set_accuracy(accuracy_new);
+        agent_new.set_prob_mut(prob_mut_new);
+        agent_new.set_marker(parent.marker());
+        agent_new.set_tracer(tracer);
+        agent_new.set_n_mut(parent.n_mut() + usize::from(mutated));
+
+        // Every neutral marker mutation creates a new allele (infinite alleles model).
+        if let Some(prob_mut_marker) = self.cfg.model.prob_mut_marker
+            && self.rng.random_bool(prob_mut_marker.get())
+        {
+            self.n_marker_mut += 1;
+            agent_new.set_marker(self.n_marker_mut);
+        }
+
+        self.push_agent(agent_new);
+        if self.cfg.output.lineage {
+            let parent_id = self.state.agents[agent_idx].id();
+            self.assign_agent_id(self.state.agents.len() - 1, parent_id);
+        }
+
+        let kind = TracerEventKind::Birth {
+            phe,
+            offspring_phe: phe_new,
+            mutated,
+        };
+        self.log_tracer_event(tracer, kind);
 
         Ok(())
     }
 
+    /// Assign the next unique id to an agent, logging its birth from a given parent.
+    fn assign_agent_id(&mut self, agent_idx: usize, parent: Option<u64>) {
+        let id = self.next_agent_id;
+        self.next_agent_id += 1;
+        let agent = &mut self.state.agents[agent_idx];
+        agent.set_id(Some(id));
+        self.lineage_entries.push(LineageEntry::Birth {
+            id,
+            parent,
+            time: self.state.time,
+            phe: agent.phe(),
+            strat_phe: agent.strat_phe().clone(),
+        });
+    }
+
+    /// Count a transition of the environment (without patches) to a given one, along with the
+    /// dwell time it ends.
+    fn count_env_trans(&mut self, next_env: usize) {
+        let env = self.state.env;
+        self.event_counts.n_env_trans += 1;
+        if let Some(env_visits) = &mut self.event_counts.env_visits {
+            env_visits.n_trans[env][next_env] += 1;
+            if let Some(time_env_trans) = self.time_env_trans {
+                let dwell_time = self.state.time - time_env_trans;
+                let moments = &mut env_visits.dwell_moments[env];
+                moments[0] += 1.0;
+                moments[1] += dwell_time;
+                moments[2] += dwell_time * dwell_time;
+                let bin = self.cfg.dwell_time_binning(env).bin(dwell_time);
+                env_visits.dist_dwell_time[env][bin] += 1;
+            }
+        }
+        self.time_env_trans = Some(self.state.time);
+    }
+
+    /// Log an environment transition (of a patch, if given) to every tracer lineage alive
+    /// (in the patch).
+    fn log_env_trans(&mut self, patch: Option<usize>) {
+        if self.cfg.output.n_tracers.is_none() {
+            return;
+        }
+        let mut tracers: Vec<usize> = (self.state.agents.iter())
+            .filter(|agent| patch.is_none_or(|patch| agent.patch() == patch))
+            .filter_map(Agent::tracer)
+            .collect();
+        tracers.sort_unstable();
+        tracers.dedup();
+        for tracer in tracers {
+            self.log_tracer_event(Some(tracer), TracerEventKind::EnvTrans);
+        }
+    }
+
+    /// Log an event affecting an agent, if it belongs to a tracer lineage.
+    fn log_tracer_event(&mut self, tracer: Option<usize>, kind: TracerEventKind) {
+        if let Some(tracer) = tracer {
+            self.tracer_events.push(TracerEvent {
+                tracer,
+                step: self.step,
+                time: self.state.time,
+                env: self.state.env,
+                kind,
+            });
+        }
+    }
+
+    /// Get the index and time of the next scheduled (deterministic) bottleneck, if any.
+    fn next_scheduled_bottleneck(&self) -> Option<(usize, f64)> {
+        self.cfg
+            .model
+            .bottlenecks
+            .iter()
+            .enumerate()
+            .filter_map(|(bottleneck_idx, bottleneck)| Some((bottleneck_idx, bottleneck.time?)))
+            .filter(|&(_, time)| time > self.state.time)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+
+    /// Get the next switch of the deterministic environment trajectory after the current time, as
+    /// the next environment and the time of the switch (none without a trajectory or after its
+    /// last switch).
+    ///
+    /// Switches to the current environment are skipped, in case the time of the last switch was
+    /// reached with a rounding error.
+    fn next_env_switch(&self) -> Option<(usize, f64)> {
+        let environment = self.cfg.model.environment.as_ref()?;
+        let time = self.state.time;
+        if let Some(env_trajectory) = &self.init_sources.env_trajectory {
+            let idx = env_trajectory.partition_point(|switch| switch.time <= time);
+            return (env_trajectory[idx..].iter())
+                .find(|switch| switch.env != self.state.env)
+                .map(|switch| (switch.env, switch.time));
+        }
+
+        // Walk the visits from the start of the current period (over two periods at most).
+        let cycle_len = environment.cycle_len();
+        let period = environment.period();
+        let mut switch_time = (time / period).floor() * period;
+        for visit_idx in 0..2 * cycle_len {
+            switch_time += environment.cycle_visit(visit_idx % cycle_len)?.1;
+            let next_env = environment.cycle_visit((visit_idx + 1) % cycle_len)?.0;
+            if switch_time > time && next_env != self.state.env {
+                return Some((next_env, switch_time));
+            }
+        }
+        None
+    }
+
+    /// Apply a bottleneck: keep only a random subset of agents, returning their number.
+    fn apply_bottleneck(&mut self, bottleneck_idx: usize) -> usize {
+        let bottleneck = &self.cfg.model.bottlenecks[bottleneck_idx];
+        let n_agents = self.state.agents.len();
+        let n_survivors = match (bottleneck.survival, bottleneck.n_survivors) {
+            (_, Some(n_survivors)) => n_survivors,
+            (Some(survival), None) => (survival.get() * n_agents as f64).round() as usize,
+            (None, None) => n_agents,
+        }
+        .min(n_agents);
+
+        // Randomly pick the agents to delete, sorted in reverse to safely remove by index.
+        let mut i_agents_del =
+            index::sample(&mut self.rng, n_agents, n_agents - n_survivors).into_vec();
+        i_agents_del.sort_by(|a, b| b.cmp(a));
+        for i_agent in i_agents_del {
+            let agent = self.swap_remove_agent(i_agent);
+            let phe = agent.phe();
+            self.log_tracer_event(agent.tracer(), TracerEventKind::Removal { phe });
+        }
+
+        n_survivors
+    }
+
     /// Normalize population size.
+    ///
+    /// Extinct species are replaced with new random agents, and the agents in excess of the
+    /// population cap (of every species, unless it is shared, or of every patch) are
+    /// culled at random.
     fn normalize_population(&mut self) -> Result<()> {
         let n_agents = self.state.agents.len();
-        if n_agents == 0 {
+        if self.cfg.species.is_empty() && n_agents == 0 {
             // Extinction: generate a new random vector of agents.
-            self.state.agents = Engine::generate_random_agents(&self.cfg, &mut self.rng)
-                .context("failed to generate random agents")?;
+            self.state.agents =
+                Engine::generate_patch_agents(&self.cfg, &self.init_sources, &mut self.rng)
+                    .context("failed to generate random agents")?;
+            self.species_counts.clear();
+            self.patch_counts.clear();
+            self.event_pool_synced = false;
+            if self.cfg.output.lineage {
+                self.start_lineage();
+            }
+
+            return Ok(());
+        }
+
+        let extinct_species: Vec<usize> = (0..self.cfg.species.len())
+            .filter(|&species| self.species_counts[species] == 0)
+            .collect();
+        if !extinct_species.is_empty() {
+            // Extinction of some species: generate new random agents of each of them.
+            for species in extinct_species {
+                let agents = Engine::generate_species_agents(&self.cfg, species, &mut self.rng)
+                    .with_context(|| {
+                        format!("failed to generate random agents of species {species}")
+                    })?;
+                self.species_counts[species] = agents.len();
+                self.state.agents.extend(agents);
+            }
+            self.patch_counts.clear();
+            self.event_pool_synced = false;
+            if self.cfg.output.lineage {
+                self.start_lineage();
+            }
+
+            return Ok(());
+        }
 
+        if self.cfg.model.carrying_capacity.is_some() {
+            // The number of agents is regulated by the death rates instead.
             return Ok(());
         }
 
-        let diff = n_agents as i32 - self.cfg.init.n_agents as i32;
+        if self.cfg.model.has_patches() {
+            for patch in 0..self.cfg.n_patches() {
+                let cap = self.cfg.patch_n_agents(patch);
+                while self.patch_counts[patch] > cap {
+                    // Pick random agents until one is in the patch (faster than listing them).
+                    let i_agent = loop {
+                        let i_agent = self.rng.random_range(0..self.state.agents.len());
+                        if self.state.agents[i_agent].patch() == patch {
+                            break i_agent;
+                        }
+                    };
+                    self.cull_agent(i_agent);
+                }
+            }
+
+            return Ok(());
+        }
+
+        if !self.cfg.species.is_empty() && !self.cfg.model.shared_cap {
+            for species in 0..self.cfg.species.len() {
+                let cap = self.cfg.species_n_agents(species);
+                while self.species_counts[species] > cap {
+                    // Pick random agents until one is of the species (faster than listing them).
+                    let i_agent = loop {
+                        let i_agent = self.rng.random_range(0..self.state.agents.len());
+                        if self.state.agents[i_agent].species() == species {
+                            break i_agent;
+                        }
+                    };
+                    self.cull_agent(i_agent);
+                }
+            }
+
+            return Ok(());
+        }
+
+        let diff = n_agents as i32 - self.cfg.total_n_agents() as i32;
         if diff > 0 {
             // Too many agents: delete excess agents.
             let excess = diff as usize;
 
             // Randomly pick excess agents to delete.
-            let mut i_agents_del = (0..n_agents).sample(&mut self.rng, excess);
+            let mut i_agents_del = index::sample(&mut self.rng, n_agents, excess).into_vec();
 
             // Sort in reverse to safely remove by index.
             i_agents_del.sort_by(|a, b| b.cmp(a));
             for i_agent in i_agents_del {
-                self.state.agents.swap_remove(i_agent);
+                self.cull_agent(i_agent);
             }
         }
 
         Ok(())
     }
+
+    /// Cull an agent to keep the population size at its cap.
+    fn cull_agent(&mut self, agent_idx: usize) {
+        let agent = self.swap_remove_agent(agent_idx);
+        self.event_counts.n_culls += 1;
+        self.event_counts.n_culls_phe[agent.phe()] += 1;
+        let phe = agent.phe();
+        self.log_tracer_event(agent.tracer(), TracerEventKind::Removal { phe });
+    }
 }
@@ -0,0 +1,35 @@
+//! Graceful handling of interruption signals (SIGINT and SIGTERM).
+
+use std::sync::atomic::{AtomicI32, Ordering::Relaxed};
+
+/// Number of the first interruption signal received (0 if none).
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// Install handlers of SIGINT and SIGTERM that only record the signal, so that long computations
+/// can check `requested` and stop gracefully.
+///
+/// A second signal of the same kind terminates the process immediately. Does nothing on
+/// non-Unix platforms.
+pub fn install_handlers() {
+    #[cfg(unix)]
+    {
+        extern "C" fn handle(signal: libc::c_int) {
+            SIGNAL.compare_exchange(0, signal, Relaxed, Relaxed).ok();
+            // Restoring the default action is async-signal-safe.
+            unsafe { libc::signal(signal, libc::SIG_DFL) };
+        }
+
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            let handler = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            unsafe { libc::signal(signal, handler) };
+        }
+    }
+}
+
+/// Get the first interruption signal received since the handlers were installed (if any).
+pub fn requested() -> Option<i32> {
+    match SIGNAL.load(Relaxed) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
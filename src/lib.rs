@@ -0,0 +1,57 @@
+//! A simple tool to simulate and analyze a stochastic agent-based model of adaptation in uncertain environments.
+//!
+//! Besides the `mutare` command-line interface, the simulation can be embedded in other programs
+//! (e.g. for custom sweeps), driving an [`Engine`] step by step and feeding its observables to an
+//! [`Analyzer`]:
+//!
+//! ```
+//! use mutare::{Analyzer, Config, Engine, types::InitSources};
+//!
+//! let cfg = Config::from_toml(
+//!     r#"
+//!     [model]
+//!     n_env = 2
+//!     n_phe = 2
+//!     rates_trans = [ [ -1.0, 1.0,], [ 1.0, -1.0,],]
+//!     rates_birth = [ [ 1.2, 0.0,], [ 0.0, 0.8,],]
+//!     rates_death = [ [ 0.0, 1.0,], [ 1.0, 0.0,],]
+//!     prob_mut = 0.001
+//!
+//!     [init]
+//!     n_agents = 100
+//!
+//!     [output]
+//!     file_steps_factor = 1024
+//!     save_steps_factor = 16
+//!     hist_bins = 64
+//!     "#,
+//! )?;
+//!
+//! let mut engine = Engine::new(cfg.clone(), InitSources::default(), Some(42))?;
+//! let mut analyzer = Analyzer::new(cfg);
+//! for _ in 0..100_000 {
+//!     if let Some(observables) = engine.step()?.observables {
+//!         analyzer.add_observables(observables);
+//!     }
+//! }
+//!
+//! let analysis = analyzer.calc_analysis()?;
+//! println!("average growth rate: {}", analysis.avg_growth_rate);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod analysis;
+pub mod config;
+pub mod engine;
+pub mod interrupt;
+pub mod manager;
+pub mod observers;
+pub mod sampling;
+pub mod stats;
+pub mod storage;
+pub mod theory;
+pub mod types;
+
+pub use analysis::Analyzer;
+pub use config::Config;
+pub use engine::Engine;
@@ -1,27 +1,32 @@
 //! A simple tool to simulate and analyze a stochastic agent-based model of adaptation in uncertain environments.
 
-mod analysis;
-mod config;
-mod engine;
-mod manager;
-mod types;
-
-use crate::manager::Manager;
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand};
+use mutare::analysis::{RebinMode, TimeWindow};
+use mutare::interrupt;
+use mutare::manager::{CleanFilter, ClockChange, Manager};
+use std::{io::Write, path::PathBuf, time::Duration};
 
 /// Command-line interface for managing, producing and analyzing simulations.
 #[derive(Debug, Parser)]
 #[command(version, about)]
-struct CLI {
-    /// Path to the simulation directory.
+struct Cli {
+    /// Path to the simulation directory (or `s3://bucket/prefix` URL).
     #[arg(long)]
     sim_dir: PathBuf,
 
-    /// Simulation run index.
+    /// Simulation run index (optional for commands acting on all runs).
     #[arg(long)]
-    run_idx: usize,
+    run_idx: Option<usize>,
+
+    /// Number of threads used to compute the event rates and observables of each run
+    /// (experimental, only worth it for very large populations).
+    #[arg(long, default_value_t = 1)]
+    engine_threads: usize,
+
+    /// Log the progress of every resumed run every 10 seconds.
+    #[arg(long)]
+    progress: bool,
 
     /// Simulation command.
     #[command(subcommand)]
@@ -32,46 +37,478 @@ struct CLI {
 #[derive(Debug, Subcommand)]
 enum SimCmd {
     /// Create simulation run.
-    Create,
+    Create {
+        /// Seed of the random number generator (for deterministic runs).
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Override a parameter of the new run (e.g. `model.prob_mut=0.01`).
+        #[arg(long = "set", value_name = "KEY=VALUE")]
+        overrides: Vec<String>,
+    },
 
     /// Resume simulation run.
-    Resume,
+    Resume {
+        /// Override an output parameter for this output file only (e.g. `output.hist_bins=32`).
+        #[arg(long = "set", value_name = "KEY=VALUE")]
+        overrides: Vec<String>,
+
+        /// Resume every simulation run in parallel (instead of the one given by `--run-idx`).
+        #[arg(long)]
+        all: bool,
+
+        /// Number of threads used to resume every simulation run (as many as available CPUs by
+        /// default).
+        #[arg(long, requires = "all")]
+        threads: Option<usize>,
+
+        /// Rewind the run to the checkpoint of its history taken before a given output file,
+        /// removing that output file and every later one.
+        #[arg(long, value_name = "FILE_IDX", conflicts_with = "all")]
+        from_checkpoint: Option<usize>,
+
+        /// Number of consecutive output files to generate, checkpointing after every one (at most
+        /// that many if stopping criteria are set).
+        #[arg(long)]
+        n_files: Option<usize>,
+    },
+
+    /// Perform one-shot simulation run, saving only its analysis (and, optionally, its checkpoint).
+    Simulate {
+        /// Number of simulation steps.
+        #[arg(long, required_unless_present = "max_time")]
+        n_steps: Option<usize>,
+
+        /// Maximum simulation time.
+        #[arg(long)]
+        max_time: Option<f64>,
+
+        /// Also save the final checkpoint.
+        #[arg(long)]
+        checkpoint: bool,
+    },
+
+    /// Fork simulation run into a new run with a fresh random number generator stream.
+    Fork {
+        /// Index of the new simulation run.
+        #[arg(long)]
+        new_run_idx: usize,
+
+        /// Override a parameter of the new run (e.g. `model.prob_mut=0.01`).
+        #[arg(long = "set", value_name = "KEY=VALUE")]
+        overrides: Vec<String>,
+
+        /// Simulation clock of the new run.
+        #[command(flatten)]
+        clock: ClockArgs,
+    },
+
+    /// Create simulation run at a given step of a deterministic run, without writing any output.
+    FastForward {
+        /// Seed of the random number generator of the deterministic run.
+        #[arg(long)]
+        seed: u64,
+
+        /// Number of steps to perform.
+        #[arg(long)]
+        steps: usize,
+    },
+
+    /// Replay the event log of an output file from the checkpoint taken before it, failing at the
+    /// first step diverging from the log.
+    Replay {
+        /// Index of the output file whose event log is replayed.
+        #[arg(long)]
+        file_idx: usize,
+
+        /// Number of steps to replay (the whole event log by default).
+        #[arg(long)]
+        n_steps: Option<usize>,
+
+        /// Save the replayed engine as the checkpoint of a new simulation run.
+        #[arg(long)]
+        new_run_idx: Option<usize>,
+    },
+
+    /// Simulate the run population in several independent environment realizations
+    /// (common-garden mode) and analyze their between-realization variability.
+    Garden {
+        /// Number of environment realizations.
+        #[arg(long)]
+        n_realizations: usize,
+
+        /// Number of steps simulated in every realization.
+        #[arg(long)]
+        n_steps: usize,
+    },
+
+    /// Simulate only the environment process and report its empirical statistics.
+    CalibrateEnv {
+        /// Number of environment transitions to simulate.
+        #[arg(long, default_value_t = 100_000)]
+        n_trans: usize,
+    },
+
+    /// Analyze the logged extinctions of a simulation run (`analysis-extinctions.msgpack` in its
+    /// directory).
+    Extinctions {
+        /// Also save the analysis as JSON (`analysis-extinctions.json`).
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Compute the theoretically optimal phenotypic strategy of the model (`optimal.msgpack` in
+    /// the simulation directory).
+    Optimal {
+        /// Number of environment transitions of the simulated trajectory.
+        #[arg(long, default_value_t = 10_000)]
+        n_trans: usize,
+
+        /// Number of divisions of the grid of strategies searched before refining the best one.
+        #[arg(long, default_value_t = 20)]
+        resolution: usize,
+
+        /// Also save the optimal strategy as pretty-printed JSON (`optimal.json`).
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Report the differences between the checkpoints of two simulation runs.
+    DiffCheckpoints {
+        /// Path to the simulation directory of the other run (defaults to the same one).
+        #[arg(long)]
+        other_sim_dir: Option<PathBuf>,
+
+        /// Index of the other simulation run.
+        #[arg(long)]
+        other_run_idx: usize,
+    },
+
+    /// Export simulation run checkpoint into a portable bundle file.
+    ExportCheckpoint {
+        /// Path to the bundle file.
+        #[arg(long)]
+        file: PathBuf,
+    },
+
+    /// Import simulation run checkpoint from a portable bundle file.
+    ImportCheckpoint {
+        /// Path to the bundle file.
+        #[arg(long)]
+        file: PathBuf,
+
+        /// Simulation clock of the new run.
+        #[command(flatten)]
+        clock: ClockArgs,
+    },
 
     /// Analyze simulation run.
-    Analyze,
+    Analyze {
+        /// Also save the analysis as pretty-printed JSON (`analysis.json`).
+        #[arg(long)]
+        json: bool,
+
+        /// Minimum simulation time of the analyzed observables.
+        #[arg(long, allow_negative_numbers = true)]
+        t_min: Option<f64>,
+
+        /// Maximum simulation time of the analyzed observables.
+        #[arg(long, allow_negative_numbers = true)]
+        t_max: Option<f64>,
+
+        /// Detect and discard the initial transient of the observables.
+        #[arg(long)]
+        burn_in: bool,
+    },
+
+    /// Aggregate the analyses of every run into a simulation-level analysis (`analysis.msgpack` in
+    /// the simulation directory).
+    Aggregate {
+        /// Also save the aggregate analysis as pretty-printed JSON (`analysis.json`).
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Collect the analyses and configuration parameters of every analyzed run (of this and other
+    /// simulation directories) into a single CSV results table (`results.csv` in the simulation
+    /// directory).
+    Collect {
+        /// Path to another simulation directory whose runs are also collected.
+        #[arg(long = "other-sim-dir", value_name = "SIM_DIR")]
+        other_sim_dirs: Vec<PathBuf>,
+    },
+
+    /// Export the output files of the simulation run into a CSV table (`observables.csv`).
+    Export,
+
+    /// Rebin the output files of the simulation run into coarser-grained versions, in place.
+    Rebin {
+        /// Number of consecutive observables merged into one.
+        #[arg(long)]
+        factor: usize,
+
+        /// Rebinning mode.
+        #[arg(long, value_enum, default_value_t = RebinMode::Sample)]
+        mode: RebinMode,
+    },
+
+    /// Concatenate groups of consecutive output files of the simulation run into larger ones.
+    ConcatOutputs {
+        /// Number of consecutive output files concatenated into one.
+        #[arg(long)]
+        group_size: usize,
+    },
+
+    /// Report the status of the simulation run (or all runs).
+    Status {
+        /// Also report storage consumption.
+        #[arg(long)]
+        disk: bool,
+    },
+
+    /// Write a fully commented example configuration (`config.toml`) into the simulation
+    /// directory.
+    Init {
+        /// Replace an existing configuration.
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Validate the simulation configuration, reporting every problem found without creating
+    /// anything.
+    Validate,
+
+    /// Prune old output files of the simulation run (or all runs) according to the retention policy.
+    Prune,
+
+    /// Remove files of the simulation run (or all runs).
+    Clean {
+        /// Only remove output (and tracer, step-level observable and lineage) files.
+        #[arg(long, conflicts_with = "analysis_only")]
+        outputs_only: bool,
+
+        /// Only remove analysis files.
+        #[arg(long)]
+        analysis_only: bool,
+
+        /// Keep analysis files.
+        #[arg(long, conflicts_with = "analysis_only")]
+        keep_analysis: bool,
+
+        /// Keep checkpoint files (with their metadata and history).
+        #[arg(long)]
+        keep_checkpoint: bool,
+
+        /// Only remove output files already added to the analysis of their run (keeping every
+        /// other file).
+        #[arg(long, conflicts_with = "analysis_only")]
+        analyzed_outputs: bool,
+
+        /// Only remove files older than a given age (e.g. `30m`, `12h`, `7d`).
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        older_than: Option<Duration>,
+
+        /// List the files that would be removed without removing them.
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 /// Entry point of the application.
 fn main() {
     // Initialize logging with millisecond timestamps and INFO level by default.
+    // Messages logged from named threads (e.g. resuming a run) are prefixed with their name.
     env_logger::Builder::new()
-        .format_timestamp_millis()
+        .format(|buf, record| {
+            let prefix = std::thread::current()
+                .name()
+                .filter(|&name| name != "main")
+                .map(|name| format!("{name}: "))
+                .unwrap_or_default();
+            writeln!(
+                buf,
+                "[{} {:<5} {}] {prefix}{}",
+                buf.timestamp_millis(),
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        })
         .filter_level(log::LevelFilter::Info)
         .parse_default_env()
         .init();
 
-    // Run the CLI and exit with error code 1 if any error occurs.
+    // Run the CLI and exit with error code 1 if any error occurs (or 128 plus the signal number
+    // if interrupted by a signal).
     if let Err(error) = run_cli() {
         log::error!("{error:#?}");
-        std::process::exit(1);
+        std::process::exit(interrupt::requested().map_or(1, |signal| 128 + signal));
+    }
+}
+
+/// Simulation clock arguments of a new run.
+#[derive(Debug, Args)]
+struct ClockArgs {
+    /// Reset the simulation time and step counters to zero.
+    #[arg(long, conflicts_with_all = ["time_offset", "step_offset"])]
+    reset_clock: bool,
+
+    /// Offset the simulation time by a given amount.
+    #[arg(long, allow_negative_numbers = true)]
+    time_offset: Option<f64>,
+
+    /// Offset the simulation step counter by a given amount.
+    #[arg(long, allow_negative_numbers = true)]
+    step_offset: Option<isize>,
+}
+
+impl ClockArgs {
+    /// Get the requested clock change (if any).
+    fn clock_change(&self) -> Option<ClockChange> {
+        if self.reset_clock {
+            Some(ClockChange::Reset)
+        } else if self.time_offset.is_some() || self.step_offset.is_some() {
+            Some(ClockChange::Offset {
+                time: self.time_offset.unwrap_or(0.0),
+                step: self.step_offset.unwrap_or(0),
+            })
+        } else {
+            None
+        }
     }
 }
 
 /// Parse CLI and execute the requested simulation command.
 fn run_cli() -> Result<()> {
     // Parse command-line interface.
-    let cli = CLI::parse();
+    let cli = Cli::parse();
     log::info!("{cli:#?}");
 
+    // Write or validate the configuration before a manager (which requires a valid one) is
+    // created.
+    match cli.sim_cmd {
+        SimCmd::Init { force } => return Manager::init_config(cli.sim_dir, force),
+        SimCmd::Validate => return Manager::validate_config(cli.sim_dir),
+        _ => {}
+    }
+
     // Create a manager for the specified simulation directory.
-    let mgr = Manager::new(cli.sim_dir).context("failed to create mgr")?;
+    let mut mgr = Manager::new(cli.sim_dir).context("failed to create mgr")?;
+    mgr.set_engine_threads(cli.engine_threads);
+    mgr.set_progress_interval(cli.progress.then_some(Duration::from_secs(10)));
+
+    // Interrupted resumes save their checkpoint before exiting.
+    if let SimCmd::Resume { .. } = cli.sim_cmd {
+        interrupt::install_handlers();
+    }
 
     // Execute the requested simulation command.
+    let run_idx = || cli.run_idx.context("this command requires --run-idx");
     match cli.sim_cmd {
-        SimCmd::Create => mgr.create_run(cli.run_idx)?,
-        SimCmd::Resume => mgr.resume_run(cli.run_idx)?,
-        SimCmd::Analyze => mgr.analyze_run(cli.run_idx)?,
+        SimCmd::Create { seed, overrides } => mgr.create_run(run_idx()?, seed, &overrides)?,
+        SimCmd::Resume {
+            overrides,
+            all: true,
+            threads,
+            n_files,
+            ..
+        } => {
+            if cli.run_idx.is_some() {
+                anyhow::bail!("--all can not be used together with --run-idx");
+            }
+            mgr.resume_all(&overrides, threads, n_files)?
+        }
+        SimCmd::Resume {
+            overrides,
+            from_checkpoint,
+            n_files,
+            ..
+        } => mgr.resume_run(run_idx()?, &overrides, from_checkpoint, n_files)?,
+        SimCmd::Simulate {
+            n_steps,
+            max_time,
+            checkpoint,
+        } => mgr.simulate_run(run_idx()?, n_steps, max_time, checkpoint)?,
+        SimCmd::Fork {
+            new_run_idx,
+            overrides,
+            clock,
+        } => mgr.fork_run(run_idx()?, new_run_idx, &overrides, clock.clock_change())?,
+        SimCmd::FastForward { seed, steps } => mgr.fast_forward(run_idx()?, seed, steps)?,
+        SimCmd::Replay {
+            file_idx,
+            n_steps,
+            new_run_idx,
+        } => mgr.replay_run(run_idx()?, file_idx, n_steps, new_run_idx)?,
+        SimCmd::Garden {
+            n_realizations,
+            n_steps,
+        } => mgr.simulate_garden(run_idx()?, n_realizations, n_steps)?,
+        SimCmd::CalibrateEnv { n_trans } => mgr.calibrate_env(n_trans)?,
+        SimCmd::Extinctions { json } => mgr.analyze_extinctions(run_idx()?, json)?,
+        SimCmd::Optimal {
+            n_trans,
+            resolution,
+            json,
+        } => mgr.optimal_strategy(n_trans, resolution, json)?,
+        SimCmd::DiffCheckpoints {
+            other_sim_dir,
+            other_run_idx,
+        } => mgr.diff_checkpoints(run_idx()?, other_sim_dir, other_run_idx)?,
+        SimCmd::ExportCheckpoint { file } => mgr.export_checkpoint(run_idx()?, file)?,
+        SimCmd::ImportCheckpoint { file, clock } => {
+            mgr.import_checkpoint(run_idx()?, file, clock.clock_change())?
+        }
+        SimCmd::Analyze {
+            json,
+            t_min,
+            t_max,
+            burn_in,
+        } => mgr.analyze_run(run_idx()?, json, TimeWindow { t_min, t_max }, burn_in)?,
+        SimCmd::Aggregate { json } => mgr.aggregate_runs(json)?,
+        SimCmd::Collect { other_sim_dirs } => mgr.collect_results(&other_sim_dirs)?,
+        SimCmd::Export => mgr.export_run(run_idx()?)?,
+        SimCmd::Rebin { factor, mode } => mgr.rebin_run(run_idx()?, factor, mode)?,
+        SimCmd::ConcatOutputs { group_size } => mgr.concat_outputs(run_idx()?, group_size)?,
+        SimCmd::Status { disk } => mgr.report_status(cli.run_idx, disk)?,
+        SimCmd::Init { .. } | SimCmd::Validate => {
+            unreachable!("init and validate are handled before creating the manager")
+        }
+        SimCmd::Prune => mgr.prune(cli.run_idx)?,
+        SimCmd::Clean {
+            outputs_only,
+            analysis_only,
+            keep_analysis,
+            keep_checkpoint,
+            analyzed_outputs,
+            older_than,
+            dry_run,
+        } => {
+            let filter = CleanFilter {
+                outputs_only,
+                analysis_only,
+                keep_analysis,
+                keep_checkpoint,
+                analyzed_outputs,
+                older_than,
+            };
+            mgr.clean(cli.run_idx, filter, dry_run)?
+        }
     }
 
     Ok(())
 }
+
+/// Parse a duration given as a number with an optional unit (`s`, `m`, `h` or `d`).
+fn parse_duration(arg: &str) -> Result<Duration> {
+    let (value, unit) = arg.split_at(arg.find(|c: char| c.is_alphabetic()).unwrap_or(arg.len()));
+    let value: f64 = value.parse().context("invalid duration value")?;
+    let unit_secs = match unit {
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3_600.0,
+        "d" => 86_400.0,
+        _ => anyhow::bail!("invalid duration unit {unit:?}"),
+    };
+    Duration::try_from_secs_f64(value * unit_secs).context("invalid duration")
+}
@@ -1,23 +1,270 @@
 //! Simulation manager.
 
-use crate::analysis::Analyzer;
-use crate::config::Config;
+use crate::analysis::{
+    AggregateAnalysis, Analysis, Analyzer, CsvLayout, EnvCalibration, ExtinctionAnalysis,
+    GardenAnalysis, RebinMode, TimeWindow, read_flushed_observables, read_observables,
+    rebin_observables, write_csv_header, write_csv_rows, write_observables,
+};
+use crate::config::{
+    CONFIG_TEMPLATE, Config, ConvergenceObservable, ConvergenceParams, OutputParams,
+    parse_agent_groups, parse_env_trajectory,
+};
 use crate::engine::Engine;
-use anyhow::{Context, Result};
+use crate::interrupt;
+use crate::stats::mser_truncation;
+use crate::storage::{Storage, open_storage};
+use crate::theory::optimal_strategy;
+use crate::types::{
+    ClockShift, EnvSwitch, Extinction, InitSources, LineageEntry, LoggedEvent, Snapshot,
+};
+use anyhow::{Context, Result, bail};
+use rmp_serde::{decode, encode};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use std::{
-    fs,
-    path::{Path, PathBuf},
-    time::Instant,
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+/// Current version of the checkpoint bundle format.
+const BUNDLE_VERSION: u32 = 1;
+
+/// Metadata of a checkpoint bundle.
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleMetadata {
+    /// Version of the bundle format.
+    bundle_version: u32,
+    /// Version of `mutare` that exported the bundle.
+    mutare_version: String,
+    /// Location of the original simulation directory.
+    sim_dir: String,
+    /// Original simulation run index.
+    run_idx: usize,
+    /// Export time (seconds since the Unix epoch).
+    exported_at: u64,
+    /// Shift of the simulation clock of the original run (if any).
+    #[serde(default)]
+    clock_shift: Option<ClockShift>,
+}
+
+/// Progress of an output file being written, stored next to it until it is complete.
+///
+/// Bounds the data lost if the simulation is interrupted: only the records written after the
+/// last flush are lost.
+#[derive(Debug, Serialize, Deserialize)]
+struct FlushMetadata {
+    /// Number of records flushed to the output file.
+    n_records: usize,
+    /// Simulation step of the last flushed record.
+    step: Option<usize>,
+    /// Simulation time of the last flushed record.
+    time: f64,
+}
+
+/// Event log of an output file, stored next to it to replay its simulation.
+#[derive(Serialize, Deserialize)]
+struct EventLog {
+    /// Output parameters used to write the output file (tracers consume random numbers).
+    output: OutputParams,
+    /// Simulation step at the start of the output file.
+    first_step: usize,
+    /// Event selected at every step and time elapsed until it.
+    events: Vec<LoggedEvent>,
+}
+
+/// Metadata of an engine checkpoint, stored next to it to detect stale or foreign checkpoints.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct CheckpointMetadata {
+    /// Simulation step of the checkpoint.
+    step: usize,
+    /// Stream id of the random number generator.
+    rng_stream: u64,
+    /// Word position of the random number generator.
+    rng_word_pos: u128,
+    /// Digest (64-bit FNV-1a hash) of the checkpoint file contents.
+    digest: u64,
+}
+
+impl CheckpointMetadata {
+    /// Create the metadata of an engine checkpoint from its file contents.
+    fn new(engine: &Engine, contents: &[u8]) -> Self {
+        Self {
+            step: engine.step_count(),
+            rng_stream: engine.rng().get_stream(),
+            rng_word_pos: engine.rng().get_word_pos(),
+            digest: digest(contents),
+        }
+    }
+}
+
+/// Provenance manifest of a simulation run (`metadata.toml`), updated on creation and after every
+/// checkpoint of a resume.
+#[derive(Debug, Deserialize)]
+struct RunManifest {
+    /// Version of `mutare` that last updated the run.
+    mutare_version: String,
+    /// Digest (64-bit FNV-1a hash) of the effective configuration of the run, in hexadecimal.
+    config_digest: String,
+    /// Creation time (seconds since the Unix epoch), unknown for runs not created by `create`.
+    #[serde(default)]
+    created_at: Option<u64>,
+    /// Cumulative wall-clock time spent resuming the run (seconds).
+    wall_time: f64,
+    /// Total number of simulation steps.
+    n_steps: usize,
+    /// Simulation time reached.
+    time: f64,
+}
+
+impl RunManifest {
+    /// Format the manifest as TOML.
+    fn to_toml(&self) -> String {
+        let mut toml = format!("mutare_version = {:?}\n", self.mutare_version)
+            + &format!("config_digest = {:?}\n", self.config_digest);
+        if let Some(created_at) = self.created_at {
+            toml += &format!("created_at = {created_at}\n");
+        }
+        toml + &format!("wall_time = {:?}\n", self.wall_time)
+            + &format!("n_steps = {}\n", self.n_steps)
+            + &format!("time = {:?}\n", self.time)
+    }
+}
+
+/// Advisory lock of a simulation run (`resume.lock`), held while resuming it so that concurrent
+/// resumes of the same run fail fast.
+#[derive(Debug, Serialize, Deserialize)]
+struct RunLock {
+    /// Host name of the process holding the lock.
+    host: String,
+    /// Id of the process holding the lock.
+    pid: u32,
+    /// Lock time (seconds since the Unix epoch).
+    locked_at: u64,
+}
+
+impl RunLock {
+    /// Check whether the process holding the lock is known to have exited (only processes of the
+    /// same host can be checked).
+    fn is_stale(&self) -> bool {
+        self.host == host_name() && !process_exists(self.pid)
+    }
+}
+
+/// Guard of a run lock, releasing it when dropped.
+struct RunLockGuard<'a> {
+    /// Storage backend of the simulation directory.
+    storage: &'a dyn Storage,
+    /// Key of the lock file.
+    lock_file: String,
+}
+
+impl Drop for RunLockGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(error) = self.storage.remove(&self.lock_file) {
+            log::warn!("failed to release lock: {error:#}");
+        }
+    }
+}
+
+/// Seed of a simulation run, stored in its directory to regenerate it exactly.
+#[derive(Debug, Serialize, Deserialize)]
+struct SeedMetadata {
+    /// Seed of the random number generator (as given to `create --seed`).
+    seed: u64,
+    /// Master seed the seed was derived from (if any).
+    master_seed: Option<u64>,
+}
+
+/// Partial analysis of a simulation run, stored to resume the analysis when new output files
+/// arrive instead of recomputing it from the first one.
+#[derive(Serialize, Deserialize)]
+struct PartialAnalysis {
+    /// Number of output files already added to the analyzer.
+    n_files: usize,
+    /// Analyzer with the observables of those output files.
+    analyzer: Analyzer,
+}
+
+/// Convergence record of a simulation run, storing the estimates of the convergence observable
+/// in every output file (so that they survive pruning) and the result of the last check.
+#[derive(Debug, Serialize, Deserialize)]
+struct ConvergenceRecord {
+    /// Observable of the estimates.
+    observable: ConvergenceObservable,
+    /// Estimates of the observable in every output file, by file index.
+    estimates: BTreeMap<usize, f64>,
+    /// Whether the running estimate had converged at the last check.
+    converged: bool,
+}
+
+impl ConvergenceRecord {
+    /// Calculate the running estimate (the average of the estimates) and its standard error.
+    fn running_estimate(&self) -> (f64, f64) {
+        let n = self.estimates.len() as f64;
+        let mean = self.estimates.values().sum::<f64>() / n;
+        let var = self
+            .estimates
+            .values()
+            .map(|estimate| (estimate - mean).powi(2))
+            .sum::<f64>()
+            / (n - 1.0);
+        (mean, (var / n).sqrt())
+    }
+}
+
+/// Self-contained checkpoint bundle.
+///
+/// Holds the entire engine state (including its configuration snapshot) and some metadata.
+#[derive(Serialize, Deserialize)]
+struct CheckpointBundle {
+    /// Bundle metadata.
+    metadata: BundleMetadata,
+    /// Simulation engine.
+    engine: Engine,
+}
+
+/// Change of the simulation clock (time and step counters) of a new run.
+#[derive(Debug, Clone, Copy)]
+pub enum ClockChange {
+    /// Reset the time and step counters to zero.
+    Reset,
+    /// Offset the time and step counters by the given amounts.
+    Offset { time: f64, step: isize },
+}
+
+/// Selection of the files removed by `Manager::clean`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CleanFilter {
+    /// Only remove output (and tracer, step-level observable and lineage) files.
+    pub outputs_only: bool,
+    /// Only remove analysis files.
+    pub analysis_only: bool,
+    /// Keep analysis files.
+    pub keep_analysis: bool,
+    /// Keep checkpoint files (with their metadata and history).
+    pub keep_checkpoint: bool,
+    /// Only remove output files already added to the partial analysis of their run.
+    pub analyzed_outputs: bool,
+    /// Only remove files older than a given age.
+    pub older_than: Option<Duration>,
+}
+
 /// Simulation manager.
 ///
 /// Manages the production and analysis of simulation runs.
 pub struct Manager {
-    /// Path to the simulation directory.
-    sim_dir: PathBuf,
+    /// Storage backend of the simulation directory.
+    storage: Box<dyn Storage>,
     /// Simulation configuration parameters.
     cfg: Config,
+    /// Number of threads used by each engine to compute event rates and observables.
+    engine_threads: usize,
+    /// Wall-clock interval between progress reports of each resumed run (silent if unset).
+    progress_interval: Option<Duration>,
 }
 
 impl Manager {
@@ -25,101 +272,2665 @@ impl Manager {
     ///
     /// Expects a `config.toml` file inside this directory.
     pub fn new<P: AsRef<Path>>(sim_dir: P) -> Result<Self> {
-        let sim_dir = sim_dir.as_ref().to_path_buf();
+        let storage = open_storage(sim_dir).context("failed to open storage")?;
 
-        let cfg = Config::from_file(sim_dir.join("config.toml")).context("failed to load cfg")?;
+        let cfg_file = "config.toml";
+        let mut cfg = String::new();
+        storage
+            .reader(cfg_file)?
+            .read_to_string(&mut cfg)
+            .with_context(|| format!("failed to read {:?}", storage.location(cfg_file)))?;
+        let cfg = Config::from_toml(&cfg).context("failed to load cfg")?;
         log::info!("{cfg:#?}");
 
-        Ok(Self { sim_dir, cfg })
+        Ok(Self {
+            storage,
+            cfg,
+            engine_threads: 1,
+            progress_interval: None,
+        })
+    }
+
+    /// Write a fully commented example configuration (`config.toml`) into a given simulation
+    /// directory, refusing to replace an existing one unless `force` is set.
+    pub fn init_config<P: AsRef<Path>>(sim_dir: P, force: bool) -> Result<()> {
+        let storage = open_storage(sim_dir).context("failed to open storage")?;
+
+        let cfg_file = "config.toml";
+        let location = storage.location(cfg_file);
+        if storage.exists(cfg_file)? && !force {
+            bail!("{location:?} already exists (use --force to replace it)");
+        }
+        let mut writer = storage.writer(cfg_file)?;
+        writer
+            .write_all(CONFIG_TEMPLATE.as_bytes())
+            .context("failed to write config")?;
+        writer
+            .finish()
+            .with_context(|| format!("failed to write {location:?}"))?;
+        log::info!("wrote {location:?}");
+
+        Ok(())
+    }
+
+    /// Validate the configuration of a given simulation directory without creating anything,
+    /// printing every problem found and failing if there is any.
+    ///
+    /// Besides the parameters themselves, the external sources of the initial agents (agents file
+    /// and strategy source) are loaded and checked.
+    pub fn validate_config<P: AsRef<Path>>(sim_dir: P) -> Result<()> {
+        let storage = open_storage(sim_dir).context("failed to open storage")?;
+
+        let cfg_file = "config.toml";
+        let location = storage.location(cfg_file);
+        let mut cfg = String::new();
+        storage
+            .reader(cfg_file)?
+            .read_to_string(&mut cfg)
+            .with_context(|| format!("failed to read {location:?}"))?;
+        let cfg: Config = toml::from_str(&cfg).context("failed to deserialize config")?;
+
+        let mut problems = cfg.problems();
+        if problems.is_empty() {
+            let mgr = Self {
+                storage,
+                cfg,
+                engine_threads: 1,
+                progress_interval: None,
+            };
+            if let Err(problem) = mgr.load_init_sources(&mgr.cfg) {
+                problems.push(problem.context("invalid external sources"));
+            }
+        }
+
+        for problem in &problems {
+            println!("{problem:#}");
+        }
+        if !problems.is_empty() {
+            bail!("found {} problems in {location:?}", problems.len());
+        }
+        log::info!("{location:?} is valid");
+
+        Ok(())
+    }
+
+    /// Set the number of threads used by each engine to compute event rates and observables of
+    /// large populations (experimental, see `Engine::set_n_threads`).
+    pub fn set_engine_threads(&mut self, engine_threads: usize) {
+        self.engine_threads = engine_threads;
+    }
+
+    /// Set the wall-clock interval between progress reports of each resumed run (silent if
+    /// `None`, see `Engine::set_progress_interval`).
+    pub fn set_progress_interval(&mut self, progress_interval: Option<Duration>) {
+        self.progress_interval = progress_interval;
     }
 
     /// Create a new simulation run directory and initialize the engine.
-    pub fn create_run(&self, run_idx: usize) -> Result<()> {
-        let run_dir = self.run_dir(run_idx);
-        fs::create_dir_all(&run_dir).with_context(|| format!("failed to create {run_dir:?}"))?;
+    ///
+    /// If no seed is given but `init.master_seed` is set, the seed is derived from the master
+    /// seed and the run index. The seed used (if any) is recorded in the run directory.
+    ///
+    /// The given `KEY=VALUE` overrides are applied on top of the simulation configuration, and the
+    /// effective configuration of the run is then recorded in its directory (`config.json`).
+    pub fn create_run(
+        &self,
+        run_idx: usize,
+        seed: Option<u64>,
+        overrides: &[String],
+    ) -> Result<()> {
+        let mut cfg = self.cfg.clone();
+        for assignment in overrides {
+            cfg.apply_override(assignment)
+                .with_context(|| format!("failed to apply override {assignment:?}"))?;
+        }
+
+        let master_seed = cfg.init.master_seed.filter(|_| seed.is_none());
+        let seed =
+            seed.or(master_seed.map(|master_seed| Engine::derive_seed(master_seed, run_idx)));
+
+        let engine = Engine::new(cfg.clone(), self.load_init_sources(&cfg)?, seed)
+            .context("failed to create engine")?;
+
+        self.save_engine(run_idx, &engine)
+            .context("failed to save checkpoint")?;
+        self.update_manifest(run_idx, &engine, Duration::ZERO, true)
+            .context("failed to update manifest")?;
+
+        if !overrides.is_empty() {
+            self.save_run_config(run_idx, &cfg)
+                .context("failed to save run config")?;
+        }
+
+        if let Some(seed) = seed {
+            log::info!("seeded run with {seed}");
+            let seed_file = self.seed_file(run_idx);
+            let metadata = SeedMetadata { seed, master_seed };
+            let mut writer = self.storage.writer(&seed_file)?;
+            encode::write(&mut writer, &metadata).context("failed to serialize seed metadata")?;
+            writer.finish().with_context(|| {
+                format!("failed to write {:?}", self.storage.location(&seed_file))
+            })?;
+        }
+
+        let run_dir = self.storage.location(&self.run_dir(run_idx));
         log::info!("created {run_dir:?}");
 
-        let engine = Engine::new(self.cfg.clone()).context("failed to create engine")?;
+        Ok(())
+    }
 
-        engine
-            .save_checkpoint(self.checkpoint_file(run_idx))
+    /// Resume a simulation run from its checkpoint and generate a new output file (or `n_files`
+    /// consecutive ones, checkpointing after every one).
+    ///
+    /// If `output.target_time` or `output.convergence` are set, new output files are generated
+    /// (checkpointing after every one) until the simulation time reaches the target and the running
+    /// estimate has converged (or `n_files` have been generated, if given), and a run that already
+    /// meets both criteria is skipped.
+    ///
+    /// The given `KEY=VALUE` overrides are restricted to the output format parameters and only
+    /// apply to these output files: the checkpoint keeps the original ones.
+    ///
+    /// If `from_checkpoint` is given, the run is first rewound to the checkpoint of its history
+    /// taken before that output file (see `rewind_run`).
+    ///
+    /// If an interruption signal is received (see `interrupt::requested`), the current output file
+    /// is finished early and the checkpoint saved before failing.
+    ///
+    /// The manifest of the run is updated after every checkpoint, and the run is locked while it is
+    /// resumed (see `lock_run`).
+    pub fn resume_run(
+        &self,
+        run_idx: usize,
+        overrides: &[String],
+        from_checkpoint: Option<usize>,
+        n_files: Option<usize>,
+    ) -> Result<()> {
+        if n_files == Some(0) {
+            bail!("number of output files must be positive");
+        }
+        let _lock = self.lock_run(run_idx).context("failed to lock run")?;
+        if let Some(file_idx) = from_checkpoint {
+            self.rewind_run(run_idx, file_idx)
+                .context("failed to rewind run")?;
+        }
+
+        let mut file_idx = self
+            .output_file_indices(run_idx)
+            .context("failed to list output files")?
+            .last()
+            .map_or(0, |file_idx| file_idx + 1);
+
+        let mut engine = self.load_engine(run_idx)?;
+
+        let saved_output = engine.cfg().output.clone();
+        if !overrides.is_empty() {
+            let mut cfg = engine.cfg().clone();
+            for assignment in overrides {
+                cfg.apply_override(assignment)
+                    .with_context(|| format!("failed to apply override {assignment:?}"))?;
+            }
+            if cfg.model != engine.cfg().model || cfg.init != engine.cfg().init {
+                bail!("only output parameters can be overridden when resuming");
+            }
+            log::info!("{:#?}", cfg.output);
+            engine.set_output_params(cfg.output);
+        }
+
+        let target_time = engine.cfg().output.target_time;
+        let convergence = engine.cfg().output.convergence.clone();
+        let is_done = |engine: &Engine| -> Result<bool> {
+            if target_time.is_some_and(|target_time| engine.time() < target_time) {
+                return Ok(false);
+            }
+            match &convergence {
+                Some(params) => self
+                    .check_convergence(run_idx, engine.cfg(), params)
+                    .context("failed to check convergence"),
+                None => Ok(true),
+            }
+        };
+        let has_criteria = target_time.is_some() || convergence.is_some();
+        if has_criteria && is_done(&engine)? {
+            log::info!("run {run_idx} already meets its stopping criteria");
+            return Ok(());
+        }
+
+        let mut last_update = Instant::now();
+        let mut save_engine = |engine: &Engine| -> Result<()> {
+            self.save_engine(run_idx, engine)
+                .context("failed to save checkpoint")?;
+            self.update_manifest(run_idx, engine, last_update.elapsed(), false)
+                .context("failed to update manifest")?;
+            last_update = Instant::now();
+            Ok(())
+        };
+
+        let mut n_written = 0;
+        loop {
+            self.write_output_file(run_idx, file_idx, &mut engine)?;
+            file_idx += 1;
+            n_written += 1;
+            if interrupt::requested().is_some() {
+                break;
+            }
+            if n_files.is_some_and(|n_files| n_written >= n_files) {
+                break;
+            }
+            // Without stopping criteria, a single output file is generated unless `n_files` is
+            // given.
+            if (has_criteria || n_files.is_none()) && is_done(&engine)? {
+                break;
+            }
+
+            let output = engine.cfg().output.clone();
+            engine.set_output_params(saved_output.clone());
+            save_engine(&engine)?;
+            engine.set_output_params(output);
+        }
+
+        engine.set_output_params(saved_output);
+        save_engine(&engine)?;
+
+        if let Some(signal) = interrupt::requested() {
+            bail!("interrupted by signal {signal} (checkpoint saved)");
+        }
+
+        Ok(())
+    }
+
+    /// Rewind a simulation run to the checkpoint of its history taken before a given output file
+    /// (`checkpoint-NNNN.msgpack`).
+    ///
+    /// That output file and every later one (with their tracer, step-level observable, lineage,
+    /// event log, snapshots and extinction files) are removed, together with the later checkpoints
+    /// of the history, the partial analysis and the convergence record.
+    fn rewind_run(&self, run_idx: usize, file_idx: usize) -> Result<()> {
+        let history_file = self.history_checkpoint_file(run_idx, file_idx);
+        let location = self.storage.location(&history_file);
+        if !self.storage.exists(&history_file)? {
+            bail!("{location:?} does not exist");
+        }
+        let mut contents = Vec::new();
+        self.storage
+            .reader(&history_file)?
+            .read_to_end(&mut contents)
+            .with_context(|| format!("failed to read {location:?}"))?;
+        let engine = Engine::load_checkpoint(&mut contents.as_slice())
+            .with_context(|| format!("failed to load {location:?}"))?;
+
+        for later_idx in self.output_file_indices(run_idx)? {
+            if later_idx < file_idx {
+                continue;
+            }
+            let output_file = self.output_file(run_idx, later_idx);
+            for file in [
+                format!("{output_file}.flushed"),
+                self.tracers_file(run_idx, later_idx),
+                self.observers_file(run_idx, later_idx),
+                self.lineage_file(run_idx, later_idx),
+                self.events_file(run_idx, later_idx),
+                self.snapshots_file(run_idx, later_idx),
+                self.extinctions_file(run_idx, later_idx),
+                output_file,
+            ] {
+                if self.storage.exists(&file)? {
+                    self.storage.remove(&file)?;
+                }
+            }
+            log::warn!("removed output file {later_idx} of run {run_idx}");
+        }
+        for later_idx in self.history_checkpoint_indices(run_idx)? {
+            if later_idx > file_idx {
+                self.storage
+                    .remove(&self.history_checkpoint_file(run_idx, later_idx))?;
+            }
+        }
+        self.discard_partial_analysis(run_idx)?;
+        let convergence_file = self.convergence_file(run_idx);
+        if self.storage.exists(&convergence_file)? {
+            self.storage.remove(&convergence_file)?;
+        }
+
+        self.save_engine(run_idx, &engine)
             .context("failed to save checkpoint")?;
+        log::info!("rewound run {run_idx} to {location:?}");
 
         Ok(())
     }
 
-    /// Resume a simulation run from its checkpoint and generate a new output file.
-    pub fn resume_run(&self, run_idx: usize) -> Result<()> {
-        let file_idx = self
-            .count_output_files(run_idx)
-            .context("failed to count output files")?;
+    /// Check whether the running estimate of the convergence observable of a simulation run has
+    /// converged, first adding the estimates of its new output files to its convergence record.
+    fn check_convergence(
+        &self,
+        run_idx: usize,
+        cfg: &Config,
+        params: &ConvergenceParams,
+    ) -> Result<bool> {
+        let mut record = self
+            .load_convergence_record(run_idx)?
+            .filter(|record| record.observable == params.observable)
+            .unwrap_or(ConvergenceRecord {
+                observable: params.observable,
+                estimates: BTreeMap::new(),
+                converged: false,
+            });
 
-        let checkpoint_file = self.checkpoint_file(run_idx);
-        let mut engine = Engine::load_checkpoint(&checkpoint_file)
-            .with_context(|| format!("failed to load {checkpoint_file:?}"))?;
-        log::info!("loaded {checkpoint_file:?}");
+        for file_idx in self.output_file_indices(run_idx)? {
+            let output_file = self.output_file(run_idx, file_idx);
+            if record.estimates.contains_key(&file_idx)
+                || self.storage.exists(&format!("{output_file}.flushed"))?
+            {
+                continue;
+            }
+            let mut analyzer = Analyzer::new(cfg.clone());
+            analyzer
+                .add_output(&mut self.storage.reader(&output_file)?)
+                .with_context(|| {
+                    format!("failed to read {:?}", self.storage.location(&output_file))
+                })?;
+            let analysis = analyzer.calc_analysis()?;
+            let estimate = match params.observable {
+                ConvergenceObservable::GrowthRate => analysis.avg_growth_rate,
+                ConvergenceObservable::BirthRate => analysis.avg_birth_rate,
+                ConvergenceObservable::StdDevStratPhe => analysis.avg_std_dev_strat_phe,
+            };
+            record.estimates.insert(file_idx, estimate);
+        }
+
+        let (estimate, std_err) = record.running_estimate();
+        record.converged =
+            record.estimates.len() >= params.min_files && std_err <= params.tolerance;
+        log::info!(
+            "run {run_idx} {:?} estimate: {estimate} +- {std_err} ({} output files)",
+            params.observable,
+            record.estimates.len()
+        );
+
+        let convergence_file = self.convergence_file(run_idx);
+        let mut writer = self.storage.writer(&convergence_file)?;
+        encode::write(&mut writer, &record).context("failed to serialize convergence record")?;
+        writer.finish().with_context(|| {
+            format!(
+                "failed to write {:?}",
+                self.storage.location(&convergence_file)
+            )
+        })?;
+
+        Ok(record.converged)
+    }
+
+    /// Simulate a run's engine for one output file, writing it along with its tracer events and
+    /// step-level observables (if enabled).
+    fn write_output_file(
+        &self,
+        run_idx: usize,
+        file_idx: usize,
+        engine: &mut Engine,
+    ) -> Result<()> {
+        let n_tracers = engine.cfg().output.n_tracers;
+        if let Some(n_tracers) = n_tracers {
+            engine.tag_tracers(n_tracers);
+        }
+        let observe = !engine.cfg().output.observers.is_empty();
+        if observe {
+            engine.start_observers();
+        }
+        let lineage = engine.cfg().output.lineage;
+        if lineage {
+            engine.start_lineage();
+        }
+        let snapshots = engine.cfg().output.snapshot_steps_factor.is_some();
+        if snapshots {
+            engine.start_snapshots();
+        }
+        let event_log = engine.cfg().output.event_log;
+        let (output, first_step) = (engine.cfg().output.clone(), engine.step_count());
+        if event_log {
+            engine.start_event_log();
+        }
+        // Only log the extinctions of this output file.
+        engine.take_extinctions();
 
+        let output_file = self.output_file(run_idx, file_idx);
+        let flush_file = format!("{output_file}.flushed");
+        // Flushed records must be readable before the output file is complete.
+        let buffer_size = engine.cfg().output.buffer_size.unwrap_or(8192);
+        let writer = match engine.cfg().output.flush_every_records {
+            Some(_) => self.storage.writer_in_place(&output_file)?,
+            None => self.storage.writer(&output_file)?,
+        };
+        let mut writer = BufWriter::with_capacity(buffer_size, writer);
         let start = Instant::now();
         engine
-            .perform_simulation(self.output_file(run_idx, file_idx))
+            .perform_simulation(&mut writer, |n_records, observables| {
+                let metadata = FlushMetadata {
+                    n_records,
+                    step: observables.step,
+                    time: observables.time,
+                };
+                let mut flush_writer = self.storage.writer(&flush_file)?;
+                encode::write(&mut flush_writer, &metadata)
+                    .context("failed to serialize flush metadata")?;
+                flush_writer.finish()
+            })
             .context("failed to perform simulation")?;
         let duration = start.elapsed();
         log::info!("finished simulation in {duration:?}");
+        writer
+            .into_inner()
+            .map_err(|error| error.into_error())
+            .context("failed to flush writer stream")?
+            .finish()
+            .with_context(|| {
+                format!("failed to write {:?}", self.storage.location(&output_file))
+            })?;
+        if self.storage.exists(&flush_file)? {
+            self.storage.remove(&flush_file)?;
+        }
 
-        engine
-            .save_checkpoint(self.checkpoint_file(run_idx))
-            .context("failed to save checkpoint")?;
+        if n_tracers.is_some() {
+            let tracers_file = self.tracers_file(run_idx, file_idx);
+            let mut tracers_writer = self.storage.writer(&tracers_file)?;
+            encode::write(&mut tracers_writer, &engine.take_tracer_events())
+                .context("failed to serialize tracer events")?;
+            tracers_writer.finish().with_context(|| {
+                format!("failed to write {:?}", self.storage.location(&tracers_file))
+            })?;
+            engine.tag_tracers(0);
+        }
+
+        if observe {
+            let observers_file = self.observers_file(run_idx, file_idx);
+            let mut observers_writer = self.storage.writer(&observers_file)?;
+            encode::write(&mut observers_writer, &engine.finish_observers())
+                .context("failed to serialize step-level observables")?;
+            observers_writer.finish().with_context(|| {
+                format!(
+                    "failed to write {:?}",
+                    self.storage.location(&observers_file)
+                )
+            })?;
+        }
+
+        if lineage {
+            let lineage_file = self.lineage_file(run_idx, file_idx);
+            let mut lineage_writer = self.storage.writer(&lineage_file)?;
+            encode::write(&mut lineage_writer, &engine.take_lineage_entries())
+                .context("failed to serialize lineage log")?;
+            lineage_writer.finish().with_context(|| {
+                format!("failed to write {:?}", self.storage.location(&lineage_file))
+            })?;
+        }
+
+        if snapshots {
+            let snapshots_file = self.snapshots_file(run_idx, file_idx);
+            let mut snapshots_writer = self.storage.writer(&snapshots_file)?;
+            encode::write(&mut snapshots_writer, &engine.take_snapshots())
+                .context("failed to serialize population snapshots")?;
+            snapshots_writer.finish().with_context(|| {
+                format!(
+                    "failed to write {:?}",
+                    self.storage.location(&snapshots_file)
+                )
+            })?;
+        }
+
+        if event_log {
+            let events_file = self.events_file(run_idx, file_idx);
+            let mut events_writer = self.storage.writer(&events_file)?;
+            let log = EventLog {
+                output,
+                first_step,
+                events: engine.take_event_log(),
+            };
+            encode::write(&mut events_writer, &log).context("failed to serialize event log")?;
+            events_writer.finish().with_context(|| {
+                format!("failed to write {:?}", self.storage.location(&events_file))
+            })?;
+        }
+
+        let extinctions = engine.take_extinctions();
+        if !extinctions.is_empty() {
+            let extinctions_file = self.extinctions_file(run_idx, file_idx);
+            let mut extinctions_writer = self.storage.writer(&extinctions_file)?;
+            encode::write(&mut extinctions_writer, &extinctions)
+                .context("failed to serialize extinction log")?;
+            extinctions_writer.finish().with_context(|| {
+                format!(
+                    "failed to write {:?}",
+                    self.storage.location(&extinctions_file)
+                )
+            })?;
+        }
 
         Ok(())
     }
 
-    /// Analyze all output files from a simulation run and save the analysis.
-    pub fn analyze_run(&self, run_idx: usize) -> Result<()> {
-        let mut analyzer = Analyzer::new(self.cfg.clone());
+    /// Resume every simulation run with a checkpoint in parallel, generating a new output file (or
+    /// `n_files` consecutive ones) for each of them (see `resume_run`).
+    ///
+    /// Runs are distributed over `n_threads` worker threads (as many as available CPUs if unset),
+    /// whose log messages are prefixed with the run directory. Failed runs do not stop the others,
+    /// but are reported together at the end.
+    pub fn resume_all(
+        &self,
+        overrides: &[String],
+        n_threads: Option<usize>,
+        n_files: Option<usize>,
+    ) -> Result<()> {
+        let mut run_indices = Vec::new();
+        for run_idx in self.run_indices().context("failed to list runs")? {
+            if self.storage.exists(&self.checkpoint_file(run_idx))? {
+                run_indices.push(run_idx);
+            }
+        }
+        let n_runs = run_indices.len();
 
-        let n_files = self
-            .count_output_files(run_idx)
-            .context("failed to count output files")?;
-        for file_idx in 0..n_files {
-            analyzer
-                .add_output_file(self.output_file(run_idx, file_idx))
-                .context("failed to add output file")?;
+        let n_threads = match n_threads {
+            Some(n_threads) => n_threads,
+            None => thread::available_parallelism()
+                .context("failed to get available parallelism")?
+                .get(),
+        };
+        if n_threads == 0 {
+            bail!("number of threads must be positive");
+        }
+        log::info!("resuming {n_runs} runs with {n_threads} threads");
+
+        let next_run = AtomicUsize::new(0);
+        let failures = thread::scope(|scope| {
+            let workers: Vec<_> = (0..n_threads.min(n_runs))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut failures = Vec::new();
+                        // No new runs are started once an interruption signal is received.
+                        while interrupt::requested().is_none()
+                            && let Some(&run_idx) = run_indices.get(next_run.fetch_add(1, Relaxed))
+                        {
+                            // The run directory is the name of the thread resuming the run, and so
+                            // the prefix of its log messages.
+                            let result = thread::Builder::new()
+                                .name(self.run_dir(run_idx))
+                                .spawn_scoped(scope, move || {
+                                    self.resume_run(run_idx, overrides, None, n_files)
+                                })
+                                .context("failed to spawn thread")
+                                .and_then(|handle| {
+                                    handle.join().unwrap_or_else(|_| bail!("thread panicked"))
+                                });
+                            if let Err(error) = result {
+                                failures.push((run_idx, error));
+                            }
+                        }
+                        failures
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap_or_default())
+                .collect::<Vec<_>>()
+        });
+
+        if !failures.is_empty() {
+            for (run_idx, error) in &failures {
+                let run_dir = self.storage.location(&self.run_dir(*run_idx));
+                log::error!("failed to resume {run_dir:?}: {error:#}");
+            }
+            let run_dirs: Vec<String> = failures
+                .iter()
+                .map(|(run_idx, _)| self.run_dir(*run_idx))
+                .collect();
+            bail!(
+                "failed to resume {} of {n_runs} runs: {}",
+                failures.len(),
+                run_dirs.join(", ")
+            );
         }
 
+        Ok(())
+    }
+
+    /// Perform a one-shot simulation run: initialize the engine and feed the simulation observables
+    /// directly into the analyzer, saving only the analysis (and, optionally, the checkpoint).
+    ///
+    /// The simulation stops after `n_steps` steps or `max_time` simulation time, whichever comes first.
+    pub fn simulate_run(
+        &self,
+        run_idx: usize,
+        n_steps: Option<usize>,
+        max_time: Option<f64>,
+        save_checkpoint: bool,
+    ) -> Result<()> {
+        if n_steps.is_none() && max_time.is_none() {
+            bail!("either a number of steps or a maximum time must be given");
+        }
+        for file in [self.checkpoint_file(run_idx), self.analysis_file(run_idx)] {
+            if self.storage.exists(&file)? {
+                let file = self.storage.location(&file);
+                bail!("{file:?} already exists");
+            }
+        }
+
+        let mut engine = Engine::new(self.cfg.clone(), self.load_init_sources(&self.cfg)?, None)
+            .context("failed to create engine")?;
+        engine.set_n_threads(self.engine_threads);
+        let mut analyzer = Analyzer::new(self.cfg.clone());
+
+        let start = Instant::now();
+        engine
+            .perform_steps_while(
+                |engine| {
+                    n_steps.is_none_or(|n_steps| engine.step_count() < n_steps)
+                        && max_time.is_none_or(|max_time| engine.time() < max_time)
+                },
+                |observables| {
+                    analyzer.add_observables(observables);
+                    Ok(())
+                },
+            )
+            .context("failed to perform simulation")?;
+        let duration = start.elapsed();
+        log::info!("finished simulation in {duration:?}");
+        log::info!(
+            "reached step {} (time {}, {} agents)",
+            engine.step_count(),
+            engine.time(),
+            engine.state().agents.len()
+        );
+
+        let analysis_file = self.analysis_file(run_idx);
+        let mut writer = self.storage.writer(&analysis_file)?;
         analyzer
-            .analyze(self.analysis_file(run_idx))
+            .analyze(&mut writer)
             .context("failed to save analysis")?;
+        writer.finish().with_context(|| {
+            format!(
+                "failed to write {:?}",
+                self.storage.location(&analysis_file)
+            )
+        })?;
+
+        if save_checkpoint {
+            self.save_engine(run_idx, &engine)
+                .context("failed to save checkpoint")?;
+        }
 
-        let run_dir = self.run_dir(run_idx);
+        let run_dir = self.storage.location(&self.run_dir(run_idx));
         log::info!("analyzed {run_dir:?}");
 
         Ok(())
     }
 
-    fn run_dir(&self, run_idx: usize) -> PathBuf {
-        self.sim_dir.join(format!("run-{run_idx:04}"))
+    /// Fork a simulation run: copy its checkpoint into a new run with a fresh random number
+    /// generator stream and, optionally, some `KEY=VALUE` parameter overrides.
+    pub fn fork_run(
+        &self,
+        run_idx: usize,
+        new_run_idx: usize,
+        overrides: &[String],
+        clock_change: Option<ClockChange>,
+    ) -> Result<()> {
+        let new_checkpoint_file = self.checkpoint_file(new_run_idx);
+        if self.storage.exists(&new_checkpoint_file)? {
+            let new_checkpoint_file = self.storage.location(&new_checkpoint_file);
+            bail!("{new_checkpoint_file:?} already exists");
+        }
+
+        let engine = self.load_engine(run_idx)?;
+
+        let mut cfg = engine.cfg().clone();
+        for assignment in overrides {
+            cfg.apply_override(assignment)
+                .with_context(|| format!("failed to apply override {assignment:?}"))?;
+        }
+        if !overrides.is_empty() {
+            log::info!("{cfg:#?}");
+            self.save_run_config(new_run_idx, &cfg)
+                .context("failed to save run config")?;
+        }
+
+        let mut engine = engine.fork(cfg).context("failed to fork engine")?;
+
+        let clock_shift = self.load_clock_shift(run_idx)?;
+        self.change_clock(new_run_idx, &mut engine, clock_change, clock_shift)?;
+
+        self.save_engine(new_run_idx, &engine)
+            .context("failed to save checkpoint")?;
+
+        let new_run_dir = self.storage.location(&self.run_dir(new_run_idx));
+        log::info!("created {new_run_dir:?}");
+
+        Ok(())
+    }
+
+    /// Create a simulation run directly at a given step of the deterministic simulation with a
+    /// given seed, without writing any output.
+    pub fn fast_forward(&self, run_idx: usize, seed: u64, n_steps: usize) -> Result<()> {
+        let checkpoint_file = self.checkpoint_file(run_idx);
+        if self.storage.exists(&checkpoint_file)? {
+            let checkpoint_file = self.storage.location(&checkpoint_file);
+            bail!("{checkpoint_file:?} already exists");
+        }
+
+        let mut engine = Engine::new(
+            self.cfg.clone(),
+            self.load_init_sources(&self.cfg)?,
+            Some(seed),
+        )
+        .context("failed to create engine")?;
+        engine.set_n_threads(self.engine_threads);
+
+        let start = Instant::now();
+        engine
+            .fast_forward(n_steps)
+            .context("failed to fast-forward simulation")?;
+        let duration = start.elapsed();
+        log::info!("finished simulation in {duration:?}");
+
+        self.save_engine(run_idx, &engine)
+            .context("failed to save checkpoint")?;
+
+        let run_dir = self.storage.location(&self.run_dir(run_idx));
+        log::info!("created {run_dir:?}");
+
+        Ok(())
+    }
+
+    /// Replay the event log of an output file of a simulation run from the checkpoint of its history
+    /// taken before it, checking that every step selects the logged event after the logged time.
+    ///
+    /// Fails at the first step diverging from the log. If `n_steps` is given, only that many
+    /// steps are replayed, and if `new_run_idx` is given, the replayed engine is saved as the
+    /// checkpoint of a new run (to inspect the state at a given step).
+    pub fn replay_run(
+        &self,
+        run_idx: usize,
+        file_idx: usize,
+        n_steps: Option<usize>,
+        new_run_idx: Option<usize>,
+    ) -> Result<()> {
+        if let Some(new_run_idx) = new_run_idx {
+            let new_checkpoint_file = self.checkpoint_file(new_run_idx);
+            if self.storage.exists(&new_checkpoint_file)? {
+                let new_checkpoint_file = self.storage.location(&new_checkpoint_file);
+                bail!("{new_checkpoint_file:?} already exists");
+            }
+        }
+
+        let events_file = self.events_file(run_idx, file_idx);
+        let location = self.storage.location(&events_file);
+        if !self.storage.exists(&events_file)? {
+            bail!("{location:?} does not exist (set output.event_log)");
+        }
+        let event_log: EventLog = decode::from_read(self.storage.reader(&events_file)?)
+            .with_context(|| format!("failed to read {location:?}"))?;
+
+        let history_file = self.history_checkpoint_file(run_idx, file_idx);
+        let location = self.storage.location(&history_file);
+        if !self.storage.exists(&history_file)? {
+            bail!("{location:?} does not exist (set output.keep_checkpoints)");
+        }
+        let mut contents = Vec::new();
+        self.storage
+            .reader(&history_file)?
+            .read_to_end(&mut contents)
+            .with_context(|| format!("failed to read {location:?}"))?;
+        let mut engine = Engine::load_checkpoint(&mut contents.as_slice())
+            .with_context(|| format!("failed to load {location:?}"))?;
+        if engine.step_count() != event_log.first_step {
+            bail!(
+                "checkpoint is at step {}, but the event log starts at step {}",
+                engine.step_count(),
+                event_log.first_step
+            );
+        }
+
+        // Prepare the engine exactly as when the output file was written.
+        let saved_output = engine.cfg().output.clone();
+        engine.set_output_params(event_log.output);
+        if let Some(n_tracers) = engine.cfg().output.n_tracers {
+            engine.tag_tracers(n_tracers);
+        }
+        if engine.cfg().output.lineage {
+            engine.start_lineage();
+        }
+        engine.start_event_log();
+
+        let events = event_log.events;
+        let n_steps = n_steps.map_or(events.len(), |n_steps| n_steps.min(events.len()));
+        for logged in &events[..n_steps] {
+            let step = engine.step_count();
+            let outcome = engine.step().context("failed to perform step")?;
+            if outcome.event != logged.event
+                || outcome.time_step.to_bits() != logged.time_step.to_bits()
+            {
+                bail!(
+                    "replay diverged at step {step}: logged {:?} after {}, replayed {:?} after {}",
+                    logged.event,
+                    logged.time_step,
+                    outcome.event,
+                    outcome.time_step
+                );
+            }
+        }
+        log::info!(
+            "replayed {n_steps} steps matching the event log (step {}, time {})",
+            engine.step_count(),
+            engine.time()
+        );
+
+        if let Some(new_run_idx) = new_run_idx {
+            engine.take_event_log();
+            engine.set_output_params(saved_output);
+            self.save_engine(new_run_idx, &engine)
+                .context("failed to save checkpoint")?;
+            let new_run_dir = self.storage.location(&self.run_dir(new_run_idx));
+            log::info!("created {new_run_dir:?}");
+        }
+
+        Ok(())
     }
 
-    fn count_output_files(&self, run_idx: usize) -> Result<usize> {
-        let pattern = self.run_dir(run_idx).join("output-*");
-        let pattern = pattern.to_str().context("pattern is not valid UTF-8")?;
-        let count = glob::glob(pattern)
-            .context("failed to glob output files")?
-            .filter_map(Result::ok)
-            .count();
-        Ok(count)
+    /// Simulate the current population of the simulation run in several independent environment
+    /// realizations (common-garden mode) and save their between-realization analysis.
+    ///
+    /// Every realization starts from the run checkpoint with a fresh random number generator
+    /// stream. The run checkpoint itself is left unchanged.
+    pub fn simulate_garden(
+        &self,
+        run_idx: usize,
+        n_realizations: usize,
+        n_steps: usize,
+    ) -> Result<()> {
+        if n_realizations < 2 {
+            bail!("number of realizations must be at least 2");
+        }
+
+        let engine = self.load_engine(run_idx)?;
+
+        let start = Instant::now();
+        let mut realizations = Vec::with_capacity(n_realizations);
+        for realization_idx in 0..n_realizations {
+            let mut engine = engine
+                .clone()
+                .fork(engine.cfg().clone())
+                .context("failed to fork engine")?;
+            let mut analyzer = Analyzer::new(engine.cfg().clone());
+
+            let last_step = engine.step_count() + n_steps;
+            engine
+                .perform_steps_while(
+                    |engine| engine.step_count() < last_step,
+                    |observables| {
+                        analyzer.add_observables(observables);
+                        Ok(())
+                    },
+                )
+                .with_context(|| format!("failed to simulate realization {realization_idx}"))?;
+
+            realizations.push(
+                analyzer
+                    .calc_analysis()
+                    .with_context(|| format!("failed to analyze realization {realization_idx}"))?,
+            );
+        }
+        let duration = start.elapsed();
+        log::info!("finished simulations in {duration:?}");
+
+        let garden = GardenAnalysis::new(realizations);
+
+        let garden_file = self.garden_file(run_idx);
+        let location = self.storage.location(&garden_file);
+        let mut writer = self.storage.writer(&garden_file)?;
+        encode::write(&mut writer, &garden).context("failed to serialize garden analysis")?;
+        writer
+            .finish()
+            .with_context(|| format!("failed to write {location:?}"))?;
+        log::info!("saved {location:?}");
+
+        Ok(())
     }
 
-    fn checkpoint_file(&self, run_idx: usize) -> PathBuf {
-        self.run_dir(run_idx).join("checkpoint.msgpack")
+    /// Simulate only the environment process and report its empirical dwell times and
+    /// transition frequencies, next to the values expected from the configuration.
+    pub fn calibrate_env(&self, n_trans: usize) -> Result<()> {
+        let n_env = self.cfg.model.n_env;
+        if n_env < 2 {
+            bail!("environment process requires at least 2 environments");
+        }
+
+        if self.cfg.model.environment.is_some() {
+            bail!("environment follows a deterministic trajectory");
+        }
+
+        let visits = Engine::simulate_env_process(&self.cfg, n_trans)
+            .context("failed to simulate environment process")?;
+        let calibration = EnvCalibration::new(n_env, &visits);
+
+        for env in 0..n_env {
+            let rates = &self.cfg.model.rates_trans[env];
+            let total_rate: f64 = (0..n_env)
+                .filter(|&next_env| next_env != env)
+                .map(|next_env| rates[next_env].get())
+                .sum();
+
+            let dwell_time = &calibration.dwell_time[env];
+            println!(
+                "env {env}: {} visits, dwell time {:.4} ± {:.4} (expected {:.4})",
+                calibration.n_visits[env],
+                dwell_time.val,
+                dwell_time.err,
+                1.0 / total_rate
+            );
+            for next_env in (0..n_env).filter(|&next_env| next_env != env) {
+                let freq = &calibration.trans_freq[env][next_env];
+                println!(
+                    "  -> env {next_env}: frequency {:.4} ± {:.4} (expected {:.4})",
+                    freq.val,
+                    freq.err,
+                    rates[next_env].get() / total_rate
+                );
+            }
+        }
+
+        Ok(())
     }
 
-    fn output_file(&self, run_idx: usize, file_idx: usize) -> PathBuf {
-        self.run_dir(run_idx)
-            .join(format!("output-{file_idx:04}.msgpack"))
+    /// Compute the theoretically optimal phenotypic strategy of the model along a simulated
+    /// environment trajectory with a given number of transitions, saving it in the simulation
+    /// directory (`optimal.msgpack`) to compare the evolved strategies against.
+    ///
+    /// The optimal strategy maximizes the mean-field long-term growth rate, ignoring mutations,
+    /// bottlenecks and the population cap (see `theory::optimal_strategy`). A pinned (or single)
+    /// environment is visited for a unit of time per transition, a periodic environment trajectory
+    /// for the given number of visits, and one read from a file until its last switch.
+    pub fn optimal_strategy(&self, n_trans: usize, resolution: usize, json: bool) -> Result<()> {
+        if resolution == 0 {
+            bail!("resolution must be positive");
+        }
+        if self.cfg.model.continuous_env.is_some() {
+            bail!("optimal strategy requires a discrete environment");
+        }
+
+        let visits = Engine::simulate_env_process(&self.cfg, n_trans)
+            .context("failed to simulate environment process")?;
+        let environment = self.cfg.model.environment.as_ref();
+        let trajectory: Vec<(usize, f64)> =
+            if let Some(env_file) = environment.and_then(|env| env.file.as_ref()) {
+                let env_trajectory = self.load_env_trajectory(env_file, &self.cfg)?;
+                (env_trajectory.windows(2))
+                    .map(|switches| (switches[0].env, switches[1].time - switches[0].time))
+                    .collect()
+            } else if let Some(environment) = environment {
+                (0..n_trans)
+                    .filter_map(|visit_idx| {
+                        environment.cycle_visit(visit_idx % environment.cycle_len())
+                    })
+                    .collect()
+            } else if visits.is_empty() {
+                let env = self.cfg.model.pinned_env.or(self.cfg.init.env).unwrap_or(0);
+                vec![(env, 1.0); n_trans]
+            } else {
+                visits
+                    .iter()
+                    .map(|&(env, dwell_time, _)| (env, dwell_time))
+                    .collect()
+            };
+        if trajectory.is_empty() {
+            bail!("environment trajectory requires at least 1 transition");
+        }
+
+        let optimal = optimal_strategy(&self.cfg, &trajectory, resolution);
+        println!(
+            "optimal strategy: {:.4?} (growth rate {:.6})",
+            optimal.strat_phe, optimal.growth_rate
+        );
+        for (phe, growth_rate) in optimal.pure_growth_rates.iter().enumerate() {
+            println!("  pure strategy {phe}: growth rate {growth_rate:.6}");
+        }
+
+        let optimal_file = "optimal.msgpack";
+        let mut writer = self.storage.writer(optimal_file)?;
+        encode::write(&mut writer, &optimal).context("failed to serialize optimal strategy")?;
+        writer.finish().with_context(|| {
+            format!("failed to write {:?}", self.storage.location(optimal_file))
+        })?;
+
+        if json {
+            let json_file = "optimal.json";
+            let mut writer = self.storage.writer(json_file)?;
+            optimal.write_json(&mut writer)?;
+            writer.finish().with_context(|| {
+                format!("failed to write {:?}", self.storage.location(json_file))
+            })?;
+        }
+
+        Ok(())
     }
 
-    fn analysis_file(&self, run_idx: usize) -> PathBuf {
-        self.run_dir(run_idx).join("analysis.msgpack")
+    /// Analyze the extinctions of a simulation run logged in its extinction files
+    /// (`extinctions-NNNN.msgpack`), saving the analysis in its directory
+    /// (`analysis-extinctions.msgpack`, and `analysis-extinctions.json` if `json` is set).
+    ///
+    /// The time of the last extinction until the current time of the run is a censored interval.
+    pub fn analyze_extinctions(&self, run_idx: usize, json: bool) -> Result<()> {
+        let prefix = format!("{}/extinctions-", self.run_dir(run_idx));
+        let mut keys = self
+            .storage
+            .list(&prefix)
+            .context("failed to list extinction files")?;
+        keys.sort();
+        let mut extinctions: Vec<Extinction> = Vec::new();
+        for key in keys {
+            let file_extinctions: Vec<Extinction> = decode::from_read(self.storage.reader(&key)?)
+                .with_context(|| {
+                format!("failed to deserialize {:?}", self.storage.location(&key))
+            })?;
+            extinctions.extend(file_extinctions);
+        }
+        let engine = self.load_engine(run_idx)?;
+
+        let analysis = ExtinctionAnalysis::new(extinctions, engine.time());
+        println!(
+            "{} extinctions (rate {:.6}), average interval {:.4} (coefficient of variation {:.4})",
+            analysis.extinctions.len(),
+            analysis.rate_extinct,
+            analysis.avg_interval,
+            analysis.cv_interval
+        );
+        if let Some(median) = analysis.median_interval {
+            println!("  median survival time {median:.4}");
+        }
+
+        let analysis_file = self.extinction_analysis_file(run_idx, "msgpack");
+        let mut writer = self.storage.writer(&analysis_file)?;
+        encode::write(&mut writer, &analysis).context("failed to serialize extinction analysis")?;
+        writer.finish().with_context(|| {
+            format!(
+                "failed to write {:?}",
+                self.storage.location(&analysis_file)
+            )
+        })?;
+
+        if json {
+            let json_file = self.extinction_analysis_file(run_idx, "json");
+            let mut writer = self.storage.writer(&json_file)?;
+            analysis.write_json(&mut writer)?;
+            writer.finish().with_context(|| {
+                format!("failed to write {:?}", self.storage.location(&json_file))
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Report the differences between the checkpoints of two simulation runs, which may belong
+    /// to another simulation directory.
+    pub fn diff_checkpoints<P: AsRef<Path>>(
+        &self,
+        run_idx: usize,
+        other_sim_dir: Option<P>,
+        other_run_idx: usize,
+    ) -> Result<()> {
+        let engine = self.load_engine(run_idx)?;
+        let other_engine = match other_sim_dir {
+            Some(other_sim_dir) => Manager::new(other_sim_dir)?.load_engine(other_run_idx)?,
+            None => self.load_engine(other_run_idx)?,
+        };
+
+        // Summarize the population composition and strategies of an engine.
+        let summarize = |engine: &Engine| {
+            let n_phe = engine.cfg().model.n_phe;
+            let agents = &engine.state().agents;
+            let mut n_agents_phe = vec![0; n_phe];
+            let mut avg_strat_phe = vec![0.0; n_phe];
+            for agent in agents {
+                n_agents_phe[agent.phe()] += 1;
+                for (sum, ele) in avg_strat_phe.iter_mut().zip(agent.strat_phe()) {
+                    *sum += ele / agents.len() as f64;
+                }
+            }
+            (n_agents_phe, avg_strat_phe)
+        };
+        let (n_agents_phe, avg_strat_phe) = summarize(&engine);
+        let (other_n_agents_phe, other_avg_strat_phe) = summarize(&other_engine);
+
+        let mut n_diffs = 0;
+        let mut report = |name: &str, val: String, other_val: String| {
+            if val != other_val {
+                println!("{name}: {val} != {other_val}");
+                n_diffs += 1;
+            }
+        };
+        report(
+            "config",
+            format!("{:?}", engine.cfg()),
+            format!("{:?}", other_engine.cfg()),
+        );
+        report(
+            "step",
+            engine.step_count().to_string(),
+            other_engine.step_count().to_string(),
+        );
+        report(
+            "time",
+            engine.time().to_string(),
+            other_engine.time().to_string(),
+        );
+        report(
+            "rng seed",
+            format!("{:?}", engine.rng().get_seed()),
+            format!("{:?}", other_engine.rng().get_seed()),
+        );
+        report(
+            "rng stream",
+            engine.rng().get_stream().to_string(),
+            other_engine.rng().get_stream().to_string(),
+        );
+        report(
+            "rng position",
+            engine.rng().get_word_pos().to_string(),
+            other_engine.rng().get_word_pos().to_string(),
+        );
+        report(
+            "env",
+            engine.state().env.to_string(),
+            other_engine.state().env.to_string(),
+        );
+        report(
+            "extinctions",
+            engine.n_extinct().to_string(),
+            other_engine.n_extinct().to_string(),
+        );
+        report(
+            "agents",
+            engine.state().agents.len().to_string(),
+            other_engine.state().agents.len().to_string(),
+        );
+        report(
+            "agents per phenotype",
+            format!("{n_agents_phe:?}"),
+            format!("{other_n_agents_phe:?}"),
+        );
+        report(
+            "average strategy",
+            format!("{avg_strat_phe:?}"),
+            format!("{other_avg_strat_phe:?}"),
+        );
+        let first_diff_agent = engine
+            .state()
+            .agents
+            .iter()
+            .zip(&other_engine.state().agents)
+            .position(|(agent, other_agent)| agent != other_agent);
+        if let Some(agent_idx) = first_diff_agent {
+            println!("agents differ from index {agent_idx}");
+            n_diffs += 1;
+        }
+
+        if n_diffs == 0 {
+            println!("checkpoints are identical");
+        }
+
+        Ok(())
+    }
+
+    /// Export the checkpoint of a simulation run into a portable bundle file.
+    pub fn export_checkpoint<P: AsRef<Path>>(&self, run_idx: usize, file: P) -> Result<()> {
+        let engine = self.load_engine(run_idx)?;
+
+        let exported_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("failed to get export time")?
+            .as_secs();
+        let metadata = BundleMetadata {
+            bundle_version: BUNDLE_VERSION,
+            mutare_version: env!("CARGO_PKG_VERSION").to_string(),
+            sim_dir: self.storage.location(""),
+            run_idx,
+            exported_at,
+            clock_shift: self.load_clock_shift(run_idx)?,
+        };
+        log::info!("{metadata:#?}");
+        let bundle = CheckpointBundle { metadata, engine };
+
+        let file = file.as_ref();
+        let writer = File::create(file).with_context(|| format!("failed to create {file:?}"))?;
+        let mut writer = BufWriter::new(writer);
+        encode::write(&mut writer, &bundle).context("failed to serialize bundle")?;
+        writer.flush().context("failed to flush writer stream")?;
+        log::info!("exported {file:?}");
+
+        Ok(())
+    }
+
+    /// Import a portable checkpoint bundle file into a new simulation run.
+    pub fn import_checkpoint<P: AsRef<Path>>(
+        &self,
+        run_idx: usize,
+        file: P,
+        clock_change: Option<ClockChange>,
+    ) -> Result<()> {
+        let checkpoint_file = self.checkpoint_file(run_idx);
+        if self.storage.exists(&checkpoint_file)? {
+            let checkpoint_file = self.storage.location(&checkpoint_file);
+            bail!("{checkpoint_file:?} already exists");
+        }
+
+        let file = file.as_ref();
+        let reader = File::open(file).with_context(|| format!("failed to open {file:?}"))?;
+        let mut reader = BufReader::new(reader);
+        let mut bundle: CheckpointBundle =
+            decode::from_read(&mut reader).context("failed to deserialize bundle")?;
+        log::info!("{:#?}", bundle.metadata);
+
+        let bundle_version = bundle.metadata.bundle_version;
+        if bundle_version != BUNDLE_VERSION {
+            bail!("bundle version must be {BUNDLE_VERSION}, but is {bundle_version}");
+        }
+        if *bundle.engine.cfg() != self.cfg {
+            log::warn!("bundle config differs from the simulation directory config");
+        }
+
+        let clock_shift = bundle.metadata.clock_shift;
+        self.change_clock(run_idx, &mut bundle.engine, clock_change, clock_shift)?;
+
+        self.save_engine(run_idx, &bundle.engine)
+            .context("failed to save checkpoint")?;
+
+        let run_dir = self.storage.location(&self.run_dir(run_idx));
+        log::info!("created {run_dir:?}");
+
+        Ok(())
+    }
+
+    /// Analyze all output files from a simulation run with its effective configuration (see
+    /// `load_run_config`) and save the analysis.
+    ///
+    /// The accumulated analysis is also saved (`analysis-partial.msgpack`), so that later calls
+    /// only add the new output files. If `json` is set, the analysis is also saved as
+    /// pretty-printed JSON, for inspection.
+    ///
+    /// Only the observables within `time_window` are analyzed and, if `burn_in` is set, the
+    /// initial transient within it is detected (see `detect_burn_in`) and discarded too. In both
+    /// cases the analysis is made from scratch (without saving the partial analysis).
+    pub fn analyze_run(
+        &self,
+        run_idx: usize,
+        json: bool,
+        mut time_window: TimeWindow,
+        burn_in: bool,
+    ) -> Result<()> {
+        let burn_in_time = if burn_in {
+            Some(self.detect_burn_in(run_idx, time_window)?)
+        } else {
+            None
+        };
+        if burn_in_time.is_some() {
+            time_window.t_min = burn_in_time;
+        }
+        let cfg = self.load_run_config(run_idx)?;
+        let from_scratch = !time_window.is_unbounded();
+        let (mut analyzer, n_files) = match self.load_partial_analysis(run_idx)? {
+            _ if from_scratch => (Analyzer::new(cfg), 0),
+            Some(partial) if partial.analyzer.cfg() == &cfg => {
+                log::info!("resuming analysis after {} output files", partial.n_files);
+                (partial.analyzer, partial.n_files)
+            }
+            Some(_) => {
+                log::warn!("discarding partial analysis made with a different config");
+                (Analyzer::new(cfg), 0)
+            }
+            None => (Analyzer::new(cfg), 0),
+        };
+
+        let file_indices: Vec<usize> = self
+            .output_file_indices(run_idx)
+            .context("failed to list output files")?
+            .into_iter()
+            .filter(|&file_idx| file_idx >= n_files)
+            .collect();
+        if file_indices
+            .first()
+            .is_some_and(|&file_idx| file_idx != n_files)
+        {
+            bail!("some output files have been pruned, the analysis would be incomplete");
+        }
+        if let Some(clock_shift) = self.load_clock_shift(run_idx)? {
+            analyzer.set_clock_shift(clock_shift);
+        }
+        analyzer.set_time_window(time_window);
+
+        // An output file with a flush metadata file is still being written, so it is not saved
+        // in the partial analysis.
+        let mut partial = PartialAnalysis { n_files, analyzer };
+        let mut in_progress = None;
+        for file_idx in file_indices {
+            let output_file = self.output_file(run_idx, file_idx);
+            if self.storage.exists(&format!("{output_file}.flushed"))? {
+                in_progress = Some(file_idx);
+                break;
+            }
+            let mut reader = self.storage.reader(&output_file)?;
+            partial.analyzer.add_output(&mut reader).with_context(|| {
+                format!("failed to add {:?}", self.storage.location(&output_file))
+            })?;
+            partial.n_files = file_idx + 1;
+        }
+
+        if !from_scratch {
+            let partial_file = self.partial_analysis_file(run_idx);
+            let mut writer = self.storage.writer(&partial_file)?;
+            encode::write(&mut writer, &partial).context("failed to serialize partial analysis")?;
+            writer.finish().with_context(|| {
+                format!("failed to write {:?}", self.storage.location(&partial_file))
+            })?;
+        }
+
+        let mut analyzer = partial.analyzer;
+        if let Some(file_idx) = in_progress {
+            // Only the flushed records are complete.
+            let output_file = self.output_file(run_idx, file_idx);
+            let flush_file = format!("{output_file}.flushed");
+            let metadata: FlushMetadata = decode::from_read(self.storage.reader(&flush_file)?)
+                .with_context(|| {
+                    format!("failed to load {:?}", self.storage.location(&flush_file))
+                })?;
+            let mut reader = self.storage.reader(&output_file)?;
+            let observables = read_flushed_observables(&mut reader, metadata.n_records)
+                .with_context(|| {
+                    format!("failed to add {:?}", self.storage.location(&output_file))
+                })?;
+            observables
+                .into_iter()
+                .for_each(|observables| analyzer.add_observables(observables));
+        }
+
+        let mut analysis = analyzer
+            .calc_analysis()
+            .context("failed to make analysis")?;
+        analysis.burn_in_time = burn_in_time;
+        for deviation in &analysis.env_trans.deviations {
+            log::warn!("environment process of run {run_idx} deviates from config: {deviation}");
+        }
+
+        let analysis_file = self.analysis_file(run_idx);
+        let mut writer = self.storage.writer(&analysis_file)?;
+        encode::write(&mut writer, &analysis).context("failed to serialize analysis")?;
+        writer.finish().with_context(|| {
+            format!(
+                "failed to write {:?}",
+                self.storage.location(&analysis_file)
+            )
+        })?;
+
+        if json {
+            let json_file = self.analysis_json_file(run_idx);
+            let mut writer = self.storage.writer(&json_file)?;
+            analysis.write_json(&mut writer)?;
+            writer.finish().with_context(|| {
+                format!("failed to write {:?}", self.storage.location(&json_file))
+            })?;
+        }
+
+        let run_dir = self.storage.location(&self.run_dir(run_idx));
+        log::info!("analyzed {run_dir:?}");
+
+        Ok(())
+    }
+
+    /// Detect the end of the initial transient of a simulation run within a window of simulation
+    /// times, returning the simulation time (of the original clock) before which its observables
+    /// should be discarded.
+    ///
+    /// The MSER-5 rule (see `stats::mser_truncation`) is applied to the first element of the
+    /// average phenotypic strategy of every saved observables in the window (or their number of
+    /// agents, if it is not computed).
+    fn detect_burn_in(&self, run_idx: usize, time_window: TimeWindow) -> Result<f64> {
+        let clock_shift = self.load_clock_shift(run_idx)?;
+        let mut times = Vec::new();
+        let mut samples = Vec::new();
+        for file_idx in self.output_file_indices(run_idx)? {
+            let output_file = self.output_file(run_idx, file_idx);
+            let mut reader = self.storage.reader(&output_file)?;
+            let all_observables = read_observables(&mut reader).with_context(|| {
+                format!("failed to read {:?}", self.storage.location(&output_file))
+            })?;
+            for mut observables in all_observables {
+                if let Some(clock_shift) = &clock_shift {
+                    clock_shift.unshift(&mut observables);
+                }
+                if !time_window.contains(observables.time) {
+                    continue;
+                }
+                times.push(observables.time);
+                samples.push(match &observables.avg_strat_phe {
+                    Some(avg_strat_phe) => avg_strat_phe[0],
+                    None => observables.n_agents,
+                });
+            }
+        }
+
+        let n_discarded = mser_truncation(&samples);
+        let Some(&burn_in_time) = times.get(n_discarded) else {
+            bail!("no observables to detect the burn-in");
+        };
+        log::info!("discarding {n_discarded} observables before time {burn_in_time} as burn-in");
+
+        Ok(burn_in_time)
+    }
+
+    /// Aggregate the analyses of every analyzed simulation run into a simulation-level analysis
+    /// (`analysis.msgpack` at the root of the simulation directory, also saved as JSON in
+    /// `analysis.json` if `json` is set).
+    pub fn aggregate_runs(&self, json: bool) -> Result<()> {
+        let mut analyses = Vec::new();
+        for run_idx in self.run_indices().context("failed to list runs")? {
+            let analysis_file = self.analysis_file(run_idx);
+            if !self.storage.exists(&analysis_file)? {
+                log::warn!("skipping run {run_idx}, which has not been analyzed");
+                continue;
+            }
+            let analysis: Analysis = decode::from_read(self.storage.reader(&analysis_file)?)
+                .with_context(|| {
+                    format!("failed to load {:?}", self.storage.location(&analysis_file))
+                })?;
+            analyses.push(analysis);
+        }
+        if analyses.is_empty() {
+            bail!("no analyzed runs to aggregate");
+        }
+
+        let aggregate = AggregateAnalysis::new(&analyses);
+
+        let aggregate_file = "analysis.msgpack";
+        let mut writer = self.storage.writer(aggregate_file)?;
+        encode::write(&mut writer, &aggregate).context("failed to serialize aggregate analysis")?;
+        writer.finish().with_context(|| {
+            format!(
+                "failed to write {:?}",
+                self.storage.location(aggregate_file)
+            )
+        })?;
+
+        if json {
+            let json_file = "analysis.json";
+            let mut writer = self.storage.writer(json_file)?;
+            aggregate.write_json(&mut writer)?;
+            writer.finish().with_context(|| {
+                format!("failed to write {:?}", self.storage.location(json_file))
+            })?;
+        }
+
+        log::info!("aggregated the analyses of {} runs", analyses.len());
+
+        Ok(())
+    }
+
+    /// Collect the analyses of every analyzed run of this and some other simulation directories,
+    /// together with their configuration parameters, into a single CSV results table with one row
+    /// per run (`results.csv` in this simulation directory).
+    ///
+    /// Configuration parameters are named by their dotted keys (e.g. `model.prob_mut`), with lists
+    /// encoded as JSON. Analysis results are named as in the analysis, with numeric lists split
+    /// into one column per element (e.g. `avg_avg_strat_phe_0`), nested results named by their
+    /// dotted path and nested lists (e.g. distributions) left out. Runs with their own effective
+    /// configuration (`config.json`) use it instead of the simulation configuration.
+    pub fn collect_results<P: AsRef<Path>>(&self, other_sim_dirs: &[P]) -> Result<()> {
+        let mut rows = self.result_rows()?;
+        for other_sim_dir in other_sim_dirs {
+            let other_sim_dir = other_sim_dir.as_ref();
+            let other_mgr = Manager::new(other_sim_dir)
+                .with_context(|| format!("failed to create mgr for {other_sim_dir:?}"))?;
+            rows.extend(other_mgr.result_rows()?);
+        }
+        if rows.is_empty() {
+            bail!("no analyzed runs to collect");
+        }
+
+        // Columns are ordered by first appearance, with empty cells for missing values.
+        let mut columns: Vec<&str> = Vec::new();
+        for row in &rows {
+            for (column, _) in row {
+                if !columns.contains(&column.as_str()) {
+                    columns.push(column);
+                }
+            }
+        }
+
+        let results_file = "results.csv";
+        let location = self.storage.location(results_file);
+        let mut writer = BufWriter::new(self.storage.writer(results_file)?);
+        let header: Vec<String> = columns.iter().map(|column| csv_cell(column)).collect();
+        writeln!(writer, "{}", header.join(",")).context("failed to write header")?;
+        for row in &rows {
+            let cells: Vec<String> = columns
+                .iter()
+                .map(|column| {
+                    row.iter()
+                        .find(|(other, _)| other == column)
+                        .map_or(String::new(), |(_, value)| csv_cell(value))
+                })
+                .collect();
+            writeln!(writer, "{}", cells.join(",")).context("failed to write row")?;
+        }
+        writer
+            .into_inner()
+            .map_err(|error| error.into_error())
+            .context("failed to flush writer stream")?
+            .finish()
+            .with_context(|| format!("failed to write {location:?}"))?;
+        log::info!(
+            "collected the results of {} runs into {location:?}",
+            rows.len()
+        );
+
+        Ok(())
+    }
+
+    /// Build the results table rows (pairs of column names and values) of every analyzed run.
+    fn result_rows(&self) -> Result<Vec<Vec<(String, String)>>> {
+        let mut rows = Vec::new();
+        for run_idx in self.run_indices().context("failed to list runs")? {
+            let analysis_file = self.analysis_file(run_idx);
+            if !self.storage.exists(&analysis_file)? {
+                log::warn!("skipping run {run_idx}, which has not been analyzed");
+                continue;
+            }
+            let analysis: Analysis = decode::from_read(self.storage.reader(&analysis_file)?)
+                .with_context(|| {
+                    format!("failed to load {:?}", self.storage.location(&analysis_file))
+                })?;
+
+            let cfg = serde_json::to_value(self.load_run_config(run_idx)?)
+                .context("failed to serialize config")?;
+
+            let mut row = vec![
+                ("sim_dir".to_string(), self.storage.location("")),
+                ("run_idx".to_string(), run_idx.to_string()),
+            ];
+            flatten_config("", &cfg, &mut row);
+            let analysis =
+                serde_json::to_value(&analysis).context("failed to serialize analysis")?;
+            flatten_analysis("", &analysis, &mut row);
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+
+    /// Export all output files from a simulation run into a single CSV table, with one row per
+    /// saved observables (`observables.csv`).
+    pub fn export_run(&self, run_idx: usize) -> Result<()> {
+        let layout = CsvLayout::new(&self.load_run_config(run_idx)?);
+
+        let csv_file = self.observables_csv_file(run_idx);
+        let location = self.storage.location(&csv_file);
+        let mut writer = BufWriter::new(self.storage.writer(&csv_file)?);
+        write_csv_header(&mut writer, &layout)?;
+
+        let file_indices = self
+            .output_file_indices(run_idx)
+            .context("failed to list output files")?;
+        let mut n_rows = 0;
+        for file_idx in file_indices {
+            let output_file = self.output_file(run_idx, file_idx);
+            let mut reader = self.storage.reader(&output_file)?;
+            let all_observables = read_observables(&mut reader).with_context(|| {
+                format!("failed to read {:?}", self.storage.location(&output_file))
+            })?;
+            write_csv_rows(&mut writer, file_idx, &all_observables, &layout)?;
+            n_rows += all_observables.len();
+        }
+
+        writer
+            .into_inner()
+            .map_err(|error| error.into_error())
+            .context("failed to flush writer stream")?
+            .finish()
+            .with_context(|| format!("failed to write {location:?}"))?;
+        log::info!("exported {n_rows} observables to {location:?}");
+
+        Ok(())
+    }
+
+    /// Rebin all output files of a simulation run into coarser-grained versions, in place.
+    ///
+    /// Every block of `factor` consecutive observables of each file is turned into a single one.
+    pub fn rebin_run(&self, run_idx: usize, factor: usize, mode: RebinMode) -> Result<()> {
+        if factor < 2 {
+            bail!("rebinning factor must be at least 2, but is {factor}");
+        }
+        let _lock = self.lock_run(run_idx).context("failed to lock run")?;
+        self.discard_partial_analysis(run_idx)?;
+
+        let file_indices = self
+            .output_file_indices(run_idx)
+            .context("failed to list output files")?;
+        for file_idx in file_indices {
+            let output_file = self.output_file(run_idx, file_idx);
+            let location = self.storage.location(&output_file);
+
+            let mut reader = self.storage.reader(&output_file)?;
+            let all_observables = read_observables(&mut reader)
+                .with_context(|| format!("failed to read {location:?}"))?;
+            drop(reader);
+
+            let rebinned = rebin_observables(&all_observables, factor, mode);
+
+            let mut writer = self.storage.writer(&output_file)?;
+            write_observables(&mut writer, &rebinned)?;
+            writer
+                .finish()
+                .with_context(|| format!("failed to write {location:?}"))?;
+
+            let (n_old, n_new) = (all_observables.len(), rebinned.len());
+            log::info!("rebinned {location:?} ({n_old} -> {n_new} observables)");
+        }
+
+        Ok(())
+    }
+
+    /// Concatenate every group of `group_size` consecutive output files of a simulation run into a
+    /// single larger one.
+    ///
+    /// The consolidated output files are renumbered consecutively, starting from the first index,
+    /// along with their lineage logs, population snapshots and extinction logs, which are
+    /// concatenated too. Tracer events, step-level observables and event logs only make sense for
+    /// the output file they were written with, so runs with any are refused.
+    pub fn concat_outputs(&self, run_idx: usize, group_size: usize) -> Result<()> {
+        if group_size < 2 {
+            bail!("group size must be at least 2, but is {group_size}");
+        }
+        let _lock = self.lock_run(run_idx).context("failed to lock run")?;
+
+        let file_indices = self
+            .output_file_indices(run_idx)
+            .context("failed to list output files")?;
+        for &file_idx in &file_indices {
+            for file in [
+                self.tracers_file(run_idx, file_idx),
+                self.observers_file(run_idx, file_idx),
+                self.events_file(run_idx, file_idx),
+            ] {
+                if self.storage.exists(&file)? {
+                    let file = self.storage.location(&file);
+                    bail!("{file:?} cannot be concatenated");
+                }
+            }
+        }
+        let Some(&first_idx) = file_indices.first() else {
+            return Ok(());
+        };
+
+        self.discard_partial_analysis(run_idx)?;
+
+        // Output files are renumbered, so their convergence estimates must be recomputed and the
+        // checkpoint history no longer matches them.
+        let convergence_file = self.convergence_file(run_idx);
+        if self.storage.exists(&convergence_file)? {
+            self.storage.remove(&convergence_file)?;
+        }
+        for history_idx in self.history_checkpoint_indices(run_idx)? {
+            self.storage
+                .remove(&self.history_checkpoint_file(run_idx, history_idx))?;
+        }
+
+        // The new index of every group is never larger than the indices of its files, so files
+        // are only overwritten after they have been read. The sources are only removed once the
+        // consolidated file has been written.
+        for (group_idx, group) in file_indices.chunks(group_size).enumerate() {
+            let mut buffer = Vec::new();
+            for &file_idx in group {
+                let output_file = self.output_file(run_idx, file_idx);
+                let location = self.storage.location(&output_file);
+                self.storage
+                    .reader(&output_file)?
+                    .read_to_end(&mut buffer)
+                    .with_context(|| format!("failed to read {location:?}"))?;
+            }
+
+            let new_idx = first_idx + group_idx;
+            let new_output_file = self.output_file(run_idx, new_idx);
+            let location = self.storage.location(&new_output_file);
+            let mut writer = self.storage.writer(&new_output_file)?;
+            writer
+                .write_all(&buffer)
+                .with_context(|| format!("failed to write {location:?}"))?;
+            writer
+                .finish()
+                .with_context(|| format!("failed to write {location:?}"))?;
+
+            for &file_idx in group.iter().filter(|&&file_idx| file_idx != new_idx) {
+                self.storage.remove(&self.output_file(run_idx, file_idx))?;
+            }
+
+            self.concat_side_files::<LineageEntry>(run_idx, Self::lineage_file, group, new_idx)?;
+            self.concat_side_files::<Snapshot>(run_idx, Self::snapshots_file, group, new_idx)?;
+            self.concat_side_files::<Extinction>(run_idx, Self::extinctions_file, group, new_idx)?;
+
+            let n_files = group.len();
+            log::info!("concatenated {n_files} output files into {location:?}");
+        }
+
+        Ok(())
+    }
+
+    /// Concatenate the logs stored next to a group of output files of a simulation run (in the
+    /// side files given by `side_file`) into the one of their consolidated output file (see
+    /// `concat_outputs`), which is only written if any of them has one.
+    fn concat_side_files<T: Serialize + DeserializeOwned>(
+        &self,
+        run_idx: usize,
+        side_file: fn(&Self, usize, usize) -> String,
+        group: &[usize],
+        new_idx: usize,
+    ) -> Result<()> {
+        let mut entries: Option<Vec<T>> = None;
+        for &file_idx in group {
+            let file = side_file(self, run_idx, file_idx);
+            if !self.storage.exists(&file)? {
+                continue;
+            }
+            let file_entries: Vec<T> = decode::from_read(self.storage.reader(&file)?)
+                .with_context(|| format!("failed to load {:?}", self.storage.location(&file)))?;
+            entries.get_or_insert_default().extend(file_entries);
+        }
+        let Some(entries) = entries else {
+            return Ok(());
+        };
+
+        let new_file = side_file(self, run_idx, new_idx);
+        let mut writer = self.storage.writer(&new_file)?;
+        encode::write(&mut writer, &entries).context("failed to serialize log")?;
+        writer
+            .finish()
+            .with_context(|| format!("failed to write {:?}", self.storage.location(&new_file)))?;
+
+        for &file_idx in group.iter().filter(|&&file_idx| file_idx != new_idx) {
+            let file = side_file(self, run_idx, file_idx);
+            if self.storage.exists(&file)? {
+                self.storage.remove(&file)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Report the status of the given simulation runs (all runs if `None`).
+    ///
+    /// For every run, the number of output files, the total number of steps and simulation time
+    /// (from its manifest, or only the step from its checkpoint metadata if it has no manifest),
+    /// the time of its checkpoint, whether it is locked and whether it has been analyzed are
+    /// reported. If `disk` is set,
+    /// also report their storage consumption.
+    pub fn report_status(&self, run_idx: Option<usize>, disk: bool) -> Result<()> {
+        let run_indices = match run_idx {
+            Some(run_idx) => vec![run_idx],
+            None => self.run_indices().context("failed to list runs")?,
+        };
+
+        let mut total_size = 0;
+        for run_idx in run_indices {
+            let run_dir = self.run_dir(run_idx);
+            let n_files = self
+                .output_file_indices(run_idx)
+                .context("failed to list output files")?
+                .len();
+            let mut line = format!("{run_dir}: {n_files} output files");
+            if let Some(manifest) = self.load_manifest(run_idx)? {
+                line += &format!(", step {}, time {:.3}", manifest.n_steps, manifest.time);
+            } else {
+                let metadata_file = self.checkpoint_metadata_file(run_idx);
+                if self.storage.exists(&metadata_file)? {
+                    let metadata: CheckpointMetadata =
+                        decode::from_read(self.storage.reader(&metadata_file)?)
+                            .context("failed to deserialize checkpoint metadata")?;
+                    line += &format!(", step {}", metadata.step);
+                }
+            }
+            let checkpoint_file = self.checkpoint_file(run_idx);
+            if self.storage.exists(&checkpoint_file)? {
+                let modified = self.storage.modified(&checkpoint_file)?;
+                line += &format!(", checkpoint: {}", format_timestamp(modified));
+            }
+            if self.storage.exists(&self.lock_file(run_idx))? {
+                line += ", locked";
+            }
+            let analyzed = self.storage.exists(&self.analysis_file(run_idx))?;
+            line += &format!(", analyzed: {analyzed}");
+            if let Some(record) = self.load_convergence_record(run_idx)? {
+                line += &format!(", converged: {}", record.converged);
+            }
+
+            if disk {
+                let mut run_size = 0;
+                let mut output_size = 0;
+                for key in self.storage.list(&format!("{run_dir}/"))? {
+                    let size = self.storage.size(&key)?;
+                    if key.starts_with(&format!("{run_dir}/output-")) {
+                        output_size += size;
+                    }
+                    run_size += size;
+                }
+                total_size += run_size;
+                line += &format!(
+                    ", outputs: {}, total: {}",
+                    format_size(output_size),
+                    format_size(run_size)
+                );
+            }
+
+            println!("{line}");
+        }
+
+        if disk {
+            println!("total: {}", format_size(total_size));
+        }
+
+        Ok(())
+    }
+
+    /// Prune the output files of the given simulation runs (all runs if `None`).
+    ///
+    /// Keeps the last `keep_files` output files (see `OutputParams`) and skips runs that have not
+    /// been analyzed yet. Checkpoints and analyses are always kept.
+    pub fn prune(&self, run_idx: Option<usize>) -> Result<()> {
+        let Some(keep_files) = self.cfg.output.keep_files else {
+            bail!("no retention policy set (output.keep_files)");
+        };
+
+        let run_indices = match run_idx {
+            Some(run_idx) => vec![run_idx],
+            None => self.run_indices().context("failed to list runs")?,
+        };
+
+        for run_idx in run_indices {
+            let run_dir = self.storage.location(&self.run_dir(run_idx));
+            let _lock = self
+                .lock_run(run_idx)
+                .with_context(|| format!("failed to lock {run_dir:?}"))?;
+            if !self.storage.exists(&self.analysis_file(run_idx))? {
+                log::warn!("skipped {run_dir:?}: not analyzed");
+                continue;
+            }
+
+            let file_indices = self
+                .output_file_indices(run_idx)
+                .context("failed to list output files")?;
+            let n_prune = file_indices.len().saturating_sub(keep_files);
+            for &file_idx in &file_indices[..n_prune] {
+                self.storage.remove(&self.output_file(run_idx, file_idx))?;
+            }
+            log::info!("pruned {n_prune} output files from {run_dir:?}");
+        }
+
+        Ok(())
+    }
+
+    /// Remove the files of the simulation run (or all runs).
+    ///
+    /// Files can be restricted to outputs or analyses and to those older than a given age, and
+    /// analyses and checkpoints can be kept (see `CleanFilter`). In a dry run the selected files
+    /// are only listed.
+    ///
+    /// Output files can also be restricted to those already added to the partial analysis of
+    /// their run (and hence to its analysis), which are no longer needed to update it.
+    pub fn clean(&self, run_idx: Option<usize>, filter: CleanFilter, dry_run: bool) -> Result<()> {
+        let prefix = match run_idx {
+            Some(run_idx) => format!("{}/", self.run_dir(run_idx)),
+            None => "run-".to_string(),
+        };
+
+        let now = SystemTime::now();
+        let mut n_analyzed_files = BTreeMap::new();
+        let mut n_files = 0;
+        for key in self.storage.list(&prefix).context("failed to list files")? {
+            let file_name = key.rsplit('/').next().unwrap_or(&key);
+            let is_output = [
+                "output-",
+                "tracers-",
+                "observers-",
+                "lineage-",
+                "events-",
+                "snapshots-",
+                "extinctions-",
+            ]
+            .iter()
+            .any(|prefix| file_name.starts_with(prefix));
+            let is_analysis = file_name.starts_with("analysis");
+            let is_checkpoint = file_name.starts_with("checkpoint");
+            if (filter.outputs_only && !is_output)
+                || (filter.analysis_only && !is_analysis)
+                || (filter.keep_analysis && is_analysis)
+                || (filter.keep_checkpoint && is_checkpoint)
+            {
+                continue;
+            }
+            if filter.analyzed_outputs {
+                let Some((run_idx, file_idx)) = key
+                    .strip_prefix("run-")
+                    .and_then(|key| key.split_once("/output-"))
+                    .and_then(|(run_idx, file_idx)| {
+                        let file_idx = file_idx.strip_suffix(".msgpack")?;
+                        Some((run_idx.parse().ok()?, file_idx.parse::<usize>().ok()?))
+                    })
+                else {
+                    continue;
+                };
+                let n_analyzed = match n_analyzed_files.get(&run_idx) {
+                    Some(&n_analyzed) => n_analyzed,
+                    None => {
+                        let partial = self.load_partial_analysis(run_idx)?;
+                        let n_analyzed = partial.map_or(0, |partial| partial.n_files);
+                        n_analyzed_files.insert(run_idx, n_analyzed);
+                        n_analyzed
+                    }
+                };
+                if file_idx >= n_analyzed {
+                    continue;
+                }
+            }
+            if let Some(older_than) = filter.older_than {
+                let age = now
+                    .duration_since(self.storage.modified(&key)?)
+                    .unwrap_or_default();
+                if age < older_than {
+                    continue;
+                }
+            }
+
+            if dry_run {
+                println!("{}", self.storage.location(&key));
+            } else {
+                self.storage.remove(&key)?;
+            }
+            n_files += 1;
+        }
+
+        if !dry_run {
+            log::info!("removed {n_files} files");
+        }
+
+        Ok(())
+    }
+
+    /// Lock a simulation run, failing if another process holds its lock.
+    ///
+    /// Stale locks, whose process has exited without releasing them, are replaced (but only those
+    /// of processes of the same host can be detected).
+    fn lock_run(&self, run_idx: usize) -> Result<RunLockGuard<'_>> {
+        let lock_file = self.lock_file(run_idx);
+        let location = self.storage.location(&lock_file);
+        let lock = RunLock {
+            host: host_name(),
+            pid: std::process::id(),
+            locked_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        };
+        let contents = serde_json::to_vec(&lock).context("failed to serialize lock")?;
+
+        // A stale lock is removed at most once, so that a racing resume is not overridden.
+        for attempt in 0..2 {
+            if self.storage.create_new(&lock_file, &contents)? {
+                return Ok(RunLockGuard {
+                    storage: &*self.storage,
+                    lock_file,
+                });
+            }
+            let mut holder = String::new();
+            self.storage
+                .reader(&lock_file)?
+                .read_to_string(&mut holder)
+                .with_context(|| format!("failed to read {location:?}"))?;
+            let holder: RunLock = serde_json::from_str(&holder)
+                .with_context(|| format!("failed to deserialize {location:?}"))?;
+            if attempt > 0 || !holder.is_stale() {
+                bail!(
+                    "run is locked by process {} on {:?} since {} (remove {location:?} if stale)",
+                    holder.pid,
+                    holder.host,
+                    format_timestamp(UNIX_EPOCH + Duration::from_secs(holder.locked_at))
+                );
+            }
+            log::warn!("removing stale lock {location:?} of process {}", holder.pid);
+            self.storage.remove(&lock_file)?;
+        }
+        unreachable!("the lock is either acquired or reported as held")
+    }
+
+    /// Load the initial agent data from the external sources specified in a configuration.
+    fn load_init_sources(&self, cfg: &Config) -> Result<InitSources> {
+        let mut init_sources = InitSources::default();
+
+        if let Some(strategy_source) = &cfg.init.strategy_source {
+            let analysis_file = strategy_source
+                .strip_prefix("analysis:")
+                .context("unknown strategy source")?;
+            let location = self.storage.location(analysis_file);
+            let mut reader = self.storage.reader(analysis_file)?;
+            let analysis: Analysis = decode::from_read(&mut reader)
+                .with_context(|| format!("failed to deserialize {location:?}"))?;
+            if analysis.dist_avg_strat_phe.len() != cfg.model.n_phe - 1 {
+                bail!("{location:?} has a different number of phenotypes");
+            }
+            log::info!("loaded strategy distribution from {location:?}");
+            init_sources.strat_hists = Some(analysis.dist_avg_strat_phe);
+            init_sources.strat_hist_range = analysis.range_avg_strat_phe;
+        }
+
+        if let Some(agents_file) = &cfg.init.agents_file {
+            let location = self.storage.location(agents_file);
+            let mut agents = String::new();
+            self.storage
+                .reader(agents_file)?
+                .read_to_string(&mut agents)
+                .with_context(|| format!("failed to read {location:?}"))?;
+            let agent_groups = parse_agent_groups(&agents, cfg.model.n_phe)
+                .with_context(|| format!("failed to load {location:?}"))?;
+            log::info!("loaded agent groups from {location:?}");
+            init_sources.agent_groups = Some(agent_groups);
+        }
+
+        if let Some(env_file) = (cfg.model.environment.as_ref()).and_then(|env| env.file.as_ref()) {
+            init_sources.env_trajectory = Some(self.load_env_trajectory(env_file, cfg)?);
+        }
+
+        Ok(init_sources)
+    }
+
+    /// Load the deterministic environment trajectory from an environment file.
+    fn load_env_trajectory(&self, env_file: &str, cfg: &Config) -> Result<Vec<EnvSwitch>> {
+        let location = self.storage.location(env_file);
+        let mut contents = String::new();
+        self.storage
+            .reader(env_file)?
+            .read_to_string(&mut contents)
+            .with_context(|| format!("failed to read {location:?}"))?;
+        let trajectory = parse_env_trajectory(&contents, cfg.model.n_env)
+            .with_context(|| format!("failed to load {location:?}"))?;
+        log::info!("loaded environment trajectory from {location:?}");
+        Ok(trajectory)
+    }
+
+    /// Save the effective configuration of a simulation run that overrides some parameters of the
+    /// simulation configuration, as pretty-printed JSON (`config.json`).
+    fn save_run_config(&self, run_idx: usize, cfg: &Config) -> Result<()> {
+        let run_cfg_file = self.run_cfg_file(run_idx);
+        let mut writer = self.storage.writer(&run_cfg_file)?;
+        serde_json::to_writer_pretty(&mut writer, cfg).context("failed to serialize config")?;
+        writeln!(writer).context("failed to write config")?;
+        writer.finish().with_context(|| {
+            format!("failed to write {:?}", self.storage.location(&run_cfg_file))
+        })?;
+        Ok(())
+    }
+
+    /// Load the effective configuration of a simulation run: the one recorded in its directory
+    /// (`config.json`), if it overrides some parameters, or else the simulation configuration.
+    fn load_run_config(&self, run_idx: usize) -> Result<Config> {
+        let run_cfg_file = self.run_cfg_file(run_idx);
+        if !self.storage.exists(&run_cfg_file)? {
+            return Ok(self.cfg.clone());
+        }
+        serde_json::from_reader(self.storage.reader(&run_cfg_file)?)
+            .with_context(|| format!("failed to load {:?}", self.storage.location(&run_cfg_file)))
+    }
+
+    /// Load the engine checkpoint of a simulation run.
+    fn load_engine(&self, run_idx: usize) -> Result<Engine> {
+        let checkpoint_file = self.checkpoint_file(run_idx);
+        let location = self.storage.location(&checkpoint_file);
+        let mut contents = Vec::new();
+        self.storage
+            .reader(&checkpoint_file)?
+            .read_to_end(&mut contents)
+            .with_context(|| format!("failed to read {location:?}"))?;
+
+        let metadata_file = self.checkpoint_metadata_file(run_idx);
+        let metadata: Option<CheckpointMetadata> = if self.storage.exists(&metadata_file)? {
+            Some(
+                decode::from_read(self.storage.reader(&metadata_file)?)
+                    .context("failed to deserialize checkpoint metadata")?,
+            )
+        } else {
+            None
+        };
+        // Refuse truncated or corrupted checkpoints before trying to decode them.
+        if let Some(metadata) = &metadata
+            && digest(&contents) != metadata.digest
+        {
+            bail!("checkpoint {location:?} is truncated or corrupted (digest mismatch)");
+        }
+
+        let mut engine = Engine::load_checkpoint(&mut contents.as_slice())
+            .with_context(|| format!("failed to load {location:?}"))?;
+        engine.set_n_threads(self.engine_threads);
+        engine.set_progress_interval(self.progress_interval);
+
+        if let Some(metadata) = metadata {
+            let actual = CheckpointMetadata::new(&engine, &contents);
+            if actual != metadata {
+                bail!(
+                    "checkpoint {location:?} does not match its metadata (stale or foreign \
+                    checkpoint?): expected {metadata:?}, found {actual:?}"
+                );
+            }
+        } else {
+            log::warn!("checkpoint {location:?} has no metadata, skipping verification");
+        }
+
+        log::info!("loaded {location:?}");
+        Ok(engine)
+    }
+
+    /// Update the manifest of a simulation run (`metadata.toml`) after checkpointing its engine,
+    /// adding the wall-clock time spent since the last update.
+    ///
+    /// A new manifest is started for new runs and for runs without one.
+    fn update_manifest(
+        &self,
+        run_idx: usize,
+        engine: &Engine,
+        wall_time: Duration,
+        new_run: bool,
+    ) -> Result<()> {
+        let prev_manifest = if new_run {
+            None
+        } else {
+            self.load_manifest(run_idx)?
+        };
+        let created_at = if new_run {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+            Some(now.as_secs())
+        } else {
+            prev_manifest
+                .as_ref()
+                .and_then(|manifest| manifest.created_at)
+        };
+        let prev_wall_time = prev_manifest.map_or(0.0, |manifest| manifest.wall_time);
+
+        let cfg = serde_json::to_vec(engine.cfg()).context("failed to serialize config")?;
+        let manifest = RunManifest {
+            mutare_version: env!("CARGO_PKG_VERSION").to_string(),
+            config_digest: format!("{:016x}", digest(&cfg)),
+            created_at,
+            wall_time: prev_wall_time + wall_time.as_secs_f64(),
+            n_steps: engine.step_count(),
+            time: engine.time(),
+        };
+
+        let manifest_file = self.manifest_file(run_idx);
+        let mut writer = self.storage.writer(&manifest_file)?;
+        writer
+            .write_all(manifest.to_toml().as_bytes())
+            .context("failed to write manifest")?;
+        writer.finish().with_context(|| {
+            format!(
+                "failed to write {:?}",
+                self.storage.location(&manifest_file)
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Save the engine checkpoint of a simulation run, together with its metadata.
+    fn save_engine(&self, run_idx: usize, engine: &Engine) -> Result<()> {
+        let mut contents = Vec::new();
+        engine.save_checkpoint(&mut contents)?;
+
+        let checkpoint_file = self.checkpoint_file(run_idx);
+        let mut writer = self.storage.writer(&checkpoint_file)?;
+        writer.write_all(&contents).with_context(|| {
+            format!(
+                "failed to write {:?}",
+                self.storage.location(&checkpoint_file)
+            )
+        })?;
+        writer.finish().with_context(|| {
+            format!(
+                "failed to write {:?}",
+                self.storage.location(&checkpoint_file)
+            )
+        })?;
+
+        let metadata_file = self.checkpoint_metadata_file(run_idx);
+        let metadata = CheckpointMetadata::new(engine, &contents);
+        let mut writer = self.storage.writer(&metadata_file)?;
+        encode::write(&mut writer, &metadata).context("failed to serialize checkpoint metadata")?;
+        writer.finish().with_context(|| {
+            format!(
+                "failed to write {:?}",
+                self.storage.location(&metadata_file)
+            )
+        })?;
+
+        if let Some(keep_checkpoints) = engine.cfg().output.keep_checkpoints {
+            self.archive_checkpoint(run_idx, &contents, keep_checkpoints)
+                .context("failed to archive checkpoint")?;
+        }
+
+        Ok(())
+    }
+
+    /// Copy the checkpoint contents of a simulation run into its checkpoint history, named after
+    /// the index of its next output file (`checkpoint-NNNN.msgpack`), and remove all but the last
+    /// `keep_checkpoints` checkpoints of the history.
+    fn archive_checkpoint(
+        &self,
+        run_idx: usize,
+        contents: &[u8],
+        keep_checkpoints: usize,
+    ) -> Result<()> {
+        let file_idx = self
+            .output_file_indices(run_idx)
+            .context("failed to list output files")?
+            .last()
+            .map_or(0, |file_idx| file_idx + 1);
+
+        let history_file = self.history_checkpoint_file(run_idx, file_idx);
+        let location = self.storage.location(&history_file);
+        let mut writer = self.storage.writer(&history_file)?;
+        writer
+            .write_all(contents)
+            .with_context(|| format!("failed to write {location:?}"))?;
+        writer
+            .finish()
+            .with_context(|| format!("failed to write {location:?}"))?;
+
+        let history_indices = self.history_checkpoint_indices(run_idx)?;
+        let n_remove = history_indices.len().saturating_sub(keep_checkpoints);
+        for &history_idx in &history_indices[..n_remove] {
+            self.storage
+                .remove(&self.history_checkpoint_file(run_idx, history_idx))?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply a clock change to the engine of a new simulation run and record the resulting clock
+    /// shift (composed with the one of the original run, if any) in the run directory.
+    fn change_clock(
+        &self,
+        run_idx: usize,
+        engine: &mut Engine,
+        clock_change: Option<ClockChange>,
+        prev_clock_shift: Option<ClockShift>,
+    ) -> Result<()> {
+        if clock_change.is_none() && prev_clock_shift.is_none() {
+            return Ok(());
+        }
+
+        let (time, step) = match clock_change {
+            None => (engine.time(), engine.step_count()),
+            Some(ClockChange::Reset) => (0.0, 0),
+            Some(ClockChange::Offset { time, step }) => (
+                engine.time() + time,
+                engine
+                    .step_count()
+                    .checked_add_signed(step)
+                    .context("shifted simulation step must be non-negative")?,
+            ),
+        };
+        if time < 0.0 {
+            bail!("shifted simulation time must be non-negative, but is {time}");
+        }
+
+        let clock_shift = engine.set_clock(time, step);
+        let clock_shift = prev_clock_shift.map_or(clock_shift, |prev| prev.then(clock_shift));
+        log::info!("{clock_shift:#?}");
+
+        let clock_file = self.clock_file(run_idx);
+        let mut writer = self.storage.writer(&clock_file)?;
+        encode::write(&mut writer, &clock_shift).context("failed to serialize clock shift")?;
+        writer
+            .finish()
+            .with_context(|| format!("failed to write {:?}", self.storage.location(&clock_file)))?;
+
+        Ok(())
+    }
+
+    /// Load the clock shift of a simulation run (if any).
+    fn load_clock_shift(&self, run_idx: usize) -> Result<Option<ClockShift>> {
+        let clock_file = self.clock_file(run_idx);
+        if !self.storage.exists(&clock_file)? {
+            return Ok(None);
+        }
+        let clock_shift = decode::from_read(self.storage.reader(&clock_file)?)
+            .with_context(|| format!("failed to load {:?}", self.storage.location(&clock_file)))?;
+        Ok(Some(clock_shift))
+    }
+
+    fn run_dir(&self, run_idx: usize) -> String {
+        format!("run-{run_idx:04}")
+    }
+
+    fn run_indices(&self) -> Result<Vec<usize>> {
+        let mut run_indices: Vec<usize> = self
+            .storage
+            .list("run-")?
+            .iter()
+            .filter_map(|key| key.strip_prefix("run-")?.split('/').next()?.parse().ok())
+            .collect();
+        run_indices.dedup();
+        Ok(run_indices)
+    }
+
+    fn output_file_indices(&self, run_idx: usize) -> Result<Vec<usize>> {
+        let prefix = format!("{}/output-", self.run_dir(run_idx));
+        let mut file_indices: Vec<usize> = self
+            .storage
+            .list(&prefix)?
+            .iter()
+            .filter_map(|key| {
+                key.strip_prefix(&prefix)?
+                    .strip_suffix(".msgpack")?
+                    .parse()
+                    .ok()
+            })
+            .collect();
+        file_indices.sort();
+        Ok(file_indices)
+    }
+
+    fn checkpoint_file(&self, run_idx: usize) -> String {
+        format!("{}/checkpoint.msgpack", self.run_dir(run_idx))
+    }
+
+    fn checkpoint_metadata_file(&self, run_idx: usize) -> String {
+        format!("{}/checkpoint-metadata.msgpack", self.run_dir(run_idx))
+    }
+
+    fn history_checkpoint_file(&self, run_idx: usize, file_idx: usize) -> String {
+        format!("{}/checkpoint-{file_idx:04}.msgpack", self.run_dir(run_idx))
+    }
+
+    fn history_checkpoint_indices(&self, run_idx: usize) -> Result<Vec<usize>> {
+        let prefix = format!("{}/checkpoint-", self.run_dir(run_idx));
+        let mut file_indices: Vec<usize> = self
+            .storage
+            .list(&prefix)?
+            .iter()
+            .filter_map(|key| {
+                key.strip_prefix(&prefix)?
+                    .strip_suffix(".msgpack")?
+                    .parse()
+                    .ok()
+            })
+            .collect();
+        file_indices.sort();
+        Ok(file_indices)
+    }
+
+    fn output_file(&self, run_idx: usize, file_idx: usize) -> String {
+        format!("{}/output-{file_idx:04}.msgpack", self.run_dir(run_idx))
+    }
+
+    fn observables_csv_file(&self, run_idx: usize) -> String {
+        format!("{}/observables.csv", self.run_dir(run_idx))
+    }
+
+    fn tracers_file(&self, run_idx: usize, file_idx: usize) -> String {
+        format!("{}/tracers-{file_idx:04}.msgpack", self.run_dir(run_idx))
+    }
+
+    fn snapshots_file(&self, run_idx: usize, file_idx: usize) -> String {
+        format!("{}/snapshots-{file_idx:04}.msgpack", self.run_dir(run_idx))
+    }
+
+    fn extinctions_file(&self, run_idx: usize, file_idx: usize) -> String {
+        format!(
+            "{}/extinctions-{file_idx:04}.msgpack",
+            self.run_dir(run_idx)
+        )
+    }
+
+    fn extinction_analysis_file(&self, run_idx: usize, ext: &str) -> String {
+        format!("{}/analysis-extinctions.{ext}", self.run_dir(run_idx))
+    }
+
+    fn events_file(&self, run_idx: usize, file_idx: usize) -> String {
+        format!("{}/events-{file_idx:04}.msgpack", self.run_dir(run_idx))
+    }
+
+    fn observers_file(&self, run_idx: usize, file_idx: usize) -> String {
+        format!("{}/observers-{file_idx:04}.msgpack", self.run_dir(run_idx))
+    }
+
+    fn lineage_file(&self, run_idx: usize, file_idx: usize) -> String {
+        format!("{}/lineage-{file_idx:04}.msgpack", self.run_dir(run_idx))
+    }
+
+    fn analysis_file(&self, run_idx: usize) -> String {
+        format!("{}/analysis.msgpack", self.run_dir(run_idx))
+    }
+
+    fn partial_analysis_file(&self, run_idx: usize) -> String {
+        format!("{}/analysis-partial.msgpack", self.run_dir(run_idx))
+    }
+
+    fn load_partial_analysis(&self, run_idx: usize) -> Result<Option<PartialAnalysis>> {
+        let partial_file = self.partial_analysis_file(run_idx);
+        if !self.storage.exists(&partial_file)? {
+            return Ok(None);
+        }
+        let partial =
+            decode::from_read(self.storage.reader(&partial_file)?).with_context(|| {
+                format!("failed to load {:?}", self.storage.location(&partial_file))
+            })?;
+        Ok(Some(partial))
+    }
+
+    /// Remove the partial analysis of a simulation run, after its output files have changed.
+    fn discard_partial_analysis(&self, run_idx: usize) -> Result<()> {
+        let partial_file = self.partial_analysis_file(run_idx);
+        if self.storage.exists(&partial_file)? {
+            self.storage.remove(&partial_file)?;
+        }
+        Ok(())
+    }
+
+    fn convergence_file(&self, run_idx: usize) -> String {
+        format!("{}/convergence.msgpack", self.run_dir(run_idx))
+    }
+
+    fn load_convergence_record(&self, run_idx: usize) -> Result<Option<ConvergenceRecord>> {
+        let convergence_file = self.convergence_file(run_idx);
+        if !self.storage.exists(&convergence_file)? {
+            return Ok(None);
+        }
+        let record =
+            decode::from_read(self.storage.reader(&convergence_file)?).with_context(|| {
+                format!(
+                    "failed to load {:?}",
+                    self.storage.location(&convergence_file)
+                )
+            })?;
+        Ok(Some(record))
+    }
+
+    fn analysis_json_file(&self, run_idx: usize) -> String {
+        format!("{}/analysis.json", self.run_dir(run_idx))
+    }
+
+    fn garden_file(&self, run_idx: usize) -> String {
+        format!("{}/garden.msgpack", self.run_dir(run_idx))
+    }
+
+    fn clock_file(&self, run_idx: usize) -> String {
+        format!("{}/clock.msgpack", self.run_dir(run_idx))
+    }
+
+    fn seed_file(&self, run_idx: usize) -> String {
+        format!("{}/seed.msgpack", self.run_dir(run_idx))
+    }
+
+    fn run_cfg_file(&self, run_idx: usize) -> String {
+        format!("{}/config.json", self.run_dir(run_idx))
+    }
+
+    fn lock_file(&self, run_idx: usize) -> String {
+        format!("{}/resume.lock", self.run_dir(run_idx))
+    }
+
+    fn manifest_file(&self, run_idx: usize) -> String {
+        format!("{}/metadata.toml", self.run_dir(run_idx))
+    }
+
+    fn load_manifest(&self, run_idx: usize) -> Result<Option<RunManifest>> {
+        let manifest_file = self.manifest_file(run_idx);
+        if !self.storage.exists(&manifest_file)? {
+            return Ok(None);
+        }
+        let location = self.storage.location(&manifest_file);
+        let mut manifest = String::new();
+        self.storage
+            .reader(&manifest_file)?
+            .read_to_string(&mut manifest)
+            .with_context(|| format!("failed to read {location:?}"))?;
+        let manifest = toml::from_str(&manifest)
+            .with_context(|| format!("failed to deserialize {location:?}"))?;
+        Ok(Some(manifest))
+    }
+}
+
+/// Compute the 64-bit FNV-1a hash of some bytes.
+fn digest(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Get the host name of the machine (empty if unknown).
+fn host_name() -> String {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } == 0 {
+            let len = buf.iter().position(|&byte| byte == 0).unwrap_or(buf.len());
+            return String::from_utf8_lossy(&buf[..len]).into_owned();
+        }
+    }
+    String::new()
+}
+
+/// Check whether a process of the same host exists (assumed to exist if it can not be checked).
+fn process_exists(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        // Signal 0 only checks whether the process exists (and may be signaled).
+        let Ok(pid) = libc::pid_t::try_from(pid) else {
+            return true;
+        };
+        if unsafe { libc::kill(pid, 0) } == 0 {
+            return true;
+        }
+        std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = pid;
+        true
+    }
+}
+
+/// Format a size in bytes using binary prefixes.
+fn format_size(size: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = size as f64;
+    let mut unit_idx = 0;
+    while size >= 1024.0 && unit_idx < units.len() - 1 {
+        size /= 1024.0;
+        unit_idx += 1;
+    }
+    format!("{size:.1} {}", units[unit_idx])
+}
+
+/// Format a time as a UTC date and time (`YYYY-MM-DD hh:mm:ss UTC`).
+fn format_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Convert the days since the Unix epoch to a civil date (Howard Hinnant's algorithm).
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_idx = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_idx + 2) / 5 + 1;
+    let month = if month_idx < 10 {
+        month_idx + 3
+    } else {
+        month_idx - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+/// Flatten a configuration into pairs of dotted keys and values, encoding lists as JSON.
+fn flatten_config(prefix: &str, value: &serde_json::Value, row: &mut Vec<(String, String)>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                flatten_config(&format!("{prefix}{key}."), value, row);
+            }
+        }
+        serde_json::Value::Array(_) => row.push((prefix_key(prefix), value.to_string())),
+        _ => row.push((prefix_key(prefix), json_cell(value))),
+    }
+}
+
+/// Flatten an analysis into pairs of names and values, splitting numeric lists into one value per
+/// element and leaving out nested lists.
+fn flatten_analysis(prefix: &str, value: &serde_json::Value, row: &mut Vec<(String, String)>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                flatten_analysis(&format!("{prefix}{key}."), value, row);
+            }
+        }
+        serde_json::Value::Array(values) => {
+            if values
+                .iter()
+                .all(|value| value.is_number() || value.is_null())
+            {
+                for (idx, value) in values.iter().enumerate() {
+                    row.push((format!("{}_{idx}", prefix_key(prefix)), json_cell(value)));
+                }
+            }
+        }
+        _ => row.push((prefix_key(prefix), json_cell(value))),
+    }
+}
+
+/// Remove the trailing separator of a flattened key prefix.
+fn prefix_key(prefix: &str) -> String {
+    prefix.trim_end_matches('.').to_string()
+}
+
+/// Format a JSON scalar as a table cell (empty if null).
+fn json_cell(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(string) => string.clone(),
+        _ => value.to_string(),
+    }
+}
+
+/// Quote a CSV cell if it contains separators, quotes or line breaks.
+fn csv_cell(cell: &str) -> String {
+    if cell.contains([',', '"', '\n']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
     }
 }
@@ -0,0 +1,183 @@
+//! Configurable step-level observables.
+
+use crate::config::{Config, ObserverKind};
+use crate::types::{Event, State};
+use serde::{Deserialize, Serialize};
+
+/// Observable updated at every simulation step and finalized at the end of every output file.
+///
+/// Unlike the saved observables, which are snapshots of the state every `steps_per_save` steps,
+/// step-level observables see every step, so they can accumulate exact time averages.
+pub trait Observable: Send + Sync {
+    /// Update the observable with the state held during a step, the event ending it and the time
+    /// elapsed.
+    fn update(&mut self, state: &State, event: &Event, time_step: f64);
+
+    /// Get the final values of the observable.
+    fn finalize(&self) -> Vec<f64>;
+
+    /// Clone the observable into a new box.
+    fn clone_box(&self) -> Box<dyn Observable>;
+}
+
+impl Clone for Box<dyn Observable> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Final values of a step-level observable.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObserverResult {
+    /// Kind of observable.
+    pub kind: ObserverKind,
+
+    /// Final values.
+    pub values: Vec<f64>,
+}
+
+/// Build the step-level observables selected in the configuration.
+pub fn build_observers(cfg: &Config) -> Vec<(ObserverKind, Box<dyn Observable>)> {
+    cfg.output
+        .observers
+        .iter()
+        .map(|&kind| {
+            let observer: Box<dyn Observable> = match kind {
+                ObserverKind::EnvOccupancy => Box::new(EnvOccupancy::new(cfg.model.n_env)),
+                ObserverKind::AvgNAgents => Box::new(AvgNAgents::default()),
+                ObserverKind::AvgDistPhe => Box::new(AvgDistPhe::new(cfg.model.n_phe)),
+                ObserverKind::EventRates => Box::new(EventRates::default()),
+            };
+            (kind, observer)
+        })
+        .collect()
+}
+
+/// Fraction of time spent in every environment.
+#[derive(Clone)]
+pub struct EnvOccupancy {
+    /// Time spent in every environment.
+    env_times: Vec<f64>,
+}
+
+impl EnvOccupancy {
+    /// Create a new environment occupancy observable.
+    pub fn new(n_env: usize) -> Self {
+        Self {
+            env_times: vec![0.0; n_env],
+        }
+    }
+}
+
+impl Observable for EnvOccupancy {
+    fn update(&mut self, state: &State, _event: &Event, time_step: f64) {
+        self.env_times[state.env] += time_step;
+    }
+
+    fn finalize(&self) -> Vec<f64> {
+        let total_time: f64 = self.env_times.iter().sum();
+        self.env_times
+            .iter()
+            .map(|env_time| env_time / total_time)
+            .collect()
+    }
+
+    fn clone_box(&self) -> Box<dyn Observable> {
+        Box::new(self.clone())
+    }
+}
+
+/// Time average of the number of agents.
+#[derive(Default, Clone)]
+pub struct AvgNAgents {
+    /// Time integral of the number of agents.
+    sum: f64,
+    /// Total time.
+    time: f64,
+}
+
+impl Observable for AvgNAgents {
+    fn update(&mut self, state: &State, _event: &Event, time_step: f64) {
+        self.sum += state.agents.len() as f64 * time_step;
+        self.time += time_step;
+    }
+
+    fn finalize(&self) -> Vec<f64> {
+        vec![self.sum / self.time]
+    }
+
+    fn clone_box(&self) -> Box<dyn Observable> {
+        Box::new(self.clone())
+    }
+}
+
+/// Time average of the distribution of phenotypes (expensive: visits every agent at every step).
+#[derive(Clone)]
+pub struct AvgDistPhe {
+    /// Time integral of the distribution of phenotypes.
+    sums: Vec<f64>,
+    /// Total time.
+    time: f64,
+}
+
+impl AvgDistPhe {
+    /// Create a new average distribution of phenotypes observable.
+    pub fn new(n_phe: usize) -> Self {
+        Self {
+            sums: vec![0.0; n_phe],
+            time: 0.0,
+        }
+    }
+}
+
+impl Observable for AvgDistPhe {
+    fn update(&mut self, state: &State, _event: &Event, time_step: f64) {
+        let weight = time_step / state.agents.len() as f64;
+        for agent in &state.agents {
+            self.sums[agent.phe()] += weight;
+        }
+        self.time += time_step;
+    }
+
+    fn finalize(&self) -> Vec<f64> {
+        self.sums.iter().map(|sum| sum / self.time).collect()
+    }
+
+    fn clone_box(&self) -> Box<dyn Observable> {
+        Box::new(self.clone())
+    }
+}
+
+/// Rate of every type of event (replications, deaths, environment transitions or updates,
+/// bottlenecks, migrations, phenotype switches and generations).
+#[derive(Default, Clone)]
+pub struct EventRates {
+    /// Number of events of every type.
+    counts: [f64; 7],
+    /// Total time.
+    time: f64,
+}
+
+impl Observable for EventRates {
+    fn update(&mut self, _state: &State, event: &Event, time_step: f64) {
+        let type_idx = match event {
+            Event::Replication { .. } => 0,
+            Event::Death { .. } => 1,
+            Event::EnvTrans { .. } | Event::PatchEnvTrans { .. } | Event::EnvUpdate => 2,
+            Event::Bottleneck { .. } => 3,
+            Event::Migration { .. } => 4,
+            Event::Switch { .. } => 5,
+            Event::Generation => 6,
+        };
+        self.counts[type_idx] += 1.0;
+        self.time += time_step;
+    }
+
+    fn finalize(&self) -> Vec<f64> {
+        self.counts.iter().map(|count| count / self.time).collect()
+    }
+
+    fn clone_box(&self) -> Box<dyn Observable> {
+        Box::new(self.clone())
+    }
+}
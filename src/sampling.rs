@@ -0,0 +1,450 @@
+//! Simulation event sampling.
+
+use crate::config::EventSampler;
+use crate::types::Event;
+use anyhow::{Result, bail};
+use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
+use std::collections::BTreeMap;
+
+/// Minimum number of rate changes between rebuilds of the rate index.
+const MIN_REBUILD_INTERVAL: usize = 1024;
+
+/// Maximum number of draws of an event before concluding that no event has a positive rate.
+const MAX_DRAWS: usize = 64;
+
+/// Collection of all possible events and their associated rates at a certain step.
+///
+/// The rates are indexed (see `EventSampler`) so that adding or removing an event, changing a
+/// rate, getting the total rate and sampling an event are all fast, even for very large pools.
+#[derive(Default, Clone)]
+pub struct EventPool {
+    /// Vector of possible events.
+    events: Vec<Event>,
+    /// Vector of associated rates.
+    rates: Vec<f64>,
+    /// Index of the rates used to sample the events.
+    index: RateIndex,
+    /// Number of rate changes since the index was last rebuilt.
+    n_changes: usize,
+}
+
+impl EventPool {
+    /// Clear the event pool, indexing the rates added from now on for a given event sampler.
+    pub fn reset(&mut self, sampler: EventSampler) {
+        self.events.clear();
+        self.rates.clear();
+        self.index = match sampler {
+            EventSampler::Tree => RateIndex::Tree(RateTree::default()),
+            EventSampler::CompositionRejection => RateIndex::Buckets(RateBuckets::default()),
+        };
+        self.n_changes = 0;
+    }
+
+    /// Add to the pool a new event with its associated rate.
+    pub fn push(&mut self, event: Event, rate: f64) {
+        self.events.push(event);
+        self.rates.push(rate);
+        match &mut self.index {
+            RateIndex::Tree(tree) => tree.push(rate),
+            RateIndex::Buckets(buckets) => buckets.push(rate),
+        }
+    }
+
+    /// Remove every event after the first `len` ones.
+    pub fn truncate(&mut self, len: usize) {
+        match &mut self.index {
+            RateIndex::Tree(tree) => tree.truncate(len),
+            RateIndex::Buckets(buckets) => buckets.truncate(len, &self.rates),
+        }
+        self.events.truncate(len);
+        self.rates.truncate(len);
+    }
+
+    /// Get the number of events in the pool.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Check whether the pool has no events.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Change the rate of the event with a given index.
+    ///
+    /// The sums of the index accumulate the rounding errors of every change, so the index is
+    /// rebuilt from the rates after a number of changes proportional to the number of events
+    /// (which keeps changing a rate fast on average).
+    pub fn set_rate(&mut self, idx: usize, rate: f64) {
+        let old_rate = std::mem::replace(&mut self.rates[idx], rate);
+        match &mut self.index {
+            RateIndex::Tree(tree) => tree.add(idx, rate - old_rate),
+            RateIndex::Buckets(buckets) => {
+                buckets.remove(idx, old_rate);
+                buckets.insert(idx, rate);
+            }
+        }
+
+        self.n_changes += 1;
+        if self.n_changes >= self.rates.len().max(MIN_REBUILD_INTERVAL) {
+            match &mut self.index {
+                RateIndex::Tree(tree) => tree.rebuild(&self.rates),
+                RateIndex::Buckets(buckets) => buckets.rebuild(&self.rates),
+            }
+            self.n_changes = 0;
+        }
+    }
+
+    /// Get the total rate of all events in the pool.
+    pub fn total_rate(&self) -> f64 {
+        match &self.index {
+            RateIndex::Tree(tree) => tree.total_rate(),
+            RateIndex::Buckets(buckets) => buckets.total_rate(),
+        }
+    }
+
+    /// Sample an event with probability proportional to its rate.
+    pub fn sample(&self, rng: &mut ChaCha12Rng) -> Result<Event> {
+        let total_rate = self.total_rate();
+        if !(total_rate.is_finite() && total_rate > 0.0) {
+            bail!("total rate must be positive and finite, but is {total_rate}");
+        }
+
+        // Rounding errors may make a draw miss every event with a positive rate, in which case
+        // the event is drawn again.
+        for _ in 0..MAX_DRAWS {
+            let idx = match &self.index {
+                RateIndex::Tree(tree) => tree.sample(&self.rates, total_rate, rng),
+                RateIndex::Buckets(buckets) => buckets.sample(&self.rates, total_rate, rng),
+            };
+            if let Some(idx) = idx {
+                return Ok(self.events[idx]);
+            }
+        }
+        bail!("no event has a positive rate");
+    }
+}
+
+/// Index of the rates of an event pool.
+#[derive(Clone)]
+enum RateIndex {
+    /// Fenwick tree index.
+    Tree(RateTree),
+    /// Composition-rejection index.
+    Buckets(RateBuckets),
+}
+
+impl Default for RateIndex {
+    fn default() -> Self {
+        Self::Tree(RateTree::default())
+    }
+}
+
+/// Fenwick tree of partial sums of the rates, where every operation takes a logarithmic time in
+/// the number of events.
+#[derive(Default, Clone)]
+struct RateTree {
+    /// Partial sums of the rates (node `k` sums the `k & k.wrapping_neg()` rates ending at the
+    /// `k`-th one).
+    tree: Vec<f64>,
+}
+
+impl RateTree {
+    /// Add a new rate.
+    fn push(&mut self, rate: f64) {
+        // The new node also sums the nodes of the rates it covers.
+        let node = self.tree.len() + 1;
+        let mut partial_sum = rate;
+        let mut span = 1;
+        while span < node & node.wrapping_neg() {
+            partial_sum += self.tree[node - span - 1];
+            span <<= 1;
+        }
+        self.tree.push(partial_sum);
+    }
+
+    /// Remove every rate after the first `len` ones.
+    fn truncate(&mut self, len: usize) {
+        // No remaining node sums a removed rate.
+        self.tree.truncate(len);
+    }
+
+    /// Recompute every partial sum from the rates, discarding accumulated rounding errors.
+    fn rebuild(&mut self, rates: &[f64]) {
+        self.tree.clear();
+        for &rate in rates {
+            self.push(rate);
+        }
+    }
+
+    /// Add a difference to the rate with a given index.
+    fn add(&mut self, idx: usize, delta: f64) {
+        let mut node = idx + 1;
+        while node <= self.tree.len() {
+            self.tree[node - 1] += delta;
+            node += node & node.wrapping_neg();
+        }
+    }
+
+    /// Get the sum of all rates.
+    fn total_rate(&self) -> f64 {
+        let mut total_rate = 0.0;
+        let mut node = self.tree.len();
+        while node > 0 {
+            total_rate += self.tree[node - 1];
+            node -= node & node.wrapping_neg();
+        }
+        total_rate
+    }
+
+    /// Sample the index of a rate with probability proportional to it (`None` if the draw misses
+    /// every positive rate because of rounding errors).
+    fn sample(&self, rates: &[f64], total_rate: f64, rng: &mut ChaCha12Rng) -> Option<usize> {
+        // Descend the tree to find the first event whose cumulative rate exceeds the target.
+        let mut target = rng.random_range(0.0..total_rate);
+        let mut idx = 0;
+        let mut span = self.tree.len().next_power_of_two();
+        while span > 0 {
+            if idx + span <= self.tree.len() && self.tree[idx + span - 1] <= target {
+                idx += span;
+                target -= self.tree[idx - 1];
+            }
+            span >>= 1;
+        }
+
+        // Rounding errors may leave the target past the last event with a positive rate.
+        (idx < rates.len() && rates[idx] > 0.0).then_some(idx)
+    }
+}
+
+/// Composition-rejection index of the rates, where every operation takes a constant time in the
+/// number of events (for a bounded range of rates).
+///
+/// The positive rates are grouped in buckets of rates within a factor of two of each other. To
+/// sample an event, a bucket is chosen with probability proportional to its total rate and then an
+/// event in it by rejection sampling, which accepts at least half of the proposals.
+#[derive(Default, Clone)]
+struct RateBuckets {
+    /// Buckets of rates, by the biased binary exponent of their rates.
+    buckets: BTreeMap<u64, RateBucket>,
+    /// Bucket (exponent) and position in it of every rate (if positive).
+    slots: Vec<Option<(u64, usize)>>,
+}
+
+/// Bucket of rates within a factor of two of each other.
+#[derive(Default, Clone)]
+struct RateBucket {
+    /// Indices of the rates in the bucket.
+    indices: Vec<usize>,
+    /// Sum of the rates in the bucket.
+    total_rate: f64,
+}
+
+impl RateBuckets {
+    /// Add a new rate.
+    fn push(&mut self, rate: f64) {
+        self.slots.push(None);
+        self.insert(self.slots.len() - 1, rate);
+    }
+
+    /// Remove every rate after the first `len` ones.
+    fn truncate(&mut self, len: usize, rates: &[f64]) {
+        for idx in (len..self.slots.len()).rev() {
+            self.remove(idx, rates[idx]);
+        }
+        self.slots.truncate(len);
+    }
+
+    /// Insert the rate with a given index into its bucket (if positive).
+    fn insert(&mut self, idx: usize, rate: f64) {
+        if rate <= 0.0 {
+            return;
+        }
+        let exponent = rate.to_bits() >> 52;
+        let bucket = self.buckets.entry(exponent).or_default();
+        self.slots[idx] = Some((exponent, bucket.indices.len()));
+        bucket.indices.push(idx);
+        bucket.total_rate += rate;
+    }
+
+    /// Remove the rate with a given index from its bucket (if any).
+    fn remove(&mut self, idx: usize, rate: f64) {
+        let Some((exponent, pos)) = self.slots[idx].take() else {
+            return;
+        };
+        let bucket = self
+            .buckets
+            .get_mut(&exponent)
+            .expect("bucket should exist");
+        bucket.indices.swap_remove(pos);
+        bucket.total_rate -= rate;
+        if let Some(&moved_idx) = bucket.indices.get(pos) {
+            self.slots[moved_idx] = Some((exponent, pos));
+        }
+
+        // Dropping empty buckets also discards their accumulated rounding errors.
+        if bucket.indices.is_empty() {
+            self.buckets.remove(&exponent);
+        }
+    }
+
+    /// Recompute the total rate of every bucket from the rates, discarding accumulated rounding
+    /// errors.
+    fn rebuild(&mut self, rates: &[f64]) {
+        for bucket in self.buckets.values_mut() {
+            bucket.total_rate = bucket.indices.iter().map(|&idx| rates[idx]).sum();
+        }
+    }
+
+    /// Get the sum of all rates.
+    fn total_rate(&self) -> f64 {
+        self.buckets.values().map(|bucket| bucket.total_rate).sum()
+    }
+
+    /// Sample the index of a rate with probability proportional to it (`None` if the draw misses
+    /// every bucket because of rounding errors).
+    fn sample(&self, rates: &[f64], total_rate: f64, rng: &mut ChaCha12Rng) -> Option<usize> {
+        // Choose a bucket with probability proportional to its total rate.
+        let mut target = rng.random_range(0.0..total_rate);
+        let (&exponent, bucket) = self.buckets.iter().find(|(_, bucket)| {
+            let found = target < bucket.total_rate;
+            target -= bucket.total_rate;
+            found
+        })?;
+
+        // Every rate in the bucket is below the bucket's upper bound.
+        let max_rate = f64::from_bits((exponent + 1) << 52);
+        loop {
+            let idx = bucket.indices[rng.random_range(0..bucket.indices.len())];
+            if rng.random_range(0.0..max_rate) < rates[idx] {
+                return Some(idx);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_distr::weighted::WeightedIndex;
+
+    const N_EVENTS: usize = 40;
+    const N_SAMPLES: usize = 200_000;
+
+    /// Build a pool of death events with random rates (some of them zero) and then change random
+    /// rates many times, returning the pool and its final rates.
+    fn updated_pool(sampler: EventSampler, rng: &mut ChaCha12Rng) -> (EventPool, Vec<f64>) {
+        let random_rate = |rng: &mut ChaCha12Rng| match rng.random_range(0..4) {
+            0 => 0.0,
+            _ => 10.0_f64.powf(rng.random_range(-3.0..3.0)),
+        };
+
+        let mut pool = EventPool::default();
+        pool.reset(sampler);
+        let mut rates = Vec::new();
+        for agent_idx in 0..N_EVENTS {
+            let rate = random_rate(rng);
+            pool.push(Event::Death { agent_idx }, rate);
+            rates.push(rate);
+        }
+        for _ in 0..100_000 {
+            let idx = rng.random_range(0..N_EVENTS);
+            let rate = random_rate(rng);
+            pool.set_rate(idx, rate);
+            rates[idx] = rate;
+        }
+
+        (pool, rates)
+    }
+
+    /// Estimate the sampling frequency of every event of a pool.
+    fn pool_freqs(pool: &EventPool, rng: &mut ChaCha12Rng) -> Vec<f64> {
+        let mut freqs = vec![0.0; N_EVENTS];
+        for _ in 0..N_SAMPLES {
+            let Event::Death { agent_idx } = pool.sample(rng).unwrap() else {
+                panic!("unexpected event");
+            };
+            freqs[agent_idx] += 1.0 / N_SAMPLES as f64;
+        }
+        freqs
+    }
+
+    fn check_sampler(sampler: EventSampler) {
+        let mut rng = ChaCha12Rng::seed_from_u64(42);
+        let (pool, rates) = updated_pool(sampler, &mut rng);
+
+        let total_rate: f64 = rates.iter().sum();
+        assert!((pool.total_rate() - total_rate).abs() <= 1e-9 * total_rate);
+
+        let freqs = pool_freqs(&pool, &mut rng);
+        let dist = WeightedIndex::new(&rates).unwrap();
+        let mut expected_freqs = vec![0.0; N_EVENTS];
+        for _ in 0..N_SAMPLES {
+            expected_freqs[dist.sample(&mut rng)] += 1.0 / N_SAMPLES as f64;
+        }
+        for (idx, (freq, expected_freq)) in freqs.iter().zip(&expected_freqs).enumerate() {
+            if rates[idx] == 0.0 {
+                assert_eq!(*freq, 0.0);
+            }
+            let std_err = (2.0 * (rates[idx] / total_rate) / N_SAMPLES as f64).sqrt();
+            assert!(
+                (freq - expected_freq).abs() <= 5.0 * std_err + 1e-12,
+                "event {idx}: frequency {freq} != {expected_freq}"
+            );
+        }
+    }
+
+    #[test]
+    fn tree_matches_weighted_index() {
+        check_sampler(EventSampler::Tree);
+    }
+
+    #[test]
+    fn buckets_match_weighted_index() {
+        check_sampler(EventSampler::CompositionRejection);
+    }
+
+    #[test]
+    fn truncated_pool_samples_remaining_events() {
+        for sampler in [EventSampler::Tree, EventSampler::CompositionRejection] {
+            let mut rng = ChaCha12Rng::seed_from_u64(7);
+            let (mut pool, rates) = updated_pool(sampler, &mut rng);
+            let len = rates.iter().position(|&rate| rate > 0.0).unwrap() + 1;
+            pool.truncate(len);
+            assert_eq!(pool.len(), len);
+            assert!((pool.total_rate() - rates[len - 1]).abs() <= 1e-9 * rates[len - 1]);
+            for _ in 0..1000 {
+                let event = pool.sample(&mut rng).unwrap();
+                assert_eq!(event, Event::Death { agent_idx: len - 1 });
+            }
+        }
+    }
+
+    #[test]
+    fn zero_rates_are_never_sampled() {
+        for sampler in [EventSampler::Tree, EventSampler::CompositionRejection] {
+            let mut rng = ChaCha12Rng::seed_from_u64(1);
+            let mut pool = EventPool::default();
+            pool.reset(sampler);
+            for agent_idx in 0..N_EVENTS {
+                pool.push(Event::Death { agent_idx }, 1.0);
+            }
+            for idx in 0..N_EVENTS {
+                pool.set_rate(idx, 0.1 + 0.2);
+                pool.set_rate(idx, 0.0);
+            }
+            assert!(pool.total_rate().abs() < 1e-12);
+            pool.set_rate(N_EVENTS / 2, 1.0);
+            for _ in 0..1000 {
+                let event = pool.sample(&mut rng).unwrap();
+                assert_eq!(
+                    event,
+                    Event::Death {
+                        agent_idx: N_EVENTS / 2
+                    }
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Statistical error estimation and equilibration detection.
+
+use serde::{Deserialize, Serialize};
+
+/// Minimum number of blocks of a blocking level to estimate the standard error from it.
+const MIN_BLOCKS: usize = 16;
+
+/// Online blocking analysis (Flyvbjerg and Petersen) of a correlated series of samples.
+///
+/// The series is repeatedly coarse-grained by averaging pairs of consecutive blocks. Once the
+/// blocks are longer than the correlation time they are effectively independent, and the naive
+/// standard error of their mean stops growing with the block length (a plateau), giving the
+/// standard error of the mean of the series. Only the sums of every level are stored, so memory
+/// grows logarithmically with the number of samples.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct Blocking {
+    /// Blocking levels, where the blocks of level `k` average `2^k` consecutive samples.
+    levels: Vec<BlockLevel>,
+}
+
+/// Blocking level of a blocking analysis.
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct BlockLevel {
+    /// Number of blocks.
+    n_blocks: usize,
+    /// Sum of the blocks.
+    sum: f64,
+    /// Sum of the squared blocks.
+    sq_sum: f64,
+    /// Last block, if not yet paired to form a block of the next level.
+    pending: Option<f64>,
+}
+
+impl BlockLevel {
+    /// Get the naive standard error of the mean of the blocks (`NaN` if there are less than 2).
+    fn std_err(&self) -> f64 {
+        let n = self.n_blocks as f64;
+        let mean = self.sum / n;
+        let var = (self.sq_sum / n - mean * mean).max(0.0);
+        (var / (n - 1.0)).sqrt()
+    }
+}
+
+/// Error estimate of the mean of a correlated series of samples.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct BlockingEstimate {
+    /// Number of samples.
+    pub n_samples: usize,
+
+    /// Mean of the samples.
+    pub mean: f64,
+
+    /// Naive standard error of the mean, assuming independent samples.
+    pub naive_std_err: f64,
+
+    /// Standard error of the mean corrected for autocorrelation (`NaN` if there are too few
+    /// samples).
+    pub std_err: f64,
+
+    /// Integrated autocorrelation time, in samples (1/2 for independent samples).
+    pub tau_int: f64,
+}
+
+impl Blocking {
+    /// Add a new sample to the series.
+    pub fn push(&mut self, sample: f64) {
+        let mut block = sample;
+        for level_idx in 0.. {
+            if level_idx == self.levels.len() {
+                self.levels.push(BlockLevel::default());
+            }
+            let level = &mut self.levels[level_idx];
+            level.n_blocks += 1;
+            level.sum += block;
+            level.sq_sum += block * block;
+            match level.pending.take() {
+                Some(prev_block) => block = 0.5 * (prev_block + block),
+                None => {
+                    level.pending = Some(block);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Estimate the error of the mean of the series.
+    ///
+    /// The standard error is taken at the first level whose next one does not exceed it by more
+    /// than its own statistical uncertainty, among those with at least `MIN_BLOCKS` blocks (or at
+    /// the last of them if there is no plateau).
+    pub fn estimate(&self) -> BlockingEstimate {
+        let Some(first) = self.levels.first() else {
+            return BlockingEstimate {
+                n_samples: 0,
+                mean: f64::NAN,
+                naive_std_err: f64::NAN,
+                std_err: f64::NAN,
+                tau_int: f64::NAN,
+            };
+        };
+
+        let levels: Vec<&BlockLevel> = self
+            .levels
+            .iter()
+            .take_while(|level| level.n_blocks >= MIN_BLOCKS)
+            .collect();
+        let mut std_err = f64::NAN;
+        for (level_idx, level) in levels.iter().enumerate() {
+            std_err = level.std_err();
+            let uncertainty = std_err / (2.0 * (level.n_blocks as f64 - 1.0)).sqrt();
+            if levels
+                .get(level_idx + 1)
+                .is_some_and(|next_level| next_level.std_err() <= std_err + uncertainty)
+            {
+                break;
+            }
+        }
+
+        let naive_std_err = first.std_err();
+        BlockingEstimate {
+            n_samples: first.n_blocks,
+            mean: first.sum / first.n_blocks as f64,
+            naive_std_err,
+            std_err,
+            tau_int: 0.5 * (std_err / naive_std_err).powi(2),
+        }
+    }
+}
+
+/// Batch size of the MSER-5 truncation rule.
+const MSER_BATCH_SIZE: usize = 5;
+
+/// Detect the initial transient of a series of samples with the MSER-5 rule (White, 1997),
+/// returning the number of samples to discard.
+///
+/// The samples are averaged in batches of 5, and the discarded prefix is the one minimizing the
+/// marginal standard error of the mean of the remaining batches, among those of at most half the
+/// batches.
+pub fn mser_truncation(samples: &[f64]) -> usize {
+    let batches: Vec<f64> = samples
+        .chunks_exact(MSER_BATCH_SIZE)
+        .map(|batch| batch.iter().sum::<f64>() / MSER_BATCH_SIZE as f64)
+        .collect();
+
+    // Accumulate the sums of the remaining batches from the end, preferring shorter prefixes.
+    let (mut sum, mut sq_sum) = (0.0, 0.0);
+    let (mut min_mser, mut n_discarded) = (f64::INFINITY, 0);
+    for (batch_idx, batch) in batches.iter().enumerate().rev() {
+        sum += batch;
+        sq_sum += batch * batch;
+        let n_remaining = (batches.len() - batch_idx) as f64;
+        let mser = (sq_sum - sum * sum / n_remaining).max(0.0) / n_remaining.powi(2);
+        if batch_idx <= batches.len() / 2 && mser <= min_mser {
+            min_mser = mser;
+            n_discarded = batch_idx * MSER_BATCH_SIZE;
+        }
+    }
+
+    n_discarded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use rand_chacha::ChaCha12Rng;
+    use rand_distr::StandardNormal;
+
+    /// Estimate the error of the mean of an AR(1) series with a given autoregressive coefficient.
+    fn ar1_estimate(phi: f64, n_samples: usize) -> BlockingEstimate {
+        let mut rng = ChaCha12Rng::seed_from_u64(42);
+        let mut blocking = Blocking::default();
+        let mut sample = 0.0;
+        for _ in 0..n_samples {
+            let noise: f64 = rng.sample(StandardNormal);
+            sample = phi * sample + noise;
+            blocking.push(sample);
+        }
+        blocking.estimate()
+    }
+
+    #[test]
+    fn independent_samples() {
+        let n_samples = 1 << 16;
+        let estimate = ar1_estimate(0.0, n_samples);
+        assert_eq!(estimate.n_samples, n_samples);
+        assert!(estimate.mean.abs() < 5.0 / (n_samples as f64).sqrt());
+        assert!((estimate.naive_std_err * (n_samples as f64).sqrt() - 1.0).abs() < 0.02);
+        assert!(
+            (estimate.tau_int - 0.5).abs() < 0.15,
+            "{}",
+            estimate.tau_int
+        );
+    }
+
+    #[test]
+    fn autoregressive_samples() {
+        // The integrated autocorrelation time of an AR(1) series is (1 + phi) / (2 (1 - phi)).
+        let phi = 0.9;
+        let estimate = ar1_estimate(phi, 1 << 18);
+        let tau_int = (1.0 + phi) / (2.0 * (1.0 - phi));
+        assert!(
+            (estimate.tau_int - tau_int).abs() < 0.25 * tau_int,
+            "{} != {tau_int}",
+            estimate.tau_int
+        );
+        assert!(estimate.std_err > estimate.naive_std_err);
+    }
+
+    #[test]
+    fn too_few_samples() {
+        let estimate = Blocking::default().estimate();
+        assert_eq!(estimate.n_samples, 0);
+        assert!(estimate.mean.is_nan() && estimate.std_err.is_nan() && estimate.tau_int.is_nan());
+
+        let mut blocking = Blocking::default();
+        for sample in 0..MIN_BLOCKS - 1 {
+            blocking.push(sample as f64);
+        }
+        let estimate = blocking.estimate();
+        assert_eq!(estimate.n_samples, MIN_BLOCKS - 1);
+        assert_eq!(estimate.mean, 0.5 * (MIN_BLOCKS - 2) as f64);
+        assert!(estimate.naive_std_err.is_finite());
+        assert!(estimate.std_err.is_nan() && estimate.tau_int.is_nan());
+    }
+
+    #[test]
+    fn mser_detects_step_change() {
+        let samples: Vec<f64> = (0..500)
+            .map(|idx| (idx < 100) as u8 as f64 * 10.0 + if idx % 2 == 0 { 1.0 } else { -1.0 })
+            .collect();
+        assert_eq!(mser_truncation(&samples), 100);
+    }
+
+    #[test]
+    fn mser_keeps_stationary_series() {
+        // Every batch of this series has the same average, so no prefix is worth discarding.
+        let samples: Vec<f64> = (0..500).map(|idx| (idx % MSER_BATCH_SIZE) as f64).collect();
+        assert_eq!(mser_truncation(&samples), 0);
+    }
+
+    #[test]
+    fn mser_of_short_series() {
+        assert_eq!(mser_truncation(&[]), 0);
+        assert_eq!(mser_truncation(&[1.0; MSER_BATCH_SIZE - 1]), 0);
+        assert_eq!(mser_truncation(&[5.0, 1.0]), 0);
+    }
+}
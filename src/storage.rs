@@ -0,0 +1,581 @@
+//! Simulation directory storage.
+
+use anyhow::{Context, Result, anyhow};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Storage backend of a simulation directory.
+///
+/// Files are identified by keys relative to the simulation directory, using `/` as separator
+/// (e.g. `run-0000/checkpoint.msgpack`). Backends can be shared between threads.
+pub trait Storage: Send + Sync {
+    /// Get a human-readable location of a key, for logging and error messages.
+    fn location(&self, key: &str) -> String;
+
+    /// Open a file for reading.
+    fn reader(&self, key: &str) -> Result<Box<dyn Read>>;
+
+    /// Open a file for writing, replacing it if it already exists.
+    ///
+    /// The file is only stored after calling `StorageWriter::finish`, atomically: an interrupted
+    /// write leaves the previous file (if any) intact.
+    fn writer(&self, key: &str) -> Result<Box<dyn StorageWriter>>;
+
+    /// Open a file for writing in place, replacing it if it already exists.
+    ///
+    /// Unlike with `writer`, the contents flushed so far can be read before calling
+    /// `StorageWriter::finish`, but an interrupted write leaves the file partially written.
+    /// Backends that can not write in place open a regular writer.
+    fn writer_in_place(&self, key: &str) -> Result<Box<dyn StorageWriter>> {
+        self.writer(key)
+    }
+
+    /// Create a file with some contents only if it does not exist yet, returning whether it was
+    /// created.
+    ///
+    /// Backends that can not create files exclusively check whether the file exists first, so
+    /// concurrent creations may race.
+    fn create_new(&self, key: &str, contents: &[u8]) -> Result<bool> {
+        if self.exists(key)? {
+            return Ok(false);
+        }
+        let mut writer = self.writer(key)?;
+        writer
+            .write_all(contents)
+            .with_context(|| format!("failed to write {:?}", self.location(key)))?;
+        writer.finish()?;
+        Ok(true)
+    }
+
+    /// Check whether a file exists.
+    fn exists(&self, key: &str) -> Result<bool>;
+
+    /// List the keys of all files starting with a given prefix, in lexicographic order.
+    fn list(&self, prefix: &str) -> Result<Vec<String>>;
+
+    /// Get the size of a file in bytes.
+    fn size(&self, key: &str) -> Result<u64>;
+
+    /// Get the last modification time of a file.
+    fn modified(&self, key: &str) -> Result<SystemTime>;
+
+    /// Remove a file.
+    fn remove(&self, key: &str) -> Result<()>;
+}
+
+/// Writer of a single storage file.
+pub trait StorageWriter: Write {
+    /// Finish writing the file and store it.
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// Open the storage backend of a simulation directory.
+///
+/// Paths of the form `s3://bucket/prefix` use the S3 backend (requires the `s3` feature);
+/// any other path uses the local filesystem.
+pub fn open_storage<P: AsRef<Path>>(sim_dir: P) -> Result<Box<dyn Storage>> {
+    let sim_dir = sim_dir.as_ref();
+    match sim_dir.to_str().and_then(|url| url.strip_prefix("s3://")) {
+        #[cfg(feature = "s3")]
+        Some(url) => Ok(Box::new(s3_storage::S3Storage::new(url)?)),
+        #[cfg(not(feature = "s3"))]
+        Some(_) => anyhow::bail!("S3 storage requires building with the `s3` feature"),
+        None => Ok(Box::new(LocalStorage::new(sim_dir))),
+    }
+}
+
+/// Local filesystem storage.
+pub struct LocalStorage {
+    /// Path to the simulation directory.
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    /// Create a new local storage rooted at a given directory.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+}
+
+impl Storage for LocalStorage {
+    fn location(&self, key: &str) -> String {
+        self.path(key).display().to_string()
+    }
+
+    fn reader(&self, key: &str) -> Result<Box<dyn Read>> {
+        let file = self.path(key);
+        let file = File::open(&file).with_context(|| format!("failed to open {file:?}"))?;
+        Ok(Box::new(BufReader::new(file)))
+    }
+
+    fn writer(&self, key: &str) -> Result<Box<dyn StorageWriter>> {
+        let file = self.path(key);
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("failed to create {dir:?}"))?;
+        }
+        let mut tmp_file = file.clone().into_os_string();
+        tmp_file.push(".tmp");
+        let tmp_file = PathBuf::from(tmp_file);
+        let writer =
+            File::create(&tmp_file).with_context(|| format!("failed to create {tmp_file:?}"))?;
+        Ok(Box::new(AtomicFileWriter {
+            writer: BufWriter::new(writer),
+            tmp_file,
+            file,
+        }))
+    }
+
+    fn writer_in_place(&self, key: &str) -> Result<Box<dyn StorageWriter>> {
+        let file = self.path(key);
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("failed to create {dir:?}"))?;
+        }
+        let file = File::create(&file).with_context(|| format!("failed to create {file:?}"))?;
+        Ok(Box::new(BufWriter::new(file)))
+    }
+
+    fn create_new(&self, key: &str, contents: &[u8]) -> Result<bool> {
+        // Hard-linking a complete temporary file fails if the file exists, so readers never see
+        // it partially written.
+        let file = self.path(key);
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("failed to create {dir:?}"))?;
+        }
+        let mut tmp_file = file.clone().into_os_string();
+        tmp_file.push(format!(".{}.tmp", std::process::id()));
+        let tmp_file = PathBuf::from(tmp_file);
+        fs::write(&tmp_file, contents).with_context(|| format!("failed to write {tmp_file:?}"))?;
+        let result = fs::hard_link(&tmp_file, &file);
+        fs::remove_file(&tmp_file).with_context(|| format!("failed to remove {tmp_file:?}"))?;
+        match result {
+            Ok(()) => Ok(true),
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => Ok(false),
+            Err(error) => Err(error).with_context(|| format!("failed to create {file:?}")),
+        }
+    }
+
+    fn exists(&self, key: &str) -> Result<bool> {
+        let file = self.path(key);
+        file.try_exists()
+            .with_context(|| format!("failed to check {file:?}"))
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        // Only the directory of the prefix is read, walking the matching subdirectories in full.
+        let (dir_key, name_prefix) = match prefix.rsplit_once('/') {
+            Some((dir_key, name_prefix)) => (format!("{dir_key}/"), name_prefix),
+            None => (String::new(), prefix),
+        };
+        let mut keys = Vec::new();
+        for (name, path) in dir_entries(&self.path(&dir_key))? {
+            if name.starts_with(name_prefix) {
+                push_file_keys(&path, format!("{dir_key}{name}"), &mut keys)?;
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn size(&self, key: &str) -> Result<u64> {
+        let file = self.path(key);
+        let metadata = fs::metadata(&file).with_context(|| format!("failed to stat {file:?}"))?;
+        Ok(metadata.len())
+    }
+
+    fn modified(&self, key: &str) -> Result<SystemTime> {
+        let file = self.path(key);
+        let metadata = fs::metadata(&file).with_context(|| format!("failed to stat {file:?}"))?;
+        let modified = metadata
+            .modified()
+            .with_context(|| format!("failed to get {file:?} modification time"))?;
+        Ok(modified)
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        let file = self.path(key);
+        fs::remove_file(&file).with_context(|| format!("failed to remove {file:?}"))?;
+
+        // Directories only exist implicitly (as in object storage), so remove them once empty.
+        if let Some(dir) = file.parent().filter(|&dir| dir != self.root) {
+            fs::remove_dir(dir).ok();
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the names and paths of the entries of a local directory (none if it does not exist).
+fn dir_entries(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error).with_context(|| format!("failed to read {dir:?}")),
+    };
+    entries
+        .map(|entry| {
+            let entry = entry.with_context(|| format!("failed to read entry of {dir:?}"))?;
+            let name = entry
+                .file_name()
+                .into_string()
+                .map_err(|name| anyhow!("file name {name:?} is not valid UTF-8"))?;
+            Ok((name, entry.path()))
+        })
+        .collect()
+}
+
+/// Add the key of a local file, or the keys of all files below a local directory, to a list.
+fn push_file_keys(path: &Path, key: String, keys: &mut Vec<String>) -> Result<()> {
+    if path.is_dir() {
+        for (name, path) in dir_entries(path)? {
+            push_file_keys(&path, format!("{key}/{name}"), keys)?;
+        }
+    } else if path.is_file() {
+        keys.push(key);
+    }
+    Ok(())
+}
+
+impl StorageWriter for BufWriter<File> {
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.flush().context("failed to flush writer stream")?;
+        Ok(())
+    }
+}
+
+/// Writer of a local file that writes to a temporary file next to it (with a `.tmp` suffix) and
+/// renames it over the file when finished, so that the file is never left partially written.
+struct AtomicFileWriter {
+    /// Buffered writer of the temporary file.
+    writer: BufWriter<File>,
+    /// Path to the temporary file.
+    tmp_file: PathBuf,
+    /// Path to the file.
+    file: PathBuf,
+}
+
+impl Write for AtomicFileWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl StorageWriter for AtomicFileWriter {
+    fn finish(self: Box<Self>) -> Result<()> {
+        let (tmp_file, file) = (self.tmp_file, self.file);
+        let writer = self
+            .writer
+            .into_inner()
+            .map_err(|error| error.into_error())
+            .context("failed to flush writer stream")?;
+        writer
+            .sync_all()
+            .with_context(|| format!("failed to sync {tmp_file:?}"))?;
+        fs::rename(&tmp_file, &file)
+            .with_context(|| format!("failed to rename {tmp_file:?} to {file:?}"))?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "s3")]
+mod s3_storage {
+    //! S3-compatible object storage.
+
+    use super::{Storage, StorageWriter};
+    use anyhow::{Context, Result, bail};
+    use s3::{Bucket, Region, creds::Credentials};
+    use std::{
+        io::{Cursor, Read, Write},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    /// S3-compatible object storage (AWS S3, MinIO, ...).
+    ///
+    /// Credentials and region are taken from the usual AWS environment variables.
+    /// Set `S3_ENDPOINT` to use a custom endpoint (e.g. a MinIO server).
+    pub struct S3Storage {
+        /// Storage bucket.
+        bucket: Box<Bucket>,
+        /// Key prefix of the simulation directory.
+        prefix: String,
+    }
+
+    impl S3Storage {
+        /// Create a new S3 storage from a `bucket/prefix` URL (without the `s3://` scheme).
+        pub fn new(url: &str) -> Result<Self> {
+            let (name, prefix) = url.split_once('/').unwrap_or((url, ""));
+            if name.is_empty() {
+                bail!("S3 URL must include a bucket name");
+            }
+            let prefix = prefix.trim_end_matches('/').to_string();
+
+            let region = match std::env::var("S3_ENDPOINT") {
+                Ok(endpoint) => Region::Custom {
+                    region: std::env::var("AWS_REGION").unwrap_or_default(),
+                    endpoint,
+                },
+                Err(_) => Region::from_default_env().context("failed to get S3 region")?,
+            };
+            let credentials = Credentials::default().context("failed to get S3 credentials")?;
+
+            let bucket = Bucket::new(name, region, credentials)
+                .context("failed to create S3 bucket")?
+                .with_path_style();
+
+            Ok(Self { bucket, prefix })
+        }
+
+        fn path(&self, key: &str) -> String {
+            if self.prefix.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}/{key}", self.prefix)
+            }
+        }
+    }
+
+    impl Storage for S3Storage {
+        fn location(&self, key: &str) -> String {
+            format!("s3://{}/{}", self.bucket.name(), self.path(key))
+        }
+
+        fn reader(&self, key: &str) -> Result<Box<dyn Read>> {
+            let location = self.location(key);
+            let response = self
+                .bucket
+                .get_object(self.path(key))
+                .with_context(|| format!("failed to get {location:?}"))?;
+            check_status(response.status_code(), &location)?;
+            Ok(Box::new(Cursor::new(response.to_vec())))
+        }
+
+        fn writer(&self, key: &str) -> Result<Box<dyn StorageWriter>> {
+            Ok(Box::new(S3Writer {
+                bucket: self.bucket.clone(),
+                path: self.path(key),
+                buffer: Vec::new(),
+            }))
+        }
+
+        fn exists(&self, key: &str) -> Result<bool> {
+            Ok(self.list(key)?.iter().any(|other| other == key))
+        }
+
+        fn list(&self, prefix: &str) -> Result<Vec<String>> {
+            let location = self.location(prefix);
+            let results = self
+                .bucket
+                .list(self.path(prefix), None)
+                .with_context(|| format!("failed to list {location:?}"))?;
+            let root = self.path("");
+            let mut keys: Vec<String> = results
+                .into_iter()
+                .flat_map(|result| result.contents)
+                .filter_map(|object| object.key.strip_prefix(&root).map(str::to_string))
+                .collect();
+            keys.sort();
+            Ok(keys)
+        }
+
+        fn size(&self, key: &str) -> Result<u64> {
+            let location = self.location(key);
+            let (head, status) = self
+                .bucket
+                .head_object(self.path(key))
+                .with_context(|| format!("failed to get {location:?} metadata"))?;
+            check_status(status, &location)?;
+            let size = head
+                .content_length
+                .with_context(|| format!("failed to get {location:?} size"))?;
+            Ok(size.try_into()?)
+        }
+
+        fn modified(&self, key: &str) -> Result<SystemTime> {
+            let location = self.location(key);
+            let results = self
+                .bucket
+                .list(self.path(key), None)
+                .with_context(|| format!("failed to list {location:?}"))?;
+            let object = results
+                .into_iter()
+                .flat_map(|result| result.contents)
+                .find(|object| object.key == self.path(key))
+                .with_context(|| format!("failed to find {location:?}"))?;
+            parse_timestamp(&object.last_modified)
+                .with_context(|| format!("failed to get {location:?} modification time"))
+        }
+
+        fn remove(&self, key: &str) -> Result<()> {
+            let location = self.location(key);
+            let response = self
+                .bucket
+                .delete_object(self.path(key))
+                .with_context(|| format!("failed to remove {location:?}"))?;
+            check_status(response.status_code(), &location)
+        }
+    }
+
+    /// Fail unless an S3 response has a success (2xx) status code.
+    fn check_status(status: u16, location: &str) -> Result<()> {
+        if !(200..300).contains(&status) {
+            bail!("request for {location:?} failed with status {status}");
+        }
+        Ok(())
+    }
+
+    /// Parse an ISO 8601 UTC timestamp (e.g. `2024-01-31T12:00:00.000Z`).
+    fn parse_timestamp(timestamp: &str) -> Result<SystemTime> {
+        let invalid = || format!("invalid timestamp {timestamp:?}");
+        let (date, time) = timestamp.split_once('T').with_context(invalid)?;
+        let parse = |fields: &str, sep| -> Result<Vec<i64>> {
+            fields
+                .split(sep)
+                .map(|field| field.parse().with_context(invalid))
+                .collect()
+        };
+        let [year, month, day] = parse(date, '-')?[..] else {
+            bail!(invalid());
+        };
+        let time = time.trim_end_matches('Z');
+        let time = time.split_once('.').map_or(time, |(time, _)| time);
+        let [hours, minutes, seconds] = parse(time, ':')?[..] else {
+            bail!(invalid());
+        };
+
+        // Count days since the Unix epoch in the proleptic Gregorian calendar.
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+
+        let secs = days * 86_400 + hours * 3_600 + minutes * 60 + seconds;
+        Ok(UNIX_EPOCH + Duration::from_secs(secs.try_into()?))
+    }
+
+    /// Writer of a single S3 object.
+    ///
+    /// Buffers the whole object in memory and uploads it when finished.
+    struct S3Writer {
+        /// Storage bucket.
+        bucket: Box<Bucket>,
+        /// Object path.
+        path: String,
+        /// Object contents.
+        buffer: Vec<u8>,
+    }
+
+    impl Write for S3Writer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.buffer.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl StorageWriter for S3Writer {
+        fn finish(self: Box<Self>) -> Result<()> {
+            let response = self
+                .bucket
+                .put_object(&self.path, &self.buffer)
+                .with_context(|| format!("failed to put {:?}", self.path))?;
+            check_status(response.status_code(), &self.path)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::{
+            io::{BufRead, BufReader},
+            net::TcpListener,
+            thread,
+        };
+
+        /// Start a mock S3 endpoint answering every request with `status`.
+        fn mock_storage(status: u16) -> S3Storage {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let endpoint = format!("http://{}", listener.local_addr().unwrap());
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mut stream = stream.unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut len = 0;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        let line = line.trim_end().to_ascii_lowercase();
+                        if line.is_empty() {
+                            break;
+                        }
+                        if let Some(value) = line.strip_prefix("content-length:") {
+                            len = value.trim().parse().unwrap();
+                        }
+                    }
+                    let mut body = vec![0; len];
+                    reader.read_exact(&mut body).unwrap();
+                    write!(
+                        stream,
+                        "HTTP/1.1 {status} Mock\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    )
+                    .unwrap();
+                }
+            });
+
+            let region = Region::Custom {
+                region: "mock".to_string(),
+                endpoint,
+            };
+            let credentials =
+                Credentials::new(Some("key"), Some("secret"), None, None, None).unwrap();
+            let bucket = Bucket::new("bucket", region, credentials)
+                .unwrap()
+                .with_path_style();
+            S3Storage {
+                bucket,
+                prefix: "sim".to_string(),
+            }
+        }
+
+        fn put(storage: &S3Storage) -> Result<()> {
+            let mut writer = storage.writer("output-0000.msgpack")?;
+            writer.write_all(b"data")?;
+            writer.finish()
+        }
+
+        #[test]
+        fn success_status_is_accepted() {
+            let storage = mock_storage(200);
+            put(&storage).unwrap();
+            storage.remove("output-0000.msgpack").unwrap();
+        }
+
+        #[test]
+        fn error_status_is_rejected() {
+            for status in [403, 404] {
+                let storage = mock_storage(status);
+                assert!(put(&storage).is_err());
+                assert!(storage.reader("output-0000.msgpack").is_err());
+                assert!(storage.size("output-0000.msgpack").is_err());
+                assert!(storage.remove("output-0000.msgpack").is_err());
+                assert!(storage.list("output-").is_err());
+            }
+        }
+    }
+}
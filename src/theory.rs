@@ -0,0 +1,455 @@
+//! Mean-field theory of the model.
+
+use crate::config::{Config, Expression};
+use crate::types::Rate;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// Maximum norm of the exponent of every matrix exponential, so that propagators do not
+/// overflow.
+const MAX_EXP_NORM: f64 = 16.0;
+
+/// Smallest strategy step of the refinement of the optimal strategy.
+const MIN_STRAT_STEP: f64 = 1e-4;
+
+/// Theoretically optimal phenotypic strategy of the model (see `optimal_strategy`).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OptimalStrategy {
+    /// Phenotypic strategy maximizing the long-term growth rate.
+    pub strat_phe: Vec<f64>,
+
+    /// Long-term growth rate of the optimal strategy.
+    pub growth_rate: f64,
+
+    /// Long-term growth rate of every pure strategy (always producing a single phenotype).
+    pub pure_growth_rates: Vec<f64>,
+
+    /// Number of visits of the environment trajectory.
+    pub n_visits: usize,
+
+    /// Total time of the environment trajectory.
+    pub env_time: f64,
+}
+
+impl OptimalStrategy {
+    /// Write the optimal strategy as pretty-printed JSON (non-finite numbers are written as
+    /// `null`).
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, self)
+            .context("failed to serialize optimal strategy as JSON")?;
+        writeln!(writer).context("failed to write optimal strategy")?;
+        Ok(())
+    }
+}
+
+/// Find the phenotypic strategy maximizing the long-term growth rate along an environment
+/// trajectory, given as the environment and the time spent in it of every visit.
+///
+/// The strategies of a regular grid of the simplex (with `resolution` divisions) are evaluated
+/// first, and the best one is then refined by moving probability between pairs of phenotypes,
+/// halving the step whenever no move improves the growth rate.
+pub fn optimal_strategy(
+    cfg: &Config,
+    trajectory: &[(usize, f64)],
+    resolution: usize,
+) -> OptimalStrategy {
+    let n_phe = cfg.model.n_phe;
+    let growth_rate = |strat_phe: &[f64]| long_term_growth_rate(cfg, strat_phe, trajectory);
+
+    let (mut strat_phe, mut max_growth_rate) = simplex_grid(n_phe, resolution)
+        .into_iter()
+        .map(|strat_phe| {
+            let growth_rate = growth_rate(&strat_phe);
+            (strat_phe, growth_rate)
+        })
+        .fold((Vec::new(), f64::NEG_INFINITY), |best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        });
+
+    let mut step = 0.5 / resolution as f64;
+    while step > MIN_STRAT_STEP {
+        let mut improved = false;
+        for phe in 0..n_phe {
+            for other_phe in (0..n_phe).filter(|&other_phe| other_phe != phe) {
+                let delta = step.min(strat_phe[other_phe]);
+                if delta <= 0.0 {
+                    continue;
+                }
+                let mut candidate = strat_phe.clone();
+                candidate[phe] += delta;
+                candidate[other_phe] -= delta;
+                let candidate_growth_rate = growth_rate(&candidate);
+                if candidate_growth_rate > max_growth_rate {
+                    (strat_phe, max_growth_rate) = (candidate, candidate_growth_rate);
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            step /= 2.0;
+        }
+    }
+
+    let pure_growth_rates = (0..n_phe)
+        .map(|phe| {
+            let mut pure_strat_phe = vec![0.0; n_phe];
+            pure_strat_phe[phe] = 1.0;
+            growth_rate(&pure_strat_phe)
+        })
+        .collect();
+
+    OptimalStrategy {
+        strat_phe,
+        growth_rate: max_growth_rate,
+        pure_growth_rates,
+        n_visits: trajectory.len(),
+        env_time: trajectory.iter().map(|&(_, dwell_time)| dwell_time).sum(),
+    }
+}
+
+/// Estimate the long-term growth rate of a population with a given phenotypic strategy along an
+/// environment trajectory, given as the environment and the time spent in it of every visit.
+///
+/// The expected population (without mutations, bottlenecks or population cap) is propagated
+/// exactly through every visit, starting from the distribution of phenotypes of the newborns,
+/// and the growth rate is the total change in the logarithm of its size over the total time (or
+/// minus infinity if the population goes extinct).
+pub fn long_term_growth_rate(cfg: &Config, strat_phe: &[f64], trajectory: &[(usize, f64)]) -> f64 {
+    let mut n_agents_phe = strat_phe.to_vec();
+    let (mut log_growth, mut time) = (0.0, 0.0);
+    for &(env, dwell_time) in trajectory {
+        // Split long visits so that the population can be renormalized before it overflows.
+        let rate_mat = rate_matrix(cfg, env, strat_phe);
+        let n_steps = (mat_norm(&rate_mat) * dwell_time / MAX_EXP_NORM)
+            .ceil()
+            .max(1.0);
+        let propagator = mat_exp(&mat_scale(&rate_mat, dwell_time / n_steps));
+        for _ in 0..n_steps as usize {
+            n_agents_phe = mat_vec(&propagator, &n_agents_phe);
+            let n_agents: f64 = n_agents_phe.iter().sum();
+            if n_agents <= 0.0 || n_agents.is_nan() {
+                return f64::NEG_INFINITY;
+            }
+            log_growth += n_agents.ln();
+            n_agents_phe.iter_mut().for_each(|ele| *ele /= n_agents);
+        }
+        time += dwell_time;
+    }
+    log_growth / time
+}
+
+/// Get the mean-field rate matrix of a population with a given phenotypic strategy in an
+/// environment, whose element `[phe][other_phe]` is the rate at which every agent of phenotype
+/// `other_phe` changes the number of agents of phenotype `phe` (including phenotype switching).
+fn rate_matrix(cfg: &Config, env: usize, strat_phe: &[f64]) -> Vec<Vec<f64>> {
+    let n_phe = cfg.model.n_phe;
+    let rates_birth = &cfg.model.rates_birth[env];
+    let rates_death = &cfg.model.rates_death[env];
+    let rate_switch = |phe: usize, next_phe: usize| {
+        cfg.model.rates_switch.as_ref().map_or(0.0, |rates| {
+            if next_phe == phe {
+                0.0
+            } else {
+                rates[env][phe][next_phe].get()
+            }
+        })
+    };
+    match cfg.model.expression {
+        Expression::Discrete => (0..n_phe)
+            .map(|phe| {
+                (0..n_phe)
+                    .map(|other_phe| {
+                        let rate = strat_phe[phe] * rates_birth[other_phe].get();
+                        if other_phe == phe {
+                            let rate_switch_out: f64 =
+                                (0..n_phe).map(|next_phe| rate_switch(phe, next_phe)).sum();
+                            rate - rates_death[phe].get() - rate_switch_out
+                        } else {
+                            rate + rate_switch(other_phe, phe)
+                        }
+                    })
+                    .collect()
+            })
+            .collect(),
+        Expression::Mixed => {
+            // Every agent has the same net growth rate, whatever its phenotype.
+            let mix = |rates: &[Rate]| -> f64 {
+                rates
+                    .iter()
+                    .zip(strat_phe)
+                    .map(|(rate, ele)| rate.get() * ele)
+                    .sum()
+            };
+            let net_rate = mix(rates_birth) - mix(rates_death);
+            (0..n_phe)
+                .map(|phe| {
+                    (0..n_phe)
+                        .map(|other_phe| if other_phe == phe { net_rate } else { 0.0 })
+                        .collect()
+                })
+                .collect()
+        }
+    }
+}
+
+/// Get every strategy of a regular grid of the simplex with a given number of divisions.
+fn simplex_grid(n_phe: usize, resolution: usize) -> Vec<Vec<f64>> {
+    // Distribute the divisions among the phenotypes in every possible way.
+    let mut grid = vec![Vec::new()];
+    for phe in 0..n_phe {
+        grid = grid
+            .into_iter()
+            .flat_map(|counts: Vec<usize>| {
+                let remaining = resolution - counts.iter().sum::<usize>();
+                let range = if phe == n_phe - 1 {
+                    remaining..=remaining
+                } else {
+                    0..=remaining
+                };
+                range.map(move |count| [counts.clone(), vec![count]].concat())
+            })
+            .collect();
+    }
+    grid.into_iter()
+        .map(|counts| {
+            counts
+                .into_iter()
+                .map(|count| count as f64 / resolution as f64)
+                .collect()
+        })
+        .collect()
+}
+
+/// Compute the exponential of a square matrix by scaling and squaring a truncated Taylor series.
+fn mat_exp(mat: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    // Scale the matrix to a norm of at most 1/2, where 16 terms are accurate to machine
+    // precision.
+    let n_squarings = (2.0 * mat_norm(mat)).log2().ceil().max(0.0) as usize;
+    let scaled = mat_scale(mat, 0.5_f64.powi(n_squarings as i32));
+
+    let identity: Vec<Vec<f64>> = (0..mat.len())
+        .map(|row| (0..mat.len()).map(|col| f64::from(row == col)).collect())
+        .collect();
+    let mut exp = identity.clone();
+    let mut term = identity;
+    for order in 1..=16 {
+        term = mat_scale(&mat_mul(&term, &scaled), 1.0 / order as f64);
+        for (exp_row, term_row) in exp.iter_mut().zip(&term) {
+            exp_row
+                .iter_mut()
+                .zip(term_row)
+                .for_each(|(ele, term_ele)| *ele += term_ele);
+        }
+    }
+
+    for _ in 0..n_squarings {
+        exp = mat_mul(&exp, &exp);
+    }
+    exp
+}
+
+/// Get the maximum absolute row sum norm of a matrix.
+fn mat_norm(mat: &[Vec<f64>]) -> f64 {
+    mat.iter()
+        .map(|row| row.iter().map(|ele| ele.abs()).sum::<f64>())
+        .fold(0.0, f64::max)
+}
+
+/// Multiply a matrix by a scalar.
+fn mat_scale(mat: &[Vec<f64>], factor: f64) -> Vec<Vec<f64>> {
+    mat.iter()
+        .map(|row| row.iter().map(|ele| ele * factor).collect())
+        .collect()
+}
+
+/// Multiply two square matrices.
+fn mat_mul(lhs: &[Vec<f64>], rhs: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    lhs.iter()
+        .map(|lhs_row| {
+            (0..rhs.len())
+                .map(|col| {
+                    lhs_row
+                        .iter()
+                        .zip(rhs)
+                        .map(|(ele, row)| ele * row[col])
+                        .sum()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Multiply a matrix by a vector.
+fn mat_vec(mat: &[Vec<f64>], vec: &[f64]) -> Vec<f64> {
+    mat.iter()
+        .map(|row| {
+            row.iter()
+                .zip(vec)
+                .map(|(ele, vec_ele)| ele * vec_ele)
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Configuration of a model where, in environment `env`, only phenotype `env` gives birth
+    /// (at rate `rate_birth`) and only the other phenotype dies (at rate `rate_death`).
+    fn specialist_cfg(rate_birth: f64, rate_death: f64, expression: &str) -> Config {
+        Config::from_toml(&format!(
+            "[model]\n\
+             n_env = 2\n\
+             n_phe = 2\n\
+             rates_trans = [ [ -1.0, 1.0,], [ 1.0, -1.0,],]\n\
+             rates_birth = [ [ {rate_birth}, 0.0,], [ 0.0, {rate_birth},],]\n\
+             rates_death = [ [ 0.0, {rate_death},], [ {rate_death}, 0.0,],]\n\
+             prob_mut = 0.001\n\
+             expression = \"{expression}\"\n\
+             [init]\n\
+             n_agents = 100\n\
+             strat_phe = [ 0.5, 0.5,]\n\
+             [output]\n\
+             file_steps_factor = 1024\n\
+             save_steps_factor = 16\n\
+             hist_bins = 64\n"
+        ))
+        .unwrap()
+    }
+
+    /// Alternating environment trajectory with a given number of periods and dwell time.
+    fn alternating_trajectory(n_periods: usize, dwell_time: f64) -> Vec<(usize, f64)> {
+        (0..2 * n_periods)
+            .map(|visit| (visit % 2, dwell_time))
+            .collect()
+    }
+
+    fn assert_mat_eq(mat: &[Vec<f64>], expected: &[Vec<f64>]) {
+        for (row, expected_row) in mat.iter().zip(expected) {
+            for (ele, expected_ele) in row.iter().zip(expected_row) {
+                assert!(
+                    (ele - expected_ele).abs() <= 1e-12 * expected_ele.abs().max(1.0),
+                    "{mat:?} != {expected:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mat_exp_of_diagonal_matrix() {
+        let diag = [1.0, -2.0, 0.5];
+        let mat: Vec<Vec<f64>> = (0..3)
+            .map(|row| {
+                (0..3)
+                    .map(|col| if row == col { diag[row] } else { 0.0 })
+                    .collect()
+            })
+            .collect();
+        let expected: Vec<Vec<f64>> = (0..3)
+            .map(|row| {
+                (0..3)
+                    .map(|col| if row == col { diag[row].exp() } else { 0.0 })
+                    .collect()
+            })
+            .collect();
+        assert_mat_eq(&mat_exp(&mat), &expected);
+    }
+
+    #[test]
+    fn mat_exp_of_rotation_generator() {
+        // A large angle requires several squarings.
+        let angle = 3.0_f64;
+        let mat = vec![vec![0.0, -angle], vec![angle, 0.0]];
+        let expected = vec![
+            vec![angle.cos(), -angle.sin()],
+            vec![angle.sin(), angle.cos()],
+        ];
+        assert_mat_eq(&mat_exp(&mat), &expected);
+    }
+
+    #[test]
+    fn specialist_growth_rate_matches_closed_form() {
+        let (rate_birth, rate_death, dwell_time) = (2.0, 3.0, 1.0);
+        let cfg = specialist_cfg(rate_birth, rate_death, "discrete");
+        let strat_phe = [0.3, 0.7];
+
+        // The rate matrices are triangular, so their exponentials have a closed form, and the
+        // long-term growth rate is given by the spectral radius of the propagator of a period.
+        let exp_triangular = |diag: f64, other_diag: f64, off_diag: f64| {
+            let (exp, other_exp) = ((diag * dwell_time).exp(), (other_diag * dwell_time).exp());
+            (
+                exp,
+                other_exp,
+                off_diag * (exp - other_exp) / (diag - other_diag),
+            )
+        };
+        let (a0, d0, c0) = exp_triangular(
+            strat_phe[0] * rate_birth,
+            -rate_death,
+            strat_phe[1] * rate_birth,
+        );
+        let (d1, a1, c1) = exp_triangular(
+            -rate_death,
+            strat_phe[1] * rate_birth,
+            strat_phe[0] * rate_birth,
+        );
+        // Propagator of environment 0, [[a0, 0], [c0, d0]], followed by environment 1,
+        // [[d1, c1], [0, a1]].
+        let period = [[d1 * a0 + c1 * c0, c1 * d0], [a1 * c0, a1 * d0]];
+        let trace = period[0][0] + period[1][1];
+        let det = period[0][0] * period[1][1] - period[0][1] * period[1][0];
+        let spectral_radius = 0.5 * (trace + (trace * trace - 4.0 * det).sqrt());
+        let expected = spectral_radius.ln() / (2.0 * dwell_time);
+
+        let trajectory = alternating_trajectory(2000, dwell_time);
+        let growth_rate = long_term_growth_rate(&cfg, &strat_phe, &trajectory);
+        assert!(
+            (growth_rate - expected).abs() < 1e-3,
+            "{growth_rate} != {expected}"
+        );
+    }
+
+    #[test]
+    fn mixed_growth_rate_is_average_net_rate() {
+        let cfg = specialist_cfg(2.0, 3.0, "mixed");
+        let strat_phe = [0.3, 0.7];
+        let trajectory = [(0, 1.0), (1, 3.0), (0, 0.5)];
+
+        // In environment 0 the net rate is 0.3 * 2 - 0.7 * 3, and in environment 1 it is
+        // 0.7 * 2 - 0.3 * 3.
+        let expected = (1.5 * (0.6 - 2.1) + 3.0 * (1.4 - 0.9)) / 4.5;
+        let growth_rate = long_term_growth_rate(&cfg, &strat_phe, &trajectory);
+        assert!((growth_rate - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn symmetric_optimal_strategy() {
+        // Both pure strategies die out in one of the environments, so hedging is optimal, and
+        // by symmetry both phenotypes are equally likely.
+        let cfg = specialist_cfg(2.0, 3.0, "discrete");
+        let trajectory = alternating_trajectory(200, 1.0);
+        let optimal = optimal_strategy(&cfg, &trajectory, 10);
+        assert!((optimal.strat_phe[0] - 0.5).abs() < 1e-3);
+        assert!(
+            optimal
+                .pure_growth_rates
+                .iter()
+                .all(|&rate| rate < optimal.growth_rate)
+        );
+        assert_eq!(optimal.n_visits, 400);
+        assert_eq!(optimal.env_time, 400.0);
+    }
+
+    #[test]
+    fn extinct_population_has_no_growth() {
+        let cfg = specialist_cfg(2.0, 3.0, "discrete");
+        let growth_rate = long_term_growth_rate(&cfg, &[0.0, 0.0], &[(0, 1.0), (1, 1.0)]);
+        assert_eq!(growth_rate, f64::NEG_INFINITY);
+    }
+}
@@ -1,21 +1,213 @@
 //! Simulation data types.
 
+use anyhow::{Error, Result, bail};
 use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+
+/// Probability: a number in the range `[0, 1]`.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "f64", into = "f64")]
+pub struct Probability(f64);
+
+impl Probability {
+    /// Create a new probability, checking that it is in the range `[0, 1]`.
+    pub fn new(prob: f64) -> Result<Self> {
+        if !(0.0..=1.0).contains(&prob) {
+            bail!("probability must be in the range [0, 1], but is {prob:?}");
+        }
+        Ok(Self(prob))
+    }
+
+    /// Get the value of the probability.
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl TryFrom<f64> for Probability {
+    type Error = Error;
+
+    fn try_from(prob: f64) -> Result<Self> {
+        Self::new(prob)
+    }
+}
+
+impl From<Probability> for f64 {
+    fn from(prob: Probability) -> Self {
+        prob.0
+    }
+}
+
+/// Rate: a finite non-negative number.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "f64", into = "f64")]
+pub struct Rate(f64);
+
+impl Rate {
+    /// Create a new rate, checking that it is finite and non-negative.
+    pub fn new(rate: f64) -> Result<Self> {
+        if !(rate.is_finite() && rate >= 0.0) {
+            bail!("rate must be finite and non-negative, but is {rate:?}");
+        }
+        Ok(Self(rate))
+    }
+
+    /// Get the value of the rate.
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl TryFrom<f64> for Rate {
+    type Error = Error;
+
+    fn try_from(rate: f64) -> Result<Self> {
+        Self::new(rate)
+    }
+}
+
+impl From<Rate> for f64 {
+    fn from(rate: Rate) -> Self {
+        rate.0
+    }
+}
+
+/// Point of the probability simplex: a non-empty vector of non-negative numbers adding up to one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<f64>", into = "Vec<f64>")]
+pub struct Simplex(Vec<f64>);
+
+impl Simplex {
+    /// Maximum deviation of the sum of the elements from one.
+    const TOLERANCE: f64 = 1e-6;
+
+    /// Create a new simplex point, checking that its elements are non-negative and add up to one.
+    pub fn new(vec: Vec<f64>) -> Result<Self> {
+        Self::check_elements(&vec)?;
+        let sum: f64 = vec.iter().sum();
+        if (sum - 1.0).abs() > Self::TOLERANCE {
+            bail!("simplex elements must add up to 1, but add up to {sum:?}");
+        }
+        Ok(Self(vec))
+    }
+
+    /// Create a new simplex point by normalizing a vector of non-negative weights.
+    pub fn normalize(mut vec: Vec<f64>) -> Result<Self> {
+        Self::check_elements(&vec)?;
+        let sum: f64 = vec.iter().sum();
+        if !(sum.is_finite() && sum > 0.0) {
+            bail!("simplex weights must have a finite positive sum, but add up to {sum:?}");
+        }
+        vec.iter_mut().for_each(|ele| *ele /= sum);
+        Ok(Self(vec))
+    }
+
+    fn check_elements(vec: &[f64]) -> Result<()> {
+        if vec.is_empty() {
+            bail!("simplex must have at least one element");
+        }
+        if let Some(ele) = vec.iter().find(|ele| !(ele.is_finite() && **ele >= 0.0)) {
+            bail!("simplex elements must be finite and non-negative, but one is {ele:?}");
+        }
+        Ok(())
+    }
+}
+
+impl Deref for Simplex {
+    type Target = [f64];
+
+    fn deref(&self) -> &[f64] {
+        &self.0
+    }
+}
+
+impl<'a> IntoIterator for &'a Simplex {
+    type Item = &'a f64;
+    type IntoIter = std::slice::Iter<'a, f64>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl TryFrom<Vec<f64>> for Simplex {
+    type Error = Error;
+
+    fn try_from(vec: Vec<f64>) -> Result<Self> {
+        Self::new(vec)
+    }
+}
+
+impl From<Simplex> for Vec<f64> {
+    fn from(simplex: Simplex) -> Self {
+        simplex.0
+    }
+}
 
 /// Agent of the simulation.
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Agent {
     /// Phenotype.
     phe: usize,
 
     /// Phenotypic strategy.
-    strat_phe: Vec<f64>,
+    strat_phe: Simplex,
+
+    /// Neutral marker allele.
+    #[serde(default)]
+    marker: usize,
+
+    /// Index of the tracer lineage of the agent (if any).
+    #[serde(default)]
+    tracer: Option<usize>,
+
+    /// Unique id of the agent in the lineage log (if assigned).
+    #[serde(default)]
+    id: Option<u64>,
+
+    /// Number of strategy mutations along the ancestry of the agent.
+    #[serde(default)]
+    n_mut: usize,
+
+    /// Species of the agent (see `Config::species`).
+    #[serde(default)]
+    species: usize,
+
+    /// Patch of the agent (see `ModelParams::lattice` and `ModelParams::demes`).
+    #[serde(default)]
+    patch: usize,
+
+    /// Phenotypic strategy conditioned on every cue (see `ModelParams::sensing`), all of them
+    /// equal to the phenotypic strategy if empty.
+    #[serde(default)]
+    cue_strats: Vec<Simplex>,
+
+    /// Cue accuracy of the agent, if it differs from the initial one (see `SensingParams`).
+    #[serde(default)]
+    accuracy: Option<Probability>,
+
+    /// Mutation probability of the agent, if it differs from that of its species (see
+    /// `EvolvableMutParams`).
+    #[serde(default)]
+    prob_mut: Option<Probability>,
 }
 
 impl Agent {
     /// Create a new agent with a given phenotype and phenotypic strategy.
-    pub fn new(phe: usize, strat_phe: Vec<f64>) -> Self {
-        Self { phe, strat_phe }
+    pub fn new(phe: usize, strat_phe: Simplex) -> Self {
+        Self {
+            phe,
+            strat_phe,
+            marker: 0,
+            tracer: None,
+            id: None,
+            n_mut: 0,
+            species: 0,
+            patch: 0,
+            cue_strats: Vec::new(),
+            accuracy: None,
+            prob_mut: None,
+        }
     }
 
     /// Get the phenotype of the agent.
@@ -23,26 +215,188 @@ impl Agent {
         self.phe
     }
 
+    /// Set the phenotype of the agent.
+    pub fn set_phe(&mut self, phe: usize) {
+        self.phe = phe;
+    }
+
     /// Get the phenotypic strategy of the agent.
-    pub fn strat_phe(&self) -> &Vec<f64> {
+    pub fn strat_phe(&self) -> &Simplex {
         &self.strat_phe
     }
+
+    /// Get the neutral marker allele of the agent.
+    pub fn marker(&self) -> usize {
+        self.marker
+    }
+
+    /// Set the neutral marker allele of the agent.
+    pub fn set_marker(&mut self, marker: usize) {
+        self.marker = marker;
+    }
+
+    /// Get the index of the tracer lineage of the agent (if any).
+    pub fn tracer(&self) -> Option<usize> {
+        self.tracer
+    }
+
+    /// Set the index of the tracer lineage of the agent.
+    pub fn set_tracer(&mut self, tracer: Option<usize>) {
+        self.tracer = tracer;
+    }
+
+    /// Get the unique id of the agent in the lineage log (if assigned).
+    pub fn id(&self) -> Option<u64> {
+        self.id
+    }
+
+    /// Set the unique id of the agent in the lineage log.
+    pub fn set_id(&mut self, id: Option<u64>) {
+        self.id = id;
+    }
+
+    /// Get the number of strategy mutations along the ancestry of the agent.
+    pub fn n_mut(&self) -> usize {
+        self.n_mut
+    }
+
+    /// Set the number of strategy mutations along the ancestry of the agent.
+    pub fn set_n_mut(&mut self, n_mut: usize) {
+        self.n_mut = n_mut;
+    }
+
+    /// Get the species of the agent.
+    pub fn species(&self) -> usize {
+        self.species
+    }
+
+    /// Set the species of the agent.
+    pub fn set_species(&mut self, species: usize) {
+        self.species = species;
+    }
+
+    /// Get the patch of the agent.
+    pub fn patch(&self) -> usize {
+        self.patch
+    }
+
+    /// Set the patch of the agent.
+    pub fn set_patch(&mut self, patch: usize) {
+        self.patch = patch;
+    }
+
+    /// Get the phenotypic strategy of the agent conditioned on every cue (empty if all of them
+    /// are equal to the phenotypic strategy).
+    pub fn cue_strats(&self) -> &[Simplex] {
+        &self.cue_strats
+    }
+
+    /// Get the phenotypic strategy of the agent conditioned on a cue.
+    pub fn cue_strat(&self, cue: usize) -> &Simplex {
+        self.cue_strats.get(cue).unwrap_or(&self.strat_phe)
+    }
+
+    /// Set the phenotypic strategy of the agent conditioned on every cue.
+    pub fn set_cue_strats(&mut self, cue_strats: Vec<Simplex>) {
+        self.cue_strats = cue_strats;
+    }
+
+    /// Get the cue accuracy of the agent, if it differs from the initial one.
+    pub fn accuracy(&self) -> Option<Probability> {
+        self.accuracy
+    }
+
+    /// Set the cue accuracy of the agent.
+    pub fn set_accuracy(&mut self, accuracy: Option<Probability>) {
+        self.accuracy = accuracy;
+    }
+
+    /// Get the mutation probability of the agent, if it differs from that of its species.
+    pub fn prob_mut(&self) -> Option<Probability> {
+        self.prob_mut
+    }
+
+    /// Set the mutation probability of the agent.
+    pub fn set_prob_mut(&mut self, prob_mut: Option<Probability>) {
+        self.prob_mut = prob_mut;
+    }
 }
 
 /// State of the simulation at a certain step.
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct State {
     /// Simulation time.
     pub time: f64,
 
-    /// Environment index.
+    /// Environment index (of the first patch, if there are patches).
     pub env: usize,
 
     /// Vector of agents in the simulation.
     pub agents: Vec<Agent>,
+
+    /// Environment index of every patch (empty without patches).
+    #[serde(default)]
+    pub patch_envs: Vec<usize>,
+
+    /// Concentration of every resource (see `ModelParams::resources`).
+    #[serde(default)]
+    pub resources: Vec<f64>,
+
+    /// Continuous environment value (see `ModelParams::continuous_env`).
+    #[serde(default)]
+    pub env_value: Option<f64>,
+}
+
+/// Group of identical agents.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AgentGroup {
+    /// Phenotype.
+    pub phe: usize,
+
+    /// Phenotypic strategy.
+    pub strat_phe: Simplex,
+
+    /// Number of agents.
+    #[serde(default = "AgentGroup::default_count")]
+    pub count: usize,
+}
+
+impl AgentGroup {
+    fn default_count() -> usize {
+        1
+    }
+}
+
+/// Switch of a deterministic environment trajectory.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EnvSwitch {
+    /// Time of the switch.
+    pub time: f64,
+
+    /// Environment from the switch on.
+    pub env: usize,
+}
+
+/// Initial agent data loaded from external sources.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct InitSources {
+    /// Histograms of every phenotypic strategy element but the last one.
+    pub strat_hists: Option<Vec<Vec<f64>>>,
+
+    /// Groups of initial agents.
+    pub agent_groups: Option<Vec<AgentGroup>>,
+
+    /// Range of the histograms of the phenotypic strategy elements (from 0 to 1 if unset).
+    #[serde(default)]
+    pub strat_hist_range: Option<[f64; 2]>,
+
+    /// Deterministic environment trajectory.
+    #[serde(default)]
+    pub env_trajectory: Option<Vec<EnvSwitch>>,
 }
 
 /// Single simulation event.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Event {
     /// Agent replication event.
     Replication { agent_idx: usize },
@@ -52,10 +406,218 @@ pub enum Event {
 
     /// Environment transition event.
     EnvTrans { next_env: usize },
+
+    /// Population bottleneck event.
+    Bottleneck { bottleneck_idx: usize },
+
+    /// Environment transition event of a patch.
+    PatchEnvTrans { patch: usize, next_env: usize },
+
+    /// Agent migration event (to a random patch, according to the migration rates of its patch).
+    Migration { agent_idx: usize },
+
+    /// Agent phenotype switching event (to a random phenotype, according to the switching rates
+    /// of its phenotype).
+    Switch { agent_idx: usize },
+
+    /// Generation event of Wright-Fisher dynamics (replacing every agent by its offspring).
+    Generation,
+
+    /// Update event of the continuous environment value.
+    EnvUpdate,
+}
+
+impl Event {
+    /// Get the type of the event.
+    pub fn event_type(&self) -> EventType {
+        match self {
+            Event::Replication { .. } => EventType::Replication,
+            Event::Death { .. } => EventType::Death,
+            Event::EnvTrans { .. } | Event::PatchEnvTrans { .. } => EventType::EnvTrans,
+            Event::Bottleneck { .. } => EventType::Bottleneck,
+            Event::Migration { .. } => EventType::Migration,
+            Event::Switch { .. } => EventType::Switch,
+            Event::Generation => EventType::Generation,
+            Event::EnvUpdate => EventType::EnvUpdate,
+        }
+    }
+}
+
+/// Type of a simulation event.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EventType {
+    /// Agent replication event.
+    Replication,
+
+    /// Agent death event.
+    Death,
+
+    /// Environment transition event.
+    EnvTrans,
+
+    /// Population bottleneck event.
+    Bottleneck,
+
+    /// Agent migration event.
+    Migration,
+
+    /// Agent phenotype switching event.
+    Switch,
+
+    /// Generation event.
+    Generation,
+
+    /// Continuous environment update event.
+    EnvUpdate,
+}
+
+/// Number of events of each type over a certain interval.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct EventCounts {
+    /// Number of agent births.
+    pub n_births: usize,
+
+    /// Number of agent births of each (parent) phenotype.
+    pub n_births_phe: Vec<usize>,
+
+    /// Number of agent deaths.
+    pub n_deaths: usize,
+
+    /// Number of agent deaths of each phenotype.
+    pub n_deaths_phe: Vec<usize>,
+
+    /// Number of environment transitions.
+    pub n_env_trans: usize,
+
+    /// Number of strategy mutations.
+    pub n_mut: usize,
+
+    /// Number of agents culled by the population cap.
+    pub n_culls: usize,
+
+    /// Number of agents culled by the population cap of each phenotype.
+    pub n_culls_phe: Vec<usize>,
+
+    /// Change in the logarithm of the population size due to births, deaths and bottlenecks
+    /// (ignoring culls and extinctions; `None` in outputs written by older versions).
+    #[serde(default)]
+    pub log_growth: Option<f64>,
+
+    /// Number of population bottlenecks.
+    #[serde(default)]
+    pub n_bottlenecks: usize,
+
+    /// Number of agent migrations between patches.
+    #[serde(default)]
+    pub n_migrations: usize,
+
+    /// Number of agent phenotype switches.
+    #[serde(default)]
+    pub n_switches: usize,
+
+    /// Environment transitions without patches, with the dwell times they ended (`None` in
+    /// outputs written by older versions).
+    #[serde(default)]
+    pub env_visits: Option<EnvVisitCounts>,
+}
+
+/// Environment transitions over a certain interval, with the dwell times they ended.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct EnvVisitCounts {
+    /// Number of transitions from every environment to every other one.
+    pub n_trans: Vec<Vec<usize>>,
+
+    /// Sums of the zeroth, first and second powers of the dwell times in every environment
+    /// (unknown for the visit a checkpoint written by an older version was in).
+    pub dwell_moments: Vec<[f64; 3]>,
+
+    /// Number of dwell times in every histogram bin of every environment (see
+    /// `Config::dwell_time_binning`).
+    pub dist_dwell_time: Vec<Vec<usize>>,
+}
+
+impl EnvVisitCounts {
+    /// Create new zeroed environment transition counts for a given number of environments and of
+    /// dwell time histogram bins.
+    pub fn new(n_env: usize, n_bins: usize) -> Self {
+        Self {
+            n_trans: vec![vec![0; n_env]; n_env],
+            dwell_moments: vec![[0.0; 3]; n_env],
+            dist_dwell_time: vec![vec![0; n_bins]; n_env],
+        }
+    }
+
+    /// Add other environment transition counts to these.
+    pub fn accumulate(&mut self, other: &EnvVisitCounts) {
+        let add = |sum: &mut Vec<usize>, other: &[usize]| {
+            sum.iter_mut().zip(other).for_each(|(sum, ele)| *sum += ele);
+        };
+        for (sum, other) in self.n_trans.iter_mut().zip(&other.n_trans) {
+            add(sum, other);
+        }
+        for (sum, other) in self.dwell_moments.iter_mut().zip(&other.dwell_moments) {
+            sum.iter_mut().zip(other).for_each(|(sum, ele)| *sum += ele);
+        }
+        for (sum, other) in self.dist_dwell_time.iter_mut().zip(&other.dist_dwell_time) {
+            add(sum, other);
+        }
+    }
+}
+
+impl EventCounts {
+    /// Create new zeroed event counts for a given number of phenotypes, of environments and of
+    /// dwell time histogram bins.
+    pub fn new(n_phe: usize, n_env: usize, n_bins: usize) -> Self {
+        Self {
+            n_births: 0,
+            n_births_phe: vec![0; n_phe],
+            n_deaths: 0,
+            n_deaths_phe: vec![0; n_phe],
+            n_env_trans: 0,
+            n_mut: 0,
+            n_culls: 0,
+            n_culls_phe: vec![0; n_phe],
+            log_growth: Some(0.0),
+            n_bottlenecks: 0,
+            n_migrations: 0,
+            n_switches: 0,
+            env_visits: Some(EnvVisitCounts::new(n_env, n_bins)),
+        }
+    }
+
+    /// Add other event counts to these.
+    pub fn accumulate(&mut self, other: &EventCounts) {
+        let add = |sum: &mut Vec<usize>, other: &[usize]| {
+            sum.iter_mut().zip(other).for_each(|(sum, ele)| *sum += ele);
+        };
+        self.n_births += other.n_births;
+        add(&mut self.n_births_phe, &other.n_births_phe);
+        self.n_deaths += other.n_deaths;
+        add(&mut self.n_deaths_phe, &other.n_deaths_phe);
+        self.n_env_trans += other.n_env_trans;
+        self.n_mut += other.n_mut;
+        self.n_culls += other.n_culls;
+        add(&mut self.n_culls_phe, &other.n_culls_phe);
+        self.log_growth = self
+            .log_growth
+            .zip(other.log_growth)
+            .map(|(sum, ele)| sum + ele);
+        self.n_bottlenecks += other.n_bottlenecks;
+        self.n_migrations += other.n_migrations;
+        self.n_switches += other.n_switches;
+        self.env_visits =
+            self.env_visits
+                .take()
+                .zip(other.env_visits.as_ref())
+                .map(|(mut sum, ele)| {
+                    sum.accumulate(ele);
+                    sum
+                });
+    }
 }
 
 /// Collection of simulation observables.
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Observables {
     /// Current simulation time.
     pub time: f64,
@@ -72,14 +634,285 @@ pub struct Observables {
     /// Number of extinctions so far.
     pub n_extinct: usize,
 
-    /// Average phenotypic strategy.
+    /// Average phenotypic strategy (if selected).
+    pub avg_strat_phe: Option<Vec<f64>>,
+
+    /// Standard deviation of the phenotypic strategy (if selected).
+    pub std_dev_strat_phe: Option<f64>,
+
+    /// Distribution of phenotypes (if selected).
+    pub dist_phe: Option<Vec<f64>>,
+
+    /// Event counts since the previous saved observables.
+    #[serde(default)]
+    pub event_counts: EventCounts,
+
+    /// Current environment index (`None` in outputs written by older versions).
+    #[serde(default)]
+    pub env: Option<usize>,
+
+    /// Current simulation step (`None` in outputs written by older versions).
+    #[serde(default)]
+    pub step: Option<usize>,
+
+    /// Type of the next event (`None` in outputs written by older versions).
+    #[serde(default)]
+    pub event_type: Option<EventType>,
+
+    /// Heterozygosity of the neutral marker (if enabled).
+    #[serde(default)]
+    pub marker_heterozygosity: Option<f64>,
+
+    /// Allele frequency spectrum of the neutral marker: number of alleles carried by every number
+    /// of agents, starting from one (if enabled).
+    #[serde(default)]
+    pub marker_afs: Option<Vec<usize>>,
+
+    /// Factor multiplying the environment transition rates (if they follow a schedule).
+    #[serde(default)]
+    pub trans_factor: Option<f64>,
+
+    /// Number of agents with every phenotype (if the distribution of phenotypes is selected).
+    #[serde(default)]
+    pub n_agents_phe: Option<Vec<f64>>,
+
+    /// Simulation time represented by the observables if saved on a uniform time grid: the grid
+    /// interval, times the number of grid times within the step.
+    #[serde(default)]
+    pub grid_weight: Option<f64>,
+
+    /// Effective number of distinct phenotypic strategies (if selected): the exponential of the
+    /// Shannon entropy of the strategies, quantized to the bins of the strategy histograms.
+    #[serde(default)]
+    pub strat_diversity: Option<f64>,
+
+    /// Mutational load: average number of strategy mutations along the ancestry of every agent
+    /// (`None` in outputs written by older versions).
+    #[serde(default)]
+    pub mut_load: Option<f64>,
+
+    /// Number of agents of every species (if there are species).
+    #[serde(default)]
+    pub n_agents_species: Option<Vec<f64>>,
+
+    /// Average phenotypic strategy of every species, without its last element (if there are
+    /// species).
+    #[serde(default)]
+    pub avg_strat_phe_species: Option<Vec<Vec<f64>>>,
+
+    /// Number of agents in every patch (if there are patches).
+    #[serde(default)]
+    pub n_agents_patch: Option<Vec<f64>>,
+
+    /// Environment index of every patch (if there are patches).
+    #[serde(default)]
+    pub env_patch: Option<Vec<usize>>,
+
+    /// Average phenotypic strategy of every patch, without its last element (`NaN` for
+    /// empty patches; if there are patches and the average phenotypic strategy is selected).
+    #[serde(default)]
+    pub avg_strat_phe_patch: Option<Vec<Vec<f64>>>,
+
+    /// Spatial average of the average phenotypic strategy: its average over the non-empty
+    /// patches, without its last element (if there are patches and the average phenotypic
+    /// strategy is selected).
+    #[serde(default)]
+    pub spatial_avg_strat_phe: Option<Vec<f64>>,
+
+    /// Average phenotypic strategy conditioned on every cue, without its last element (with
+    /// sensing, if the average phenotypic strategy is selected).
+    #[serde(default)]
+    pub avg_cue_strat_phe: Option<Vec<Vec<f64>>>,
+
+    /// Average cue accuracy (with sensing).
+    #[serde(default)]
+    pub avg_accuracy: Option<f64>,
+
+    /// Concentration of every resource (if there are resources).
+    #[serde(default)]
+    pub resources: Option<Vec<f64>>,
+
+    /// Continuous environment value (if the environment is continuous).
+    #[serde(default)]
+    pub env_value: Option<f64>,
+
+    /// Average decimal logarithm of the mutation probability (if it is evolvable).
+    #[serde(default)]
+    pub avg_log_prob_mut: Option<f64>,
+
+    /// Standard deviation of the decimal logarithm of the mutation probability (if it is
+    /// evolvable).
+    #[serde(default)]
+    pub std_dev_log_prob_mut: Option<f64>,
+}
+
+impl Observables {
+    /// Get the weight of the observables in time averages: the simulation time represented by them
+    /// if saved on a uniform time grid, or the time until the next event otherwise.
+    pub fn weight(&self) -> f64 {
+        self.grid_weight.unwrap_or(self.time_step)
+    }
+}
+
+/// Event affecting an agent of a tracer lineage.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TracerEvent {
+    /// Index of the tracer lineage.
+    pub tracer: usize,
+
+    /// Simulation step.
+    pub step: usize,
+
+    /// Simulation time.
+    pub time: f64,
+
+    /// Environment index.
+    pub env: usize,
+
+    /// Kind of event.
+    pub kind: TracerEventKind,
+}
+
+/// Kind of event affecting an agent of a tracer lineage.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum TracerEventKind {
+    /// The agent replicated, with a given offspring phenotype (and possibly mutated strategy).
+    Birth {
+        phe: usize,
+        offspring_phe: usize,
+        mutated: bool,
+    },
+
+    /// The agent died.
+    Death { phe: usize },
+
+    /// The agent was removed by the population cap or a bottleneck.
+    Removal { phe: usize },
+
+    /// The environment changed while the lineage was alive.
+    EnvTrans,
+}
+
+/// Entry of the lineage log of a simulation run.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum LineageEntry {
+    /// An agent was born from a parent (or, without parent, was already alive when the lineage
+    /// log was started or was generated after an extinction).
+    Birth {
+        id: u64,
+        parent: Option<u64>,
+        time: f64,
+        phe: usize,
+        strat_phe: Simplex,
+    },
+
+    /// An agent died or was removed by the population cap or a bottleneck.
+    Death { id: u64, time: f64 },
+}
+
+/// Full snapshot of the population of a simulation run at a certain step.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Simulation step.
+    pub step: usize,
+
+    /// Simulation time.
+    pub time: f64,
+
+    /// Environment index.
+    pub env: usize,
+
+    /// Phenotype of every agent.
+    pub phe: Vec<usize>,
+
+    /// Phenotypic strategy of every agent.
+    pub strat_phe: Vec<Simplex>,
+}
+
+/// Extinction of the population of a simulation run.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Extinction {
+    /// Simulation step of the event causing the extinction.
+    pub step: usize,
+
+    /// Simulation time of the extinction.
+    pub time: f64,
+
+    /// Environment index.
+    pub env: usize,
+
+    /// Type of the event causing the extinction (a death or a bottleneck).
+    pub cause: EventType,
+
+    /// Number of agents (of the extinct species) just before the extinction.
+    pub n_agents: usize,
+
+    /// Average phenotypic strategy of the agents (of the extinct species) just before the
+    /// extinction.
     pub avg_strat_phe: Vec<f64>,
 
-    /// Standard deviation of the phenotypic strategy.
-    pub std_dev_strat_phe: f64,
+    /// Extinct species (the whole population without species).
+    #[serde(default)]
+    pub species: usize,
+}
+
+/// Entry of the event log of a simulation run: an event selected at a step and the time elapsed
+/// until it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LoggedEvent {
+    /// Selected event.
+    pub event: Event,
 
-    /// Distribution of phenotypes.
-    pub dist_phe: Vec<f64>,
+    /// Time elapsed during the step.
+    pub time_step: f64,
+}
+
+/// Outcome of a single simulation step.
+pub struct StepOutcome {
+    /// Performed event.
+    pub event: Event,
+
+    /// Time elapsed during the step.
+    pub time_step: f64,
+
+    /// Simulation observables (if saved at this step).
+    pub observables: Option<Observables>,
+}
+
+/// Shift of the simulation clock (time and step counters) of a run, mapping the original clock
+/// to the new one.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ClockShift {
+    /// Simulation time in the original clock.
+    pub from_time: f64,
+
+    /// Simulation step in the original clock.
+    pub from_step: usize,
+
+    /// Simulation time in the new clock.
+    pub to_time: f64,
+
+    /// Simulation step in the new clock.
+    pub to_step: usize,
+}
+
+impl ClockShift {
+    /// Compose this shift with a later one, applied to the new clock.
+    pub fn then(self, later: ClockShift) -> Self {
+        Self {
+            from_time: later.from_time - (self.to_time - self.from_time),
+            from_step: (later.from_step + self.from_step).saturating_sub(self.to_step),
+            ..later
+        }
+    }
+
+    /// Map observables from the new clock back to the original one.
+    pub fn unshift(&self, observables: &mut Observables) {
+        observables.time += self.from_time - self.to_time;
+        observables.step = observables
+            .step
+            .map(|step| (step + self.from_step).saturating_sub(self.to_step));
+    }
 }
 
 /// Time-dependent statistic.
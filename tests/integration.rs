@@ -1,4 +1,9 @@
-use std::{env, fs, path::PathBuf, process::Command};
+use serde_json::Value;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
 
 #[test]
 fn basic_workflow() {
@@ -65,3 +70,1418 @@ fn basic_workflow() {
 
     fs::remove_dir_all(&test_dir).ok();
 }
+
+/// Create the simulation directory of a test, writing the configuration of `basic_workflow` with
+/// some extra `(section, line)` entries appended to their sections (top-level entries have an
+/// empty section).
+fn setup(name: &str, extra: &[(&str, &str)]) -> PathBuf {
+    let test_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+
+    fs::remove_dir_all(&test_dir).ok();
+    fs::create_dir(&test_dir).expect("failed to create test directory");
+
+    let sections = [
+        (
+            "model",
+            vec![
+                "n_env = 2",
+                "n_phe = 2",
+                "rates_trans = [ [ -1.0, 1.0,], [ 1.0, -1.0,],]",
+                "rates_birth = [ [ 1.2, 0.0,], [ 0.0, 0.8,],]",
+                "rates_death = [ [ 0.0, 1.0,], [ 1.0, 0.0,],]",
+                "prob_mut = 0.001",
+            ],
+        ),
+        ("init", vec!["n_agents = 100", "strat_phe = [ 0.5, 0.5,]"]),
+        (
+            "output",
+            vec![
+                "file_steps_factor = 1024",
+                "save_steps_factor = 16",
+                "hist_bins = 64",
+            ],
+        ),
+    ];
+    let mut config_contents = String::new();
+    for (_, line) in extra.iter().filter(|(section, _)| section.is_empty()) {
+        config_contents += line;
+        config_contents += "\n";
+    }
+    for (section, lines) in sections {
+        config_contents += &format!("[{section}]\n");
+        let extra_lines = extra
+            .iter()
+            .filter(|(other, _)| *other == section)
+            .map(|(_, line)| *line);
+        for line in lines.into_iter().chain(extra_lines) {
+            config_contents += line;
+            config_contents += "\n";
+        }
+        config_contents += "\n";
+    }
+
+    fs::write(test_dir.join("config.toml"), config_contents).expect("failed to write config file");
+
+    test_dir
+}
+
+/// Run the binary on a simulation directory, returning its output.
+fn run_sim_output(sim_dir: &Path, args: &[&str]) -> Output {
+    let bin = PathBuf::from(env!("CARGO_BIN_EXE_mutare"));
+
+    Command::new(bin)
+        .arg("--sim-dir")
+        .arg(sim_dir)
+        .args(args)
+        .output()
+        .expect("failed to execute command")
+}
+
+/// Run the binary on a simulation directory, expecting it to succeed, and return its stdout.
+fn run_sim(sim_dir: &Path, args: &[&str]) -> String {
+    let output = run_sim_output(sim_dir, args);
+
+    let stdout_str = String::from_utf8(output.stdout).expect("failed to convert stdout to string");
+    let stderr_str = String::from_utf8(output.stderr).expect("failed to convert stderr to string");
+
+    assert!(
+        output.status.success(),
+        "failed to run binary with {args:?}\nstdout:\n{stdout_str}\nstderr:\n{stderr_str}\n"
+    );
+
+    stdout_str
+}
+
+/// Run the binary on a simulation directory, expecting it to fail, and return its stdout and
+/// stderr.
+fn run_sim_fails(sim_dir: &Path, args: &[&str]) -> String {
+    let output = run_sim_output(sim_dir, args);
+
+    let stdout_str = String::from_utf8(output.stdout).expect("failed to convert stdout to string");
+    let stderr_str = String::from_utf8(output.stderr).expect("failed to convert stderr to string");
+
+    assert!(
+        !output.status.success(),
+        "binary unexpectedly succeeded with {args:?}\nstdout:\n{stdout_str}\nstderr:\n{stderr_str}\n"
+    );
+
+    stdout_str + &stderr_str
+}
+
+/// List the names of the files of a directory starting with a given prefix, in order.
+fn file_names(dir: &Path, prefix: &str) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .expect("failed to read directory")
+        .map(|entry| {
+            entry
+                .expect("failed to read directory entry")
+                .file_name()
+                .into_string()
+                .expect("failed to convert file name to string")
+        })
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Read a JSON file.
+fn read_json(file: &Path) -> Value {
+    let contents = fs::read_to_string(file).expect("failed to read JSON file");
+    serde_json::from_str(&contents).expect("failed to parse JSON file")
+}
+
+/// Read a CSV table into its header and rows (cells may be quoted).
+fn read_csv(file: &Path) -> (Vec<String>, Vec<Vec<String>>) {
+    let contents = fs::read_to_string(file).expect("failed to read CSV file");
+    let mut lines = contents.lines().map(|line| {
+        let mut cells = vec![String::new()];
+        let mut quoted = false;
+        let mut chars = line.chars().peekable();
+        while let Some(char) = chars.next() {
+            match char {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    cells.last_mut().unwrap().push('"');
+                }
+                '"' => quoted = !quoted,
+                ',' if !quoted => cells.push(String::new()),
+                _ => cells.last_mut().unwrap().push(char),
+            }
+        }
+        cells
+    });
+    let header = lines.next().expect("missing CSV header");
+    (header, lines.collect())
+}
+
+/// Get a column of a CSV table parsed as numbers.
+fn csv_column(table: &(Vec<String>, Vec<Vec<String>>), name: &str) -> Vec<f64> {
+    let (header, rows) = table;
+    let idx = header
+        .iter()
+        .position(|column| column == name)
+        .unwrap_or_else(|| panic!("missing CSV column {name:?}"));
+    rows.iter()
+        .map(|row| row[idx].parse().expect("failed to parse CSV cell"))
+        .collect()
+}
+
+/// Create, resume and analyze (also as JSON) the first run of a simulation directory, returning
+/// its analysis.
+fn simulate_run(sim_dir: &Path, create_args: &[&str]) -> Value {
+    run_sim(
+        sim_dir,
+        &[&["--run-idx", "0", "create"], create_args].concat(),
+    );
+    run_sim(sim_dir, &["--run-idx", "0", "resume"]);
+    run_sim(sim_dir, &["--run-idx", "0", "analyze", "--json"]);
+    read_json(&sim_dir.join("run-0000/analysis.json"))
+}
+
+#[test]
+fn init_and_validate() {
+    let test_dir = setup("init_and_validate", &[]);
+
+    let template_dir = test_dir.join("template");
+    run_sim(&template_dir, &["init"]);
+    let template = fs::read_to_string(template_dir.join("config.toml")).unwrap();
+    assert!(template.contains("[model]"));
+    run_sim(&template_dir, &["validate"]);
+    run_sim_fails(&template_dir, &["init"]);
+    run_sim(&template_dir, &["init", "--force"]);
+
+    run_sim(&test_dir, &["validate"]);
+
+    let invalid_dir = setup("init_and_validate_invalid", &[("output", "keep_files = 0")]);
+    let output = run_sim_fails(&invalid_dir, &["validate"]);
+    assert!(output.contains("invalid number of kept output files"));
+
+    fs::remove_dir_all(&test_dir).ok();
+    fs::remove_dir_all(&invalid_dir).ok();
+}
+
+#[test]
+fn create_with_overrides() {
+    let test_dir = setup("create_with_overrides", &[]);
+
+    run_sim(&test_dir, &["--run-idx", "0", "create"]);
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "1",
+            "create",
+            "--seed",
+            "42",
+            "--set",
+            "model.prob_mut=0.002",
+        ],
+    );
+    assert!(!test_dir.join("run-0000/config.json").exists());
+    let run_cfg = read_json(&test_dir.join("run-0001/config.json"));
+    assert_eq!(run_cfg["model"]["prob_mut"], 0.002);
+
+    let output = run_sim_fails(
+        &test_dir,
+        &["--run-idx", "2", "create", "--set", "model.prob_mut=2.0"],
+    );
+    assert!(output.contains("model.prob_mut=2.0"));
+    run_sim_fails(
+        &test_dir,
+        &["--run-idx", "2", "create", "--set", "model.unknown=1"],
+    );
+    assert!(!test_dir.join("run-0002/checkpoint.msgpack").exists());
+
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "3",
+            "create",
+            "--set",
+            "init.n_agents=400",
+            "--set",
+            "output.hist_bins=8",
+        ],
+    );
+    run_sim(&test_dir, &["--run-idx", "3", "resume"]);
+    run_sim(&test_dir, &["--run-idx", "3", "analyze", "--json"]);
+    run_sim(&test_dir, &["--run-idx", "3", "export"]);
+    let analysis = read_json(&test_dir.join("run-0003/analysis.json"));
+    assert_eq!(analysis["dist_n_agents"].as_array().unwrap().len(), 8);
+    assert!(test_dir.join("run-0003/observables.csv").exists());
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn resume_output_files() {
+    let test_dir = setup("resume_output_files", &[]);
+    let run_dir = test_dir.join("run-0000");
+
+    run_sim(&test_dir, &["--run-idx", "0", "create"]);
+    run_sim(&test_dir, &["--run-idx", "0", "resume"]);
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "resume",
+            "--n-files",
+            "2",
+            "--set",
+            "output.save_time_step=0.5",
+        ],
+    );
+    assert_eq!(
+        file_names(&run_dir, "output-"),
+        [
+            "output-0000.msgpack",
+            "output-0001.msgpack",
+            "output-0002.msgpack"
+        ]
+    );
+    let metadata = fs::read_to_string(run_dir.join("metadata.toml")).unwrap();
+    assert!(metadata.contains("n_steps = "));
+
+    let output = run_sim_fails(
+        &test_dir,
+        &["--run-idx", "0", "resume", "--set", "model.prob_mut=0.01"],
+    );
+    assert!(output.contains("only output parameters can be overridden when resuming"));
+    assert_eq!(file_names(&run_dir, "output-").len(), 3);
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn rewind_from_checkpoint() {
+    let test_dir = setup("rewind_from_checkpoint", &[]);
+    let run_dir = test_dir.join("run-0000");
+
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "create",
+            "--set",
+            "output.keep_checkpoints=2",
+        ],
+    );
+    run_sim(&test_dir, &["--run-idx", "0", "resume", "--n-files", "3"]);
+    assert_eq!(file_names(&run_dir, "output-").len(), 3);
+
+    run_sim(
+        &test_dir,
+        &["--run-idx", "0", "resume", "--from-checkpoint", "2"],
+    );
+    assert_eq!(file_names(&run_dir, "output-").len(), 3);
+
+    let output = run_sim_fails(
+        &test_dir,
+        &["--run-idx", "0", "resume", "--from-checkpoint", "0"],
+    );
+    assert!(output.contains("checkpoint"));
+    assert_eq!(file_names(&run_dir, "output-").len(), 3);
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn resume_output_logs() {
+    let test_dir = setup("resume_output_logs", &[]);
+    let run_dir = test_dir.join("run-0000");
+
+    run_sim(&test_dir, &["--run-idx", "0", "create"]);
+    run_sim(&test_dir, &["--run-idx", "0", "resume"]);
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "resume",
+            "--set",
+            "output.save_steps_factor=4",
+            "--set",
+            "output.n_tracers=4",
+            "--set",
+            "output.observers=[\"env-occupancy\", \"avg-n-agents\", \"avg-dist-phe\", \"event-rates\"]",
+            "--set",
+            "output.lineage=true",
+            "--set",
+            "output.event_log=true",
+            "--set",
+            "output.snapshot_steps_factor=64",
+        ],
+    );
+    for prefix in ["tracers", "observers", "lineage", "events", "snapshots"] {
+        let names = file_names(&run_dir, &format!("{prefix}-"));
+        assert_eq!(names, [format!("{prefix}-0001.msgpack")]);
+        assert!(fs::metadata(run_dir.join(&names[0])).unwrap().len() > 0);
+    }
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn replay_event_log() {
+    let test_dir = setup("replay_event_log", &[]);
+
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "create",
+            "--set",
+            "output.keep_checkpoints=2",
+        ],
+    );
+    run_sim(&test_dir, &["--run-idx", "0", "resume"]);
+    run_sim(
+        &test_dir,
+        &["--run-idx", "0", "resume", "--set", "output.event_log=true"],
+    );
+    run_sim(&test_dir, &["--run-idx", "0", "replay", "--file-idx", "1"]);
+
+    // The log ends with the time step of its last event, whose last mantissa bit is flipped.
+    let event_log_path = test_dir.join("run-0000/events-0001.msgpack");
+    let mut event_log = fs::read(&event_log_path).expect("failed to read event log");
+    *event_log.last_mut().unwrap() ^= 1;
+    fs::write(&event_log_path, event_log).expect("failed to write event log");
+    let output = run_sim_fails(&test_dir, &["--run-idx", "0", "replay", "--file-idx", "1"]);
+    assert!(output.contains("replay diverged at step"));
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "replay",
+            "--file-idx",
+            "1",
+            "--n-steps",
+            "100",
+        ],
+    );
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn extinctions_analysis() {
+    let test_dir = setup("extinctions_analysis", &[]);
+
+    simulate_run(&test_dir, &[]);
+    run_sim(&test_dir, &["--run-idx", "0", "extinctions", "--json"]);
+    let analysis = read_json(&test_dir.join("run-0000/analysis-extinctions.json"));
+    assert!(analysis["extinctions"].is_array());
+    assert!(analysis["rate_extinct"].as_f64().unwrap() >= 0.0);
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn fork_run() {
+    let test_dir = setup("fork_run", &[]);
+
+    simulate_run(&test_dir, &[]);
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "fork",
+            "--new-run-idx",
+            "1",
+            "--set",
+            "model.prob_mut=0.01",
+            "--set",
+            "model.prob_mut_marker=0.01",
+            "--set",
+            "model.std_dev_mut=0.1",
+            "--set",
+            "model.mutation_kernel=\"logit-normal\"",
+            "--set",
+            "model.expression=\"mixed\"",
+            "--set",
+            "model.bottlenecks=[{ rate = 0.01, survival = 0.5 }]",
+            "--set",
+            "init.env=1",
+            "--set",
+            "model.pinned_env=1",
+            "--reset-clock",
+        ],
+    );
+    let run_cfg = read_json(&test_dir.join("run-0001/config.json"));
+    assert_eq!(run_cfg["model"]["pinned_env"], 1);
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "1",
+            "--engine-threads",
+            "2",
+            "--progress",
+            "resume",
+        ],
+    );
+    run_sim(&test_dir, &["--run-idx", "1", "export"]);
+    let observables = read_csv(&test_dir.join("run-0001/observables.csv"));
+    assert!(
+        csv_column(&observables, "env")
+            .iter()
+            .all(|&env| env == 1.0)
+    );
+    assert!(csv_column(&observables, "time")[0] < 1.0);
+
+    let output = run_sim_fails(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "fork",
+            "--new-run-idx",
+            "2",
+            "--set",
+            "model.n_phe=3",
+        ],
+    );
+    assert!(output.contains("n_phe"));
+    assert!(!test_dir.join("run-0002/checkpoint.msgpack").exists());
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn checkpoint_bundle() {
+    let test_dir = setup("checkpoint_bundle", &[]);
+
+    run_sim(&test_dir, &["--run-idx", "0", "create"]);
+    run_sim(&test_dir, &["--run-idx", "0", "resume"]);
+
+    let bundle_path = test_dir.join("bundle.msgpack");
+    let bundle_str = bundle_path
+        .to_str()
+        .expect("failed to convert bundle path to string");
+    run_sim(
+        &test_dir,
+        &["--run-idx", "0", "export-checkpoint", "--file", bundle_str],
+    );
+    assert!(fs::metadata(&bundle_path).unwrap().len() > 0);
+    run_sim(
+        &test_dir,
+        &["--run-idx", "1", "import-checkpoint", "--file", bundle_str],
+    );
+    let stdout = run_sim(
+        &test_dir,
+        &["--run-idx", "0", "diff-checkpoints", "--other-run-idx", "1"],
+    );
+    assert!(
+        !stdout.contains("!="),
+        "imported checkpoint differs:\n{stdout}"
+    );
+
+    let output = run_sim_fails(
+        &test_dir,
+        &["--run-idx", "1", "import-checkpoint", "--file", bundle_str],
+    );
+    assert!(output.contains("already exists"));
+
+    run_sim(&test_dir, &["--run-idx", "1", "resume"]);
+    assert_eq!(
+        file_names(&test_dir.join("run-0001"), "output-"),
+        ["output-0000.msgpack"]
+    );
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn rebin_and_concat_outputs() {
+    let test_dir = setup("rebin_and_concat_outputs", &[]);
+    let run_dir = test_dir.join("run-0000");
+
+    run_sim(&test_dir, &["--run-idx", "0", "create"]);
+    run_sim(&test_dir, &["--run-idx", "0", "resume", "--n-files", "2"]);
+    run_sim(&test_dir, &["--run-idx", "0", "export"]);
+    let n_rows = read_csv(&run_dir.join("observables.csv")).1.len();
+
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "rebin",
+            "--factor",
+            "4",
+            "--mode",
+            "average",
+        ],
+    );
+    run_sim(&test_dir, &["--run-idx", "0", "export"]);
+    let rebinned = read_csv(&run_dir.join("observables.csv"));
+    assert_eq!(rebinned.1.len(), n_rows / 4);
+
+    run_sim(
+        &test_dir,
+        &["--run-idx", "0", "concat-outputs", "--group-size", "2"],
+    );
+    assert_eq!(file_names(&run_dir, "output-"), ["output-0000.msgpack"]);
+    run_sim(&test_dir, &["--run-idx", "0", "export"]);
+    let concatenated = read_csv(&run_dir.join("observables.csv"));
+    assert_eq!(
+        csv_column(&concatenated, "time"),
+        csv_column(&rebinned, "time")
+    );
+
+    let output = run_sim_fails(&test_dir, &["--run-idx", "0", "rebin", "--factor", "1"]);
+    assert!(output.contains("rebinning factor must be at least 2"));
+    let output = run_sim_fails(
+        &test_dir,
+        &["--run-idx", "0", "concat-outputs", "--group-size", "1"],
+    );
+    assert!(output.contains("group size must be at least 2"));
+
+    let run_dir = test_dir.join("run-0001");
+    run_sim(&test_dir, &["--run-idx", "1", "create"]);
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "1",
+            "resume",
+            "--n-files",
+            "2",
+            "--set",
+            "output.lineage=true",
+        ],
+    );
+    run_sim(
+        &test_dir,
+        &["--run-idx", "1", "concat-outputs", "--group-size", "2"],
+    );
+    assert_eq!(file_names(&run_dir, "lineage-"), ["lineage-0000.msgpack"]);
+
+    run_sim(&test_dir, &["--run-idx", "2", "create"]);
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "2",
+            "resume",
+            "--n-files",
+            "2",
+            "--set",
+            "output.n_tracers=2",
+        ],
+    );
+    let output = run_sim_fails(
+        &test_dir,
+        &["--run-idx", "2", "concat-outputs", "--group-size", "2"],
+    );
+    assert!(output.contains("cannot be concatenated"));
+    assert_eq!(
+        file_names(&test_dir.join("run-0002"), "output-"),
+        ["output-0000.msgpack", "output-0001.msgpack"]
+    );
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn resume_all_runs() {
+    let test_dir = setup("resume_all_runs", &[]);
+
+    run_sim(&test_dir, &["--run-idx", "0", "create"]);
+    run_sim(&test_dir, &["--run-idx", "1", "create"]);
+    run_sim(&test_dir, &["resume", "--all", "--threads", "2"]);
+    for run_dir in ["run-0000", "run-0001"] {
+        assert_eq!(
+            file_names(&test_dir.join(run_dir), "output-"),
+            ["output-0000.msgpack"]
+        );
+    }
+
+    let output = run_sim_fails(&test_dir, &["--run-idx", "0", "resume", "--all"]);
+    assert!(output.contains("--all can not be used together with --run-idx"));
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn analyze_and_aggregate() {
+    let test_dir = setup("analyze_and_aggregate", &[]);
+
+    let output = run_sim_fails(&test_dir, &["aggregate"]);
+    assert!(output.contains("no analyzed runs to aggregate"));
+
+    let analysis = simulate_run(&test_dir, &[]);
+    assert!(analysis["avg_growth_rate"].is_f64());
+    assert!(analysis["time_window"]["t_min"].is_null());
+
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "analyze",
+            "--t-min",
+            "10.0",
+            "--t-max",
+            "500.0",
+            "--json",
+        ],
+    );
+    let analysis = read_json(&test_dir.join("run-0000/analysis.json"));
+    assert_eq!(analysis["time_window"]["t_min"], 10.0);
+    assert_eq!(analysis["time_window"]["t_max"], 500.0);
+
+    run_sim(
+        &test_dir,
+        &["--run-idx", "0", "analyze", "--burn-in", "--json"],
+    );
+    let analysis = read_json(&test_dir.join("run-0000/analysis.json"));
+    assert!(analysis["burn_in_time"].as_f64().unwrap() >= 0.0);
+
+    run_sim(&test_dir, &["aggregate", "--json"]);
+    let aggregate = read_json(&test_dir.join("analysis.json"));
+    assert_eq!(aggregate["n_runs"], 1);
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn export_observables() {
+    let test_dir = setup("export_observables", &[]);
+
+    run_sim(&test_dir, &["--run-idx", "0", "create"]);
+    run_sim(&test_dir, &["--run-idx", "0", "resume"]);
+    run_sim(&test_dir, &["--run-idx", "0", "export"]);
+    let observables = read_csv(&test_dir.join("run-0000/observables.csv"));
+    assert_eq!(observables.0[..2], ["file_idx", "time"]);
+    assert_eq!(observables.1.len(), 1024 / 16);
+    assert!(
+        observables
+            .1
+            .iter()
+            .all(|row| row.len() == observables.0.len())
+    );
+    let time = csv_column(&observables, "time");
+    assert!(time.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert_eq!(csv_column(&observables, "n_agents")[0], 100.0);
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn simulate_and_garden() {
+    let test_dir = setup("simulate_and_garden", &[]);
+    let run_dir = test_dir.join("run-0000");
+
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "simulate",
+            "--n-steps",
+            "100000",
+            "--checkpoint",
+        ],
+    );
+    assert!(run_dir.join("analysis.msgpack").exists());
+    assert!(run_dir.join("checkpoint.msgpack").exists());
+    assert!(file_names(&run_dir, "output-").is_empty());
+
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "garden",
+            "--n-realizations",
+            "2",
+            "--n-steps",
+            "10000",
+        ],
+    );
+    assert!(run_dir.join("garden.msgpack").exists());
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn environment_theory() {
+    let test_dir = setup("environment_theory", &[]);
+
+    let stdout = run_sim(&test_dir, &["calibrate-env", "--n-trans", "1000"]);
+    assert!(stdout.contains("env 0:") && stdout.contains("env 1:"));
+
+    run_sim(&test_dir, &["optimal", "--n-trans", "100", "--json"]);
+    let optimal = read_json(&test_dir.join("optimal.json"));
+    let strat_phe: Vec<f64> = optimal["strat_phe"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|ele| ele.as_f64().unwrap())
+        .collect();
+    assert_eq!(strat_phe.len(), 2);
+    assert!((strat_phe.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn environment_transitions() {
+    let test_dir = setup("environment_transitions", &[]);
+
+    let env_trans = simulate_run(&test_dir, &[])["env_trans"].clone();
+    assert_eq!(env_trans["n_missed"], 0);
+    let n_visits: u64 = (env_trans["n_visits"].as_array().unwrap().iter())
+        .map(|n| n.as_u64().unwrap())
+        .sum();
+    assert!(n_visits > 0);
+    assert!(
+        (env_trans["deviations"].as_array().unwrap().iter())
+            .all(|deviation| !deviation.as_str().unwrap().contains("missed"))
+    );
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn fast_forward_run() {
+    let test_dir = setup("fast_forward_run", &[]);
+
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "fast-forward",
+            "--seed",
+            "42",
+            "--steps",
+            "10000",
+        ],
+    );
+    assert!(file_names(&test_dir.join("run-0000"), "output-").is_empty());
+    let stdout = run_sim(&test_dir, &["--run-idx", "0", "status"]);
+    assert!(
+        stdout.contains("step 10000"),
+        "unexpected status:\n{stdout}"
+    );
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn transition_schedule() {
+    let test_dir = setup(
+        "transition_schedule",
+        &[(
+            "model",
+            "trans_schedule = [ { time = 0.0, factor = 1.0 }, { time = 100.0, factor = 2.0 } ]",
+        )],
+    );
+
+    run_sim(&test_dir, &["--run-idx", "0", "create"]);
+    run_sim(&test_dir, &["--run-idx", "0", "resume"]);
+    run_sim(&test_dir, &["--run-idx", "0", "export"]);
+    let observables = read_csv(&test_dir.join("run-0000/observables.csv"));
+    let time = csv_column(&observables, "time");
+    let trans_factor = csv_column(&observables, "trans_factor");
+    for (time, trans_factor) in time.into_iter().zip(trans_factor) {
+        if time >= 100.0 {
+            assert_eq!(trans_factor, 2.0);
+        }
+    }
+
+    let invalid_dir = setup(
+        "transition_schedule_invalid",
+        &[(
+            "model",
+            "trans_schedule = [ { time = 0.0, factor = 1.0 }, { time = 0.0, factor = 2.0 } ]",
+        )],
+    );
+    let output = run_sim_fails(&invalid_dir, &["validate"]);
+    assert!(output.contains("transition schedule knot times must be strictly increasing"));
+
+    fs::remove_dir_all(&test_dir).ok();
+    fs::remove_dir_all(&invalid_dir).ok();
+}
+
+#[test]
+fn master_seed_and_initial_env() {
+    let extra = [("init", "master_seed = 7"), ("init", "env = 0")];
+    let test_dirs = [
+        setup("master_seed_and_initial_env_0", &extra),
+        setup("master_seed_and_initial_env_1", &extra),
+    ];
+
+    let observables = test_dirs.each_ref().map(|test_dir| {
+        run_sim(test_dir, &["--run-idx", "0", "create"]);
+        run_sim(test_dir, &["--run-idx", "0", "resume"]);
+        run_sim(test_dir, &["--run-idx", "0", "export"]);
+        fs::read_to_string(test_dir.join("run-0000/observables.csv")).unwrap()
+    });
+    assert_eq!(observables[0], observables[1]);
+    let observables = read_csv(&test_dirs[0].join("run-0000/observables.csv"));
+    assert_eq!(csv_column(&observables, "env")[0], 0.0);
+
+    for test_dir in test_dirs {
+        fs::remove_dir_all(&test_dir).ok();
+    }
+}
+
+#[test]
+fn aligned_averages_and_histograms() {
+    let test_dir = setup(
+        "aligned_averages_and_histograms",
+        &[
+            ("output", "aligned_time = 4.0"),
+            (
+                "output",
+                "histograms = { n_agents = { bins = 32, range = [ 0.0, 200.0,] } }",
+            ),
+        ],
+    );
+
+    let analysis = simulate_run(&test_dir, &[]);
+    assert!(!analysis["aligned_n_agents"].as_array().unwrap().is_empty());
+    assert_eq!(analysis["dist_n_agents"].as_array().unwrap().len(), 32);
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn flushed_output_files() {
+    let test_dir = setup(
+        "flushed_output_files",
+        &[("output", "flush_every_records = 64")],
+    );
+    let run_dir = test_dir.join("run-0000");
+
+    simulate_run(&test_dir, &[]);
+    assert_eq!(file_names(&run_dir, "output-"), ["output-0000.msgpack"]);
+    run_sim(&test_dir, &["--run-idx", "0", "export"]);
+    assert_eq!(
+        read_csv(&run_dir.join("observables.csv")).1.len(),
+        1024 / 16
+    );
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn prune_output_files() {
+    let test_dir = setup("prune_output_files", &[("output", "keep_files = 1")]);
+    let run_dir = test_dir.join("run-0000");
+
+    run_sim(&test_dir, &["--run-idx", "0", "create"]);
+    run_sim(&test_dir, &["--run-idx", "0", "resume", "--n-files", "3"]);
+    run_sim(&test_dir, &["prune"]);
+    assert_eq!(file_names(&run_dir, "output-").len(), 3);
+
+    run_sim(&test_dir, &["--run-idx", "0", "analyze"]);
+    run_sim(&test_dir, &["prune"]);
+    assert_eq!(file_names(&run_dir, "output-"), ["output-0002.msgpack"]);
+
+    run_sim(&test_dir, &["--run-idx", "0", "resume"]);
+    assert_eq!(
+        file_names(&run_dir, "output-"),
+        ["output-0002.msgpack", "output-0003.msgpack"]
+    );
+    run_sim(&test_dir, &["--run-idx", "0", "analyze"]);
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn stopping_criteria() {
+    let test_dir = setup("stopping_criteria", &[]);
+
+    run_sim(&test_dir, &["--run-idx", "0", "create"]);
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "resume",
+            "--set",
+            "output.target_time=100.0",
+        ],
+    );
+    run_sim(&test_dir, &["--run-idx", "0", "export"]);
+    let observables = read_csv(&test_dir.join("run-0000/observables.csv"));
+    let time = csv_column(&observables, "time");
+    assert!(*time.last().unwrap() <= 100.0);
+
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "resume",
+            "--set",
+            "output.convergence={ tolerance = 0.05, min_files = 3 }",
+        ],
+    );
+    let stdout = run_sim(&test_dir, &["--run-idx", "0", "status"]);
+    assert!(
+        stdout.contains("converged: "),
+        "unexpected status:\n{stdout}"
+    );
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn status_and_clean() {
+    let test_dir = setup("status_and_clean", &[]);
+    let run_dir = test_dir.join("run-0000");
+
+    simulate_run(&test_dir, &[]);
+    let stdout = run_sim(&test_dir, &["status", "--disk"]);
+    assert!(
+        stdout.contains("1 output files"),
+        "unexpected status:\n{stdout}"
+    );
+    assert!(stdout.contains("analyzed: true"));
+    assert!(stdout.contains("total: "));
+
+    for args in [
+        &["clean", "--outputs-only", "--dry-run"][..],
+        &["clean", "--keep-analysis", "--keep-checkpoint", "--dry-run"],
+        &["clean", "--analyzed-outputs", "--dry-run"],
+    ] {
+        run_sim(&test_dir, args);
+        assert!(run_dir.join("output-0000.msgpack").exists());
+    }
+
+    run_sim(&test_dir, &["clean", "--analyzed-outputs"]);
+    assert!(file_names(&run_dir, "output-").is_empty());
+    assert!(run_dir.join("analysis.msgpack").exists());
+
+    run_sim(&test_dir, &["--run-idx", "0", "clean"]);
+    assert!(!run_dir.join("checkpoint.msgpack").exists());
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn relative_sim_dir() {
+    let test_dir = setup("relative_sim_dir", &[]);
+    let bin = PathBuf::from(env!("CARGO_BIN_EXE_mutare"));
+
+    for args in [
+        &["create"][..],
+        &["resume"],
+        &["resume"],
+        &["analyze"],
+        &["status"],
+    ] {
+        let output = Command::new(&bin)
+            .current_dir(env!("CARGO_TARGET_TMPDIR"))
+            .args(["--sim-dir", "./relative_sim_dir", "--run-idx", "0"])
+            .args(args)
+            .output()
+            .expect("failed to execute command");
+        assert!(
+            output.status.success(),
+            "failed to run binary with {args:?}\nstderr:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    assert_eq!(
+        file_names(&test_dir.join("run-0000"), "output-"),
+        ["output-0000.msgpack", "output-0001.msgpack"]
+    );
+    assert!(test_dir.join("run-0000/analysis.msgpack").exists());
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn multiple_species() {
+    let test_dir = setup(
+        "multiple_species",
+        &[(
+            "",
+            "species = [ { n_agents = 60 }, { n_agents = 40, prob_mut = 0.01 } ]",
+        )],
+    );
+
+    let analysis = simulate_run(&test_dir, &[]);
+    assert_eq!(
+        analysis["avg_n_agents_species"].as_array().unwrap().len(),
+        2
+    );
+    run_sim(&test_dir, &["--run-idx", "0", "export"]);
+    let observables = read_csv(&test_dir.join("run-0000/observables.csv"));
+    assert!(observables.0.contains(&"n_agents_species_1".to_string()));
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn lattice_patches() {
+    let test_dir = setup(
+        "lattice_patches",
+        &[(
+            "model",
+            "lattice = { shape = [2, 3], rate_migration = 0.1 }",
+        )],
+    );
+
+    let analysis = simulate_run(&test_dir, &["--set", "init.n_agents=20"]);
+    assert_eq!(analysis["avg_n_agents_patch"].as_array().unwrap().len(), 6);
+    assert!(analysis["migration_rate"].as_f64().unwrap() > 0.0);
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn migrating_demes() {
+    let test_dir = setup(
+        "migrating_demes",
+        &[(
+            "model",
+            "demes = { n_agents = [30, 60], rates_migration = [[0.0, 0.1], [0.05, 0.0]] }",
+        )],
+    );
+
+    let analysis = simulate_run(&test_dir, &[]);
+    assert_eq!(analysis["avg_n_agents_patch"].as_array().unwrap().len(), 2);
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn environment_sensing() {
+    let test_dir = setup(
+        "environment_sensing",
+        &[(
+            "model",
+            "sensing = { accuracy = 0.8, std_dev_accuracy = 0.05 }",
+        )],
+    );
+
+    let analysis = simulate_run(&test_dir, &["--set", "model.prob_mut=0.01"]);
+    let avg_accuracy = analysis["avg_avg_accuracy"].as_f64().unwrap();
+    assert!((0.0..=1.0).contains(&avg_accuracy));
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn phenotypic_switching() {
+    let test_dir = setup(
+        "phenotypic_switching",
+        &[(
+            "model",
+            "rates_switch = [[[0.0, 0.05], [0.2, 0.0]], [[0.0, 0.2], [0.05, 0.0]]]",
+        )],
+    );
+
+    let analysis = simulate_run(&test_dir, &[]);
+    assert!(analysis["switch_rate"].as_f64().unwrap() > 0.0);
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn carrying_capacity() {
+    let test_dir = setup(
+        "carrying_capacity",
+        &[("model", "carrying_capacity = 100.0")],
+    );
+
+    run_sim(
+        &test_dir,
+        &[
+            "--run-idx",
+            "0",
+            "create",
+            "--seed",
+            "1",
+            "--set",
+            "model.rates_death=[[0.2, 1.0], [1.0, 0.2]]",
+        ],
+    );
+    run_sim(&test_dir, &["--run-idx", "0", "resume"]);
+    run_sim(&test_dir, &["--run-idx", "0", "export"]);
+    let observables = read_csv(&test_dir.join("run-0000/observables.csv"));
+    let n_agents = csv_column(&observables, "n_agents");
+    // The best specialist equilibrates at 1.2 / 0.2 times the carrying capacity.
+    assert!(n_agents.iter().any(|&n_agents| n_agents > 100.0));
+    assert!(n_agents.iter().all(|&n_agents| n_agents < 1200.0));
+
+    let invalid_dir = setup(
+        "carrying_capacity_invalid",
+        &[("model", "carrying_capacity = -1.0")],
+    );
+    run_sim_fails(&invalid_dir, &["validate"]);
+
+    fs::remove_dir_all(&test_dir).ok();
+    fs::remove_dir_all(&invalid_dir).ok();
+}
+
+#[test]
+fn fixed_size_dynamics() {
+    for dynamics in ["moran", "wright-fisher"] {
+        let test_dir = setup(
+            &format!("fixed_size_dynamics_{dynamics}"),
+            &[("model", &format!("dynamics = \"{dynamics}\""))],
+        );
+
+        run_sim(&test_dir, &["--run-idx", "0", "create"]);
+        run_sim(&test_dir, &["--run-idx", "0", "resume"]);
+        run_sim(&test_dir, &["--run-idx", "0", "export"]);
+        let observables = read_csv(&test_dir.join("run-0000/observables.csv"));
+        let n_agents = csv_column(&observables, "n_agents");
+        assert!(n_agents.iter().all(|&n_agents| n_agents == 100.0));
+
+        fs::remove_dir_all(&test_dir).ok();
+    }
+}
+
+#[test]
+fn consumable_resources() {
+    let test_dir = setup(
+        "consumable_resources",
+        &[(
+            "model",
+            "resources = [ { capacity = 100.0, rate_supply = 1.0, half_saturation = 10.0, cost = 1.0 } ]",
+        )],
+    );
+
+    let analysis = simulate_run(&test_dir, &[]);
+    let avg_resources = analysis["avg_resources"].as_array().unwrap();
+    assert_eq!(avg_resources.len(), 1);
+    assert!((0.0..=100.0).contains(&avg_resources[0].as_f64().unwrap()));
+    run_sim(&test_dir, &["--run-idx", "0", "export"]);
+    let observables = read_csv(&test_dir.join("run-0000/observables.csv"));
+    let resource = csv_column(&observables, "resource_0");
+    assert!(resource.iter().all(|&resource| resource <= 100.0));
+
+    let invalid_dir = setup(
+        "consumable_resources_invalid",
+        &[
+            (
+                "model",
+                "resources = [ { capacity = 100.0, rate_supply = 1.0, half_saturation = 10.0, cost = 1.0 } ]",
+            ),
+            ("model", "dynamics = \"moran\""),
+        ],
+    );
+    run_sim_fails(&invalid_dir, &["validate"]);
+
+    fs::remove_dir_all(&test_dir).ok();
+    fs::remove_dir_all(&invalid_dir).ok();
+}
+
+#[test]
+fn environment_file() {
+    let test_dir = setup(
+        "environment_file",
+        &[("model", "environment = { file = \"environment.csv\" }")],
+    );
+    fs::write(
+        test_dir.join("environment.csv"),
+        "time,env\n0.0,1\n5.0,0\n20.0,1\n",
+    )
+    .expect("failed to write environment file");
+
+    run_sim(&test_dir, &["--run-idx", "0", "create"]);
+    run_sim(&test_dir, &["--run-idx", "0", "resume"]);
+    run_sim(&test_dir, &["--run-idx", "0", "export"]);
+    let observables = read_csv(&test_dir.join("run-0000/observables.csv"));
+    let time = csv_column(&observables, "time");
+    let env = csv_column(&observables, "env");
+    for (time, env) in time.into_iter().zip(env) {
+        let expected = if (5.0..20.0).contains(&time) {
+            0.0
+        } else {
+            1.0
+        };
+        assert_eq!(env, expected, "unexpected environment at time {time}");
+    }
+
+    fs::write(
+        test_dir.join("environment.csv"),
+        "time,env\n0.0,1\n5.0,0\n5.0,1\n",
+    )
+    .expect("failed to write environment file");
+    let output = run_sim_fails(&test_dir, &["--run-idx", "1", "create"]);
+    assert!(output.contains("environment.csv"));
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn periodic_environment() {
+    let test_dir = setup(
+        "periodic_environment",
+        &[("model", "environment = { dwell_times = [3.0, 1.0] }")],
+    );
+
+    run_sim(&test_dir, &["--run-idx", "0", "create"]);
+    run_sim(&test_dir, &["--run-idx", "0", "resume"]);
+    run_sim(&test_dir, &["--run-idx", "0", "export"]);
+    let observables = read_csv(&test_dir.join("run-0000/observables.csv"));
+    let time = csv_column(&observables, "time");
+    let env = csv_column(&observables, "env");
+    for (time, env) in time.into_iter().zip(env) {
+        let expected = if time % 4.0 < 3.0 { 0.0 } else { 1.0 };
+        assert_eq!(env, expected, "unexpected environment at time {time}");
+    }
+
+    let invalid_dir = setup(
+        "periodic_environment_invalid",
+        &[(
+            "model",
+            "environment = { dwell_times = [3.0, 1.0], file = \"environment.csv\" }",
+        )],
+    );
+    run_sim_fails(&invalid_dir, &["validate"]);
+
+    fs::remove_dir_all(&test_dir).ok();
+    fs::remove_dir_all(&invalid_dir).ok();
+}
+
+#[test]
+fn gaussian_mutation_kernel() {
+    let test_dir = setup(
+        "gaussian_mutation_kernel",
+        &[("model", "std_dev_mut = 0.1")],
+    );
+
+    let kernel = "model.mutation_kernel=\"gaussian\"";
+    run_sim(&test_dir, &["--run-idx", "0", "create", "--seed", "3"]);
+    run_sim(
+        &test_dir,
+        &["--run-idx", "1", "create", "--seed", "3", "--set", kernel],
+    );
+    for run_idx in ["0", "1"] {
+        run_sim(&test_dir, &["--run-idx", run_idx, "resume"]);
+    }
+    let output_file =
+        |run_dir| fs::read(test_dir.join(run_dir).join("output-0000.msgpack")).unwrap();
+    assert_eq!(output_file("run-0000"), output_file("run-0001"));
+
+    fs::remove_dir_all(&test_dir).ok();
+}
+
+#[test]
+fn evolvable_mutation_probability() {
+    let test_dir = setup(
+        "evolvable_mutation_probability",
+        &[(
+            "model",
+            "evolvable_mut = { prob_mut = 0.1, std_dev_log = 0.2 }",
+        )],
+    );
+
+    let analysis = simulate_run(&test_dir, &[]);
+    let avg_log_prob_mut = analysis["avg_avg_log_prob_mut"].as_f64().unwrap();
+    assert!(avg_log_prob_mut <= 0.0);
+    assert!(analysis["avg_std_dev_log_prob_mut"].as_f64().unwrap() >= 0.0);
+
+    let invalid_dir = setup(
+        "evolvable_mutation_probability_invalid",
+        &[(
+            "model",
+            "evolvable_mut = { prob_mut = 1.5, std_dev_log = 0.2 }",
+        )],
+    );
+    run_sim_fails(&invalid_dir, &["validate"]);
+
+    fs::remove_dir_all(&test_dir).ok();
+    fs::remove_dir_all(&invalid_dir).ok();
+}
+
+#[test]
+fn continuous_environment() {
+    let continuous_env = String::new()
+        + "continuous_env = { mean = 0.0, rate_relax = 0.5, std_dev = 1.0, update_interval = 0.05, "
+        + "rates_birth = [ { coeffs = [ 1.0, 0.5,] }, "
+        + "{ values = [ 1.5, 1.0, 0.5,], range = [ -1.0, 1.0,] } ], "
+        + "rates_death = [ { coeffs = [ 0.5,] }, { coeffs = [ 0.5,] } ] }";
+    let test_dir = setup("continuous_environment", &[]);
+    let config = fs::read_to_string(test_dir.join("config.toml"))
+        .unwrap()
+        .replace("n_env = 2", "n_env = 1")
+        .replace(
+            "rates_trans = [ [ -1.0, 1.0,], [ 1.0, -1.0,],]",
+            "rates_trans = [ [ 0.0,],]",
+        )
+        .replace(
+            "rates_birth = [ [ 1.2, 0.0,], [ 0.0, 0.8,],]",
+            "rates_birth = [ [ 1.0, 1.0,],]",
+        )
+        .replace(
+            "rates_death = [ [ 0.0, 1.0,], [ 1.0, 0.0,],]",
+            &format!("rates_death = [ [ 0.5, 0.5,],]\n{continuous_env}"),
+        );
+    fs::write(test_dir.join("config.toml"), &config).expect("failed to write config file");
+
+    let analysis = simulate_run(&test_dir, &[]);
+    assert!(analysis["avg_env_value"].is_f64());
+    run_sim(&test_dir, &["--run-idx", "0", "export"]);
+    let observables = read_csv(&test_dir.join("run-0000/observables.csv"));
+    let env_value = csv_column(&observables, "env_value");
+    assert!(env_value.windows(2).any(|pair| pair[0] != pair[1]));
+
+    let invalid_dir = setup(
+        "continuous_environment_invalid",
+        &[("model", &continuous_env)],
+    );
+    run_sim_fails(&invalid_dir, &["validate"]);
+
+    fs::remove_dir_all(&test_dir).ok();
+    fs::remove_dir_all(&invalid_dir).ok();
+}
+
+#[test]
+fn collect_results() {
+    let test_dir = setup("collect_results", &[]);
+    let other_dir = setup("collect_results_other", &[]);
+    let other_str = other_dir
+        .to_str()
+        .expect("failed to convert other directory to string");
+
+    let output = run_sim_fails(&test_dir, &["collect"]);
+    assert!(output.contains("no analyzed runs to collect"));
+
+    simulate_run(&test_dir, &[]);
+    simulate_run(&other_dir, &["--set", "model.prob_mut=0.002"]);
+    run_sim(&test_dir, &["--run-idx", "1", "create"]);
+    run_sim(&test_dir, &["collect", "--other-sim-dir", other_str]);
+
+    let results = read_csv(&test_dir.join("results.csv"));
+    assert_eq!(results.1.len(), 2);
+    assert_eq!(csv_column(&results, "run_idx"), [0.0, 0.0]);
+    assert_eq!(csv_column(&results, "model.prob_mut"), [0.001, 0.002]);
+    let analysis = read_json(&test_dir.join("run-0000/analysis.json"));
+    let avg_growth_rate = analysis["avg_growth_rate"].as_f64().unwrap();
+    // JSON floats are only parsed to within an ulp.
+    let error = csv_column(&results, "avg_growth_rate")[0] - avg_growth_rate;
+    assert!(error.abs() <= 1e-12 * avg_growth_rate.abs());
+    assert!(results.0.contains(&"avg_avg_strat_phe_0".to_string()));
+    assert!(
+        results
+            .0
+            .contains(&"error_estimates.growth_rate.mean".to_string())
+    );
+    let strat_phe_idx = results
+        .0
+        .iter()
+        .position(|column| column == "init.strat_phe");
+    assert_eq!(results.1[0][strat_phe_idx.unwrap()], "[0.5,0.5]");
+
+    fs::remove_dir_all(&test_dir).ok();
+    fs::remove_dir_all(&other_dir).ok();
+}